@@ -1,3 +1,7 @@
+// Shared by every bench target; not all helpers are used by each one.
+#![allow(dead_code)]
+
+use memori_core::util::l2_normalize;
 use memori_core::Memori;
 use rand::Rng;
 use rand::rngs::StdRng;
@@ -43,12 +47,7 @@ const MEMORY_TYPES: &[&str] = &[
 /// Generate a random 384-dim unit-normalized vector.
 pub fn random_unit_vector(rng: &mut StdRng) -> Vec<f32> {
     let mut v: Vec<f32> = (0..384).map(|_| rng.gen::<f32>() - 0.5).collect();
-    let norm: f32 = v.iter().map(|x| x * x).sum::<f32>().sqrt();
-    if norm > 0.0 {
-        for x in &mut v {
-            *x /= norm;
-        }
-    }
+    l2_normalize(&mut v);
     v
 }
 
@@ -74,8 +73,14 @@ pub fn random_metadata(rng: &mut StdRng) -> serde_json::Value {
 /// Seed an in-memory DB with N memories using insert_with_id (bypasses embedding + dedup).
 /// Returns (Memori, Vec<id>, Vec<vector>).
 pub fn seed_db(n: usize) -> (Memori, Vec<String>, Vec<Vec<f32>>) {
+    seed_db_at(":memory:", n)
+}
+
+/// `seed_db` into the database at `path`, for benches that also need to
+/// reach the file with a raw SQLite connection.
+pub fn seed_db_at(path: &str, n: usize) -> (Memori, Vec<String>, Vec<Vec<f32>>) {
     let mut rng = StdRng::seed_from_u64(42);
-    let db = Memori::open(":memory:").expect("failed to open in-memory DB");
+    let db = Memori::open(path).expect("failed to open DB");
 
     let mut ids = Vec::with_capacity(n);
     let mut vectors = Vec::with_capacity(n);
@@ -104,7 +104,7 @@ fn bench_list(c: &mut Criterion) {
 
         group.bench_with_input(BenchmarkId::from_parameter(scale), &scale, |bencher, _| {
             bencher.iter(|| {
-                db.list(None, &SortField::Created, 20, 0, None, None).unwrap()
+                db.list(None, &SortField::Created, 20, 0, None, None, false).unwrap()
             })
         });
     }
@@ -1,6 +1,6 @@
 mod common;
 
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
 use memori_core::embed;
 use memori_core::Memori;
 use rand::rngs::StdRng;
@@ -69,10 +69,39 @@ fn bench_insert_with_auto_embed(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_backfill_parallelism(c: &mut Criterion) {
+    let mut group = c.benchmark_group("backfill_embeddings");
+    group.sample_size(10);
+    group.measurement_time(std::time::Duration::from_secs(60));
+    group.warm_up_time(std::time::Duration::from_secs(5));
+
+    let mut rng = StdRng::seed_from_u64(42);
+    let contents: Vec<String> = (0..400).map(|_| common::random_content(&mut rng)).collect();
+
+    for &parallelism in &[1usize, 4] {
+        group.bench_function(format!("400_rows_x{}", parallelism), |bencher| {
+            bencher.iter_batched(
+                || {
+                    let db = Memori::open(":memory:").expect("failed to open in-memory DB");
+                    for content in &contents {
+                        db.insert(content, None, None, None, true).unwrap();
+                    }
+                    db
+                },
+                |db| db.backfill_embeddings_parallel(50, parallelism, |_, _| {}).unwrap(),
+                BatchSize::PerIteration,
+            )
+        });
+    }
+
+    group.finish();
+}
+
 criterion_group!(
     benches,
     bench_embed_text,
     bench_embed_batch,
     bench_insert_with_auto_embed,
+    bench_backfill_parallelism,
 );
 criterion_main!(benches);
@@ -5,7 +5,7 @@
 
 mod common;
 
-use memori_core::Memori;
+use memori_core::{Memori, MemoriConfig, VectorPrecision};
 use rand::rngs::StdRng;
 use rand::SeedableRng;
 use std::time::Instant;
@@ -54,13 +54,48 @@ fn cleanup(path: &str) {
     let _ = std::fs::remove_file(format!("{}-shm", path));
 }
 
-fn measure_scale(n: usize) {
+/// On-disk size of one seeded database, read back after VACUUM.
+struct Footprint {
+    file_bytes: u64,
+    pages: u64,
+    vector_bytes: u64,
+}
+
+/// Page count and stored vector bytes, read straight from the file.
+fn read_footprint(path: &str) -> Footprint {
+    let conn = rusqlite::Connection::open(path).expect("reopen failed");
+    let pages: i64 = conn
+        .query_row("PRAGMA page_count", [], |row| row.get(0))
+        .unwrap();
+    let vector_bytes: i64 = conn
+        .query_row("SELECT COALESCE(SUM(length(vector)), 0) FROM memories", [], |row| row.get(0))
+        .unwrap();
+    Footprint {
+        file_bytes: file_size_bytes(path),
+        pages: pages as u64,
+        vector_bytes: vector_bytes as u64,
+    }
+}
+
+fn measure_scale(n: usize, enable_fts: bool, vector_precision: VectorPrecision) -> Footprint {
     let path = format!("/tmp/memori-bench-memory-{}.db", n);
     cleanup(&path);
 
-    eprint!("  Seeding {} memories ... ", format_count(n));
+    let fts_label = if enable_fts { "on" } else { "off" };
+    let precision_label = vector_precision.as_str();
+    eprint!(
+        "  Seeding {} memories (FTS {}, {}) ... ",
+        format_count(n),
+        fts_label,
+        precision_label
+    );
 
-    let db = Memori::open(&path).expect("open failed");
+    let config = MemoriConfig {
+        enable_fts,
+        vector_precision,
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(&path, config).expect("open failed");
 
     let insert_start = Instant::now();
     {
@@ -83,34 +118,69 @@ fn measure_scale(n: usize) {
     db.vacuum().unwrap();
     drop(db);
 
-    let db_size = file_size_bytes(&path);
+    let footprint = read_footprint(&path);
+    let db_size = footprint.file_bytes;
     let per_memory = db_size / n as u64;
 
     eprintln!("{:.1}s", insert_time.as_secs_f64());
 
     println!(
-        "| {} | {} | {} | {} |",
+        "| {} | {} | {} | {} | {} | {} |",
         format_count(n),
+        fts_label,
+        precision_label,
         format_bytes(db_size),
         format_bytes(per_memory),
         format_rate(n, insert_time),
     );
 
     cleanup(&path);
+    footprint
 }
 
 fn main() {
     println!("### Memory Efficiency\n");
-    println!("| Memories | DB Size | Per-Memory | Write Throughput |");
-    println!("|---|---|---|---|");
+    println!("| Memories | FTS | Vectors | DB Size | Per-Memory | Write Throughput |");
+    println!("|---|---|---|---|---|---|");
 
+    let mut precision_pairs = Vec::new();
     for &scale in &[1_000, 10_000, 100_000, 500_000, 1_000_000] {
-        measure_scale(scale);
+        let full = measure_scale(scale, true, VectorPrecision::F32);
+        measure_scale(scale, false, VectorPrecision::F32);
+        if cfg!(feature = "f16") {
+            let half = measure_scale(scale, true, VectorPrecision::F16);
+            precision_pairs.push((scale, full, half));
+        }
     }
 
     println!();
     println!(
         "*Each memory includes ~100 words of content + 384-dim embedding vector + JSON metadata.*"
     );
-    println!("*DB Size measured after VACUUM. Write throughput = inserts/sec including content + vector + FTS5 indexing.*");
+    println!("*DB Size measured after VACUUM. Write throughput = inserts/sec including content + vector (+ FTS5 indexing when on).*");
+    println!("*FTS off = `MemoriConfig::enable_fts = false`, for vector-only workloads.*");
+    println!("*f16 rows (with `--features f16`) = `MemoriConfig::vector_precision = F16`.*");
+
+    if precision_pairs.is_empty() {
+        return;
+    }
+    println!();
+    println!("### f16 vs f32 Vectors (FTS on)\n");
+    println!("| Memories | f32 File | f16 File | f16 / f32 | f32 Pages | f16 Pages | f32 Vector Bytes | f16 Vector Bytes |");
+    println!("|---|---|---|---|---|---|---|---|");
+    for (n, full, half) in &precision_pairs {
+        println!(
+            "| {} | {} | {} | {:.0}% | {} | {} | {} | {} |",
+            format_count(*n),
+            format_bytes(full.file_bytes),
+            format_bytes(half.file_bytes),
+            100.0 * half.file_bytes as f64 / full.file_bytes as f64,
+            format_count(full.pages as usize),
+            format_count(half.pages as usize),
+            format_bytes(full.vector_bytes),
+            format_bytes(half.vector_bytes),
+        );
+    }
+    println!();
+    println!("*Measured from the VACUUMed files: `PRAGMA page_count` and `SUM(length(vector))` over `memories`.*");
 }
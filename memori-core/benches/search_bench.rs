@@ -1,7 +1,7 @@
 mod common;
 
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
-use memori_core::SearchQuery;
+use memori_core::{SearchMode, SearchQuery};
 
 fn bench_vector_search(c: &mut Criterion) {
     let mut group = c.benchmark_group("vector_search");
@@ -37,6 +37,47 @@ fn bench_vector_search(c: &mut Criterion) {
     group.finish();
 }
 
+/// Vector search using the stored `vector_norm` column against the fallback
+/// that recomputes every stored vector's norm (norms cleared to NULL).
+fn bench_vector_search_norms(c: &mut Criterion) {
+    let mut group = c.benchmark_group("vector_search_norms");
+    group.sample_size(30);
+    group.measurement_time(std::time::Duration::from_secs(5));
+
+    for &scale in &[10_000usize, 100_000] {
+        let dir = std::env::temp_dir().join(format!("memori_norm_bench_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{}.db", scale)).to_string_lossy().to_string();
+        let _ = std::fs::remove_file(&path);
+        let (db, _ids, vecs) = common::seed_db_at(&path, scale);
+        let query_vec = vecs[0].clone();
+        let search = || {
+            db.search(SearchQuery {
+                vector: Some(query_vec.clone()),
+                limit: 10,
+                ..Default::default()
+            })
+            .unwrap()
+        };
+
+        group.bench_with_input(BenchmarkId::new("stored", scale), &scale, |bencher, _| {
+            bencher.iter(search)
+        });
+        rusqlite::Connection::open(&path)
+            .unwrap()
+            .execute_batch("UPDATE memories SET vector_norm = NULL")
+            .unwrap();
+        group.bench_with_input(BenchmarkId::new("recomputed", scale), &scale, |bencher, _| {
+            bencher.iter(search)
+        });
+
+        drop(db);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    group.finish();
+}
+
 fn bench_text_search(c: &mut Criterion) {
     let mut group = c.benchmark_group("text_search");
     let queries = common::text_queries();
@@ -62,7 +103,7 @@ fn bench_text_search(c: &mut Criterion) {
                 query_idx += 1;
                 db.search(SearchQuery {
                     text: Some(text.to_string()),
-                    text_only: true,
+                    mode: SearchMode::TextOnly,
                     limit: 10,
                     ..Default::default()
                 })
@@ -149,6 +190,6 @@ fn bench_filtered_search(c: &mut Criterion) {
 criterion_group! {
     name = benches;
     config = Criterion::default();
-    targets = bench_vector_search, bench_text_search, bench_hybrid_search, bench_filtered_search
+    targets = bench_vector_search, bench_vector_search_norms, bench_text_search, bench_hybrid_search, bench_filtered_search
 }
 criterion_main!(benches);
@@ -4,6 +4,7 @@ use criterion::{black_box, criterion_group, criterion_main, Criterion, Benchmark
 use memori_core::util::{cosine_similarity, vec_to_blob, blob_to_vec};
 use memori_core::storage::find_duplicate;
 use memori_core::schema;
+use memori_core::MemoriConfig;
 use rand::rngs::StdRng;
 use rand::SeedableRng;
 
@@ -64,10 +65,11 @@ fn bench_find_duplicate(c: &mut Criterion) {
         }
 
         let query_vec = common::random_unit_vector(&mut rng);
+        let config = MemoriConfig::default();
 
         group.bench_with_input(BenchmarkId::from_parameter(scale), &scale, |bencher, _| {
             bencher.iter(|| {
-                find_duplicate(black_box(&conn), black_box(&query_vec), None, 0.92).unwrap()
+                find_duplicate(black_box(&conn), &config, black_box(&query_vec), None, 0.92).unwrap()
             })
         });
     }
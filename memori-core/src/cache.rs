@@ -0,0 +1,273 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::types::Memory;
+
+/// Pending access-stat bumps for cached hits are written back once this many
+/// accumulate (and on eviction or drop).
+pub(crate) const ACCESS_FLUSH_EVERY: usize = 32;
+
+fn now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs_f64()
+}
+
+/// Read-through LRU cache of memories for `Memori::get`, keyed by full id.
+/// Cache hits bump the cached access stats and record the bump as pending
+/// instead of writing it; the owner flushes pending bumps to the database.
+/// A capacity of 0 disables caching.
+#[derive(Debug, Default)]
+pub(crate) struct MemoryCache {
+    capacity: usize,
+    entries: HashMap<String, Memory>,
+    order: VecDeque<String>,
+    /// id -> (accesses not yet written, latest access time)
+    pending: HashMap<String, (i64, f64)>,
+    pending_total: usize,
+}
+
+impl MemoryCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// On a hit, return the memory as `get` would (stats as of before this
+    /// access), then count the access against the cached copy.
+    pub(crate) fn get_and_touch(&mut self, id: &str) -> Option<Memory> {
+        let entry = self.entries.get_mut(id)?;
+        let snapshot = entry.clone();
+        let ts = now();
+        entry.access_count += 1;
+        entry.last_accessed = ts;
+        self.bump_pending(id, ts);
+
+        if let Some(pos) = self.order.iter().position(|k| k == id) {
+            let key = self.order.remove(pos).unwrap();
+            self.order.push_back(key);
+        }
+        Some(snapshot)
+    }
+
+    /// Record an access to an uncached `id` without writing it (touch
+    /// batching), returning the bumps already pending for it before this one.
+    pub(crate) fn record_access(&mut self, id: &str) -> Option<(i64, f64)> {
+        let prior = self.pending.get(id).copied();
+        self.bump_pending(id, now());
+        prior
+    }
+
+    /// Cache a memory fetched from the database by `get`, which has already
+    /// touched it, so the cached stats include that access. Returns the
+    /// pending bumps of an evicted entry, which the caller must flush.
+    pub(crate) fn insert_fetched(&mut self, mut mem: Memory) -> Option<(String, i64, f64)> {
+        if !self.enabled() {
+            return None;
+        }
+        mem.access_count += 1;
+        mem.last_accessed = now();
+
+        let mut evicted = None;
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&mem.id) {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+                evicted = self.take_pending(&oldest).map(|(n, ts)| (oldest, n, ts));
+            }
+        }
+        if !self.entries.contains_key(&mem.id) {
+            self.order.push_back(mem.id.clone());
+        }
+        self.entries.insert(mem.id.clone(), mem);
+        evicted
+    }
+
+    /// Drop `id` from the cache, returning its pending bumps for flushing.
+    pub(crate) fn remove(&mut self, id: &str) -> Option<(i64, f64)> {
+        if self.entries.remove(id).is_some() {
+            if let Some(pos) = self.order.iter().position(|k| k == id) {
+                self.order.remove(pos);
+            }
+        }
+        self.take_pending(id)
+    }
+
+    /// Empty the cache, returning every pending bump for flushing.
+    pub(crate) fn clear(&mut self) -> Vec<(String, i64, f64)> {
+        self.entries.clear();
+        self.order.clear();
+        self.drain_pending()
+    }
+
+    /// Take every pending bump, keeping the cached entries.
+    pub(crate) fn drain_pending(&mut self) -> Vec<(String, i64, f64)> {
+        self.pending_total = 0;
+        self.pending
+            .drain()
+            .map(|(id, (n, ts))| (id, n, ts))
+            .collect()
+    }
+
+    pub(crate) fn pending_total(&self) -> usize {
+        self.pending_total
+    }
+
+    fn bump_pending(&mut self, id: &str, ts: f64) {
+        let pending = self.pending.entry(id.to_string()).or_insert((0, ts));
+        pending.0 += 1;
+        pending.1 = ts;
+        self.pending_total += 1;
+    }
+
+    fn take_pending(&mut self, id: &str) -> Option<(i64, f64)> {
+        let taken = self.pending.remove(id)?;
+        self.pending_total -= taken.0 as usize;
+        Some(taken)
+    }
+}
+
+/// LRU of `search` results keyed by a query fingerprint. Holds whole result
+/// sets, so the owner clears it on every write. A capacity of 0 disables it.
+#[derive(Debug, Default)]
+pub(crate) struct ResultCache {
+    capacity: usize,
+    entries: HashMap<String, Vec<Memory>>,
+    order: VecDeque<String>,
+}
+
+impl ResultCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    pub(crate) fn get(&mut self, key: &str) -> Option<Vec<Memory>> {
+        let hit = self.entries.get(key)?.clone();
+        if let Some(pos) = self.order.iter().position(|k| k == key) {
+            let key = self.order.remove(pos).unwrap();
+            self.order.push_back(key);
+        }
+        Some(hit)
+    }
+
+    pub(crate) fn insert(&mut self, key: String, results: Vec<Memory>) {
+        if !self.enabled() {
+            return;
+        }
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+        if !self.entries.contains_key(&key) {
+            self.order.push_back(key.clone());
+        }
+        self.entries.insert(key, results);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mem(id: &str) -> Memory {
+        Memory {
+            id: id.to_string(),
+            content: id.to_string(),
+            vector: None,
+            metadata: None,
+            created_at: 0.0,
+            updated_at: 0.0,
+            last_accessed: 0.0,
+            access_count: 0,
+            score: None,
+            base_score: None,
+            deleted_at: None,
+        }
+    }
+
+    #[test]
+    fn test_memory_cache_hit_counts_pending_access() {
+        let mut cache = MemoryCache::new(2);
+        assert!(cache.get_and_touch("a").is_none());
+        cache.insert_fetched(mem("a"));
+
+        let first = cache.get_and_touch("a").unwrap();
+        assert_eq!(first.access_count, 1);
+        let second = cache.get_and_touch("a").unwrap();
+        assert_eq!(second.access_count, 2);
+        assert_eq!(cache.pending_total(), 2);
+
+        let (n, _) = cache.remove("a").unwrap();
+        assert_eq!(n, 2);
+        assert_eq!(cache.pending_total(), 0);
+        assert!(cache.get_and_touch("a").is_none());
+    }
+
+    #[test]
+    fn test_memory_cache_evicts_lru_with_pending() {
+        let mut cache = MemoryCache::new(2);
+        cache.insert_fetched(mem("a"));
+        cache.insert_fetched(mem("b"));
+        cache.get_and_touch("a");
+        cache.get_and_touch("b");
+        // "a" is least recently used now that "b" was touched after it
+        let evicted = cache.insert_fetched(mem("c")).unwrap();
+        assert_eq!((evicted.0.as_str(), evicted.1), ("a", 1));
+        assert!(cache.get_and_touch("a").is_none());
+        assert!(cache.get_and_touch("b").is_some());
+    }
+
+    #[test]
+    fn test_memory_cache_record_access_without_entry() {
+        let mut cache = MemoryCache::new(0);
+        assert!(cache.record_access("a").is_none());
+        let (n, _) = cache.record_access("a").unwrap();
+        assert_eq!(n, 1);
+        assert_eq!(cache.pending_total(), 2);
+        assert_eq!(cache.drain_pending()[0].1, 2);
+        assert_eq!(cache.pending_total(), 0);
+    }
+
+    #[test]
+    fn test_result_cache_evicts_lru() {
+        let mut cache = ResultCache::new(2);
+        cache.insert("q1".to_string(), vec![mem("a")]);
+        cache.insert("q2".to_string(), vec![mem("b")]);
+        assert_eq!(cache.get("q1").unwrap()[0].id, "a");
+        cache.insert("q3".to_string(), vec![]);
+        assert!(cache.get("q2").is_none());
+        assert!(cache.get("q1").is_some());
+        cache.clear();
+        assert!(cache.get("q3").is_none());
+
+        let mut off = ResultCache::new(0);
+        off.insert("q".to_string(), vec![mem("a")]);
+        assert!(off.get("q").is_none());
+    }
+
+    #[test]
+    fn test_memory_cache_disabled_at_zero() {
+        let mut cache = MemoryCache::new(0);
+        assert!(cache.insert_fetched(mem("a")).is_none());
+        assert!(cache.get_and_touch("a").is_none());
+    }
+}
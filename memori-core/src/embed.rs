@@ -1,11 +1,23 @@
-#[cfg(feature = "embeddings")]
+use std::collections::{HashMap, VecDeque};
+
+#[cfg(all(feature = "embeddings", not(feature = "mock_embeddings")))]
 mod inner {
+    use std::cell::Cell;
     use std::sync::OnceLock;
 
     use fastembed::{EmbeddingModel, InitOptions, TextEmbedding};
 
     static MODEL: OnceLock<TextEmbedding> = OnceLock::new();
 
+    thread_local! {
+        static MODEL_CALLS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// Number of `embed_text`/`embed_batch` calls made on the current thread.
+    pub fn model_calls() -> usize {
+        MODEL_CALLS.with(Cell::get)
+    }
+
     fn get_model() -> &'static TextEmbedding {
         MODEL.get_or_init(|| {
             let options = InitOptions::new(EmbeddingModel::AllMiniLML6V2)
@@ -16,16 +28,194 @@ mod inner {
     }
 
     pub fn embed_text(text: &str) -> Vec<f32> {
+        MODEL_CALLS.with(|c| c.set(c.get() + 1));
         let model = get_model();
         let results = model.embed(vec![text], None).expect("embedding failed");
         results.into_iter().next().unwrap()
     }
 
     pub fn embed_batch(texts: &[&str]) -> Vec<Vec<f32>> {
+        MODEL_CALLS.with(|c| c.set(c.get() + 1));
         let model = get_model();
         model.embed(texts.to_vec(), None).expect("embedding failed")
     }
 }
 
-#[cfg(feature = "embeddings")]
+#[cfg(all(feature = "embeddings", not(feature = "mock_embeddings")))]
 pub use inner::*;
+
+/// Deterministic stand-in for the model, for tests that exercise the embed
+/// paths without downloading it. Takes precedence over `embeddings` when
+/// both features are on.
+#[cfg(feature = "mock_embeddings")]
+mod mock {
+    use std::cell::Cell;
+
+    /// Dimension of mock vectors, the same as the real model's.
+    pub const MOCK_DIM: usize = 384;
+
+    thread_local! {
+        static MODEL_CALLS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// Number of `embed_text`/`embed_batch` calls made on the current thread.
+    pub fn model_calls() -> usize {
+        MODEL_CALLS.with(Cell::get)
+    }
+
+    /// Bag of words: each lowercased alphanumeric word adds +-1 at a position
+    /// picked by its hash, then the vector is unit-normalized. Texts sharing
+    /// words are similar; text without words embeds as all zeros.
+    fn hash_embed(text: &str) -> Vec<f32> {
+        let mut v = vec![0.0f32; MOCK_DIM];
+        for word in text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+            let h = crate::util::fnv1a_64(word.to_lowercase().as_bytes());
+            let sign = if h >> 63 == 0 { 1.0 } else { -1.0 };
+            v[(h % MOCK_DIM as u64) as usize] += sign;
+        }
+        crate::util::l2_normalize(&mut v);
+        v
+    }
+
+    pub fn embed_text(text: &str) -> Vec<f32> {
+        MODEL_CALLS.with(|c| c.set(c.get() + 1));
+        hash_embed(text)
+    }
+
+    pub fn embed_batch(texts: &[&str]) -> Vec<Vec<f32>> {
+        MODEL_CALLS.with(|c| c.set(c.get() + 1));
+        texts.iter().map(|t| hash_embed(t)).collect()
+    }
+}
+
+#[cfg(feature = "mock_embeddings")]
+pub use mock::*;
+
+/// Tokens the bundled model reads before truncating the rest of its input.
+pub const MODEL_MAX_TOKENS: usize = 256;
+
+/// Cheap estimate of how many WordPiece tokens `text` becomes: each run of
+/// letters and digits is one token per started 6 characters, and every other
+/// non-whitespace character is its own token. Meant for budget checks, not
+/// exact counts; special tokens are not included.
+pub fn estimate_tokens(text: &str) -> usize {
+    let mut tokens = 0;
+    let mut run = 0usize;
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            run += 1;
+            continue;
+        }
+        tokens += run.div_ceil(6);
+        run = 0;
+        if !c.is_whitespace() {
+            tokens += 1;
+        }
+    }
+    tokens + run.div_ceil(6)
+}
+
+/// LRU cache of query-text embeddings so repeated searches skip the model.
+/// Only query-side embeddings go through it; stored content is never cached.
+/// A capacity of 0 disables caching.
+#[derive(Debug, Default)]
+pub struct QueryCache {
+    capacity: usize,
+    map: HashMap<String, Vec<f32>>,
+    order: VecDeque<String>,
+    hits: usize,
+    misses: usize,
+}
+
+impl QueryCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            ..Default::default()
+        }
+    }
+
+    /// Return the cached embedding for `text`, or compute it with `embed` and
+    /// cache it, evicting the least recently used entry when full.
+    pub fn get_or_embed(&mut self, text: &str, embed: impl FnOnce(&str) -> Vec<f32>) -> Vec<f32> {
+        if self.capacity == 0 {
+            return embed(text);
+        }
+        if let Some(v) = self.map.get(text) {
+            self.hits += 1;
+            let v = v.clone();
+            if let Some(pos) = self.order.iter().position(|k| k == text) {
+                let key = self.order.remove(pos).unwrap();
+                self.order.push_back(key);
+            }
+            return v;
+        }
+
+        self.misses += 1;
+        let v = embed(text);
+        if self.map.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.map.remove(&oldest);
+            }
+        }
+        self.map.insert(text.to_string(), v.clone());
+        self.order.push_back(text.to_string());
+        v
+    }
+
+    /// `(hits, misses)` since the cache was created.
+    pub fn stats(&self) -> (usize, usize) {
+        (self.hits, self.misses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("kafka lag"), 2);
+        // 14 letters split into three pieces, plus the comma and the period
+        assert_eq!(estimate_tokens("internationals, ok."), 6);
+        assert_eq!(estimate_tokens(&"word ".repeat(300)), 300);
+    }
+
+    #[test]
+    fn test_query_cache_hit_skips_embed() {
+        let mut cache = QueryCache::new(2);
+        let mut calls = 0;
+        let a = cache.get_or_embed("kafka", |_| {
+            calls += 1;
+            vec![1.0]
+        });
+        let b = cache.get_or_embed("kafka", |_| {
+            calls += 1;
+            vec![2.0]
+        });
+        assert_eq!(a, b);
+        assert_eq!(calls, 1);
+        assert_eq!(cache.stats(), (1, 1));
+    }
+
+    #[test]
+    fn test_query_cache_evicts_least_recently_used() {
+        let mut cache = QueryCache::new(2);
+        cache.get_or_embed("a", |_| vec![1.0]);
+        cache.get_or_embed("b", |_| vec![2.0]);
+        cache.get_or_embed("a", |_| vec![0.0]); // refresh "a"
+        cache.get_or_embed("c", |_| vec![3.0]); // evicts "b"
+
+        assert_eq!(cache.get_or_embed("a", |_| vec![9.0]), vec![1.0]);
+        assert_eq!(cache.get_or_embed("b", |_| vec![9.0]), vec![9.0]);
+    }
+
+    #[test]
+    fn test_query_cache_zero_capacity_disabled() {
+        let mut cache = QueryCache::new(0);
+        cache.get_or_embed("a", |_| vec![1.0]);
+        assert_eq!(cache.get_or_embed("a", |_| vec![2.0]), vec![2.0]);
+        assert_eq!(cache.stats(), (0, 0));
+    }
+}
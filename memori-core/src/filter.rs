@@ -0,0 +1,346 @@
+//! Allowlist parser for `SearchQuery::raw_filter`.
+//!
+//! Grammar (keywords case-insensitive):
+//!
+//! ```text
+//! expr       := and_expr (OR and_expr)*
+//! and_expr   := unary (AND unary)*
+//! unary      := NOT unary | '(' expr ')' | comparison
+//! comparison := operand op operand        op: = == != <> < <= > >=
+//! operand    := json_extract(metadata, '$.key[.key...]') | number | 'string'
+//!             | func '(' [operand (',' operand)*] ')'
+//! ```
+//!
+//! `func` is a name listed in `MemoriConfig::filter_functions` (see
+//! `Memori::register_scalar_function`); any other call is rejected.
+//!
+//! A predicate that parses is re-rendered from its tokens rather than passed
+//! through, so nothing outside the grammar can reach SQL.
+
+use crate::types::{MemoriError, Result};
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Num(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+    Comma,
+}
+
+fn invalid(msg: impl std::fmt::Display) -> MemoriError {
+    MemoriError::InvalidFilter(format!("raw_filter: {}", msg))
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '(' => {
+                tokens.push(Token::LParen);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::RParen);
+                i += 1;
+            }
+            ',' => {
+                tokens.push(Token::Comma);
+                i += 1;
+            }
+            '=' | '!' | '<' | '>' => {
+                let next = chars.get(i + 1).copied();
+                let (op, len) = match (c, next) {
+                    ('=', Some('=')) => ("=", 2),
+                    ('=', _) => ("=", 1),
+                    ('!', Some('=')) => ("!=", 2),
+                    ('<', Some('=')) => ("<=", 2),
+                    ('<', Some('>')) => ("!=", 2),
+                    ('<', _) => ("<", 1),
+                    ('>', Some('=')) => (">=", 2),
+                    ('>', _) => (">", 1),
+                    _ => return Err(invalid(format!("unexpected '{}' at position {}", c, i))),
+                };
+                tokens.push(Token::Op(op));
+                i += len;
+            }
+            '\'' => {
+                let mut s = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        Some('\'') if chars.get(i + 1) == Some(&'\'') => {
+                            s.push('\'');
+                            i += 2;
+                        }
+                        Some('\'') => {
+                            i += 1;
+                            break;
+                        }
+                        Some(&ch) => {
+                            s.push(ch);
+                            i += 1;
+                        }
+                        None => return Err(invalid("unterminated string literal")),
+                    }
+                }
+                tokens.push(Token::Str(s));
+            }
+            c if c.is_ascii_digit() || (c == '-' && chars.get(i + 1).is_some_and(|d| d.is_ascii_digit())) => {
+                let start = i;
+                i += 1;
+                while chars.get(i).is_some_and(|d| d.is_ascii_digit()) {
+                    i += 1;
+                }
+                if chars.get(i) == Some(&'.') && chars.get(i + 1).is_some_and(|d| d.is_ascii_digit()) {
+                    i += 1;
+                    while chars.get(i).is_some_and(|d| d.is_ascii_digit()) {
+                        i += 1;
+                    }
+                }
+                tokens.push(Token::Num(chars[start..i].iter().collect()));
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let start = i;
+                while chars
+                    .get(i)
+                    .is_some_and(|d| d.is_ascii_alphanumeric() || *d == '_')
+                {
+                    i += 1;
+                }
+                tokens.push(Token::Ident(chars[start..i].iter().collect()));
+            }
+            _ => return Err(invalid(format!("unexpected '{}' at position {}", c, i))),
+        }
+    }
+    Ok(tokens)
+}
+
+/// `$.key` or `$.a.b`, each segment `[a-zA-Z_][a-zA-Z0-9_]*`.
+fn is_valid_path(path: &str) -> bool {
+    let Some(rest) = path.strip_prefix("$.") else {
+        return false;
+    };
+    rest.split('.').all(|seg| {
+        let mut chars = seg.chars();
+        chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    out: String,
+    functions: &'a [String],
+    metadata: &'a str,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let t = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        t
+    }
+
+    fn is_keyword(&self, kw: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(s)) if s.eq_ignore_ascii_case(kw))
+    }
+
+    fn expect(&mut self, want: Token, what: &str) -> Result<()> {
+        match self.next() {
+            Some(t) if t == want => Ok(()),
+            Some(t) => Err(invalid(format!("expected {}, found {:?}", what, t))),
+            None => Err(invalid(format!("expected {}, found end of input", what))),
+        }
+    }
+
+    fn expr(&mut self) -> Result<()> {
+        self.and_expr()?;
+        while self.is_keyword("or") {
+            self.pos += 1;
+            self.out.push_str(" OR ");
+            self.and_expr()?;
+        }
+        Ok(())
+    }
+
+    fn and_expr(&mut self) -> Result<()> {
+        self.unary()?;
+        while self.is_keyword("and") {
+            self.pos += 1;
+            self.out.push_str(" AND ");
+            self.unary()?;
+        }
+        Ok(())
+    }
+
+    fn unary(&mut self) -> Result<()> {
+        if self.is_keyword("not") {
+            self.pos += 1;
+            self.out.push_str("NOT ");
+            return self.unary();
+        }
+        if self.peek() == Some(&Token::LParen) {
+            self.pos += 1;
+            self.out.push('(');
+            self.expr()?;
+            self.expect(Token::RParen, "')'")?;
+            self.out.push(')');
+            return Ok(());
+        }
+        self.operand()?;
+        match self.next() {
+            Some(Token::Op(op)) => {
+                self.out.push(' ');
+                self.out.push_str(op);
+                self.out.push(' ');
+            }
+            Some(t) => return Err(invalid(format!("expected comparison operator, found {:?}", t))),
+            None => return Err(invalid("expected comparison operator, found end of input")),
+        }
+        self.operand()
+    }
+
+    fn operand(&mut self) -> Result<()> {
+        match self.next() {
+            Some(Token::Num(n)) => self.out.push_str(&n),
+            Some(Token::Str(s)) => {
+                self.out.push('\'');
+                self.out.push_str(&s.replace('\'', "''"));
+                self.out.push('\'');
+            }
+            Some(Token::Ident(f)) if f.eq_ignore_ascii_case("json_extract") => {
+                self.expect(Token::LParen, "'(' after json_extract")?;
+                match self.next() {
+                    Some(Token::Ident(col)) if col.eq_ignore_ascii_case("metadata") => {}
+                    _ => return Err(invalid("json_extract must read the metadata column")),
+                }
+                self.expect(Token::Comma, "','")?;
+                let path = match self.next() {
+                    Some(Token::Str(p)) if is_valid_path(&p) => p,
+                    _ => return Err(invalid("json_extract path must look like '$.key' or '$.a.b'")),
+                };
+                self.expect(Token::RParen, "')'")?;
+                self.out.push_str(&format!("json_extract({}, '{}')", self.metadata, path));
+            }
+            Some(Token::Ident(f)) if self.peek() == Some(&Token::LParen) => {
+                let Some(name) = self.functions.iter().find(|n| n.eq_ignore_ascii_case(&f)) else {
+                    return Err(invalid(format!("function '{}' is not registered", f)));
+                };
+                self.out.push_str(name);
+                self.out.push('(');
+                self.pos += 1;
+                if self.peek() == Some(&Token::RParen) {
+                    self.pos += 1;
+                } else {
+                    self.operand()?;
+                    while self.peek() == Some(&Token::Comma) {
+                        self.pos += 1;
+                        self.out.push_str(", ");
+                        self.operand()?;
+                    }
+                    self.expect(Token::RParen, "')'")?;
+                }
+                self.out.push(')');
+            }
+            Some(t) => return Err(invalid(format!("unexpected {:?}", t))),
+            None => return Err(invalid("unexpected end of input")),
+        }
+        Ok(())
+    }
+}
+
+/// Validate a raw metadata predicate against the allowlist grammar and return
+/// its canonical SQL, or `InvalidFilter` describing the first problem.
+/// `functions` are the names callers may call besides `json_extract`; the
+/// metadata column is rendered as `metadata`, an SQL expression such as
+/// `memories.metadata` (see `search::metadata_column`).
+pub(crate) fn parse_raw_filter(input: &str, functions: &[String], metadata: &str) -> Result<String> {
+    let tokens = tokenize(input)?;
+    if tokens.is_empty() {
+        return Err(invalid("empty predicate"));
+    }
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        out: String::new(),
+        functions,
+        metadata,
+    };
+    parser.expr()?;
+    if let Some(t) = parser.peek() {
+        return Err(invalid(format!("unexpected trailing {:?}", t)));
+    }
+    Ok(parser.out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_raw_filter_canonicalizes() {
+        let sql = parse_raw_filter(
+            "JSON_EXTRACT(metadata,'$.priority')>=2 and (json_extract(metadata, '$.author.name') = 'O''Brien' OR not json_extract(metadata,'$.n') <> -1.5)",
+            &[],
+            "metadata",
+        )
+        .unwrap();
+        assert_eq!(
+            sql,
+            "json_extract(metadata, '$.priority') >= 2 AND (json_extract(metadata, '$.author.name') = 'O''Brien' OR NOT json_extract(metadata, '$.n') != -1.5)"
+        );
+    }
+
+    #[test]
+    fn test_parse_raw_filter_rejects_outside_grammar() {
+        for bad in [
+            "",
+            "1 = 1; DROP TABLE memories",
+            "json_extract(metadata, '$.a') = 1 -- trailing",
+            "json_extract(metadata, '$.a') = (SELECT 1)",
+            "id = 'x'",
+            "json_extract(content, '$.a') = 1",
+            "json_extract(metadata, '$..a') = 1",
+            "json_extract(metadata, '$.a') = 'unterminated",
+            "json_extract(metadata, '$.a') = 1 OR",
+            "(json_extract(metadata, '$.a') = 1",
+            "json_extract(metadata, '$.a') LIKE 'x%'",
+            "lower(json_extract(metadata, '$.a')) = 'x'",
+        ] {
+            assert!(
+                matches!(parse_raw_filter(bad, &[], "metadata"), Err(MemoriError::InvalidFilter(_))),
+                "accepted {:?}",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_raw_filter_allows_listed_functions() {
+        let functions = vec!["my_upper".to_string()];
+        let sql = parse_raw_filter("MY_UPPER(json_extract(metadata, '$.a')) = my_upper('x')", &functions, "metadata")
+            .unwrap();
+        assert_eq!(sql, "my_upper(json_extract(metadata, '$.a')) = my_upper('x')");
+        for bad in ["my_upper(1; DROP TABLE memories) = 1", "my_upper(1, ) = 1", "other(1) = 1"] {
+            assert!(
+                matches!(parse_raw_filter(bad, &functions, "metadata"), Err(MemoriError::InvalidFilter(_))),
+                "accepted {:?}",
+                bad
+            );
+        }
+    }
+}
@@ -7,35 +7,166 @@
 //!
 //! See <https://github.com/archit15singh/memori> for the full design and CLI usage.
 
+mod cache;
 pub mod embed;
+mod filter;
+mod metrics;
 pub mod schema;
 pub mod search;
 pub mod storage;
+mod trace;
 pub mod types;
 pub mod util;
 
+use std::cell::RefCell;
 use std::collections::HashMap;
 
-pub use types::{InsertResult, Memory, MemoriError, Result, SearchQuery, SortField};
+use cache::{MemoryCache, ResultCache};
+use embed::QueryCache;
+use metrics::Metrics;
+
+pub use rusqlite::OpenFlags;
+pub use types::{
+    AbsenceReason, ArrayMerge, Attachment, AttachmentInfo, CheckpointMode, CheckpointResult,
+    DedupScope, EmbedPreprocess, EmptyQueryBehavior, FtsReport, FusionMode, GetOrInsert,
+    HistoryEntry, IdStrategy, InsertResult, ListSnapshot, Memory, MemoryColumns, MemoriConfig,
+    MemoriError, MergeStrategy, MetricsSnapshot, MigrationReport, OpenReport, PatchOp, RawRow,
+    Result, ScoreReport, ScoringConfig, SearchMode, SearchQuery, SortDirection, SortField,
+    StoreStats, TextMatchMode, TokenBudgetBehavior, VectorPrecision, WarningHook,
+};
 
 pub struct Memori {
     conn: rusqlite::Connection,
+    config: MemoriConfig,
+    query_cache: RefCell<QueryCache>,
+    get_cache: RefCell<MemoryCache>,
+    result_cache: RefCell<ResultCache>,
+    /// `user_version` before `init_db` migrated the file, for `migrate_legacy`.
+    opened_version: i32,
+    metrics: Metrics,
 }
 
 impl Memori {
     pub fn open(path: &str) -> Result<Self> {
-        let conn = if path == ":memory:" {
-            rusqlite::Connection::open_in_memory()?
-        } else {
-            rusqlite::Connection::open(path)?
+        Self::open_with_config(path, MemoriConfig::default())
+    }
+
+    pub fn open_with_config(path: &str, config: MemoriConfig) -> Result<Self> {
+        let conn = Self::connect(path, &config)?;
+        Self::from_connection(conn, config)
+    }
+
+    /// `open_with_config`, also reporting whether the file was new, its
+    /// schema version before and after migrating, and which search features
+    /// are available, e.g. for ops to confirm an upgrade ran.
+    pub fn open_diagnostic(path: &str, config: MemoriConfig) -> Result<(Self, OpenReport)> {
+        let conn = Self::connect(path, &config)?;
+        let created: bool = !conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'memories')",
+            [],
+            |r| r.get(0),
+        )?;
+        let db = Self::from_connection(conn, config)?;
+        let version_after: i32 = db.conn.pragma_query_value(None, "user_version", |r| r.get(0))?;
+        let report = OpenReport {
+            created,
+            version_before: db.opened_version,
+            version_after,
+            migrated: !created && version_after > db.opened_version,
+            embeddings_enabled: cfg!(any(feature = "embeddings", feature = "mock_embeddings")),
+            fts_enabled: schema::has_fts(&db.conn)?,
         };
-        schema::init_db(&conn)?;
-        Ok(Self { conn })
+        Ok((db, report))
+    }
+
+    fn connect(path: &str, config: &MemoriConfig) -> Result<rusqlite::Connection> {
+        if path == ":memory:" {
+            return Ok(rusqlite::Connection::open_in_memory()?);
+        }
+        prepare_parent_dir(path, config.create_parent_dirs)?;
+        Ok(rusqlite::Connection::open(path)?)
+    }
+
+    /// Open with explicit SQLite `flags` (e.g. no `SQLITE_OPEN_CREATE` to
+    /// require an existing file, `SQLITE_OPEN_URI` for `file:` names, or a
+    /// mutex mode). A read-only handle skips migrations and records nothing
+    /// (`track_access` is forced off), so the file must already be at the
+    /// current schema; an older one fails with
+    /// `MemoriError::SchemaNeedsMigration`.
+    pub fn open_with_flags(path: &str, flags: OpenFlags) -> Result<Self> {
+        Self::open_with_config_and_flags(path, MemoriConfig::default(), flags)
+    }
+
+    /// `open_with_flags` with a config.
+    pub fn open_with_config_and_flags(path: &str, config: MemoriConfig, flags: OpenFlags) -> Result<Self> {
+        if path != ":memory:" && flags.contains(OpenFlags::SQLITE_OPEN_CREATE) {
+            prepare_parent_dir(path, config.create_parent_dirs)?;
+        }
+        Self::from_connection(rusqlite::Connection::open_with_flags(path, flags)?, config)
+    }
+
+    fn from_connection(conn: rusqlite::Connection, mut config: MemoriConfig) -> Result<Self> {
+        let opened_version: i32 = conn.pragma_query_value(None, "user_version", |r| r.get(0))?;
+        if opened_version > schema::CURRENT_SCHEMA_VERSION {
+            return Err(MemoriError::SchemaTooNew(opened_version, schema::CURRENT_SCHEMA_VERSION));
+        }
+        let read_only = conn.is_readonly(rusqlite::DatabaseName::Main)?;
+        storage::register_metadata_function(&conn)?;
+        if read_only {
+            if opened_version < schema::CURRENT_SCHEMA_VERSION {
+                return Err(MemoriError::SchemaNeedsMigration(opened_version, schema::CURRENT_SCHEMA_VERSION));
+            }
+            // Reads would otherwise try to write access stats
+            config.track_access = false;
+        } else {
+            schema::init_db_with_fts_layout(&conn, config.text_separator(), config.enable_fts, config.split_fts)?;
+        }
+        config.vector_precision =
+            storage::settle_vector_precision(&conn, config.vector_precision, read_only)?;
+        storage::settle_metadata_compression(&conn, &config, read_only)?;
+        // An unset separator means the one the database's triggers use
+        if config.text_separator.is_none() {
+            config.text_separator = schema::get_meta(&conn, "text_separator")?;
+        }
+        let query_cache = RefCell::new(QueryCache::new(config.query_cache_size));
+        let get_cache = RefCell::new(MemoryCache::new(config.get_cache_size));
+        let result_cache = RefCell::new(ResultCache::new(config.result_cache_size));
+        Ok(Self {
+            conn,
+            config,
+            query_cache,
+            get_cache,
+            result_cache,
+            opened_version,
+            metrics: Metrics::default(),
+        })
+    }
+
+    pub fn config(&self) -> &MemoriConfig {
+        &self.config
+    }
+
+    /// Drop `id` from the `get` cache, writing back its pending access bumps,
+    /// and clear the result cache. Called before any write to that row.
+    fn evict(&self, id: &str) -> Result<()> {
+        self.result_cache.borrow_mut().clear();
+        if let Some((count, last)) = self.get_cache.borrow_mut().remove(id) {
+            storage::apply_access_deltas(&self.conn, &[(id.to_string(), count, last)])?;
+        }
+        Ok(())
+    }
+
+    /// Empty the `get` and result caches, writing back all pending access
+    /// bumps. Called before bulk writes.
+    fn evict_all(&self) -> Result<()> {
+        self.result_cache.borrow_mut().clear();
+        let pending = self.get_cache.borrow_mut().clear();
+        storage::apply_access_deltas(&self.conn, &pending)
     }
 
     /// Resolve a short ID prefix to the full UUID.
     pub fn resolve_id(&self, id: &str) -> Result<String> {
-        storage::resolve_prefix(&self.conn, id)
+        storage::resolve_prefix(&self.conn, &self.config, id)
     }
 
     pub fn insert(
@@ -46,7 +177,131 @@ impl Memori {
         dedup_threshold: Option<f32>,
         no_embed: bool,
     ) -> Result<InsertResult> {
-        storage::insert(&self.conn, content, vector, metadata, dedup_threshold, no_embed)
+        self.result_cache.borrow_mut().clear();
+        let result =
+            storage::insert(&self.conn, &self.config, content, vector, metadata, dedup_threshold, no_embed)?;
+        if result.is_deduplicated() {
+            self.evict(result.id())?;
+        }
+        self.metrics.record_insert(result.is_deduplicated());
+        Ok(result)
+    }
+
+    /// `insert` with a content hash computed upstream (16 hex digits, else
+    /// `InvalidHash`), stored in place of memori's own. Under `exact_dedup`
+    /// any memory stored with the same hash counts as a duplicate, whatever
+    /// its content.
+    pub fn insert_with_hash(
+        &self,
+        content: &str,
+        content_hash: &str,
+        vector: Option<&[f32]>,
+        metadata: Option<serde_json::Value>,
+        dedup_threshold: Option<f32>,
+        no_embed: bool,
+    ) -> Result<InsertResult> {
+        self.result_cache.borrow_mut().clear();
+        let result = storage::insert_hashed(
+            &self.conn,
+            &self.config,
+            content,
+            Some(content_hash),
+            vector,
+            metadata,
+            dedup_threshold,
+            no_embed,
+        )?;
+        if result.is_deduplicated() {
+            self.evict(result.id())?;
+        }
+        self.metrics.record_insert(result.is_deduplicated());
+        Ok(result)
+    }
+
+    /// `insert` with an embedding computed ahead of time by
+    /// `storage::auto_embedding` for the same content, e.g. on another thread
+    /// so the model doesn't run under a lock. Dedup, the token budget and
+    /// `embed_hash` behave as if `insert` had embedded it.
+    pub fn insert_embedded(
+        &self,
+        content: &str,
+        embedding: Vec<f32>,
+        metadata: Option<serde_json::Value>,
+        dedup_threshold: Option<f32>,
+    ) -> Result<InsertResult> {
+        self.result_cache.borrow_mut().clear();
+        let result =
+            storage::insert_embedded(&self.conn, &self.config, content, embedding, metadata, dedup_threshold)?;
+        if result.is_deduplicated() {
+            self.evict(result.id())?;
+        }
+        self.metrics.record_insert(result.is_deduplicated());
+        Ok(result)
+    }
+
+    /// Insert a memory whose body is kept outside memori: `reference` (a URI
+    /// or path) is stored and returned as `content`, and `summary` is what
+    /// full-text search and auto-embedding see. Updating or replacing the
+    /// content later turns it back into an ordinary memory.
+    pub fn insert_external(
+        &self,
+        reference: &str,
+        summary: &str,
+        vector: Option<&[f32]>,
+        metadata: Option<serde_json::Value>,
+        no_embed: bool,
+    ) -> Result<String> {
+        self.result_cache.borrow_mut().clear();
+        let id = storage::insert_external(&self.conn, &self.config, reference, summary, vector, metadata, no_embed)?;
+        self.metrics.record_insert(false);
+        Ok(id)
+    }
+
+    /// The indexed summary of an external-content memory, or `None` if
+    /// `id` is an ordinary one.
+    pub fn external_summary(&self, id: &str) -> Result<Option<String>> {
+        let full_id = storage::resolve_prefix(&self.conn, &self.config, id)?;
+        storage::external_summary(&self.conn, &self.config, &full_id)
+    }
+
+    /// Store `data` as attachment `name` on a memory, replacing any attachment
+    /// of that name. Attachments are kept out of search and embedding, follow
+    /// the memory into the archive, and are removed when it is deleted.
+    pub fn add_attachment(&self, id: &str, name: &str, mime: &str, data: &[u8]) -> Result<()> {
+        let full_id = storage::resolve_prefix(&self.conn, &self.config, id)?;
+        storage::add_attachment(&self.conn, &self.config, &full_id, name, mime, data)
+    }
+
+    /// The attachment `name` on a memory, or `None` if it has none by that name.
+    pub fn get_attachment(&self, id: &str, name: &str) -> Result<Option<Attachment>> {
+        let full_id = storage::resolve_prefix(&self.conn, &self.config, id)?;
+        storage::get_attachment(&self.conn, &full_id, name)
+    }
+
+    /// A memory's attachments ordered by name, without their data.
+    pub fn list_attachments(&self, id: &str) -> Result<Vec<AttachmentInfo>> {
+        let full_id = storage::resolve_prefix(&self.conn, &self.config, id)?;
+        storage::list_attachments(&self.conn, &full_id)
+    }
+
+    /// Return the memory whose metadata `key` equals `value`, or insert
+    /// `content` with that key added to `metadata`, atomically. Use for
+    /// external keys (a ticket number, a file path) that must map to one memory.
+    pub fn get_or_insert_by_metadata(
+        &self,
+        key: &str,
+        value: &serde_json::Value,
+        content: &str,
+        vector: Option<&[f32]>,
+        metadata: Option<serde_json::Value>,
+    ) -> Result<GetOrInsert> {
+        self.result_cache.borrow_mut().clear();
+        let result =
+            storage::get_or_insert_by_metadata(&self.conn, &self.config, key, value, content, vector, metadata)?;
+        if result.was_created() {
+            self.metrics.record_insert(false);
+        }
+        Ok(result)
     }
 
     pub fn insert_with_id(
@@ -58,17 +313,146 @@ impl Memori {
         created_at: f64,
         updated_at: f64,
     ) -> Result<String> {
-        storage::insert_with_id(&self.conn, id, content, vector, metadata, created_at, updated_at)
+        self.result_cache.borrow_mut().clear();
+        let id = storage::insert_with_id(
+            &self.conn,
+            &self.config,
+            id,
+            content,
+            vector,
+            metadata,
+            created_at,
+            updated_at,
+        )?;
+        self.metrics.record_insert(false);
+        Ok(id)
     }
 
     pub fn get(&self, id: &str) -> Result<Option<Memory>> {
+        self.metrics.record_get();
+        if !self.config.track_access {
+            return match storage::resolve_prefix(&self.conn, &self.config, id) {
+                Ok(full_id) => storage::get_raw(&self.conn, &self.config, &full_id),
+                Err(MemoriError::NotFound(_)) => Ok(None),
+                Err(e) => Err(e),
+            };
+        }
+        if let Some(hit) = self.cached_get(id)? {
+            return Ok(Some(hit));
+        }
+
         // Resolve prefix; if not found, return None (backwards compat)
-        let full_id = match storage::resolve_prefix(&self.conn, id) {
+        let full_id = match storage::resolve_prefix(&self.conn, &self.config, id) {
             Ok(fid) => fid,
             Err(MemoriError::NotFound(_)) => return Ok(None),
             Err(e) => return Err(e),
         };
-        storage::get(&self.conn, &full_id)
+        if full_id != id {
+            if let Some(hit) = self.cached_get(&full_id)? {
+                return Ok(Some(hit));
+            }
+        }
+
+        let mem = if self.config.touch_batch_size > 0 {
+            // Report the stats this access would see if earlier ones were written
+            storage::get_raw(&self.conn, &self.config, &full_id)?.map(|mut m| {
+                if let Some((count, last)) = self.get_cache.borrow_mut().record_access(&full_id) {
+                    m.access_count += count;
+                    m.last_accessed = m.last_accessed.max(last);
+                }
+                m
+            })
+        } else {
+            storage::get(&self.conn, &self.config, &full_id)?
+        };
+        let mut cache = self.get_cache.borrow_mut();
+        if let (Some(m), true) = (&mem, cache.enabled()) {
+            if let Some(evicted) = cache.insert_fetched(m.clone()) {
+                storage::apply_access_deltas(&self.conn, &[evicted])?;
+            }
+        }
+        if cache.pending_total() >= self.access_flush_every() {
+            storage::apply_access_deltas(&self.conn, &cache.drain_pending())?;
+        }
+        Ok(mem)
+    }
+
+    /// Fetch several memories by id or prefix, one entry per id in order:
+    /// `Ok(None)` when nothing matches, `Err` for that id alone (e.g. an
+    /// ambiguous prefix). Under `track_access` every memory read from the
+    /// database is touched in one statement (or batched with
+    /// `touch_batch_size`); otherwise nothing is written. The outer error is
+    /// only for that write.
+    pub fn get_many(&self, ids: &[&str]) -> Result<Vec<Result<Option<Memory>>>> {
+        let track = self.config.track_access;
+        let mut results = Vec::with_capacity(ids.len());
+        let mut to_touch = Vec::new();
+        for id in ids {
+            self.metrics.record_get();
+            let full_id = match storage::resolve_prefix(&self.conn, &self.config, id) {
+                Ok(fid) => fid,
+                Err(MemoriError::NotFound(_)) => {
+                    results.push(Ok(None));
+                    continue;
+                }
+                Err(e) => {
+                    results.push(Err(e));
+                    continue;
+                }
+            };
+            if track {
+                if let Some(hit) = self.cached_get(&full_id)? {
+                    results.push(Ok(Some(hit)));
+                    continue;
+                }
+            }
+            let fetched = storage::get_raw(&self.conn, &self.config, &full_id).map(|mem| {
+                mem.map(|mut m| {
+                    if track && self.config.touch_batch_size > 0 {
+                        if let Some((count, last)) = self.get_cache.borrow_mut().record_access(&full_id) {
+                            m.access_count += count;
+                            m.last_accessed = m.last_accessed.max(last);
+                        }
+                    } else if track {
+                        to_touch.push(full_id.clone());
+                    }
+                    m
+                })
+            });
+            results.push(fetched);
+        }
+
+        storage::touch_many(&self.conn, &to_touch)?;
+        let mut cache = self.get_cache.borrow_mut();
+        if cache.pending_total() >= self.access_flush_every() {
+            storage::apply_access_deltas(&self.conn, &cache.drain_pending())?;
+        }
+        Ok(results)
+    }
+
+    /// Serve `get` from the cache, flushing pending access bumps once enough
+    /// have accumulated.
+    fn cached_get(&self, full_id: &str) -> Result<Option<Memory>> {
+        let mut cache = self.get_cache.borrow_mut();
+        let hit = cache.get_and_touch(full_id);
+        if hit.is_some() && cache.pending_total() >= self.access_flush_every() {
+            storage::apply_access_deltas(&self.conn, &cache.drain_pending())?;
+        }
+        Ok(hit)
+    }
+
+    fn access_flush_every(&self) -> usize {
+        match self.config.touch_batch_size {
+            0 => cache::ACCESS_FLUSH_EVERY,
+            n => n,
+        }
+    }
+
+    /// Write every pending access bump (from `touch_batch_size` or cached
+    /// `get` hits) to the database now. Also happens on drop.
+    pub fn flush_access(&self) -> Result<()> {
+        let pending = self.get_cache.borrow_mut().drain_pending();
+        storage::apply_access_deltas(&self.conn, &pending)
     }
 
     pub fn update(
@@ -79,37 +463,258 @@ impl Memori {
         metadata: Option<serde_json::Value>,
         merge_metadata: bool,
     ) -> Result<()> {
-        let full_id = storage::resolve_prefix(&self.conn, id)?;
-        storage::update(&self.conn, &full_id, content, vector, metadata, merge_metadata)
+        let full_id = storage::resolve_prefix(&self.conn, &self.config, id)?;
+        self.evict(&full_id)?;
+        storage::update(&self.conn, &self.config, &full_id, content, vector, metadata, merge_metadata)
+    }
+
+    /// Overwrite content, vector, and metadata with a complete new version,
+    /// keeping `created_at` and access stats. Unlike `update`, nothing is
+    /// merged: `None` metadata clears it and `None` vector re-embeds `content`.
+    pub fn replace(
+        &self,
+        id: &str,
+        content: &str,
+        vector: Option<&[f32]>,
+        metadata: Option<serde_json::Value>,
+    ) -> Result<()> {
+        let full_id = storage::resolve_prefix(&self.conn, &self.config, id)?;
+        self.evict(&full_id)?;
+        storage::replace(&self.conn, &self.config, &full_id, content, vector, metadata)
+    }
+
+    /// Apply JSON-Patch style `add`/`remove`/`replace` ops to a memory's metadata.
+    pub fn patch_metadata(&self, id: &str, ops: Vec<PatchOp>) -> Result<()> {
+        let full_id = storage::resolve_prefix(&self.conn, &self.config, id)?;
+        self.evict(&full_id)?;
+        storage::patch_metadata(&self.conn, &self.config, &full_id, &ops)
+    }
+
+    /// Atomically add `delta` to the number at metadata `key` (missing keys
+    /// start at 0) and return the new value. Plain rows change in one UPDATE
+    /// with no read step; compressed ones are rewritten under the write lock.
+    pub fn increment_metadata_number(&self, id: &str, key: &str, delta: f64) -> Result<f64> {
+        let full_id = storage::resolve_prefix(&self.conn, &self.config, id)?;
+        self.evict(&full_id)?;
+        storage::increment_metadata_number(&self.conn, &self.config, &full_id, key, delta)
     }
 
     pub fn delete(&self, id: &str) -> Result<()> {
-        let full_id = storage::resolve_prefix(&self.conn, id)?;
+        let full_id = storage::resolve_prefix(&self.conn, &self.config, id)?;
+        self.evict(&full_id)?;
         storage::delete(&self.conn, &full_id)
     }
 
+    /// Delete a memory but keep a tombstone: it leaves `get`, `count`, and
+    /// default searches and lists, and comes back with `deleted_at` set only
+    /// from `include_deleted` ones.
+    pub fn soft_delete(&self, id: &str) -> Result<()> {
+        let full_id = storage::resolve_prefix(&self.conn, &self.config, id)?;
+        self.evict(&full_id)?;
+        storage::soft_delete(&self.conn, &self.config, &full_id)
+    }
+
+    /// Prior versions of a memory's content and metadata recorded by `update`
+    /// and `replace`, newest first. Empty unless `history_limit` is set.
+    pub fn history(&self, id: &str) -> Result<Vec<HistoryEntry>> {
+        let full_id = storage::resolve_prefix(&self.conn, &self.config, id)?;
+        storage::history(&self.conn, &self.config, &full_id)
+    }
+
+    /// Consolidate `drop_id` into `keep_id` and delete `drop_id`, atomically.
+    pub fn merge(&self, keep_id: &str, drop_id: &str, content_strategy: MergeStrategy) -> Result<()> {
+        let keep = storage::resolve_prefix(&self.conn, &self.config, keep_id)?;
+        let dropped = storage::resolve_prefix(&self.conn, &self.config, drop_id)?;
+        self.evict(&keep)?;
+        self.evict(&dropped)?;
+        storage::merge(&self.conn, &self.config, &keep, &dropped, content_strategy)
+    }
+
+    /// Retroactive dedup: merge near-duplicate memories of the same type
+    /// (cosine similarity above `threshold`) into the most-accessed one of
+    /// each group, atomically. Returns the number of memories merged away.
+    pub fn dedup_existing(&self, threshold: f32) -> Result<usize> {
+        self.evict_all()?;
+        storage::dedup_existing(&self.conn, &self.config, threshold)
+    }
+
     pub fn search(&self, query: SearchQuery) -> Result<Vec<Memory>> {
-        search::search(&self.conn, query)
+        let key = self
+            .result_cache
+            .borrow()
+            .enabled()
+            .then(|| search::query_fingerprint(&query));
+        if let Some(hit) = key.as_deref().and_then(|k| self.result_cache.borrow_mut().get(k)) {
+            self.metrics.record_search();
+            return Ok(hit);
+        }
+        let results = search::search(&self.conn, &self.config, &mut self.query_cache.borrow_mut(), query)?;
+        if let Some(key) = key {
+            self.result_cache.borrow_mut().insert(key, results.clone());
+        }
+        self.metrics.record_search();
+        Ok(results)
+    }
+
+    /// Up to `n` random memories matching the metadata `filter`, e.g. for
+    /// spot checks. Access stats are left alone. A `seed` makes the sample
+    /// reproducible; see `storage::sample`.
+    pub fn sample(&self, n: usize, filter: Option<&serde_json::Value>, seed: Option<u64>) -> Result<Vec<Memory>> {
+        storage::sample(&self.conn, &self.config, n, filter, seed)
+    }
+
+    /// Why `query` does not return memory `id` (or prefix): the first gate it
+    /// fails, or `AbsenceReason::Returned` if it is in the results after
+    /// all. The result cache is not consulted.
+    pub fn explain_absence(&self, query: &SearchQuery, id: &str) -> Result<AbsenceReason> {
+        let full_id = storage::resolve_prefix(&self.conn, &self.config, id)?;
+        search::explain_absence(&self.conn, &self.config, &mut self.query_cache.borrow_mut(), query, &full_id)
+    }
+
+    /// Run several independent queries, returning each one's results as
+    /// `search` would, in query order. Vector queries with the same filters
+    /// share a single scan of the stored vectors; see `search::search_many`.
+    /// The result cache is not consulted.
+    pub fn search_many(&self, queries: &[SearchQuery]) -> Result<Vec<Vec<Memory>>> {
+        let results = search::search_many(&self.conn, &self.config, &mut self.query_cache.borrow_mut(), queries)?;
+        for _ in queries {
+            self.metrics.record_search();
+        }
+        Ok(results)
+    }
+
+    /// Move a memory into the archive tier: out of `search`, `list`, `count`,
+    /// and `get`, and out of the hot table's indexes. See `unarchive`.
+    pub fn archive(&self, id: &str) -> Result<()> {
+        let full_id = storage::resolve_prefix(&self.conn, &self.config, id)?;
+        self.evict(&full_id)?;
+        storage::archive(&self.conn, &self.config, &full_id)
+    }
+
+    /// Move an archived memory (id or prefix) back to the hot table, returning
+    /// its full id.
+    pub fn unarchive(&self, id: &str) -> Result<String> {
+        self.result_cache.borrow_mut().clear();
+        storage::unarchive(&self.conn, &self.config, id)
+    }
+
+    /// Number of archived memories in this collection.
+    pub fn archive_count(&self) -> Result<usize> {
+        storage::archive_count(&self.conn, &self.config)
+    }
+
+    /// Search archived memories only. The archive has no FTS index, so text
+    /// matching is substring-based and unscored; see `search::search_archive`.
+    pub fn search_archive(&self, query: SearchQuery) -> Result<Vec<Memory>> {
+        let results =
+            search::search_archive(&self.conn, &self.config, &mut self.query_cache.borrow_mut(), query)?;
+        self.metrics.record_search();
+        Ok(results)
+    }
+
+    /// Search hot and archived memories together; see `search::search_all`.
+    pub fn search_all(&self, query: SearchQuery) -> Result<Vec<Memory>> {
+        let results =
+            search::search_all(&self.conn, &self.config, &mut self.query_cache.borrow_mut(), query)?;
+        self.metrics.record_search();
+        Ok(results)
+    }
+
+    /// Operation counts since this handle opened or last `reset_metrics`.
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    pub fn reset_metrics(&self) {
+        self.metrics.reset();
+    }
+
+    /// Run `query` once and distribute the results into time buckets by
+    /// `created_at`. Each bucket is `(label, after, before)`, covering
+    /// `after <= created_at < before` with `None` meaning unbounded. A result
+    /// goes to the first bucket it fits; results outside every bucket are
+    /// dropped. Buckets are returned in the given order (empty ones included),
+    /// and relevance order is preserved within each.
+    pub fn search_timeline(
+        &self,
+        query: SearchQuery,
+        buckets: &[(&str, Option<f64>, Option<f64>)],
+    ) -> Result<Vec<(String, Vec<Memory>)>> {
+        let results = self.search(query)?;
+        Ok(search::bucket_by_created(results, buckets))
+    }
+
+    /// `(hits, misses)` for the query-embedding cache (see `MemoriConfig::query_cache_size`).
+    pub fn query_cache_stats(&self) -> (usize, usize) {
+        self.query_cache.borrow().stats()
+    }
+
+    /// How important a memory looks from its access stats alone, in 0..1:
+    /// search's access boost and recency decay applied to a base of 1.0.
+    /// Reading it doesn't count as an access.
+    pub fn importance_score(&self, id: &str) -> Result<f32> {
+        let full_id = storage::resolve_prefix(&self.conn, &self.config, id)?;
+        search::importance_score(&self.conn, &self.config, &full_id)
+    }
+
+    /// The `limit` memories with the highest `importance_score`, which is
+    /// set as each one's `score`.
+    pub fn top_important(&self, limit: usize) -> Result<Vec<Memory>> {
+        search::top_important(&self.conn, &self.config, limit)
     }
 
     pub fn count(&self) -> Result<usize> {
-        storage::count(&self.conn)
+        storage::count(&self.conn, &self.config)
+    }
+
+    /// How many memories match `text` in full-text search and `filter`,
+    /// without fetching them; see `search::count_text_matches`.
+    pub fn count_text_matches(&self, text: &str, filter: Option<&serde_json::Value>) -> Result<usize> {
+        search::count_text_matches(&self.conn, &self.config, text, filter)
     }
 
     pub fn type_distribution(&self) -> Result<HashMap<String, usize>> {
-        storage::type_distribution(&self.conn)
+        storage::type_distribution(&self.conn, &self.config)
+    }
+
+    /// `type_distribution` as a list, most common type first; equal counts
+    /// are ordered by type name.
+    pub fn type_distribution_sorted(&self) -> Result<Vec<(String, usize)>> {
+        let mut dist: Vec<(String, usize)> = self.type_distribution()?.into_iter().collect();
+        dist.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        Ok(dist)
+    }
+
+    /// Counts and approximate size of `collection`, which need not be this
+    /// handle's.
+    pub fn collection_stats(&self, collection: &str) -> Result<StoreStats> {
+        storage::store_stats(&self.conn, Some(collection))
+    }
+
+    /// `collection_stats` summed over every collection in the file.
+    pub fn stats(&self) -> Result<StoreStats> {
+        storage::store_stats(&self.conn, None)
+    }
+
+    /// Every collection with at least one memory (hot or archived) in this
+    /// file, sorted. Unlike the other methods, not limited to this handle's.
+    pub fn collections(&self) -> Result<Vec<String>> {
+        storage::collections(&self.conn)
     }
 
     pub fn delete_before(&self, before_timestamp: f64) -> Result<usize> {
-        storage::delete_before(&self.conn, before_timestamp)
+        self.evict_all()?;
+        storage::delete_before(&self.conn, &self.config, before_timestamp)
     }
 
     pub fn delete_by_type(&self, type_value: &str) -> Result<usize> {
-        storage::delete_by_type(&self.conn, type_value)
+        self.evict_all()?;
+        storage::delete_by_type(&self.conn, &self.config, type_value)
     }
 
     pub fn touch(&self, id: &str) -> Result<()> {
-        let full_id = storage::resolve_prefix(&self.conn, id)?;
+        let full_id = storage::resolve_prefix(&self.conn, &self.config, id)?;
+        self.evict(&full_id)?;
         storage::touch(&self.conn, &full_id)
     }
 
@@ -117,20 +722,235 @@ impl Memori {
         storage::vacuum(&self.conn)
     }
 
+    /// `vacuum`, then `collection_stats` for every collection in the file,
+    /// sorted by name. SQLite compacts the whole file, so the per-collection
+    /// sizes are estimates of each one's share.
+    pub fn vacuum_with_stats(&self) -> Result<Vec<(String, StoreStats)>> {
+        storage::vacuum(&self.conn)?;
+        storage::collections(&self.conn)?
+            .into_iter()
+            .map(|name| {
+                let stats = storage::store_stats(&self.conn, Some(&name))?;
+                Ok((name, stats))
+            })
+            .collect()
+    }
+
+    /// Write pending access bumps, then a compacted copy of the database to
+    /// `dest_path`, leaving this one untouched; see `storage::backup_to`.
+    pub fn backup_to(&self, dest_path: &str) -> Result<()> {
+        self.flush_access()?;
+        storage::backup_to(&self.conn, dest_path)
+    }
+
+    /// Write pending access bumps, then checkpoint the WAL into the main
+    /// database file, e.g. before copying it. `Truncate` also empties the
+    /// `-wal` file.
+    pub fn checkpoint(&self, mode: CheckpointMode) -> Result<CheckpointResult> {
+        self.flush_access()?;
+        storage::checkpoint(&self.conn, mode)
+    }
+
+    /// Advanced: register a deterministic SQL scalar function on this
+    /// connection and add it to `config().filter_functions`, so
+    /// `SearchQuery::raw_filter` can call it (e.g. a custom case fold). The
+    /// name must be an identifier and not a filter keyword; `arity` -1
+    /// accepts any argument count. Registering a name again replaces the
+    /// function.
+    pub fn register_scalar_function<F, T>(&mut self, name: &str, arity: i32, f: F) -> Result<()>
+    where
+        F: FnMut(&rusqlite::functions::Context<'_>) -> rusqlite::Result<T>
+            + Send
+            + std::panic::UnwindSafe
+            + 'static,
+        T: rusqlite::functions::SqlFnOutput,
+    {
+        let lower = name.to_ascii_lowercase();
+        if !search::is_valid_filter_key(name)
+            || ["and", "or", "not", "json_extract"].contains(&lower.as_str())
+        {
+            return Err(MemoriError::InvalidFilter(format!(
+                "'{}' can't be used as a filter function name",
+                name
+            )));
+        }
+        self.conn.create_scalar_function(
+            name,
+            arity,
+            rusqlite::functions::FunctionFlags::SQLITE_UTF8 | rusqlite::functions::FunctionFlags::SQLITE_DETERMINISTIC,
+            f,
+        )?;
+        if !self.config.filter_functions.iter().any(|n| n.eq_ignore_ascii_case(name)) {
+            self.config.filter_functions.push(name.to_string());
+        }
+        self.result_cache.borrow_mut().clear();
+        Ok(())
+    }
+
+    /// Recreate any missing schema index, such as the metadata `type` index.
+    /// Safe to call at any time.
+    pub fn ensure_indexes(&self) -> Result<()> {
+        schema::ensure_indexes(&self.conn)?;
+        Ok(())
+    }
+
+    /// Index metadata `key` for filtered searches (see `vector_query_plan`).
+    /// Returns the index name.
+    pub fn add_metadata_index(&self, key: &str) -> Result<String> {
+        storage::add_metadata_index(&self.conn, key)
+    }
+
+    /// The SQLite query plan of the row scan behind a vector search of `query`.
+    pub fn vector_query_plan(&self, query: &SearchQuery) -> Result<Vec<String>> {
+        search::vector_query_plan(&self.conn, &self.config, query)
+    }
+
+    /// Refresh query planner statistics, e.g. after a bulk load.
+    pub fn analyze(&self) -> Result<()> {
+        storage::analyze(&self.conn)
+    }
+
+    /// Check the FTS index against the base table: memories missing from the
+    /// index, index entries with no memory, and FTS5's structural check.
+    pub fn verify_fts(&self) -> Result<FtsReport> {
+        storage::verify_fts(&self.conn)
+    }
+
+    /// Rebuild the FTS index from scratch, fixing anything `verify_fts` reports.
+    /// Equivalent to `rebuild_fts`, so the configured `text_separator` is used.
+    pub fn repair_fts(&self) -> Result<()> {
+        self.rebuild_fts()
+    }
+
+    /// Reinstall the FTS triggers with this handle's `text_separator` and
+    /// re-index every memory. Needed to apply a changed separator, or
+    /// `split_fts`, to an existing database. Without an explicit separator,
+    /// the one recorded for the database is kept.
+    pub fn rebuild_fts(&self) -> Result<()> {
+        self.result_cache.borrow_mut().clear();
+        storage::rebuild_fts(&self.conn, &self.config)
+    }
+
+    /// Bring rows from an older schema fully up to date: force an FTS rebuild
+    /// so every row is indexed with its metadata (skipped when FTS is
+    /// disabled), then backfill embeddings for memories without a vector.
+    /// Safe to run on a current database.
+    pub fn migrate_legacy(&self) -> Result<MigrationReport> {
+        self.evict_all()?;
+        let reindexed: i64 = if schema::has_fts(&self.conn)? {
+            self.rebuild_fts()?;
+            self.conn.query_row("SELECT COUNT(*) FROM memories", [], |r| r.get(0))?
+        } else {
+            0
+        };
+        let embedded = storage::backfill_embeddings(&self.conn, &self.config, 100, 1, |_, _| {})?;
+        let to_version: i32 = self.conn.pragma_query_value(None, "user_version", |r| r.get(0))?;
+        Ok(MigrationReport {
+            from_version: self.opened_version,
+            to_version,
+            reindexed: reindexed as usize,
+            embedded,
+        })
+    }
+
+    /// One memory as a JSON object (vector and access stats included), for
+    /// sharing; the single-row analog of a JSONL export. An external-content
+    /// memory exports its summary as `content` plus a `content_ref` key.
+    /// Reading it does not count as an access.
+    pub fn export_memory(&self, id: &str) -> Result<String> {
+        let full_id = storage::resolve_prefix(&self.conn, &self.config, id)?;
+        // Flush cached hits first so the exported stats are current
+        self.evict(&full_id)?;
+        let (mem, content_ref) =
+            storage::get_stored(&self.conn, &self.config, &full_id)?.ok_or(MemoriError::NotFound(full_id))?;
+        let mut value = serde_json::to_value(&mem)?;
+        if let Some(reference) = content_ref {
+            value["content_ref"] = serde_json::Value::String(reference);
+        }
+        Ok(serde_json::to_string(&value)?)
+    }
+
+    /// Recreate a memory from `export_memory` output under its original id,
+    /// timestamps, and access stats. Returns the id.
+    pub fn import_memory(&self, json: &str) -> Result<String> {
+        let mut value: serde_json::Value = serde_json::from_str(json)?;
+        let content_ref = match value.as_object_mut().and_then(|o| o.remove("content_ref")) {
+            Some(serde_json::Value::String(reference)) => Some(reference),
+            _ => None,
+        };
+        let mem: Memory = serde_json::from_value(value)?;
+        self.result_cache.borrow_mut().clear();
+        let id = storage::import_memory(&self.conn, &self.config, &mem, content_ref.as_deref())?;
+        self.metrics.record_insert(false);
+        Ok(id)
+    }
+
+    /// Insert a memory scored in vector search by the best of several chunk
+    /// vectors (the first is its own `vector`). Returns the id.
+    pub fn insert_chunked(
+        &self,
+        content: &str,
+        vectors: &[Vec<f32>],
+        metadata: Option<serde_json::Value>,
+    ) -> Result<String> {
+        self.result_cache.borrow_mut().clear();
+        let id = storage::insert_chunked(&self.conn, &self.config, content, vectors, metadata)?;
+        self.metrics.record_insert(false);
+        Ok(id)
+    }
+
     pub fn set_access_stats(
         &self,
         id: &str,
         last_accessed: Option<f64>,
         access_count: i64,
     ) -> Result<()> {
-        let full_id = storage::resolve_prefix(&self.conn, id)?;
+        let full_id = storage::resolve_prefix(&self.conn, &self.config, id)?;
+        self.evict(&full_id)?;
         storage::set_access_stats(&self.conn, &full_id, last_accessed, access_count)
     }
 
+    /// Apply many `(id, last_accessed, access_count)` entries in one
+    /// transaction, e.g. when importing. Ids must be full ids; missing ones
+    /// are skipped. Returns how many rows were updated.
+    pub fn set_access_stats_batch(&self, entries: &[(String, Option<f64>, i64)]) -> Result<usize> {
+        self.evict_all()?;
+        storage::set_access_stats_batch(&self.conn, &self.config, entries)
+    }
+
+    pub fn clear_last_accessed(&self, id: &str) -> Result<()> {
+        let full_id = storage::resolve_prefix(&self.conn, &self.config, id)?;
+        self.evict(&full_id)?;
+        storage::clear_last_accessed(&self.conn, &full_id)
+    }
+
     pub fn backfill_embeddings(&self, batch_size: usize) -> Result<usize> {
-        storage::backfill_embeddings(&self.conn, batch_size)
+        self.backfill_embeddings_parallel(batch_size, 1, |_, _| {})
     }
 
+    /// `backfill_embeddings` with a `progress(processed, total)` callback per batch.
+    pub fn backfill_embeddings_with_progress<F: FnMut(usize, usize)>(
+        &self,
+        batch_size: usize,
+        progress: F,
+    ) -> Result<usize> {
+        self.backfill_embeddings_parallel(batch_size, 1, progress)
+    }
+
+    /// `backfill_embeddings_with_progress` embedding up to `parallelism`
+    /// batches concurrently. The stored vectors are the same as a serial
+    /// backfill's; only the embedding step runs on several threads.
+    pub fn backfill_embeddings_parallel<F: FnMut(usize, usize)>(
+        &self,
+        batch_size: usize,
+        parallelism: usize,
+        progress: F,
+    ) -> Result<usize> {
+        self.evict_all()?;
+        storage::backfill_embeddings(&self.conn, &self.config, batch_size, parallelism, progress)
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn list(
         &self,
         type_filter: Option<&str>,
@@ -139,26 +959,174 @@ impl Memori {
         offset: usize,
         before: Option<f64>,
         after: Option<f64>,
+        include_deleted: bool,
+    ) -> Result<Vec<Memory>> {
+        storage::list(&self.conn, &self.config, type_filter, sort, limit, offset, before, after, include_deleted)
+    }
+
+    /// Up to `limit` memories changed after the cursor, for incremental sync,
+    /// ascending by change time and id. A soft-deleted memory is reported
+    /// as a tombstone with `deleted_at` set, changed at its `deleted_at`;
+    /// otherwise the change time is `updated_at`. Pass the last row's change
+    /// time and id as the next `since` and `after_id`. Hard deletes leave
+    /// nothing behind, so they aren't reported.
+    pub fn changed_since(&self, since: f64, after_id: Option<&str>, limit: usize) -> Result<Vec<Memory>> {
+        storage::changed_since(&self.conn, &self.config, since, after_id, limit)
+    }
+
+    /// Freeze the collection's `sort` order (newest/most first) for paging with
+    /// `snapshot_page`, so accesses between page fetches can't reorder or
+    /// repeat memories. The snapshot lives until `release_snapshot` or until
+    /// this handle is dropped.
+    pub fn list_snapshot(&self, sort: &SortField, page_size: usize) -> Result<ListSnapshot> {
+        storage::list_snapshot(&self.conn, &self.config, sort, page_size)
+    }
+
+    /// Page `page` (0-based) of a snapshot from `list_snapshot`.
+    pub fn snapshot_page(&self, snapshot: &ListSnapshot, page: usize) -> Result<Vec<Memory>> {
+        storage::snapshot_page(&self.conn, &self.config, snapshot, page)
+    }
+
+    pub fn release_snapshot(&self, snapshot: ListSnapshot) -> Result<()> {
+        storage::release_snapshot(&self.conn, &snapshot.token)
+    }
+
+    /// `list` with a multi-column sort, e.g. most accessed first, then newest.
+    pub fn list_sorted(
+        &self,
+        type_filter: Option<&str>,
+        sorts: &[(SortField, SortDirection)],
+        limit: usize,
+        offset: usize,
+        before: Option<f64>,
+        after: Option<f64>,
     ) -> Result<Vec<Memory>> {
-        storage::list(&self.conn, type_filter, sort, limit, offset, before, after)
+        storage::list_sorted(&self.conn, &self.config, type_filter, sorts, limit, offset, before, after, false)
+    }
+
+    /// Requested percentiles (0-100) of the cosine similarity between
+    /// `query_vec` and every embedded memory, as `(percentile, similarity)`,
+    /// for calibrating dedup and `min_score` thresholds.
+    pub fn similarity_distribution(&self, query_vec: &[f32], percentiles: &[f32]) -> Result<Vec<(f32, f32)>> {
+        search::similarity_distribution(&self.conn, &self.config, query_vec, percentiles)
+    }
+
+    /// Pairwise cosine similarity matrix of `ids` (prefixes allowed), or of all
+    /// embedded memories when `None`. Without ids, more than
+    /// `search::SIMILARITY_MATRIX_MAX` embedded memories is an error.
+    pub fn similarity_matrix(&self, ids: Option<&[String]>) -> Result<(Vec<String>, Vec<Vec<f32>>)> {
+        let resolved = ids
+            .map(|ids| {
+                ids.iter()
+                    .map(|id| storage::resolve_prefix(&self.conn, &self.config, id))
+                    .collect::<Result<Vec<_>>>()
+            })
+            .transpose()?;
+        search::similarity_matrix(&self.conn, &self.config, resolved.as_deref())
     }
 
     pub fn embedding_stats(&self) -> Result<(usize, usize)> {
-        storage::embedding_stats(&self.conn)
+        storage::embedding_stats(&self.conn, &self.config)
+    }
+
+    /// Number of memories without a vector, e.g. for backfill progress.
+    pub fn missing_embedding_count(&self) -> Result<usize> {
+        storage::missing_embedding_count(&self.conn, &self.config)
+    }
+
+    /// `(embedded, total)` per metadata `type`; untyped memories are omitted.
+    pub fn embedding_coverage_by_type(&self) -> Result<HashMap<String, (usize, usize)>> {
+        storage::embedding_coverage_by_type(&self.conn, &self.config)
+    }
+
+    /// IDs of memories whose vector has a dimension other than `expected_dim`,
+    /// e.g. left over from a previous embedding model.
+    pub fn find_dimension_mismatches(&self, expected_dim: usize) -> Result<Vec<String>> {
+        storage::find_dimension_mismatches(&self.conn, &self.config, expected_dim)
+    }
+
+    /// Re-embed the memories `find_dimension_mismatches` reports. Without the
+    /// embeddings feature their vectors are cleared instead. Vector-only
+    /// memories are left as they are.
+    pub fn reembed_mismatches(&self, expected_dim: usize) -> Result<usize> {
+        self.evict_all()?;
+        storage::reembed_mismatches(&self.conn, &self.config, expected_dim)
     }
 
     /// Get a memory by ID or prefix without bumping access_count.
     pub fn get_readonly(&self, id_or_prefix: &str) -> Result<Option<Memory>> {
-        let full_id = match storage::resolve_prefix(&self.conn, id_or_prefix) {
+        let full_id = match storage::resolve_prefix(&self.conn, &self.config, id_or_prefix) {
             Ok(fid) => fid,
             Err(MemoriError::NotFound(_)) => return Ok(None),
             Err(e) => return Err(e),
         };
-        storage::get_raw(&self.conn, &full_id)
+        storage::get_raw(&self.conn, &self.config, &full_id)
+    }
+
+    /// Everything stored for a row (raw metadata string, vector byte length, FTS
+    /// presence) without parsing it or bumping access stats.
+    pub fn inspect(&self, id: &str) -> Result<Option<RawRow>> {
+        let full_id = match storage::resolve_prefix(&self.conn, &self.config, id) {
+            Ok(fid) => fid,
+            Err(MemoriError::NotFound(_)) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        storage::inspect(&self.conn, &full_id)
     }
 
     pub fn related(&self, id: &str, limit: usize) -> Result<Vec<Memory>> {
-        let full_id = storage::resolve_prefix(&self.conn, id)?;
-        search::related(&self.conn, &full_id, limit)
+        let full_id = storage::resolve_prefix(&self.conn, &self.config, id)?;
+        search::related(&self.conn, &self.config, &full_id, limit)
+    }
+
+    /// `related`, with `metadata_boost` added to the similarity for each
+    /// top-level metadata value a candidate shares with the memory.
+    pub fn related_weighted(&self, id: &str, limit: usize, metadata_boost: f32) -> Result<Vec<Memory>> {
+        let full_id = storage::resolve_prefix(&self.conn, &self.config, id)?;
+        search::related_weighted(&self.conn, &self.config, &full_id, limit, metadata_boost)
+    }
+
+    /// `id` plus its related memories out to `depth` hops, `per_node` per
+    /// memory, each memory once. Depth 0 is just the memory itself.
+    pub fn export_neighborhood(
+        &self,
+        id: &str,
+        depth: usize,
+        per_node: usize,
+    ) -> Result<Vec<Memory>> {
+        let full_id = storage::resolve_prefix(&self.conn, &self.config, id)?;
+        search::export_neighborhood(&self.conn, &self.config, &full_id, depth, per_node)
+    }
+}
+
+impl Drop for Memori {
+    fn drop(&mut self) {
+        // Best effort: write back access bumps from cached `get` hits
+        let _ = self.evict_all();
+    }
+}
+
+/// Make sure a file database's parent directory exists before SQLite opens
+/// it, creating it when `create` is set. `file:` URIs are left to SQLite.
+fn prepare_parent_dir(path: &str, create: bool) -> Result<()> {
+    if path.starts_with("file:") {
+        return Ok(());
+    }
+    let Some(parent) = std::path::Path::new(path).parent().filter(|p| !p.as_os_str().is_empty()) else {
+        return Ok(());
+    };
+    if parent.is_dir() {
+        return Ok(());
+    }
+    if create {
+        std::fs::create_dir_all(parent)?;
+        return Ok(());
     }
+    Err(MemoriError::Io(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!(
+            "directory '{}' does not exist (set create_parent_dirs to create it)",
+            parent.display()
+        ),
+    )))
 }
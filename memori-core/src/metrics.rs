@@ -0,0 +1,46 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::types::MetricsSnapshot;
+
+/// Cumulative operation counts for one `Memori` handle, behind
+/// `Memori::metrics`. In-process only: nothing here is persisted.
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    inserts: AtomicU64,
+    dedup_merges: AtomicU64,
+    searches: AtomicU64,
+    gets: AtomicU64,
+}
+
+impl Metrics {
+    /// Count an insert; a deduplicated one also counts as a dedup merge.
+    pub(crate) fn record_insert(&self, deduplicated: bool) {
+        self.inserts.fetch_add(1, Ordering::Relaxed);
+        if deduplicated {
+            self.dedup_merges.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn record_search(&self) {
+        self.searches.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_get(&self) {
+        self.gets.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            inserts: self.inserts.load(Ordering::Relaxed),
+            dedup_merges: self.dedup_merges.load(Ordering::Relaxed),
+            searches: self.searches.load(Ordering::Relaxed),
+            gets: self.gets.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        for counter in [&self.inserts, &self.dedup_merges, &self.searches, &self.gets] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
@@ -1,6 +1,257 @@
-use rusqlite::Connection;
+use rusqlite::{Connection, OptionalExtension};
+
+/// Separator between content and metadata in FTS-indexed and embedded text
+/// when `MemoriConfig::text_separator` is unset.
+pub const DEFAULT_TEXT_SEPARATOR: &str = " ";
+
+/// The newest `user_version` this build knows how to read. `Memori` refuses
+/// to open a database stamped with a later one. Bump with each migration.
+pub const CURRENT_SCHEMA_VERSION: i32 = 17;
+
+/// Collection used when `MemoriConfig::collection` is unset, and the one rows
+/// created before v4 belong to.
+pub const DEFAULT_COLLECTION: &str = "default";
+
+/// Every index the current schema expects. Migrations create these as they
+/// go; `ensure_indexes` recreates any that went missing. New migrations that
+/// add an index should add it here too.
+const INDEXES_SQL: &str = "
+  CREATE INDEX IF NOT EXISTS idx_memories_type
+      ON memories(collection, json_extract(metadata, '$.type'));
+  CREATE INDEX IF NOT EXISTS idx_memories_collection ON memories(collection);
+  CREATE INDEX IF NOT EXISTS idx_memories_archive_collection ON memories_archive(collection);
+  CREATE INDEX IF NOT EXISTS idx_memories_content_hash ON memories(collection, content_hash);
+  CREATE INDEX IF NOT EXISTS idx_memories_deleted_collection ON memories_deleted(collection);
+";
+
+/// Idempotently (re)create every index the schema expects, e.g. after rows
+/// were imported with direct SQL or an index was dropped.
+pub fn ensure_indexes(conn: &Connection) -> rusqlite::Result<()> {
+  // Created first so INDEXES_SQL's plain form doesn't take the name
+  if has_compressed_metadata(conn)? {
+    conn.execute_batch(&type_index_sql(true))?;
+  }
+  conn.execute_batch(INDEXES_SQL)
+}
+
+/// `memori_meta` key recording that rows may hold compressed metadata.
+pub const COMPRESSED_METADATA_KEY: &str = "compressed_metadata";
+
+/// Whether the database may hold rows whose metadata is deflated into
+/// `metadata_z` (see `MemoriConfig::compress_metadata_over`). SQL reading
+/// metadata there must go through `metadata_sql`.
+pub fn has_compressed_metadata(conn: &Connection) -> rusqlite::Result<bool> {
+  let has_meta: bool = conn.query_row(
+    "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'memori_meta')",
+    [],
+    |r| r.get(0),
+  )?;
+  Ok(has_meta && get_meta(conn, COMPRESSED_METADATA_KEY)?.is_some())
+}
+
+/// SQL expression for a row's full metadata JSON. A compressed row's
+/// `metadata` keeps only top-level scalars, so with `compressed` this reads
+/// both columns through the `memori_metadata` function `Memori` registers.
+/// `row` is `new.`, `old.`, a table qualifier such as `memories.`, or `""`.
+pub fn metadata_sql(row: &str, compressed: bool) -> String {
+  if compressed {
+    format!("memori_metadata({row}metadata, {row}metadata_z)", row = row)
+  } else {
+    format!("{}metadata", row)
+  }
+}
+
+/// DDL for `idx_memories_type`, on the expression queries on `type` read.
+fn type_index_sql(compressed: bool) -> String {
+  format!(
+    "CREATE INDEX IF NOT EXISTS idx_memories_type ON memories(collection, json_extract({}, '$.type'));",
+    metadata_sql("", compressed)
+  )
+}
+
+/// SQL expression for the text the FTS index holds for a row: content, the
+/// separator, then the full metadata JSON (see `metadata_sql`). `row` is
+/// `new.`, `old.`, or `""`.
+pub fn fts_text_sql(row: &str, separator: &str, compressed: bool) -> String {
+  format!(
+    "{row}content || '{sep}' || COALESCE({meta}, '')",
+    row = row,
+    sep = separator.replace('\'', "''"),
+    meta = metadata_sql(row, compressed)
+  )
+}
+
+/// DDL for the insert/delete/update triggers that keep `memories_fts` in sync.
+pub fn fts_triggers_sql(separator: &str, compressed: bool) -> String {
+  let new_text = fts_text_sql("new.", separator, compressed);
+  let old_text = fts_text_sql("old.", separator, compressed);
+  format!(
+    "
+    CREATE TRIGGER memories_ai AFTER INSERT ON memories BEGIN
+        INSERT INTO memories_fts(rowid, content)
+        VALUES (new.rowid, {new_text});
+    END;
+
+    CREATE TRIGGER memories_ad AFTER DELETE ON memories BEGIN
+        INSERT INTO memories_fts(memories_fts, rowid, content)
+        VALUES('delete', old.rowid, {old_text});
+    END;
+
+    CREATE TRIGGER memories_au AFTER UPDATE ON memories BEGIN
+        INSERT INTO memories_fts(memories_fts, rowid, content)
+        VALUES('delete', old.rowid, {old_text});
+        INSERT INTO memories_fts(rowid, content)
+        VALUES (new.rowid, {new_text});
+    END;
+    ",
+    new_text = new_text,
+    old_text = old_text
+  )
+}
+
+/// DDL for the triggers of a split FTS index (see `has_split_fts`), which
+/// indexes content and metadata as separate columns, so no separator.
+pub fn fts_split_triggers_sql(compressed: bool) -> String {
+  format!(
+    "
+    CREATE TRIGGER memories_ai AFTER INSERT ON memories BEGIN
+        INSERT INTO memories_fts(rowid, content, metadata)
+        VALUES (new.rowid, new.content, COALESCE({new_meta}, ''));
+    END;
+
+    CREATE TRIGGER memories_ad AFTER DELETE ON memories BEGIN
+        INSERT INTO memories_fts(memories_fts, rowid, content, metadata)
+        VALUES('delete', old.rowid, old.content, COALESCE({old_meta}, ''));
+    END;
+
+    CREATE TRIGGER memories_au AFTER UPDATE ON memories BEGIN
+        INSERT INTO memories_fts(memories_fts, rowid, content, metadata)
+        VALUES('delete', old.rowid, old.content, COALESCE({old_meta}, ''));
+        INSERT INTO memories_fts(rowid, content, metadata)
+        VALUES (new.rowid, new.content, COALESCE({new_meta}, ''));
+    END;
+    ",
+    new_meta = metadata_sql("new.", compressed),
+    old_meta = metadata_sql("old.", compressed)
+  )
+}
+
+/// Reinstall the FTS triggers with `separator` and refill the index from
+/// `memories`, keeping its current layout. FTS5's `'rebuild'` isn't used:
+/// with external content it would index `memories.content` alone, without
+/// the metadata.
+pub fn rebuild_fts(conn: &Connection, separator: &str) -> rusqlite::Result<()> {
+  rebuild_fts_with_layout(conn, separator, has_split_fts(conn)?)
+}
+
+/// Like `rebuild_fts`, recreating the index as split (separate `content` and
+/// `metadata` columns, which `bm25()` can weight) or combined first if it
+/// isn't laid out that way already. A split index ignores `separator`.
+pub fn rebuild_fts_with_layout(conn: &Connection, separator: &str, split: bool) -> rusqlite::Result<()> {
+  conn.execute_batch(
+    "
+    DROP TRIGGER IF EXISTS memories_ai;
+    DROP TRIGGER IF EXISTS memories_ad;
+    DROP TRIGGER IF EXISTS memories_au;
+    ",
+  )?;
+  let compressed = has_compressed_metadata(conn)?;
+  if split != has_split_fts(conn)? {
+    conn.execute_batch(&format!(
+      "
+      DROP TABLE memories_fts;
+      CREATE VIRTUAL TABLE memories_fts USING fts5(
+          {columns},
+          content=memories,
+          content_rowid=rowid
+      );
+      ",
+      columns = if split { "content, metadata" } else { "content" }
+    ))?;
+  }
+  if split {
+    conn.execute_batch(&format!(
+      "
+      {triggers}
+      INSERT INTO memories_fts(memories_fts) VALUES('delete-all');
+      INSERT INTO memories_fts(rowid, content, metadata)
+          SELECT rowid, content, COALESCE({meta}, '') FROM memories;
+      ",
+      triggers = fts_split_triggers_sql(compressed),
+      meta = metadata_sql("", compressed)
+    ))
+  } else {
+    conn.execute_batch(&format!(
+      "
+      {triggers}
+      INSERT INTO memories_fts(memories_fts) VALUES('delete-all');
+      INSERT INTO memories_fts(rowid, content)
+          SELECT rowid, {text} FROM memories;
+      ",
+      triggers = fts_triggers_sql(separator, compressed),
+      text = fts_text_sql("", separator, compressed)
+    ))
+  }
+}
+
+/// Whether `memories_fts` indexes metadata in its own column rather than
+/// concatenated after the content. Databases created with
+/// `MemoriConfig::split_fts` do; `false` without an FTS index.
+pub fn has_split_fts(conn: &Connection) -> rusqlite::Result<bool> {
+  conn.query_row(
+    "SELECT EXISTS(SELECT 1 FROM pragma_table_info('memories_fts') WHERE name = 'metadata')",
+    [],
+    |r| r.get(0),
+  )
+}
+
+/// Whether the database has the `memories_fts` index. Databases created with
+/// `MemoriConfig::enable_fts = false` don't.
+pub fn has_fts(conn: &Connection) -> rusqlite::Result<bool> {
+  conn.query_row(
+    "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'memories_fts')",
+    [],
+    |r| r.get(0),
+  )
+}
 
 pub fn init_db(conn: &Connection) -> rusqlite::Result<()> {
+  init_db_with_separator(conn, DEFAULT_TEXT_SEPARATOR)
+}
+
+/// Like `init_db`, but a newly created FTS index joins content and metadata
+/// with `separator`. Existing databases keep their triggers; see `rebuild_fts`.
+pub fn init_db_with_separator(conn: &Connection, separator: &str) -> rusqlite::Result<()> {
+  init_db_with_options(conn, separator, true)
+}
+
+/// Like `init_db_with_separator`; with `enable_fts = false` a new database
+/// gets no FTS index or triggers. Ignored for existing databases.
+///
+/// Does not check for a database newer than `CURRENT_SCHEMA_VERSION`; callers
+/// opening one should refuse it first, as `Memori` does.
+pub fn init_db_with_options(
+  conn: &Connection,
+  separator: &str,
+  enable_fts: bool,
+) -> rusqlite::Result<()> {
+  init_db_with_fts_layout(conn, separator, enable_fts, false)
+}
+
+/// Like `init_db_with_options`; with `split_fts` a new database's FTS index
+/// is split (see `rebuild_fts_with_layout`). Ignored for existing databases.
+pub fn init_db_with_fts_layout(
+  conn: &Connection,
+  separator: &str,
+  enable_fts: bool,
+  split_fts: bool,
+) -> rusqlite::Result<()> {
+  let fresh: bool = !conn.query_row(
+    "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'memories')",
+    [],
+    |r| r.get(0),
+  )?;
+
   // Base table and WAL mode (always idempotent)
   conn.execute_batch(
     "
@@ -19,8 +270,11 @@ pub fn init_db(conn: &Connection) -> rusqlite::Result<()> {
 
   // Check schema version to decide if FTS5 needs migration
   let version: i32 = conn.pragma_query_value(None, "user_version", |r| r.get(0))?;
+  let builds_fts = version < 1 && (enable_fts || !fresh);
 
-  if version < 1 {
+  if version < 1 && fresh && !enable_fts {
+    conn.execute_batch("PRAGMA user_version = 1;")?;
+  } else if version < 1 {
     // Drop old FTS and triggers, recreate with metadata-aware triggers.
     // The FTS5 content column label stays "content" but the data fed into it
     // via triggers now concatenates content + metadata JSON text, so text
@@ -38,29 +292,10 @@ pub fn init_db(conn: &Connection) -> rusqlite::Result<()> {
           content=memories,
           content_rowid=rowid
       );
-
-      CREATE TRIGGER memories_ai AFTER INSERT ON memories BEGIN
-          INSERT INTO memories_fts(rowid, content)
-          VALUES (new.rowid, new.content || ' ' || COALESCE(new.metadata, ''));
-      END;
-
-      CREATE TRIGGER memories_ad AFTER DELETE ON memories BEGIN
-          INSERT INTO memories_fts(memories_fts, rowid, content)
-          VALUES('delete', old.rowid, old.content || ' ' || COALESCE(old.metadata, ''));
-      END;
-
-      CREATE TRIGGER memories_au AFTER UPDATE ON memories BEGIN
-          INSERT INTO memories_fts(memories_fts, rowid, content)
-          VALUES('delete', old.rowid, old.content || ' ' || COALESCE(old.metadata, ''));
-          INSERT INTO memories_fts(rowid, content)
-          VALUES (new.rowid, new.content || ' ' || COALESCE(new.metadata, ''));
-      END;
-
-      INSERT INTO memories_fts(memories_fts) VALUES('rebuild');
-
-      PRAGMA user_version = 1;
       ",
     )?;
+    rebuild_fts(conn, separator)?;
+    conn.execute_batch("PRAGMA user_version = 1;")?;
   }
 
   // Re-read version after potential v0->v1 migration
@@ -93,5 +328,358 @@ pub fn init_db(conn: &Connection) -> rusqlite::Result<()> {
     )?;
   }
 
+  // Re-read version after potential v2->v3 migration
+  let version: i32 = conn.pragma_query_value(None, "user_version", |r| r.get(0))?;
+
+  if version < 4 {
+    // Named collections sharing one file. Existing rows land in "default".
+    conn.execute_batch(
+      "
+      ALTER TABLE memories ADD COLUMN collection TEXT NOT NULL DEFAULT 'default';
+      CREATE INDEX IF NOT EXISTS idx_memories_collection ON memories(collection);
+      PRAGMA user_version = 4;
+      ",
+    )?;
+  }
+
+  // Re-read version after potential v3->v4 migration
+  let version: i32 = conn.pragma_query_value(None, "user_version", |r| r.get(0))?;
+
+  if version < 5 {
+    // Archive tier: cold memories moved out of the hot table (and its FTS
+    // index) by `Memori::archive`. Same columns plus when it was archived.
+    conn.execute_batch(
+      "
+      CREATE TABLE IF NOT EXISTS memories_archive (
+          id            TEXT PRIMARY KEY,
+          content       TEXT NOT NULL,
+          vector        BLOB,
+          metadata      TEXT,
+          created_at    REAL NOT NULL,
+          updated_at    REAL NOT NULL,
+          last_accessed REAL DEFAULT 0.0,
+          access_count  INTEGER DEFAULT 0,
+          collection    TEXT NOT NULL DEFAULT 'default',
+          archived_at   REAL NOT NULL
+      );
+      CREATE INDEX IF NOT EXISTS idx_memories_archive_collection ON memories_archive(collection);
+      PRAGMA user_version = 5;
+      ",
+    )?;
+  }
+
+  // Re-read version after potential v4->v5 migration
+  let version: i32 = conn.pragma_query_value(None, "user_version", |r| r.get(0))?;
+
+  if version < 6 {
+    // Precomputed vector norms, so vector search only computes dot products.
+    // SQLite can't compute them, so every write of a vector also writes its
+    // norm; search recomputes rows where it is NULL (e.g. direct SQL inserts).
+    conn.execute_batch(
+      "
+      ALTER TABLE memories ADD COLUMN vector_norm REAL;
+      ALTER TABLE memories_archive ADD COLUMN vector_norm REAL;
+      ",
+    )?;
+    backfill_vector_norms(conn, "memories")?;
+    backfill_vector_norms(conn, "memories_archive")?;
+    conn.execute_batch("PRAGMA user_version = 6;")?;
+  }
+
+  // Re-read version after potential v5->v6 migration
+  let version: i32 = conn.pragma_query_value(None, "user_version", |r| r.get(0))?;
+
+  if version < 7 {
+    // Hash of the text an auto-embedded vector came from, so `update` can
+    // skip re-embedding unchanged text. NULL for caller-supplied vectors.
+    conn.execute_batch(
+      "
+      ALTER TABLE memories ADD COLUMN embed_hash INTEGER;
+      ALTER TABLE memories_archive ADD COLUMN embed_hash INTEGER;
+      PRAGMA user_version = 7;
+      ",
+    )?;
+  }
+
+  // Re-read version after potential v6->v7 migration
+  let version: i32 = conn.pragma_query_value(None, "user_version", |r| r.get(0))?;
+
+  if version < 8 {
+    // Content hash for `MemoriConfig::exact_dedup`, written on every insert
+    // and content update whether or not exact dedup is on.
+    conn.execute_batch(
+      "
+      ALTER TABLE memories ADD COLUMN content_hash INTEGER;
+      ALTER TABLE memories_archive ADD COLUMN content_hash INTEGER;
+      CREATE INDEX IF NOT EXISTS idx_memories_content_hash ON memories(collection, content_hash);
+      ",
+    )?;
+    backfill_content_hashes(conn, "memories")?;
+    backfill_content_hashes(conn, "memories_archive")?;
+    conn.execute_batch("PRAGMA user_version = 8;")?;
+  }
+
+  // Re-read version after potential v7->v8 migration
+  let version: i32 = conn.pragma_query_value(None, "user_version", |r| r.get(0))?;
+
+  if version < 9 {
+    // Deflated full metadata for `MemoriConfig::compress_metadata_over`;
+    // `metadata` then holds only its top-level scalars
+    conn.execute_batch(
+      "
+      ALTER TABLE memories ADD COLUMN metadata_z BLOB;
+      ALTER TABLE memories_archive ADD COLUMN metadata_z BLOB;
+      PRAGMA user_version = 9;
+      ",
+    )?;
+  }
+
+  // Re-read version after potential v8->v9 migration
+  let version: i32 = conn.pragma_query_value(None, "user_version", |r| r.get(0))?;
+
+  if version < 10 {
+    // Per-database settings that reads depend on (e.g. vector precision)
+    conn.execute_batch(
+      "
+      CREATE TABLE IF NOT EXISTS memori_meta (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+      );
+      PRAGMA user_version = 10;
+      ",
+    )?;
+  }
+
+  // Re-read version after potential v9->v10 migration
+  let version: i32 = conn.pragma_query_value(None, "user_version", |r| r.get(0))?;
+
+  if version < 11 {
+    // Tombstones: memories removed by `Memori::soft_delete`, kept out of the
+    // hot table (and its FTS index) like the archive, plus when it was deleted
+    conn.execute_batch(
+      "
+      CREATE TABLE IF NOT EXISTS memories_deleted (
+          id            TEXT PRIMARY KEY,
+          content       TEXT NOT NULL,
+          vector        BLOB,
+          metadata      TEXT,
+          created_at    REAL NOT NULL,
+          updated_at    REAL NOT NULL,
+          last_accessed REAL DEFAULT 0.0,
+          access_count  INTEGER DEFAULT 0,
+          collection    TEXT NOT NULL DEFAULT 'default',
+          vector_norm   REAL,
+          embed_hash    INTEGER,
+          content_hash  INTEGER,
+          metadata_z    BLOB,
+          deleted_at    REAL NOT NULL
+      );
+      CREATE INDEX IF NOT EXISTS idx_memories_deleted_collection ON memories_deleted(collection);
+      PRAGMA user_version = 11;
+      ",
+    )?;
+  }
+
+  // Re-read version after potential v10->v11 migration
+  let version: i32 = conn.pragma_query_value(None, "user_version", |r| r.get(0))?;
+
+  if version < 12 {
+    // Prior versions of a memory, kept by `update`/`replace` under
+    // `MemoriConfig::history_limit`. `metadata` is as stored (possibly deflated)
+    conn.execute_batch(
+      "
+      CREATE TABLE IF NOT EXISTS memory_history (
+          id         TEXT NOT NULL,
+          version    INTEGER NOT NULL,
+          content    TEXT NOT NULL,
+          metadata   TEXT,
+          changed_at REAL NOT NULL,
+          PRIMARY KEY (id, version)
+      );
+      PRAGMA user_version = 12;
+      ",
+    )?;
+  }
+
+  // Re-read version after potential v11->v12 migration
+  let version: i32 = conn.pragma_query_value(None, "user_version", |r| r.get(0))?;
+
+  if version < 13 {
+    // External-content rows: `content` holds the indexed summary and
+    // `content_ref` the reference reads return in its place
+    conn.execute_batch(
+      "
+      ALTER TABLE memories ADD COLUMN content_ref TEXT;
+      ALTER TABLE memories_archive ADD COLUMN content_ref TEXT;
+      ALTER TABLE memories_deleted ADD COLUMN content_ref TEXT;
+      PRAGMA user_version = 13;
+      ",
+    )?;
+  }
+
+  // Re-read version after potential v12->v13 migration
+  let version: i32 = conn.pragma_query_value(None, "user_version", |r| r.get(0))?;
+
+  if version < 14 {
+    // Binary attachments keyed by (memory, name). They follow a memory into
+    // the archive and are dropped when it leaves the hot table any other way
+    conn.execute_batch(
+      "
+      CREATE TABLE IF NOT EXISTS attachments (
+        memory_id TEXT NOT NULL,
+        name TEXT NOT NULL,
+        mime TEXT NOT NULL,
+        data BLOB NOT NULL,
+        created_at REAL NOT NULL,
+        PRIMARY KEY (memory_id, name)
+      );
+      CREATE TRIGGER IF NOT EXISTS memories_attachments_ad AFTER DELETE ON memories
+      WHEN NOT EXISTS (SELECT 1 FROM memories_archive WHERE id = old.id) BEGIN
+        DELETE FROM attachments WHERE memory_id = old.id;
+      END;
+      PRAGMA user_version = 14;
+      ",
+    )?;
+  }
+
+  // Re-read version after potential v13->v14 migration
+  let version: i32 = conn.pragma_query_value(None, "user_version", |r| r.get(0))?;
+
+  if version < 15 {
+    // Lead the type index with collection: every query also filters on it,
+    // and the planner otherwise picks idx_memories_collection instead
+    conn.execute_batch(
+      "
+      DROP INDEX IF EXISTS idx_memories_type;
+      CREATE INDEX idx_memories_type
+          ON memories(collection, json_extract(metadata, '$.type'));
+      PRAGMA user_version = 15;
+      ",
+    )?;
+  }
+
+  // Re-read version after potential v14->v15 migration
+  let version: i32 = conn.pragma_query_value(None, "user_version", |r| r.get(0))?;
+
+  if version < 16 {
+    // Extra chunk vectors for multi-vector memories; the memory's own vector
+    // is chunk 0. Kept and dropped like attachments
+    conn.execute_batch(
+      "
+      CREATE TABLE IF NOT EXISTS memory_chunks (
+        memory_id TEXT NOT NULL,
+        chunk_index INTEGER NOT NULL,
+        vector BLOB NOT NULL,
+        vector_norm REAL,
+        PRIMARY KEY (memory_id, chunk_index)
+      );
+      CREATE TRIGGER IF NOT EXISTS memories_chunks_ad AFTER DELETE ON memories
+      WHEN NOT EXISTS (SELECT 1 FROM memories_archive WHERE id = old.id) BEGIN
+        DELETE FROM memory_chunks WHERE memory_id = old.id;
+      END;
+      PRAGMA user_version = 16;
+      ",
+    )?;
+  }
+
+  // Re-read version after potential v15->v16 migration
+  let version: i32 = conn.pragma_query_value(None, "user_version", |r| r.get(0))?;
+
+  if version < 17 {
+    // Prior versions go with their memory like attachments, so a reused id
+    // starts without history; rows already orphaned are dropped
+    conn.execute_batch(
+      "
+      CREATE TRIGGER IF NOT EXISTS memories_history_ad AFTER DELETE ON memories
+      WHEN NOT EXISTS (SELECT 1 FROM memories_archive WHERE id = old.id) BEGIN
+        DELETE FROM memory_history WHERE id = old.id;
+      END;
+      DELETE FROM memory_history
+      WHERE id NOT IN (SELECT id FROM memories UNION ALL SELECT id FROM memories_archive);
+      PRAGMA user_version = 17;
+      ",
+    )?;
+  }
+
+  if fresh && enable_fts && split_fts {
+    rebuild_fts_with_layout(conn, separator, true)?;
+  }
+  // Later handles and `rebuild_fts` reuse the separator the triggers got
+  if builds_fts {
+    set_meta(conn, "text_separator", separator)?;
+  }
+
+  Ok(())
+}
+
+/// Whether any memory has extra chunk vectors (false if the table predates
+/// v16).
+pub fn has_chunks(conn: &Connection) -> rusqlite::Result<bool> {
+  let exists: bool = conn.query_row(
+    "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'memory_chunks')",
+    [],
+    |r| r.get(0),
+  )?;
+  if !exists {
+    return Ok(false);
+  }
+  conn.query_row("SELECT EXISTS(SELECT 1 FROM memory_chunks)", [], |r| r.get(0))
+}
+
+/// Read a `memori_meta` setting.
+pub fn get_meta(conn: &Connection, key: &str) -> rusqlite::Result<Option<String>> {
+  conn
+    .query_row("SELECT value FROM memori_meta WHERE key = ?1", [key], |r| r.get(0))
+    .optional()
+}
+
+/// Write a `memori_meta` setting, replacing any previous value.
+pub fn set_meta(conn: &Connection, key: &str, value: &str) -> rusqlite::Result<()> {
+  conn.execute(
+    "INSERT INTO memori_meta (key, value) VALUES (?1, ?2)
+     ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+    [key, value],
+  )?;
+  Ok(())
+}
+
+/// Compute `content_hash` for every row of `table`.
+fn backfill_content_hashes(conn: &Connection, table: &str) -> rusqlite::Result<()> {
+  let mut stmt = conn.prepare(&format!("SELECT rowid, content FROM {}", table))?;
+  let hashes = stmt
+    .query_map([], |r| {
+      let content: String = r.get(1)?;
+      Ok((r.get::<_, i64>(0)?, crate::storage::content_hash(&content)))
+    })?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+  let mut update = conn.prepare(&format!(
+    "UPDATE {} SET content_hash = ?1 WHERE rowid = ?2",
+    table
+  ))?;
+  for (rowid, hash) in hashes {
+    update.execute(rusqlite::params![hash, rowid])?;
+  }
+  Ok(())
+}
+
+/// Compute `vector_norm` for every row of `table` that has a vector.
+fn backfill_vector_norms(conn: &Connection, table: &str) -> rusqlite::Result<()> {
+  let mut stmt = conn.prepare(&format!(
+    "SELECT rowid, vector FROM {} WHERE vector IS NOT NULL",
+    table
+  ))?;
+  let norms = stmt
+    .query_map([], |r| {
+      let blob: Vec<u8> = r.get(1)?;
+      Ok((r.get::<_, i64>(0)?, crate::util::vector_norm(&crate::util::blob_to_vec(&blob))))
+    })?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+  let mut update = conn.prepare(&format!(
+    "UPDATE {} SET vector_norm = ?1 WHERE rowid = ?2",
+    table
+  ))?;
+  for (rowid, norm) in norms {
+    update.execute(rusqlite::params![norm as f64, rowid])?;
+  }
   Ok(())
 }
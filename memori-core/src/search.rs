@@ -1,14 +1,25 @@
-use rusqlite::params;
+use rusqlite::{params, OptionalExtension};
 use serde_json::Value;
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::storage::{get_raw, row_to_memory};
-use crate::types::{Memory, MemoriError, Result, SearchQuery};
-use crate::util::{blob_to_vec, cosine_similarity};
+use crate::embed::QueryCache;
+use crate::filter::parse_raw_filter;
+use crate::storage::{decode_vector, get_raw, read_metadata, row_to_memory};
+use crate::trace::span;
+use crate::types::{
+    AbsenceReason, EmptyQueryBehavior, FusionMode, Memory, MemoriConfig, MemoriError, Result, ScoreReport, ScoringConfig, SearchMode,
+    SearchQuery, TextMatchMode,
+};
+use crate::util::{cosine_similarity, cosine_similarity_with_norms, percentile, vector_norm};
 
 const RRF_K: f32 = 60.0;
 
+/// Most embedded memories `similarity_matrix` will compare without an
+/// explicit id list; past this the O(n^2) matrix has to be asked for.
+pub const SIMILARITY_MATRIX_MAX: usize = 1000;
+
 fn now_secs() -> f64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -16,134 +27,708 @@ fn now_secs() -> f64 {
         .as_secs_f64()
 }
 
-pub fn search(conn: &rusqlite::Connection, query: SearchQuery) -> Result<Vec<Memory>> {
+pub fn search(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    query_cache: &mut QueryCache,
+    query: SearchQuery,
+) -> Result<Vec<Memory>> {
+    if query.fallback {
+        return search_fallback(conn, config, query_cache, query);
+    }
+    if query.include_deleted {
+        return search_with_deleted(conn, config, query_cache, query);
+    }
+    let span = span!("search");
+    let now = now_secs();
+    let combined_filter = Some(query_conditions(conn, config, &query, "memories")?);
+    let (pool, limit) = fetch_limits(&query);
+
+    let mode = effective_mode(&query);
+    let filter = combined_filter.as_deref();
+    let min_fts = query.min_fts_score;
+    let text_match = query.text_match;
+
+    if let (Some(vec), false) = (&query.vector, mode == SearchMode::TextOnly) {
+        check_query_dimension(conn, config, vec.len())?;
+    }
+
+    let mut results = match mode {
+        SearchMode::Auto => match (derive_vector(config, &query, query_cache), &query.text) {
+            (Some(vec), Some(text)) => hybrid_search(conn, config, &vec, text, text_match, filter, limit, min_fts, now)?,
+            (Some(vec), None) => vector_search(conn, config, "memories", &vec, filter, limit, Some(now))?,
+            (None, Some(text)) => text_search(conn, config, text, text_match, filter, limit, min_fts, now)?,
+            (None, None) => empty_query(conn, config, "memories", filter, limit)?,
+        },
+        SearchMode::TextOnly => {
+            let text = query.text.as_deref().ok_or_else(|| {
+                MemoriError::InvalidQuery("text-only search requires text".to_string())
+            })?;
+            text_search(conn, config, text, text_match, filter, limit, min_fts, now)?
+        }
+        SearchMode::VectorOnly => {
+            let vec = derive_vector(config, &query, query_cache).ok_or_else(|| {
+                MemoriError::InvalidQuery(
+                    "vector-only search requires a vector (or text with the embeddings feature)".to_string(),
+                )
+            })?;
+            vector_search(conn, config, "memories", &vec, filter, limit, Some(now))?
+        }
+        SearchMode::Hybrid => {
+            let text = query.text.as_deref().ok_or_else(|| {
+                MemoriError::InvalidQuery("hybrid search requires text".to_string())
+            })?;
+            let vec = derive_vector(config, &query, query_cache).ok_or_else(|| {
+                MemoriError::InvalidQuery(
+                    "hybrid search requires a vector (or the embeddings feature)".to_string(),
+                )
+            })?;
+            hybrid_search(conn, config, &vec, text, text_match, filter, limit, min_fts, now)?
+        }
+    };
+
+    results = finish_results(results, &query, pool, &span);
+    report_scores(conn, config, query_cache, &query, &mut results)?;
+    span.record("results", results.len() as u64);
+    Ok(results)
+}
+
+/// Replace each result's `score` per `query.report_score`.
+fn report_scores(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    query_cache: &mut QueryCache,
+    query: &SearchQuery,
+    results: &mut [Memory],
+) -> Result<()> {
+    match query.report_score {
+        ScoreReport::Fusion => {}
+        ScoreReport::VectorCosine => {
+            let vec = derive_vector(config, query, query_cache);
+            for m in results.iter_mut() {
+                m.score = match (&vec, &m.vector) {
+                    (Some(q), Some(v)) => Some(cosine_similarity(q, v)),
+                    _ => None,
+                };
+            }
+        }
+        ScoreReport::TextRank => {
+            let ranks: HashMap<String, f32> = match &query.text {
+                Some(text) if !results.is_empty() && crate::schema::has_fts(conn)? => {
+                    let ids: Vec<String> =
+                        results.iter().map(|m| format!("'{}'", m.id.replace('\'', "''"))).collect();
+                    let filter = format!("memories.id IN ({})", ids.join(", "));
+                    text_search(conn, config, text, query.text_match, Some(&filter), results.len(), None, now_secs())?
+                        .into_iter()
+                        .filter_map(|m| Some((m.id, m.base_score?)))
+                        .collect()
+                }
+                _ => HashMap::new(),
+            };
+            for m in results.iter_mut() {
+                m.score = ranks.get(&m.id).copied();
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `SearchQuery::fallback`: vector, then text, then recent, first non-empty
+/// result wins. Each arm is an ordinary `search` of the query in that mode.
+fn search_fallback(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    query_cache: &mut QueryCache,
+    query: SearchQuery,
+) -> Result<Vec<Memory>> {
+    let base = SearchQuery { fallback: false, ..query };
+
+    if let Some(vec) = derive_vector(config, &base, query_cache).map(Cow::into_owned) {
+        let arm = SearchQuery { vector: Some(vec), mode: SearchMode::VectorOnly, ..base.clone() };
+        let mut results = search(conn, config, query_cache, arm)?;
+        if let Some(floor) = base.fallback_min_score {
+            results.retain(|m| m.base_score.is_some_and(|s| s >= floor));
+        }
+        if !results.is_empty() {
+            return Ok(results);
+        }
+    }
+
+    if base.text.is_some() && crate::schema::has_fts(conn)? {
+        let arm = SearchQuery { vector: None, mode: SearchMode::TextOnly, ..base.clone() };
+        let results = search(conn, config, query_cache, arm)?;
+        if !results.is_empty() {
+            return Ok(results);
+        }
+    }
+
+    let filter = query_conditions(conn, config, &base, "memories")?;
+    recent_search(conn, config, "memories", Some(&filter), base.limit)
+}
+
+/// Which gate keeps memory `id` (full id, hot table) out of `query`'s
+/// results: the filters first, then whether the query's ranking arms can
+/// reach it, then its rank against the limit.
+pub fn explain_absence(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    query_cache: &mut QueryCache,
+    query: &SearchQuery,
+    id: &str,
+) -> Result<AbsenceReason> {
+    if query.fallback {
+        return Err(MemoriError::InvalidQuery("explain_absence does not support fallback queries".to_string()));
+    }
+    let mem = get_raw(conn, config, id)?.ok_or_else(|| MemoriError::NotFound(id.to_string()))?;
+    let id_clause = format!("id = {}", text_literal(id));
+    let passes = |gate: SearchQuery| -> Result<bool> {
+        let sql = format!(
+            "SELECT EXISTS(SELECT 1 FROM memories WHERE {} AND {})",
+            id_clause,
+            query_conditions(conn, config, &gate, "memories")?
+        );
+        Ok(conn.query_row(&sql, [], |r| r.get(0))?)
+    };
+    let gate = SearchQuery::default();
+    if !passes(SearchQuery { exclude_ids: query.exclude_ids.clone(), ..gate.clone() })? {
+        return Ok(AbsenceReason::Excluded);
+    }
+    if !passes(SearchQuery { filter: query.filter.clone(), raw_filter: query.raw_filter.clone(), ..gate.clone() })? {
+        return Ok(AbsenceReason::FilterMismatch);
+    }
+    if !passes(SearchQuery { before: query.before, after: query.after, ..gate })? {
+        return Ok(AbsenceReason::DateOutOfRange);
+    }
+
+    let mode = effective_mode(query);
+    let has_vector = mode != SearchMode::TextOnly && derive_vector(config, query, query_cache).is_some();
+    let text = query.text.as_deref().filter(|_| mode != SearchMode::VectorOnly);
+    if let Some(text) = text {
+        let filter = format!("memories.{}", id_clause);
+        let matches = |floor| text_search(conn, config, text, query.text_match, Some(&filter), 1, floor, now_secs());
+        // A hybrid query can still reach it through the vector arm
+        let vector_reachable = has_vector && mem.vector.is_some();
+        if !vector_reachable && matches(None)?.is_empty() {
+            return Ok(AbsenceReason::TextNoMatch);
+        }
+        if !vector_reachable && query.min_fts_score.is_some() && matches(query.min_fts_score)?.is_empty() {
+            return Ok(AbsenceReason::BelowScoreFloor);
+        }
+    } else if has_vector && mem.vector.is_none() {
+        return Ok(AbsenceReason::NoVector);
+    }
+
+    let rank_of = |results: Vec<Memory>| results.iter().position(|m| m.id == id);
+    if let Some(rank) = rank_of(search(conn, config, query_cache, query.clone())?) {
+        return Ok(AbsenceReason::Returned { rank });
+    }
+    let total: usize = conn.query_row(
+        &format!("SELECT COUNT(*) FROM memories WHERE {}", collection_clause(config)),
+        [],
+        |r| r.get(0),
+    )?;
+    let everything = SearchQuery {
+        limit: total.max(1),
+        dedup_results: None,
+        rerank_by: None,
+        report_score: ScoreReport::Fusion,
+        ..query.clone()
+    };
+    let (pool, _) = fetch_limits(query);
+    match rank_of(search(conn, config, query_cache, everything)?) {
+        Some(rank) if query.dedup_results.is_some() && rank < pool => Ok(AbsenceReason::CollapsedDuplicate),
+        Some(rank) => Ok(AbsenceReason::TruncatedByLimit { rank }),
+        None => Ok(AbsenceReason::NotRanked),
+    }
+}
+
+/// `(pool, limit)` for a query: the relevance pool `rerank_by` re-orders
+/// (just `limit` without it), and how many rows to fetch for it, which is
+/// more when collapsing duplicates so dropped rows can be backfilled.
+fn fetch_limits(query: &SearchQuery) -> (usize, usize) {
+    let pool = if query.rerank_by.is_some() {
+        query.limit * query.rerank_oversample.max(1)
+    } else {
+        query.limit
+    };
+    let limit = if query.dedup_results.is_some() {
+        pool * 3
+    } else {
+        pool
+    };
+    (pool, limit)
+}
+
+/// Collapse duplicates, apply `rerank_by`, and cut to `query.limit`.
+fn finish_results(mut results: Vec<Memory>, query: &SearchQuery, pool: usize, span: &crate::trace::Span) -> Vec<Memory> {
+    if let Some(threshold) = query.dedup_results {
+        let before_dedup = results.len();
+        results = collapse_duplicates(results, threshold);
+        span.record_bool("dedup", results.len() < before_dedup);
+        results.truncate(pool);
+    }
+
+    if let Some(ref field) = query.rerank_by {
+        results.sort_by(|a, b| {
+            field
+                .value_of(b)
+                .partial_cmp(&field.value_of(a))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+    }
+    results.truncate(query.limit);
+    results
+}
+
+/// Run several queries, returning each one's results as `search` would.
+/// Pure vector queries (a `vector`, no `text`, not `include_deleted`) that
+/// filter alike share one scan of the stored vectors; the rest run through
+/// `search` individually.
+pub fn search_many(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    query_cache: &mut QueryCache,
+    queries: &[SearchQuery],
+) -> Result<Vec<Vec<Memory>>> {
+    let span = span!("search_many");
     let now = now_secs();
+    let mut results: Vec<Option<Vec<Memory>>> = vec![None; queries.len()];
+    // WHERE clause -> indexes of the batchable queries using it
+    let mut groups: Vec<(String, Vec<usize>)> = Vec::new();
+
+    for (i, query) in queries.iter().enumerate() {
+        let batchable = query.vector.is_some()
+            && query.text.is_none()
+            && !query.include_deleted
+            && !query.fallback
+            && matches!(effective_mode(query), SearchMode::Auto | SearchMode::VectorOnly);
+        if !batchable {
+            results[i] = Some(search(conn, config, query_cache, query.clone())?);
+            continue;
+        }
+        let filter = query_conditions(conn, config, query, "memories")?;
+        match groups.iter_mut().find(|(f, _)| *f == filter) {
+            Some((_, members)) => members.push(i),
+            None => groups.push((filter, vec![i])),
+        }
+    }
+
+    for (filter, members) in groups {
+        let rows = load_vector_rows(conn, config, "memories", Some(&filter))?;
+        let chunks = load_chunks(conn, config, "memories", Some(&filter))?;
+        span.record("candidates", rows.len() as u64);
+        for i in members {
+            let query = &queries[i];
+            let vec = query.vector.as_deref().unwrap_or_default();
+            check_query_dimension(conn, config, vec.len())?;
+            let (pool, limit) = fetch_limits(query);
+            let scored = score_vector_rows(&config.scoring, &rows, &chunks, vec, limit, Some(now));
+            let mut finished = finish_results(scored, query, pool, &span);
+            report_scores(conn, config, query_cache, query, &mut finished)?;
+            results[i] = Some(finished);
+        }
+    }
 
-    // Build combined filter: metadata filter AND date range filters
-    let mut conditions = Vec::new();
+    let results: Vec<Vec<Memory>> = results.into_iter().map(Option::unwrap_or_default).collect();
+    span.record("results", results.iter().map(Vec::len).sum::<usize>() as u64);
+    Ok(results)
+}
+
+/// The query's WHERE clause: collection AND metadata filter AND raw filter
+/// AND date range AND excluded ids, which are resolved as prefixes against
+/// `table` (one that matches nothing is excluded as given). Metadata reads
+/// are qualified with `table` (see `metadata_column`) so the clause can be
+/// joined with the FTS index.
+fn query_conditions(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    query: &SearchQuery,
+    table: &str,
+) -> Result<String> {
+    let mut conditions = vec![collection_clause(config)];
+    let metadata = metadata_column(conn, table)?;
 
     if let Some(ref filter) = query.filter {
-        let meta_clause = build_filter_clause(filter)?;
+        let meta_clause = build_filter_clause(filter, &metadata)?;
         if meta_clause != "1=1" {
             conditions.push(meta_clause);
         }
     }
+    if let Some(ref raw) = query.raw_filter {
+        conditions.push(format!("({})", parse_raw_filter(raw, &config.filter_functions, &metadata)?));
+    }
     if let Some(before) = query.before {
         conditions.push(format!("created_at < {}", before));
     }
     if let Some(after) = query.after {
         conditions.push(format!("created_at > {}", after));
     }
+    if !query.exclude_ids.is_empty() {
+        let mut excluded = Vec::with_capacity(query.exclude_ids.len());
+        for id in &query.exclude_ids {
+            let full_id = match crate::storage::resolve_prefix_in(conn, config, table, id) {
+                Ok(full_id) => full_id,
+                Err(MemoriError::NotFound(_)) => id.clone(),
+                Err(e) => return Err(e),
+            };
+            excluded.push(text_literal(&full_id));
+        }
+        conditions.push(format!("id NOT IN ({})", excluded.join(", ")));
+    }
 
-    let combined_filter = if conditions.is_empty() {
-        None
-    } else {
-        Some(conditions.join(" AND "))
-    };
+    Ok(conditions.join(" AND "))
+}
 
-    let results = match (&query.vector, &query.text) {
-        (Some(vec), Some(text)) => {
-            hybrid_search(conn, vec, text, combined_filter.as_deref(), query.limit, now)?
-        }
-        (Some(vec), None) => {
-            vector_search(conn, vec, combined_filter.as_deref(), query.limit, now)?
-        }
-        (None, Some(text)) => {
-            #[cfg(feature = "embeddings")]
-            {
-                if query.text_only {
-                    text_search(conn, text, combined_filter.as_deref(), query.limit, now)?
-                } else {
-                    let query_vec = crate::embed::embed_text(text);
-                    hybrid_search(conn, &query_vec, text, combined_filter.as_deref(), query.limit, now)?
-                }
-            }
-            #[cfg(not(feature = "embeddings"))]
-            {
-                text_search(conn, text, combined_filter.as_deref(), query.limit, now)?
-            }
+/// `query.mode` with the deprecated `text_only` flag folded in. The old flag
+/// only ever applied to text queries without a vector.
+fn effective_mode(query: &SearchQuery) -> SearchMode {
+    #[allow(deprecated)]
+    match query.mode {
+        SearchMode::Auto if query.text_only && query.text.is_some() && query.vector.is_none() => {
+            SearchMode::TextOnly
         }
-        (None, None) => {
-            recent_search(conn, combined_filter.as_deref(), query.limit)?
+        mode => mode,
+    }
+}
+
+/// Reject a caller-supplied query vector whose length no stored vector in the
+/// collection shares. `cosine_similarity` scores mismatched lengths 0.0, so
+/// such a query would otherwise return arbitrary rows. Rows of another
+/// dimension alongside matching ones (e.g. after a model change) are allowed.
+fn check_query_dimension(conn: &rusqlite::Connection, config: &MemoriConfig, dim: usize) -> Result<()> {
+    let width = config.vector_precision.bytes_per_dim();
+    let stored: Option<i64> = conn
+        .query_row(
+            "SELECT length(vector) FROM memories
+             WHERE collection = ?1 AND vector IS NOT NULL
+             ORDER BY length(vector) = ?2 DESC LIMIT 1",
+            params![config.collection(), (dim * width) as i64],
+            |row| row.get(0),
+        )
+        .optional()?;
+    match stored {
+        Some(bytes) if bytes as usize != dim * width => Err(MemoriError::InvalidVector(format!(
+            "query vector has {} dimensions but stored vectors have {}",
+            dim,
+            bytes as usize / width
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Key for the `search` result cache: the whole query, with text
+/// whitespace-normalized since tokenization and embedding ignore it.
+pub(crate) fn query_fingerprint(query: &SearchQuery) -> String {
+    let mut query = query.clone();
+    if let Some(text) = query.text.as_mut() {
+        *text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    }
+    format!("{:?}", query)
+}
+
+/// The query's vector, or its text embedded when the embeddings feature is
+/// compiled in. `None` when neither applies.
+fn derive_vector<'a>(
+    config: &MemoriConfig,
+    query: &'a SearchQuery,
+    query_cache: &mut QueryCache,
+) -> Option<Cow<'a, [f32]>> {
+    if let Some(ref vec) = query.vector {
+        return Some(Cow::Borrowed(vec));
+    }
+    #[cfg(any(feature = "embeddings", feature = "mock_embeddings"))]
+    if let Some(ref text) = query.text {
+        let text = config.embed_preprocess.apply(text);
+        return Some(Cow::Owned(query_cache.get_or_embed(&text, crate::embed::embed_text)));
+    }
+    #[cfg(not(any(feature = "embeddings", feature = "mock_embeddings")))]
+    let _ = (config, query_cache);
+    None
+}
+
+/// Partition ranked results into `(label, after, before)` buckets by
+/// `created_at`, first match wins. Input order is kept within each bucket.
+pub fn bucket_by_created(
+    results: Vec<Memory>,
+    buckets: &[(&str, Option<f64>, Option<f64>)],
+) -> Vec<(String, Vec<Memory>)> {
+    let mut grouped: Vec<(String, Vec<Memory>)> = buckets
+        .iter()
+        .map(|(label, _, _)| (label.to_string(), Vec::new()))
+        .collect();
+    for mem in results {
+        let slot = buckets.iter().position(|(_, after, before)| {
+            after.map_or(true, |a| mem.created_at >= a) && before.map_or(true, |b| mem.created_at < b)
+        });
+        if let Some(i) = slot {
+            grouped[i].1.push(mem);
         }
-    };
+    }
+    grouped
+}
 
-    Ok(results)
+/// Walk ranked results in order and keep only those whose vector is not more
+/// than `threshold` similar to one already kept. Results without a vector are
+/// always kept since there is nothing to compare.
+fn collapse_duplicates(results: Vec<Memory>, threshold: f32) -> Vec<Memory> {
+    let mut kept: Vec<Memory> = Vec::with_capacity(results.len());
+    for mem in results {
+        let is_dup = mem.vector.as_ref().is_some_and(|v| {
+            kept.iter().any(|k| {
+                k.vector
+                    .as_ref()
+                    .is_some_and(|kv| cosine_similarity(v, kv) > threshold)
+            })
+        });
+        if !is_dup {
+            kept.push(mem);
+        }
+    }
+    kept
 }
 
 /// Apply access frequency boost with recency decay.
 /// - boost: logarithmic amplification of access count (monotonic but sublinear)
 /// - decay: exponential time decay with ~69 day half-life
 /// - access_count==0 guard: never-accessed memories get no decay penalty
-fn apply_access_boost(base_score: f32, access_count: i64, last_accessed: f64, now: f64) -> f32 {
-    let boost = 1.0 + 0.1 * (1.0 + access_count as f32).ln();
+fn apply_access_boost(scoring: &ScoringConfig, base_score: f32, access_count: i64, last_accessed: f64, now: f64) -> f32 {
     let decay = if access_count == 0 || last_accessed <= 0.0 {
         1.0f32 // never accessed: no decay penalty
     } else {
         let days_since = ((now - last_accessed) / 86400.0) as f32;
         (-0.01 * days_since.max(0.0)).exp() // half-life ~69 days
     };
-    base_score * boost * decay
+    base_score * access_boost(scoring, access_count) * decay
+}
+
+/// The frequency half of `apply_access_boost`, without recency decay,
+/// capped at `scoring.max_access_boost`.
+fn access_boost(scoring: &ScoringConfig, access_count: i64) -> f32 {
+    let boost = 1.0 + 0.1 * (1.0 + access_count as f32).ln();
+    scoring.max_access_boost.map_or(boost, |max| boost.min(max))
+}
+
+/// `apply_access_boost` on a base of 1.0, squashed into 0..1 as
+/// `raw / (1 + raw)`: 0.5 for a never-accessed memory, higher with frequent
+/// recent access, toward 0 as accesses age.
+fn importance(access_count: i64, last_accessed: f64, now: f64) -> f32 {
+    let raw = apply_access_boost(&ScoringConfig::default(), 1.0, access_count, last_accessed, now);
+    raw / (1.0 + raw)
+}
+
+/// Importance (see `importance`) of the memory `id` in 0..1.
+pub fn importance_score(conn: &rusqlite::Connection, config: &MemoriConfig, id: &str) -> Result<f32> {
+    let mem = get_raw(conn, config, id)?.ok_or_else(|| MemoriError::NotFound(id.to_string()))?;
+    Ok(importance(mem.access_count, mem.last_accessed, now_secs()))
 }
 
+/// The `limit` most important memories in the collection, most important
+/// first, with the importance as `score`. Ties keep insertion order.
+pub fn top_important(conn: &rusqlite::Connection, config: &MemoriConfig, limit: usize) -> Result<Vec<Memory>> {
+    let now = now_secs();
+    let sql = format!(
+        "SELECT id, COALESCE(content_ref, content), vector, COALESCE(metadata_z, metadata), created_at, updated_at, last_accessed, access_count
+         FROM memories WHERE {} ORDER BY rowid",
+        collection_clause(config)
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let mut rows = stmt.query([])?;
+    let mut scored = Vec::new();
+    while let Some(row) = rows.next()? {
+        let mem = row_to_memory(row, config)?;
+        let score = importance(mem.access_count, mem.last_accessed, now);
+        scored.push((mem, score));
+    }
+    scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    scored.truncate(limit);
+    Ok(scored
+        .into_iter()
+        .map(|(mut m, s)| {
+            m.score = Some(s);
+            m
+        })
+        .collect())
+}
+
+/// Brute-force cosine ranking over `table` (`memories`, `memories_archive`,
+/// or `memories_deleted`).
+/// `now` drives the access boost; `None` scores by similarity alone.
+#[allow(clippy::too_many_arguments)]
 fn vector_search(
     conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    table: &str,
     query_vec: &[f32],
     filter: Option<&str>,
     limit: usize,
-    now: f64,
+    now: Option<f64>,
 ) -> Result<Vec<Memory>> {
+    let span = span!("vector_search");
+    let rows = load_vector_rows(conn, config, table, filter)?;
+    let chunks = load_chunks(conn, config, table, filter)?;
+    let results = score_vector_rows(&config.scoring, &rows, &chunks, query_vec, limit, now);
+    span.record("candidates", rows.len() as u64);
+    span.record("results", results.len() as u64);
+    Ok(results)
+}
+
+fn vector_rows_sql(table: &str, filter: Option<&str>) -> String {
+    let where_clause = filter.map_or(String::new(), |f| format!("WHERE {}", f));
+    format!(
+        "SELECT id, COALESCE(content_ref, content), vector, COALESCE(metadata_z, metadata), created_at, updated_at, last_accessed, access_count,
+                vector_norm
+         FROM {} {} ORDER BY rowid",
+        table, where_clause
+    )
+}
+
+/// `EXPLAIN QUERY PLAN` detail lines for the scan a vector search of `query`
+/// runs over the hot table, to confirm its metadata filter uses an index.
+pub fn vector_query_plan(conn: &rusqlite::Connection, config: &MemoriConfig, query: &SearchQuery) -> Result<Vec<String>> {
+    let filter = query_conditions(conn, config, query, "memories")?;
+    let sql = format!("EXPLAIN QUERY PLAN {}", vector_rows_sql("memories", Some(&filter)));
+    let mut stmt = conn.prepare(&sql)?;
+    let details = stmt
+        .query_map([], |row| row.get(3))?
+        .collect::<std::result::Result<Vec<String>, _>>()?;
+    Ok(details)
+}
+
+/// Every row of `table` matching `filter`, in rowid order, with its stored
+/// `vector_norm`.
+fn load_vector_rows(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    table: &str,
+    filter: Option<&str>,
+) -> Result<Vec<(Memory, Option<f64>)>> {
+    let mut stmt = conn.prepare(&vector_rows_sql(table, filter))?;
+    let mut rows = stmt.query([])?;
+    let mut loaded = Vec::new();
+    while let Some(row) = rows.next()? {
+        loaded.push((row_to_memory(row, config)?, row.get(8)?));
+    }
+    Ok(loaded)
+}
+
+/// Chunk vectors with their stored norms, keyed by memory id.
+type Chunks = HashMap<String, Vec<(Vec<f32>, Option<f64>)>>;
+
+/// Extra chunk vectors (see `storage::insert_chunked`) of the hot-table rows
+/// matching `filter`, by memory id. Other tiers keep no chunks.
+fn load_chunks(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    table: &str,
+    filter: Option<&str>,
+) -> Result<Chunks> {
+    let mut chunks = Chunks::new();
+    // Read-only handles on older files may lack the table
+    if table != "memories" || !crate::schema::has_chunks(conn)? {
+        return Ok(chunks);
+    }
     let where_clause = filter.map_or(String::new(), |f| format!("WHERE {}", f));
     let sql = format!(
-        "SELECT id, content, vector, metadata, created_at, updated_at, last_accessed, access_count
-         FROM memories {} ORDER BY rowid",
+        "SELECT c.memory_id, c.vector, c.vector_norm
+         FROM memory_chunks c JOIN memories ON memories.id = c.memory_id {}
+         ORDER BY c.memory_id, c.chunk_index",
         where_clause
     );
-
     let mut stmt = conn.prepare(&sql)?;
-    let mut scored: Vec<(Memory, f32)> = Vec::new();
     let mut rows = stmt.query([])?;
-
     while let Some(row) = rows.next()? {
-        let mem = row_to_memory(row)?;
+        let blob: Vec<u8> = row.get(1)?;
+        chunks
+            .entry(row.get(0)?)
+            .or_default()
+            .push((decode_vector(config, &blob), row.get(2)?));
+    }
+    Ok(chunks)
+}
+
+/// Rank `rows` by cosine similarity to `query_vec` (a multi-vector memory by
+/// its best chunk in `chunks`), boosted by access stats as of `now` when
+/// given, and copy out the top `limit`. Rows without a vector are skipped;
+/// ties keep row order.
+fn score_vector_rows(
+    scoring: &ScoringConfig,
+    rows: &[(Memory, Option<f64>)],
+    chunks: &Chunks,
+    query_vec: &[f32],
+    limit: usize,
+    now: Option<f64>,
+) -> Vec<Memory> {
+    let query_norm = vector_norm(query_vec);
+    let mut scored: Vec<(usize, f32, f32)> = Vec::new();
+    for (i, (mem, stored_norm)) in rows.iter().enumerate() {
         if let Some(ref vec) = mem.vector {
-            let sim = cosine_similarity(query_vec, vec);
-            let boosted = apply_access_boost(sim, mem.access_count, mem.last_accessed, now);
-            scored.push((mem, boosted));
+            // Rows written without a norm (e.g. by direct SQL) take the full computation
+            let similarity = |vec: &[f32], norm: Option<f64>| match norm {
+                Some(norm) => cosine_similarity_with_norms(query_vec, query_norm, vec, norm as f32),
+                None => cosine_similarity(query_vec, vec),
+            };
+            let sim = chunks
+                .get(&mem.id)
+                .into_iter()
+                .flatten()
+                .map(|(chunk, norm)| similarity(chunk, *norm))
+                .fold(similarity(vec, *stored_norm), f32::max);
+            let boosted = match now {
+                Some(now) => apply_access_boost(scoring, sim, mem.access_count, mem.last_accessed, now),
+                None => sim,
+            };
+            scored.push((i, sim, boosted));
         }
     }
 
-    scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    scored.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal));
     scored.truncate(limit);
-
-    Ok(scored
+    scored
         .into_iter()
-        .map(|(mut m, s)| {
-            m.score = Some(s);
+        .map(|(i, sim, boosted)| {
+            let mut m = rows[i].0.clone();
+            m.base_score = Some(sim);
+            m.score = Some(boosted);
             m
         })
-        .collect())
+        .collect()
 }
 
 /// Sanitize user input for FTS5 MATCH queries. FTS5 has its own query syntax
 /// where `-` means NOT, `:` means column filter, `*` means prefix, etc.
-/// Wrapping each token in double quotes forces literal matching.
-fn sanitize_fts_query(query: &str) -> String {
-    query
+/// Wrapping each token in double quotes forces literal matching. Repeated
+/// terms are dropped and at most `max_terms` are kept (0 keeps all), so a
+/// pasted document can't blow past FTS5's expression limits. Terms are joined
+/// by implicit AND, or by `OR` under `TextMatchMode::Any`.
+fn sanitize_fts_query(query: &str, max_terms: usize, match_mode: TextMatchMode) -> String {
+    let mut seen = HashSet::new();
+    let terms = query
         .split_whitespace()
-        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
-        .collect::<Vec<_>>()
-        .join(" ")
+        .filter(|term| seen.insert(term.to_lowercase()))
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")));
+    let terms: Vec<String> = match max_terms {
+        0 => terms.collect(),
+        n => terms.take(n).collect(),
+    };
+    terms.join(match_mode.joiner())
 }
 
+#[allow(clippy::too_many_arguments)]
 fn text_search(
     conn: &rusqlite::Connection,
+    config: &MemoriConfig,
     query_text: &str,
+    match_mode: TextMatchMode,
     filter: Option<&str>,
     limit: usize,
+    min_score: Option<f32>,
     now: f64,
 ) -> Result<Vec<Memory>> {
-    let safe_query = sanitize_fts_query(query_text);
+    let span = span!("text_search");
+    if !crate::schema::has_fts(conn)? {
+        return Err(MemoriError::FtsDisabled);
+    }
+    let safe_query = sanitize_fts_query(query_text, config.max_query_terms, match_mode);
 
     // Empty query (whitespace-only or blank input) produces no tokens -- return
     // early instead of passing an empty string to FTS5 MATCH which would error.
@@ -151,72 +736,109 @@ fn text_search(
         return Ok(Vec::new());
     }
 
+    // Column weights only apply to a split index; a combined one has one column
+    let rank = match config.bm25_weights {
+        Some((content, metadata)) if crate::schema::has_split_fts(conn)? => {
+            format!("bm25(memories_fts, {}, {})", content, metadata)
+        }
+        _ => "fts.rank".to_string(),
+    };
     let sql = if let Some(f) = filter {
         format!(
-            "SELECT m.id, m.content, m.vector, m.metadata, m.created_at, m.updated_at,
-                    m.last_accessed, m.access_count, fts.rank
+            "SELECT memories.id, COALESCE(memories.content_ref, memories.content), memories.vector, COALESCE(memories.metadata_z, memories.metadata), memories.created_at, memories.updated_at,
+                    memories.last_accessed, memories.access_count, {rank} AS score_rank
              FROM memories_fts fts
-             JOIN memories m ON m.rowid = fts.rowid
-             WHERE memories_fts MATCH ?1 AND {}
-             ORDER BY fts.rank
+             JOIN memories ON memories.rowid = fts.rowid
+             WHERE memories_fts MATCH ?1 AND {filter}
+             ORDER BY score_rank
              LIMIT ?2",
-            f.replace("metadata", "m.metadata")
+            rank = rank,
+            filter = f
         )
     } else {
-        "SELECT m.id, m.content, m.vector, m.metadata, m.created_at, m.updated_at,
-                m.last_accessed, m.access_count, fts.rank
-         FROM memories_fts fts
-         JOIN memories m ON m.rowid = fts.rowid
-         WHERE memories_fts MATCH ?1
-         ORDER BY fts.rank
-         LIMIT ?2"
-            .to_string()
+        format!(
+            "SELECT memories.id, COALESCE(memories.content_ref, memories.content), memories.vector, COALESCE(memories.metadata_z, memories.metadata), memories.created_at, memories.updated_at,
+                    memories.last_accessed, memories.access_count, {} AS score_rank
+             FROM memories_fts fts
+             JOIN memories ON memories.rowid = fts.rowid
+             WHERE memories_fts MATCH ?1
+             ORDER BY score_rank
+             LIMIT ?2",
+            rank
+        )
     };
 
     let mut stmt = conn.prepare(&sql)?;
     let mut rows = stmt.query(params![safe_query, limit as i64])?;
     let mut results = Vec::new();
+    let mut candidates = 0u64;
 
     while let Some(row) = rows.next()? {
+        candidates += 1;
         let rank: f64 = row.get(8)?;
+        let base_score = -rank as f32;
+        // Rows arrive best-first, so everything after the first weak match is weaker
+        if min_score.is_some_and(|min| base_score < min) {
+            break;
+        }
+
+        let id: String = row.get(0)?;
         let vector_blob: Option<Vec<u8>> = row.get(2)?;
-        let metadata_str: Option<String> = row.get(3)?;
+        let metadata = read_metadata(config, &id, row.get(3)?)?;
         let access_count: i64 = row.get(7)?;
         let last_accessed: f64 = row.get(6)?;
 
-        let base_score = -rank as f32;
-        let boosted = apply_access_boost(base_score, access_count, last_accessed, now);
+        let boosted = if config.text_decay {
+            apply_access_boost(&config.scoring, base_score, access_count, last_accessed, now)
+        } else {
+            base_score * access_boost(&config.scoring, access_count)
+        };
 
         let mem = Memory {
-            id: row.get(0)?,
+            id,
             content: row.get(1)?,
-            vector: vector_blob.map(|b| blob_to_vec(&b)),
-            metadata: metadata_str.and_then(|s| serde_json::from_str(&s).ok()),
+            vector: vector_blob.map(|b| decode_vector(config, &b)),
+            metadata,
             created_at: row.get(4)?,
             updated_at: row.get(5)?,
             last_accessed,
             access_count,
             score: Some(boosted),
+            base_score: Some(base_score),
+            deleted_at: None,
         };
         results.push(mem);
     }
 
+    span.record("candidates", candidates);
+    span.record("results", results.len() as u64);
     Ok(results)
 }
 
+#[allow(clippy::too_many_arguments)]
 fn hybrid_search(
     conn: &rusqlite::Connection,
+    config: &MemoriConfig,
     query_vec: &[f32],
     query_text: &str,
+    match_mode: TextMatchMode,
     filter: Option<&str>,
     limit: usize,
+    min_fts_score: Option<f32>,
     now: f64,
 ) -> Result<Vec<Memory>> {
+    let span = span!("hybrid_search");
+    if !crate::schema::has_fts(conn)? {
+        return Err(MemoriError::FtsDisabled);
+    }
     // Get more candidates from each source for better fusion
     let candidate_limit = limit * 3;
 
-    let vec_results = vector_search(conn, query_vec, filter, candidate_limit, now)?;
-    let text_results = text_search(conn, query_text, filter, candidate_limit, now)?;
+    let vec_results = vector_search(conn, config, "memories", query_vec, filter, candidate_limit, Some(now))?;
+    let text_results = text_search(conn, config, query_text, match_mode, filter, candidate_limit, min_fts_score, now)?;
+
+    let vec_scores = normalized_scores(&vec_results);
+    let text_scores = normalized_scores(&text_results);
 
     // Build rank maps (1-indexed)
     let mut vec_ranks: HashMap<String, usize> = HashMap::new();
@@ -234,23 +856,36 @@ fn hybrid_search(
     for m in vec_results {
         all_memories.insert(m.id.clone(), m);
     }
+    // Vector entries win, so base_score stays the similarity when both matched
     for m in text_results {
         all_memories.entry(m.id.clone()).or_insert(m);
     }
+    span.record("candidates", all_memories.len() as u64);
 
-    // Compute RRF scores (access boost already applied in sub-searches)
-    let mut scored: Vec<(Memory, f32)> = all_memories
-        .into_values()
-        .map(|m| {
-            let vec_rank = vec_ranks.get(&m.id).copied().unwrap_or(candidate_limit + 1);
-            let text_rank = text_ranks.get(&m.id).copied().unwrap_or(candidate_limit + 1);
-            let rrf = 1.0 / (RRF_K + vec_rank as f32) + 1.0 / (RRF_K + text_rank as f32);
-            (m, rrf)
-        })
-        .collect();
+    // Fuse (access boost already applied in sub-searches)
+    let mut scored: Vec<(Memory, f32)> = match config.fusion {
+        FusionMode::Rrf => all_memories
+            .into_values()
+            .map(|m| {
+                let vec_rank = vec_ranks.get(&m.id).copied().unwrap_or(candidate_limit + 1);
+                let text_rank = text_ranks.get(&m.id).copied().unwrap_or(candidate_limit + 1);
+                let rrf = 1.0 / (RRF_K + vec_rank as f32) + 1.0 / (RRF_K + text_rank as f32);
+                (m, rrf)
+            })
+            .collect(),
+        FusionMode::WeightedScore { vector_weight, text_weight } => all_memories
+            .into_values()
+            .map(|m| {
+                let vec = vec_scores.get(&m.id).copied().unwrap_or(0.0);
+                let text = text_scores.get(&m.id).copied().unwrap_or(0.0);
+                (m, vector_weight * vec + text_weight * text)
+            })
+            .collect(),
+    };
 
     scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
     scored.truncate(limit);
+    span.record("results", scored.len() as u64);
 
     Ok(scored
         .into_iter()
@@ -261,16 +896,51 @@ fn hybrid_search(
         .collect())
 }
 
+/// Each result's score min-max scaled to 0..1 within `results`. When every
+/// score is equal (including a single result) they all map to 1.
+fn normalized_scores(results: &[Memory]) -> HashMap<String, f32> {
+    let scores = results.iter().map(|m| m.score.unwrap_or(0.0));
+    let min = scores.clone().fold(f32::INFINITY, f32::min);
+    let max = scores.fold(f32::NEG_INFINITY, f32::max);
+    results
+        .iter()
+        .map(|m| {
+            let s = m.score.unwrap_or(0.0);
+            let norm = if max > min { (s - min) / (max - min) } else { 1.0 };
+            (m.id.clone(), norm)
+        })
+        .collect()
+}
+
+/// A query with no vector and no text, per `config.empty_query_behavior`.
+fn empty_query(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    table: &str,
+    filter: Option<&str>,
+    limit: usize,
+) -> Result<Vec<Memory>> {
+    match config.empty_query_behavior {
+        EmptyQueryBehavior::Recent => recent_search(conn, config, table, filter, limit),
+        EmptyQueryBehavior::Empty => Ok(Vec::new()),
+        EmptyQueryBehavior::Error => Err(MemoriError::InvalidQuery(
+            "search requires a vector or text".to_string(),
+        )),
+    }
+}
+
 fn recent_search(
     conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    table: &str,
     filter: Option<&str>,
     limit: usize,
 ) -> Result<Vec<Memory>> {
     let where_clause = filter.map_or(String::new(), |f| format!("WHERE {}", f));
     let sql = format!(
-        "SELECT id, content, vector, metadata, created_at, updated_at, last_accessed, access_count
-         FROM memories {} ORDER BY updated_at DESC LIMIT ?1",
-        where_clause
+        "SELECT id, COALESCE(content_ref, content), vector, COALESCE(metadata_z, metadata), created_at, updated_at, last_accessed, access_count
+         FROM {} {} ORDER BY updated_at DESC LIMIT ?1",
+        table, where_clause
     );
 
     let mut stmt = conn.prepare(&sql)?;
@@ -278,30 +948,382 @@ fn recent_search(
     let mut results = Vec::new();
 
     while let Some(row) = rows.next()? {
-        results.push(row_to_memory(row)?);
+        results.push(row_to_memory(row, config)?);
     }
 
     Ok(results)
 }
 
+/// Search the archive tier, which has no FTS index. A vector (explicit, or
+/// derived from the text unless the mode is `TextOnly`) ranks by cosine
+/// similarity as in `search`; otherwise text matches rows whose content or
+/// metadata contains every term (case-insensitive), most recently updated
+/// first and unscored; with neither, the most recently updated archived rows.
+/// Filters and date ranges apply as in `search`.
+pub fn search_archive(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    query_cache: &mut QueryCache,
+    query: SearchQuery,
+) -> Result<Vec<Memory>> {
+    search_tier(conn, config, query_cache, query, "memories_archive")
+}
+
+/// `search_archive` over any tier without an FTS index (`memories_archive`
+/// or `memories_deleted`).
+fn search_tier(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    query_cache: &mut QueryCache,
+    query: SearchQuery,
+    table: &str,
+) -> Result<Vec<Memory>> {
+    let now = now_secs();
+    let filter = query_conditions(conn, config, &query, table)?;
+    let vec = if effective_mode(&query) == SearchMode::TextOnly {
+        None
+    } else {
+        derive_vector(config, &query, query_cache)
+    };
+    match (vec, &query.text) {
+        (Some(vec), _) => vector_search(conn, config, table, &vec, Some(&filter), query.limit, Some(now)),
+        (None, Some(text)) => tier_text_scan(conn, config, table, text, &filter, query.limit),
+        (None, None) => empty_query(conn, config, table, Some(&filter), query.limit),
+    }
+}
+
+/// `search` and `search_archive` together. When both tiers rank by vector
+/// similarity (a vector and no text, or `VectorOnly`), results merge by
+/// score; otherwise the scales differ, so hot results come first. Truncated
+/// to `limit` either way.
+pub fn search_all(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    query_cache: &mut QueryCache,
+    query: SearchQuery,
+) -> Result<Vec<Memory>> {
+    let hot = search(conn, config, query_cache, query.clone())?;
+    let archived = search_archive(conn, config, query_cache, query.clone())?;
+    Ok(merge_tiers(&query, hot, archived))
+}
+
+/// `search` plus soft-deleted memories (with `deleted_at` set), merged as in
+/// `search_all`.
+fn search_with_deleted(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    query_cache: &mut QueryCache,
+    mut query: SearchQuery,
+) -> Result<Vec<Memory>> {
+    query.include_deleted = false;
+    // Tiers merge on ranking scores, so report only once they are merged
+    let report = std::mem::take(&mut query.report_score);
+    let live = search(conn, config, query_cache, query.clone())?;
+    let mut deleted = search_tier(conn, config, query_cache, query.clone(), "memories_deleted")?;
+    crate::storage::fill_deleted_at(conn, &mut deleted)?;
+    let mut merged = merge_tiers(&query, live, deleted);
+    query.report_score = report;
+    report_scores(conn, config, query_cache, &query, &mut merged)?;
+    Ok(merged)
+}
+
+/// Append `cold` results to `hot`. When both rank by vector similarity (a
+/// vector and no text, or `VectorOnly`), they merge by score; otherwise the
+/// scales differ, so hot results come first. Truncated to `limit`.
+fn merge_tiers(query: &SearchQuery, mut hot: Vec<Memory>, cold: Vec<Memory>) -> Vec<Memory> {
+    let mode = effective_mode(query);
+    let by_score = mode == SearchMode::VectorOnly
+        || (mode == SearchMode::Auto && query.vector.is_some() && query.text.is_none());
+
+    hot.extend(cold);
+    if by_score {
+        hot.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+    }
+    hot.truncate(query.limit);
+    hot
+}
+
+/// Text matching for tiers without FTS: every whitespace-separated term must
+/// appear in `content` or the metadata JSON, as a case-insensitive substring.
+fn tier_text_scan(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    table: &str,
+    text: &str,
+    filter: &str,
+    limit: usize,
+) -> Result<Vec<Memory>> {
+    let terms: Vec<String> = text
+        .split_whitespace()
+        .map(|t| format!("%{}%", t.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")))
+        .collect();
+    if terms.is_empty() {
+        return Ok(Vec::new());
+    }
+    let metadata = metadata_column(conn, table)?;
+    let matches = (0..terms.len())
+        .map(|i| format!("(content || ' ' || COALESCE({}, '')) LIKE ?{} ESCAPE '\\'", metadata, i + 2))
+        .collect::<Vec<_>>()
+        .join(" AND ");
+    let sql = format!(
+        "SELECT id, COALESCE(content_ref, content), vector, COALESCE(metadata_z, metadata), created_at, updated_at, last_accessed, access_count
+         FROM {} WHERE {} AND {} ORDER BY updated_at DESC LIMIT ?1",
+        table, filter, matches
+    );
+
+    let mut bind: Vec<rusqlite::types::Value> = vec![(limit as i64).into()];
+    bind.extend(terms.into_iter().map(rusqlite::types::Value::from));
+    let mut stmt = conn.prepare(&sql)?;
+    let mut rows = stmt.query(rusqlite::params_from_iter(bind))?;
+    let mut results = Vec::new();
+    while let Some(row) = rows.next()? {
+        results.push(row_to_memory(row, config)?);
+    }
+    Ok(results)
+}
+
 /// Find memories similar to a given memory by its ID.
 /// Uses the source memory's vector to run a vector search, excluding itself.
-pub fn related(conn: &rusqlite::Connection, id: &str, limit: usize) -> Result<Vec<Memory>> {
-    let source = get_raw(conn, id)?
+/// With `config.related_pure_similarity` (the default) results rank by cosine
+/// similarity alone; otherwise the access boost applies as in `search`.
+pub fn related(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    id: &str,
+    limit: usize,
+) -> Result<Vec<Memory>> {
+    let source = get_raw(conn, config, id)?
         .ok_or_else(|| MemoriError::NotFound(id.to_string()))?;
 
     let source_vec = source.vector
         .ok_or_else(|| MemoriError::InvalidVector("memory has no embedding".to_string()))?;
 
-    let now = now_secs();
-    let exclude_filter = format!("id != '{}'", id.replace('\'', "''"));
-    vector_search(conn, &source_vec, Some(&exclude_filter), limit, now)
+    let now = (!config.related_pure_similarity).then(now_secs);
+    let exclude_filter = format!(
+        "id != '{}' AND {}",
+        id.replace('\'', "''"),
+        collection_clause(config)
+    );
+    vector_search(conn, config, "memories", &source_vec, Some(&exclude_filter), limit, now)
+}
+
+/// `related`, ranked by cosine similarity plus `metadata_boost` for each
+/// top-level metadata key whose value the candidate shares with the source
+/// (e.g. the same `topic`). `score` is that combined value and `base_score`
+/// the plain similarity.
+pub fn related_weighted(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    id: &str,
+    limit: usize,
+    metadata_boost: f32,
+) -> Result<Vec<Memory>> {
+    let source = get_raw(conn, config, id)?.ok_or_else(|| MemoriError::NotFound(id.to_string()))?;
+    let candidates = related(conn, config, id, usize::MAX)?;
+
+    let mut scored: Vec<(Memory, f32)> = candidates
+        .into_iter()
+        .map(|m| {
+            let shared = shared_metadata_pairs(source.metadata.as_ref(), m.metadata.as_ref());
+            let score = m.base_score.unwrap_or(0.0) + metadata_boost * shared as f32;
+            (m, score)
+        })
+        .collect();
+    scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    scored.truncate(limit);
+    Ok(scored
+        .into_iter()
+        .map(|(mut m, s)| {
+            m.score = Some(s);
+            m
+        })
+        .collect())
+}
+
+/// Number of top-level metadata keys with equal values in both objects.
+fn shared_metadata_pairs(a: Option<&serde_json::Value>, b: Option<&serde_json::Value>) -> usize {
+    match (a.and_then(|v| v.as_object()), b.and_then(|v| v.as_object())) {
+        (Some(a), Some(b)) => a.iter().filter(|(k, v)| b.get(*k) == Some(*v)).count(),
+        _ => 0,
+    }
+}
+
+/// The memory `id` plus, breadth-first up to `depth` hops, the `per_node`
+/// most related memories of each memory collected so far. Every memory
+/// appears once, in discovery order with the seed first; a neighbor keeps
+/// the scores from the `related` call that first found it. Nothing here
+/// counts as an access.
+pub fn export_neighborhood(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    id: &str,
+    depth: usize,
+    per_node: usize,
+) -> Result<Vec<Memory>> {
+    let seed = get_raw(conn, config, id)?.ok_or_else(|| MemoriError::NotFound(id.to_string()))?;
+    let mut seen: HashSet<String> = HashSet::from([seed.id.clone()]);
+    let mut frontier = vec![seed.id.clone()];
+    let mut collected = vec![seed];
+
+    for _ in 0..depth {
+        let mut next = Vec::new();
+        for node in &frontier {
+            for neighbor in related(conn, config, node, per_node)? {
+                if seen.insert(neighbor.id.clone()) {
+                    next.push(neighbor.id.clone());
+                    collected.push(neighbor);
+                }
+            }
+        }
+        if next.is_empty() {
+            break;
+        }
+        frontier = next;
+    }
+    Ok(collected)
+}
+
+/// Pairwise cosine similarity of the given memories, or of every embedded
+/// memory in the collection when `ids` is `None` (up to
+/// `SIMILARITY_MATRIX_MAX`). Returns the ids in matrix order and the
+/// symmetric matrix; rows and columns follow the same order.
+pub fn similarity_matrix(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    ids: Option<&[String]>,
+) -> Result<(Vec<String>, Vec<Vec<f32>>)> {
+    let rows: Vec<(String, Vec<f32>)> = match ids {
+        Some(ids) => ids
+            .iter()
+            .map(|id| {
+                let mem = get_raw(conn, config, id)?.ok_or_else(|| MemoriError::NotFound(id.to_string()))?;
+                let vec = mem
+                    .vector
+                    .ok_or_else(|| MemoriError::InvalidVector(format!("memory {} has no embedding", id)))?;
+                Ok((mem.id, vec))
+            })
+            .collect::<Result<_>>()?,
+        None => {
+            let embedded: i64 = conn.query_row(
+                "SELECT COUNT(*) FROM memories WHERE collection = ?1 AND vector IS NOT NULL",
+                params![config.collection()],
+                |row| row.get(0),
+            )?;
+            if embedded as usize > SIMILARITY_MATRIX_MAX {
+                return Err(MemoriError::InvalidQuery(format!(
+                    "{} embedded memories exceeds the similarity matrix limit of {}; pass explicit ids",
+                    embedded, SIMILARITY_MATRIX_MAX
+                )));
+            }
+            let mut stmt = conn.prepare(
+                "SELECT id, vector FROM memories
+                 WHERE collection = ?1 AND vector IS NOT NULL ORDER BY rowid",
+            )?;
+            let rows = stmt
+                .query_map(params![config.collection()], |row| {
+                    let blob: Vec<u8> = row.get(1)?;
+                    Ok((row.get(0)?, decode_vector(config, &blob)))
+                })?
+                .collect::<rusqlite::Result<_>>()?;
+            rows
+        }
+    };
+
+    let n = rows.len();
+    let mut matrix = vec![vec![0.0f32; n]; n];
+    for (i, (_, a)) in rows.iter().enumerate() {
+        matrix[i][i] = cosine_similarity(a, a);
+        for (j, (_, b)) in rows.iter().enumerate().skip(i + 1) {
+            let sim = cosine_similarity(a, b);
+            matrix[i][j] = sim;
+            matrix[j][i] = sim;
+        }
+    }
+    Ok((rows.into_iter().map(|(id, _)| id).collect(), matrix))
+}
+
+/// Number of memories in the collection whose FTS text matches every term of
+/// `text` (sanitized as in `text_search`) and that pass the metadata
+/// `filter`, counted in SQL without reading the rows. Blank text counts 0.
+pub fn count_text_matches(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    text: &str,
+    filter: Option<&Value>,
+) -> Result<usize> {
+    if !crate::schema::has_fts(conn)? {
+        return Err(MemoriError::FtsDisabled);
+    }
+    let safe_query = sanitize_fts_query(text, config.max_query_terms, TextMatchMode::All);
+    if safe_query.is_empty() {
+        return Ok(0);
+    }
+
+    let mut conditions = vec![collection_clause(config)];
+    if let Some(f) = filter {
+        conditions.push(build_filter_clause(f, &metadata_column(conn, "memories")?)?);
+    }
+    let sql = format!(
+        "SELECT COUNT(*) FROM memories_fts fts
+         JOIN memories ON memories.rowid = fts.rowid
+         WHERE memories_fts MATCH ?1 AND {}",
+        conditions.join(" AND ")
+    );
+    let n: i64 = conn.query_row(&sql, params![safe_query], |row| row.get(0))?;
+    Ok(n as usize)
+}
+
+/// Cosine similarity of `query_vec` to every embedded memory in the
+/// collection, summarized as `(percentile, similarity)` pairs in the order
+/// asked for. Percentiles are 0-100, linearly interpolated. Empty when
+/// nothing is embedded.
+pub fn similarity_distribution(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    query_vec: &[f32],
+    percentiles: &[f32],
+) -> Result<Vec<(f32, f32)>> {
+    if let Some(bad) = percentiles.iter().find(|p| !(0.0..=100.0).contains(*p)) {
+        return Err(MemoriError::InvalidQuery(format!(
+            "percentile {} is outside 0-100",
+            bad
+        )));
+    }
+    check_query_dimension(conn, config, query_vec.len())?;
+
+    let filter = collection_clause(config);
+    let mut sims: Vec<f32> = vector_search(conn, config, "memories", query_vec, Some(&filter), usize::MAX, None)?
+        .into_iter()
+        .filter_map(|m| m.base_score)
+        .collect();
+    if sims.is_empty() {
+        return Ok(Vec::new());
+    }
+    sims.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    Ok(percentiles.iter().map(|&p| (p, percentile(&sims, p))).collect())
+}
+
+/// SQL condition restricting rows to the handle's collection.
+fn collection_clause(config: &MemoriConfig) -> String {
+    format!("collection = {}", text_literal(config.collection()))
+}
+
+/// `s` as a SQL text literal written as a hex blob: filter strings are
+/// rewritten for the FTS join (`metadata` -> `m.metadata`), which must not
+/// touch names or ids.
+fn text_literal(s: &str) -> String {
+    let hex: String = s.bytes().map(|b| format!("{:02x}", b)).collect();
+    format!("CAST(X'{}' AS TEXT)", hex)
 }
 
 /// Validate that a metadata filter key is a safe identifier.
 /// Keys must match `[a-zA-Z_][a-zA-Z0-9_]*` to prevent SQL injection
 /// through the json_extract path expression.
-fn is_valid_filter_key(key: &str) -> bool {
+pub(crate) fn is_valid_filter_key(key: &str) -> bool {
     if key.is_empty() {
         return false;
     }
@@ -313,7 +1335,16 @@ fn is_valid_filter_key(key: &str) -> bool {
     chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
 }
 
-fn build_filter_clause(filter: &Value) -> Result<String> {
+/// SQL reading the full metadata JSON of a `table` row, qualified since
+/// `memories_fts` has a `metadata` column of its own. Filters use it so
+/// compressed rows match on nested and array values too.
+pub(crate) fn metadata_column(conn: &rusqlite::Connection, table: &str) -> Result<String> {
+    Ok(crate::schema::metadata_sql(&format!("{}.", table), crate::schema::has_compressed_metadata(conn)?))
+}
+
+/// SQL for an exact-match metadata filter over `metadata`, an expression
+/// from `metadata_column`.
+pub(crate) fn build_filter_clause(filter: &Value, metadata: &str) -> Result<String> {
     match filter {
         Value::Object(map) => {
             let mut conditions = Vec::with_capacity(map.len());
@@ -336,7 +1367,7 @@ fn build_filter_clause(filter: &Value) -> Result<String> {
                     }
                     _ => format!("'{}'", val.to_string().replace('\'', "''")),
                 };
-                conditions.push(format!("json_extract(metadata, '$.{}') = {}", key, json_val));
+                conditions.push(format!("json_extract({}, '$.{}') = {}", metadata, key, json_val));
             }
             Ok(conditions.join(" AND "))
         }
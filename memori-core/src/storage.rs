@@ -1,10 +1,17 @@
-use rusqlite::params;
+use rusqlite::{params, OptionalExtension};
 use serde_json::Value;
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::types::{InsertResult, Memory, MemoriError, Result, SortField};
-use crate::util::{blob_to_vec, cosine_similarity, vec_to_blob};
+use crate::types::{
+    ArrayMerge, Attachment, AttachmentInfo, CheckpointMode, CheckpointResult, DedupScope, FtsReport,
+    GetOrInsert, HistoryEntry, InsertResult, ListSnapshot, Memory, MemoriConfig, MemoriError,
+    MergeStrategy, PatchOp, RawRow, Result, SortDirection, SortField, StoreStats,
+    TokenBudgetBehavior, VectorPrecision,
+};
+use crate::trace::span;
+use crate::util::{blob_to_vec, cosine_similarity, l2_normalize, vec_to_blob, vector_norm};
 
 fn now() -> f64 {
     SystemTime::now()
@@ -15,113 +22,674 @@ fn now() -> f64 {
 
 /// Auto-generate an embedding for content if no explicit vector is provided.
 /// Returns the vector to use (either the explicit one or the auto-generated one).
-fn auto_embed(content: &str, vector: Option<&[f32]>) -> Option<Vec<f32>> {
-    if vector.is_some() {
+/// Empty content is never embedded: vector-only memories keep what they have.
+fn auto_embed(config: &MemoriConfig, content: &str, vector: Option<&[f32]>) -> Option<Vec<f32>> {
+    if vector.is_some() || content.is_empty() {
         return None; // caller already has a vector, use it directly
     }
 
-    #[cfg(feature = "embeddings")]
+    #[cfg(any(feature = "embeddings", feature = "mock_embeddings"))]
     {
-        Some(crate::embed::embed_text(content))
+        let mut v = crate::embed::embed_text(&config.embed_preprocess.apply(content));
+        if config.normalize_embeddings {
+            l2_normalize(&mut v);
+        }
+        Some(v)
     }
 
-    #[cfg(not(feature = "embeddings"))]
+    #[cfg(not(any(feature = "embeddings", feature = "mock_embeddings")))]
     {
-        let _ = content;
+        let _ = (config, content);
         None
     }
 }
 
-/// Find a duplicate memory by cosine similarity against existing memories of the same type.
-/// Returns the ID of the best match if similarity exceeds the threshold.
+/// The embedding `insert` would generate for `content`, after
+/// `embed_preprocess` and `normalize_embeddings`. Needs no connection, so the
+/// model can run off the write path; hand the result to `insert_embedded`.
+/// `None` for empty content or without an embedding feature.
+pub fn auto_embedding(config: &MemoriConfig, content: &str) -> Option<Vec<f32>> {
+    auto_embed(config, content, None)
+}
+
+/// Apply `normalize_vectors` to a caller-supplied vector, borrowing when no
+/// copy is needed.
+fn explicit_vector<'a>(config: &MemoriConfig, vector: Option<&'a [f32]>) -> Option<Cow<'a, [f32]>> {
+    vector.map(|v| {
+        if config.normalize_vectors {
+            let mut owned = v.to_vec();
+            l2_normalize(&mut owned);
+            Cow::Owned(owned)
+        } else {
+            Cow::Borrowed(v)
+        }
+    })
+}
+
+/// SQL value `json_extract` yields for a metadata value, so scope keys can be
+/// compared with `IS`.
+fn json_extract_value(value: Option<&Value>) -> rusqlite::types::Value {
+    use rusqlite::types::Value as Sql;
+    match value {
+        None | Some(Value::Null) => Sql::Null,
+        Some(Value::Bool(b)) => Sql::Integer(*b as i64),
+        Some(Value::Number(n)) => match n.as_i64() {
+            Some(i) => Sql::Integer(i),
+            None => Sql::Real(n.as_f64().unwrap_or(0.0)),
+        },
+        Some(Value::String(s)) => Sql::Text(s.clone()),
+        Some(other) => Sql::Text(other.to_string()),
+    }
+}
+
+/// Norm written to `vector_norm` alongside a vector.
+fn stored_norm(v: &[f32]) -> f64 {
+    vector_norm(v) as f64
+}
+
+/// Hash written to `content_hash` for exact deduplication.
+pub(crate) fn content_hash(content: &str) -> i64 {
+    crate::util::fnv1a_64(content.as_bytes()) as i64
+}
+
+/// Hash written to `embed_hash` for the text a vector was auto-embedded
+/// from, after `embed_preprocess`, so a changed rewrite forces a re-embed.
+fn embed_hash(config: &MemoriConfig, text: &str) -> i64 {
+    crate::util::fnv1a_64(config.embed_preprocess.apply(text).as_bytes()) as i64
+}
+
+/// Auto-embed `text` as the vector of `id`, unless its current vector was
+/// already auto-embedded from exactly this text.
+fn reembed(conn: &rusqlite::Connection, config: &MemoriConfig, id: &str, text: &str) -> Result<()> {
+    let hash = embed_hash(config, text);
+    let stored: Option<i64> = conn
+        .query_row(
+            "SELECT embed_hash FROM memories WHERE id = ?1 AND vector IS NOT NULL",
+            params![id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .flatten();
+    if stored == Some(hash) {
+        return Ok(());
+    }
+    if let Some(v) = auto_embed(config, text, None) {
+        conn.execute(
+            "UPDATE memories SET vector = ?1, vector_norm = ?2, embed_hash = ?3 WHERE id = ?4",
+            params![encode_vector(config, &v), stored_norm(&v), hash, id],
+        )?;
+    }
+    Ok(())
+}
+
+/// Append `config.dedup_scope`'s conditions for the incoming `metadata` to a
+/// candidate query, binding their values after those already in `bind`.
+fn push_dedup_scope(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    metadata: Option<&Value>,
+    sql: &mut String,
+    bind: &mut Vec<rusqlite::types::Value>,
+) -> Result<()> {
+    let field = |key: &str| metadata.and_then(|m| m.get(key));
+    let column = crate::search::metadata_column(conn, "memories")?;
+    match &config.dedup_scope {
+        DedupScope::Global => {}
+        DedupScope::Type => {
+            if let Some(t) = field("type").and_then(|t| t.as_str()) {
+                bind.push(t.to_string().into());
+                sql.push_str(&format!(" AND json_extract({}, '$.type') = ?{}", column, bind.len()));
+            }
+        }
+        DedupScope::Keys(keys) => {
+            for key in keys {
+                if !crate::search::is_valid_filter_key(key) {
+                    return Err(MemoriError::InvalidFilter(format!(
+                        "dedup_scope key '{}' must match [a-zA-Z_][a-zA-Z0-9_]*",
+                        key
+                    )));
+                }
+                bind.push(json_extract_value(field(key)));
+                sql.push_str(&format!(
+                    " AND json_extract({}, '$.{}') IS ?{}",
+                    column,
+                    key,
+                    bind.len()
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Find a duplicate memory by cosine similarity against the existing memories
+/// `config.dedup_scope` selects for the incoming `metadata`.
+/// Returns the ID of the best match if similarity exceeds the threshold, or
+/// the first match reaching `config.dedup_sure_match` when that is set.
 pub fn find_duplicate(
     conn: &rusqlite::Connection,
+    config: &MemoriConfig,
     content_vector: &[f32],
-    type_filter: Option<&str>,
+    metadata: Option<&Value>,
     threshold: f32,
 ) -> Result<Option<String>> {
-    let (sql, has_param) = match type_filter {
-        Some(_) => (
-            "SELECT id, vector FROM memories WHERE json_extract(metadata, '$.type') = ?1 AND vector IS NOT NULL",
-            true,
-        ),
-        None => (
-            "SELECT id, vector FROM memories WHERE vector IS NOT NULL",
-            false,
-        ),
-    };
+    let span = span!("find_duplicate");
+    let mut sql = String::from(
+        "SELECT id, vector FROM memories WHERE collection = ?1 AND vector IS NOT NULL",
+    );
+    let mut bind: Vec<rusqlite::types::Value> = vec![config.collection().to_string().into()];
+    push_dedup_scope(conn, config, metadata, &mut sql, &mut bind)?;
 
-    let mut stmt = conn.prepare(sql)?;
-    let mut rows = if has_param {
-        stmt.query(params![type_filter.unwrap()])?
-    } else {
-        stmt.query([])?
-    };
+    let mut stmt = conn.prepare(&sql)?;
+    let mut rows = stmt.query(rusqlite::params_from_iter(bind))?;
 
     let mut best_id: Option<String> = None;
     let mut best_sim: f32 = threshold;
+    let mut candidates = 0u64;
 
     while let Some(row) = rows.next()? {
+        candidates += 1;
         let id: String = row.get(0)?;
         let blob: Vec<u8> = row.get(1)?;
-        let vec = blob_to_vec(&blob);
+        let vec = decode_vector(config, &blob);
         let sim = cosine_similarity(content_vector, &vec);
         if sim > best_sim {
             best_sim = sim;
             best_id = Some(id);
+            if config.dedup_sure_match.is_some_and(|sure| sim >= sure) {
+                break;
+            }
         }
     }
 
+    span.record("candidates", candidates);
+    span.record_bool("dedup", best_id.is_some());
     Ok(best_id)
 }
 
+/// The id of the oldest memory whose content is exactly `content`, among the
+/// memories `config.dedup_scope` selects. Looks up the `content_hash` index,
+/// then compares the content itself.
+pub fn find_exact_duplicate(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    content: &str,
+    metadata: Option<&Value>,
+) -> Result<Option<String>> {
+    find_hash_duplicate(conn, config, content_hash(content), Some(content), metadata)
+}
+
+/// Like `find_exact_duplicate` for a stored `content_hash` of `hash`; with
+/// `content` unset, the hash alone decides.
+fn find_hash_duplicate(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    hash: i64,
+    content: Option<&str>,
+    metadata: Option<&Value>,
+) -> Result<Option<String>> {
+    let mut sql = String::from(
+        "SELECT id FROM memories WHERE collection = ?1 AND content_hash = ?2 AND content_ref IS NULL",
+    );
+    let mut bind: Vec<rusqlite::types::Value> = vec![config.collection().to_string().into(), hash.into()];
+    if let Some(content) = content {
+        sql.push_str(" AND content = ?3");
+        bind.push(content.to_string().into());
+    }
+    push_dedup_scope(conn, config, metadata, &mut sql, &mut bind)?;
+    sql.push_str(" ORDER BY rowid LIMIT 1");
+    Ok(conn
+        .query_row(&sql, rusqlite::params_from_iter(bind), |row| row.get(0))
+        .optional()?)
+}
+
+/// Apply `MemoriConfig::token_budget` to text about to be written: `Err`
+/// under `TokenBudgetBehavior::Error`, else the warning to hand to
+/// `token_warning` once the row has an id.
+fn check_token_budget(config: &MemoriConfig, text: &str) -> Result<Option<String>> {
+    let Some(budget) = config.token_budget else {
+        return Ok(None);
+    };
+    let estimate = crate::embed::estimate_tokens(text);
+    if estimate <= budget {
+        return Ok(None);
+    }
+    match config.token_budget_behavior {
+        TokenBudgetBehavior::Error => Err(MemoriError::OverTokenBudget(estimate, budget)),
+        TokenBudgetBehavior::Warn => Ok(Some(format!(
+            "content is an estimated {} tokens, over the budget of {}; its embedding may be truncated",
+            estimate, budget
+        ))),
+    }
+}
+
+fn warn_token_budget(config: &MemoriConfig, id: &str, warning: Option<String>) {
+    if let (Some(hook), Some(msg)) = (&config.token_warning, warning) {
+        (hook.0)(id, &msg);
+    }
+}
+
+/// The metadata a duplicate insert leaves on `dup_id`: the incoming
+/// metadata merged over the stored one, arrays combined per
+/// `config.array_merge`.
+fn dedup_metadata(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    dup_id: &str,
+    incoming: Option<Value>,
+) -> Result<Option<Value>> {
+    let Some(incoming) = incoming else {
+        return Ok(None);
+    };
+    let stored = get_stored(conn, config, dup_id)?.and_then(|(m, _)| m.metadata);
+    Ok(Some(match stored {
+        Some(existing) => merge_json(&existing, &incoming, config.array_merge),
+        None => incoming,
+    }))
+}
+
 pub fn insert(
     conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    content: &str,
+    vector: Option<&[f32]>,
+    metadata: Option<Value>,
+    dedup_threshold: Option<f32>,
+    no_embed: bool,
+) -> Result<InsertResult> {
+    insert_hashed(conn, config, content, None, vector, metadata, dedup_threshold, no_embed)
+}
+
+/// Like `insert`, storing `supplied_hash` (see `util::parse_content_hash`) as
+/// the content hash instead of hashing `content`. Exact dedup then trusts
+/// it: any memory stored with the same hash is a duplicate.
+#[allow(clippy::too_many_arguments)]
+pub fn insert_hashed(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    content: &str,
+    supplied_hash: Option<&str>,
+    vector: Option<&[f32]>,
+    metadata: Option<Value>,
+    dedup_threshold: Option<f32>,
+    no_embed: bool,
+) -> Result<InsertResult> {
+    insert_prepared(conn, config, content, supplied_hash, vector, None, metadata, dedup_threshold, no_embed)
+}
+
+/// Like `insert`, with `embedding` (from `auto_embedding` on the same
+/// content) standing in for the model call. It is stored as an auto-embedded
+/// vector, so `embed_hash` is set and later rewrites of unchanged text skip
+/// the model.
+pub fn insert_embedded(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
     content: &str,
+    embedding: Vec<f32>,
+    metadata: Option<Value>,
+    dedup_threshold: Option<f32>,
+) -> Result<InsertResult> {
+    insert_prepared(conn, config, content, None, None, Some(embedding), metadata, dedup_threshold, false)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn insert_prepared(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    content: &str,
+    supplied_hash: Option<&str>,
     vector: Option<&[f32]>,
+    embedding: Option<Vec<f32>>,
     metadata: Option<Value>,
     dedup_threshold: Option<f32>,
     no_embed: bool,
 ) -> Result<InsertResult> {
-    let id = uuid::Uuid::new_v4().to_string();
+    let span = span!("insert");
+    let supplied_hash = supplied_hash
+        .map(|hex| {
+            crate::util::parse_content_hash(hex)
+                .map(|h| h as i64)
+                .ok_or_else(|| MemoriError::InvalidHash(hex.to_string()))
+        })
+        .transpose()?;
+    let over_budget = check_token_budget(config, content)?;
+    let id = config.id_strategy.generate();
     let ts = now();
 
+    // Exact dedup runs first, so a repeat skips the embedding model entirely.
+    // Vector-only memories all share empty content, so they never match.
+    let exact_dup = match supplied_hash {
+        _ if !config.exact_dedup => None,
+        Some(hash) => find_hash_duplicate(conn, config, hash, None, metadata.as_ref())?,
+        None if content.is_empty() => None,
+        None => find_exact_duplicate(conn, config, content, metadata.as_ref())?,
+    };
+    if let Some(dup_id) = exact_dup {
+        match dedup_metadata(conn, config, &dup_id, metadata)? {
+            Some(meta) => update(conn, config, &dup_id, None, None, Some(meta), false)?,
+            None => {
+                conn.execute(
+                    "UPDATE memories SET updated_at = ?1 WHERE id = ?2",
+                    params![ts, dup_id],
+                )?;
+            }
+        }
+        span.record_bool("dedup", true);
+        return Ok(InsertResult::Deduplicated(dup_id));
+    }
+
     // Auto-embed if no explicit vector and not suppressed
+    let explicit = explicit_vector(config, vector);
     let auto_vec = if no_embed {
         None
     } else {
-        auto_embed(content, vector)
+        embedding.or_else(|| auto_embed(config, content, vector))
     };
-    let effective_vec = vector.or(auto_vec.as_deref());
+    let effective_vec = explicit.as_deref().or(auto_vec.as_deref());
 
     // Dedup check: if we have a vector and dedup is enabled, look for duplicates
     if let (Some(threshold), Some(vec)) = (dedup_threshold, effective_vec) {
-        let type_filter = metadata
-            .as_ref()
-            .and_then(|m| m.get("type"))
-            .and_then(|t| t.as_str());
-
-        if let Some(dup_id) = find_duplicate(conn, vec, type_filter, threshold)? {
+        if let Some(dup_id) = find_duplicate(conn, config, vec, metadata.as_ref(), threshold)? {
             // Update the existing memory instead of creating a new one
-            update(conn, &dup_id, Some(content), Some(vec), metadata, false)?;
+            let metadata = dedup_metadata(conn, config, &dup_id, metadata)?;
+            update(conn, config, &dup_id, Some(content), Some(vec), metadata, false)?;
+            span.record_bool("dedup", true);
             return Ok(InsertResult::Deduplicated(dup_id));
         }
     }
 
-    let vector_blob = effective_vec.map(vec_to_blob);
-    let metadata_str = metadata.map(|m| m.to_string());
+    let vector_blob = effective_vec.map(|v| encode_vector(config, v));
+    let norm = effective_vec.map(stored_norm);
+    let hash = auto_vec.as_ref().map(|_| embed_hash(config, content));
+    let (metadata_str, metadata_z) = metadata_columns(config, metadata.as_ref());
 
     conn.execute(
-        "INSERT INTO memories (id, content, vector, metadata, created_at, updated_at)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-        params![id, content, vector_blob, metadata_str, ts, ts],
+        "INSERT INTO memories (id, content, vector, metadata, created_at, updated_at, collection,
+                               vector_norm, embed_hash, content_hash, metadata_z)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        params![
+            id,
+            content,
+            vector_blob,
+            metadata_str,
+            ts,
+            ts,
+            config.collection(),
+            norm,
+            hash,
+            supplied_hash.unwrap_or_else(|| content_hash(content)),
+            metadata_z
+        ],
     )?;
 
+    span.record_bool("dedup", false);
+    warn_token_budget(config, &id, over_budget);
     Ok(InsertResult::Created(id))
 }
 
+/// Insert a memory whose body lives elsewhere: `reference` (e.g. a URI) is
+/// what reads return as `content`, while `summary` is what gets FTS-indexed,
+/// embedded, and hashed. No deduplication is attempted.
+pub fn insert_external(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    reference: &str,
+    summary: &str,
+    vector: Option<&[f32]>,
+    metadata: Option<Value>,
+    no_embed: bool,
+) -> Result<String> {
+    let over_budget = check_token_budget(config, summary)?;
+    let id = config.id_strategy.generate();
+    let ts = now();
+
+    let explicit = explicit_vector(config, vector);
+    let auto_vec = if no_embed {
+        None
+    } else {
+        auto_embed(config, summary, vector)
+    };
+    let effective_vec = explicit.as_deref().or(auto_vec.as_deref());
+
+    let vector_blob = effective_vec.map(|v| encode_vector(config, v));
+    let norm = effective_vec.map(stored_norm);
+    let hash = auto_vec.as_ref().map(|_| embed_hash(config, summary));
+    let (metadata_str, metadata_z) = metadata_columns(config, metadata.as_ref());
+
+    conn.execute(
+        "INSERT INTO memories (id, content, vector, metadata, created_at, updated_at, collection,
+                               vector_norm, embed_hash, content_hash, metadata_z, content_ref)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        params![
+            id,
+            summary,
+            vector_blob,
+            metadata_str,
+            ts,
+            ts,
+            config.collection(),
+            norm,
+            hash,
+            content_hash(summary),
+            metadata_z,
+            reference
+        ],
+    )?;
+    warn_token_budget(config, &id, over_budget);
+    Ok(id)
+}
+
+/// Insert a memory represented by several chunk vectors (e.g. one per
+/// passage of a long document). The first is stored as the memory's own
+/// vector and the rest in `memory_chunks`; vector search scores the memory by
+/// its best chunk. No auto-embedding or deduplication.
+pub fn insert_chunked(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    content: &str,
+    vectors: &[Vec<f32>],
+    metadata: Option<Value>,
+) -> Result<String> {
+    let Some(first) = vectors.first() else {
+        return Err(MemoriError::InvalidVector("chunked insert needs at least one vector".to_string()));
+    };
+    if let Some(bad) = vectors.iter().find(|v| v.len() != first.len()) {
+        return Err(MemoriError::InvalidVector(format!(
+            "chunk vectors must share a dimension: {} vs {}",
+            first.len(),
+            bad.len()
+        )));
+    }
+    let over_budget = check_token_budget(config, content)?;
+    let id = config.id_strategy.generate();
+    let ts = now();
+    let (metadata_str, metadata_z) = metadata_columns(config, metadata.as_ref());
+
+    let tx = conn.unchecked_transaction()?;
+    for (i, v) in vectors.iter().enumerate() {
+        let v = explicit_vector(config, Some(v)).expect("vector given");
+        if i == 0 {
+            tx.execute(
+                "INSERT INTO memories (id, content, vector, metadata, created_at, updated_at, collection,
+                                       vector_norm, content_hash, metadata_z)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                params![
+                    id,
+                    content,
+                    encode_vector(config, &v),
+                    metadata_str,
+                    ts,
+                    ts,
+                    config.collection(),
+                    stored_norm(&v),
+                    content_hash(content),
+                    metadata_z
+                ],
+            )?;
+        } else {
+            tx.execute(
+                "INSERT INTO memory_chunks (memory_id, chunk_index, vector, vector_norm) VALUES (?1, ?2, ?3, ?4)",
+                params![id, i as i64, encode_vector(config, &v), stored_norm(&v)],
+            )?;
+        }
+    }
+    tx.commit()?;
+    warn_token_budget(config, &id, over_budget);
+    Ok(id)
+}
+
+/// Drop a memory's extra chunk vectors once its content or vector is
+/// replaced, since they described the old version.
+fn clear_chunks(conn: &rusqlite::Connection, id: &str) -> Result<()> {
+    conn.execute("DELETE FROM memory_chunks WHERE memory_id = ?1", params![id])?;
+    Ok(())
+}
+
+/// The indexed summary of an external-content memory (see
+/// `insert_external`), or `None` for an ordinary one.
+pub fn external_summary(conn: &rusqlite::Connection, config: &MemoriConfig, id: &str) -> Result<Option<String>> {
+    let row: Option<(String, Option<String>)> = conn
+        .query_row(
+            "SELECT content, content_ref FROM memories WHERE id = ?1 AND collection = ?2",
+            params![id, config.collection()],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    match row {
+        Some((summary, Some(_))) => Ok(Some(summary)),
+        Some((_, None)) => Ok(None),
+        None => Err(MemoriError::NotFound(id.to_string())),
+    }
+}
+
+/// Store `data` as attachment `name` on memory `id`, replacing any attachment
+/// of that name. `id` must be a full id in the collection.
+pub fn add_attachment(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    id: &str,
+    name: &str,
+    mime: &str,
+    data: &[u8],
+) -> Result<()> {
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM memories WHERE id = ?1 AND collection = ?2)",
+        params![id, config.collection()],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        return Err(MemoriError::NotFound(id.to_string()));
+    }
+    conn.execute(
+        "INSERT OR REPLACE INTO attachments (memory_id, name, mime, data, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![id, name, mime, data, now()],
+    )?;
+    Ok(())
+}
+
+pub fn get_attachment(conn: &rusqlite::Connection, id: &str, name: &str) -> Result<Option<Attachment>> {
+    let attachment = conn
+        .query_row(
+            "SELECT memory_id, name, mime, data, created_at FROM attachments
+             WHERE memory_id = ?1 AND name = ?2",
+            params![id, name],
+            |row| {
+                Ok(Attachment {
+                    memory_id: row.get(0)?,
+                    name: row.get(1)?,
+                    mime: row.get(2)?,
+                    data: row.get(3)?,
+                    created_at: row.get(4)?,
+                })
+            },
+        )
+        .optional()?;
+    Ok(attachment)
+}
+
+/// Memory `id`'s attachments by name, without their data.
+pub fn list_attachments(conn: &rusqlite::Connection, id: &str) -> Result<Vec<AttachmentInfo>> {
+    let mut stmt = conn.prepare(
+        "SELECT name, mime, length(data), created_at FROM attachments
+         WHERE memory_id = ?1 ORDER BY name",
+    )?;
+    let infos = stmt
+        .query_map(params![id], |row| {
+            Ok(AttachmentInfo {
+                name: row.get(0)?,
+                mime: row.get(1)?,
+                size: row.get::<_, i64>(2)? as usize,
+                created_at: row.get(3)?,
+            })
+        })?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    Ok(infos)
+}
+
+/// Return the oldest memory in the collection whose metadata `key` equals
+/// `value`, or insert one with `key` set in its metadata, in one write
+/// transaction, so concurrent callers agree on a single memory.
+/// Found memories are returned as stored, without counting an access.
+pub fn get_or_insert_by_metadata(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    key: &str,
+    value: &Value,
+    content: &str,
+    vector: Option<&[f32]>,
+    metadata: Option<Value>,
+) -> Result<GetOrInsert> {
+    let mut key_filter = serde_json::Map::new();
+    key_filter.insert(key.to_string(), value.clone());
+    let clause = crate::search::build_filter_clause(&Value::Object(key_filter), &crate::search::metadata_column(conn, "memories")?)?;
+
+    // Take the write lock up front so another handle can't insert the same
+    // key between the lookup and the insert.
+    let tx = rusqlite::Transaction::new_unchecked(conn, rusqlite::TransactionBehavior::Immediate)?;
+    let existing: Option<String> = tx
+        .query_row(
+            &format!(
+                "SELECT id FROM memories WHERE collection = ?1 AND {} ORDER BY rowid LIMIT 1",
+                clause
+            ),
+            params![config.collection()],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    let (id, created) = match existing {
+        Some(id) => (id, false),
+        None => {
+            let mut meta = match metadata {
+                Some(Value::Object(map)) => map,
+                None => serde_json::Map::new(),
+                Some(_) => {
+                    return Err(MemoriError::InvalidFilter(format!(
+                        "metadata must be an object to carry key '{}'",
+                        key
+                    )))
+                }
+            };
+            meta.insert(key.to_string(), value.clone());
+            let result = insert(&tx, config, content, vector, Some(Value::Object(meta)), None, false)?;
+            (result.id().to_string(), true)
+        }
+    };
+
+    let mem = get_raw(&tx, config, &id)?.ok_or_else(|| MemoriError::NotFound(id.clone()))?;
+    tx.commit()?;
+    Ok(if created {
+        GetOrInsert::Created(mem)
+    } else {
+        GetOrInsert::Found(mem)
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn insert_with_id(
     conn: &rusqlite::Connection,
+    config: &MemoriConfig,
     id: &str,
     content: &str,
     vector: Option<&[f32]>,
@@ -129,32 +697,67 @@ pub fn insert_with_id(
     created_at: f64,
     updated_at: f64,
 ) -> Result<String> {
+    let over_budget = check_token_budget(config, content)?;
+    write_with_id(conn, config, id, content, vector, metadata, created_at, updated_at)?;
+    warn_token_budget(config, id, over_budget);
+    Ok(id.to_string())
+}
+
+/// `insert_with_id` without the token budget, for restoring a memory as it
+/// was exported.
+#[allow(clippy::too_many_arguments)]
+fn write_with_id(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    id: &str,
+    content: &str,
+    vector: Option<&[f32]>,
+    metadata: Option<Value>,
+    created_at: f64,
+    updated_at: f64,
+) -> Result<()> {
     // Auto-embed if no explicit vector
-    let auto_vec = auto_embed(content, vector);
-    let effective_vec = vector.or(auto_vec.as_deref());
+    let explicit = explicit_vector(config, vector);
+    let auto_vec = auto_embed(config, content, vector);
+    let effective_vec = explicit.as_deref().or(auto_vec.as_deref());
 
-    let vector_blob = effective_vec.map(vec_to_blob);
-    let metadata_str = metadata.map(|m| m.to_string());
+    let vector_blob = effective_vec.map(|v| encode_vector(config, v));
+    let norm = effective_vec.map(stored_norm);
+    let hash = auto_vec.as_ref().map(|_| embed_hash(config, content));
+    let (metadata_str, metadata_z) = metadata_columns(config, metadata.as_ref());
 
     conn.execute(
-        "INSERT INTO memories (id, content, vector, metadata, created_at, updated_at)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-        params![id, content, vector_blob, metadata_str, created_at, updated_at],
+        "INSERT INTO memories (id, content, vector, metadata, created_at, updated_at, collection,
+                               vector_norm, embed_hash, content_hash, metadata_z)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        params![
+            id,
+            content,
+            vector_blob,
+            metadata_str,
+            created_at,
+            updated_at,
+            config.collection(),
+            norm,
+            hash,
+            content_hash(content),
+            metadata_z
+        ],
     )?;
 
-    Ok(id.to_string())
+    Ok(())
 }
 
-pub fn get(conn: &rusqlite::Connection, id: &str) -> Result<Option<Memory>> {
+pub fn get(conn: &rusqlite::Connection, config: &MemoriConfig, id: &str) -> Result<Option<Memory>> {
     let mut stmt = conn.prepare(
-        "SELECT id, content, vector, metadata, created_at, updated_at, last_accessed, access_count
+        "SELECT id, COALESCE(content_ref, content), vector, COALESCE(metadata_z, metadata), created_at, updated_at, last_accessed, access_count
          FROM memories WHERE id = ?1",
     )?;
 
     let mut rows = stmt.query(params![id])?;
     match rows.next()? {
         Some(row) => {
-            let mem = row_to_memory(row)?;
+            let mem = row_to_memory(row, config)?;
             // Touch on access
             let _ = touch(conn, id);
             Ok(Some(mem))
@@ -163,21 +766,33 @@ pub fn get(conn: &rusqlite::Connection, id: &str) -> Result<Option<Memory>> {
     }
 }
 
-/// Deep-merge two JSON values. For objects, recursively merge keys.
-/// For other types, `overlay` replaces `base`.
-fn merge_json(base: &Value, overlay: &Value) -> Value {
-    match (base, overlay) {
-        (Value::Object(base_map), Value::Object(overlay_map)) => {
+/// Deep-merge two JSON values. For objects, recursively merge keys; arrays
+/// combine per `arrays`. For other types, `overlay` replaces `base`.
+fn merge_json(base: &Value, overlay: &Value, arrays: ArrayMerge) -> Value {
+    match (base, overlay, arrays) {
+        (Value::Object(base_map), Value::Object(overlay_map), _) => {
             let mut merged = base_map.clone();
             for (key, val) in overlay_map {
                 let merged_val = match merged.get(key) {
-                    Some(existing) => merge_json(existing, val),
+                    Some(existing) => merge_json(existing, val, arrays),
                     None => val.clone(),
                 };
                 merged.insert(key.clone(), merged_val);
             }
             Value::Object(merged)
         }
+        (Value::Array(base_items), Value::Array(overlay_items), ArrayMerge::Union) => {
+            let mut merged: Vec<Value> = Vec::with_capacity(base_items.len() + overlay_items.len());
+            for item in base_items.iter().chain(overlay_items) {
+                if !merged.contains(item) {
+                    merged.push(item.clone());
+                }
+            }
+            Value::Array(merged)
+        }
+        (Value::Array(base_items), Value::Array(overlay_items), ArrayMerge::Append) => {
+            Value::Array(base_items.iter().chain(overlay_items).cloned().collect())
+        }
         _ => overlay.clone(),
     }
 }
@@ -203,138 +818,870 @@ fn metadata_values_text(metadata: &Value) -> String {
 
 pub fn update(
     conn: &rusqlite::Connection,
+    config: &MemoriConfig,
     id: &str,
     content: Option<&str>,
     vector: Option<&[f32]>,
     metadata: Option<Value>,
     merge_metadata: bool,
 ) -> Result<()> {
-    let existing = get_raw(conn, id)?;
-    let existing = existing.ok_or_else(|| MemoriError::NotFound(id.to_string()))?;
+    let (existing, _) = get_stored(conn, config, id)?.ok_or_else(|| MemoriError::NotFound(id.to_string()))?;
+    let over_budget = content.map(|c| check_token_budget(config, c)).transpose()?.flatten();
+    let explicit = explicit_vector(config, vector);
+    let vector = explicit.as_deref();
 
     let ts = now();
+    if content.is_some() || metadata.is_some() {
+        record_history(conn, config, id, ts)?;
+    }
+
+    if content.is_some() || vector.is_some() {
+        clear_chunks(conn, id)?;
+    }
 
     if let Some(content) = content {
         conn.execute(
-            "UPDATE memories SET content = ?1, updated_at = ?2 WHERE id = ?3",
-            params![content, ts, id],
+            "UPDATE memories SET content = ?1, content_hash = ?2, content_ref = NULL, updated_at = ?3
+             WHERE id = ?4",
+            params![content, content_hash(content), ts, id],
         )?;
 
         // Re-embed if content changes and no explicit vector provided
         if vector.is_none() {
-            let auto_vec = auto_embed(content, None);
-            if let Some(v) = auto_vec {
-                let blob = vec_to_blob(&v);
-                conn.execute(
-                    "UPDATE memories SET vector = ?1 WHERE id = ?2",
-                    params![blob, id],
-                )?;
-            }
+            reembed(conn, config, id, content)?;
         }
     }
 
     if let Some(v) = vector {
-        let blob = vec_to_blob(v);
+        let blob = encode_vector(config, v);
         conn.execute(
-            "UPDATE memories SET vector = ?1, updated_at = ?2 WHERE id = ?3",
-            params![blob, ts, id],
+            "UPDATE memories SET vector = ?1, vector_norm = ?2, embed_hash = NULL, updated_at = ?3
+             WHERE id = ?4",
+            params![blob, stored_norm(v), ts, id],
         )?;
     }
 
     if let Some(new_meta) = metadata {
         let final_meta = if merge_metadata {
             match &existing.metadata {
-                Some(existing_meta) => merge_json(existing_meta, &new_meta),
+                Some(existing_meta) => merge_json(existing_meta, &new_meta, ArrayMerge::Replace),
                 None => new_meta,
             }
         } else {
             new_meta
         };
 
-        let json_str = final_meta.to_string();
+        let (json_str, metadata_z) = metadata_columns(config, Some(&final_meta));
         conn.execute(
-            "UPDATE memories SET metadata = ?1, updated_at = ?2 WHERE id = ?3",
-            params![json_str, ts, id],
+            "UPDATE memories SET metadata = ?1, metadata_z = ?2, updated_at = ?3 WHERE id = ?4",
+            params![json_str, metadata_z, ts, id],
         )?;
 
         // Re-embed when metadata changes so vector search finds tagged content.
         // FTS5 triggers already handle text search via the update trigger, but
-        // the vector embedding needs explicit regeneration.
-        if vector.is_none() {
-            // Use current content (possibly just updated above)
-            let current_content = content.map(|s| s.to_string()).unwrap_or(existing.content);
+        // the vector embedding needs explicit regeneration. A vector-only
+        // memory (empty content) keeps its vector instead.
+        // Use current content (possibly just updated above)
+        let current_content = content.map(|s| s.to_string()).unwrap_or(existing.content);
+        if vector.is_none() && !current_content.is_empty() {
             let meta_text = metadata_values_text(&final_meta);
             let embed_text = if meta_text.is_empty() {
                 current_content
             } else {
-                format!("{} {}", current_content, meta_text)
+                format!("{}{}{}", current_content, config.text_separator(), meta_text)
             };
-            let auto_vec = auto_embed(&embed_text, None);
-            if let Some(v) = auto_vec {
-                let blob = vec_to_blob(&v);
-                conn.execute(
-                    "UPDATE memories SET vector = ?1 WHERE id = ?2",
-                    params![blob, id],
-                )?;
-            }
+            reembed(conn, config, id, &embed_text)?;
         }
     }
 
+    warn_token_budget(config, id, over_budget);
     Ok(())
 }
 
-/// Raw get without touching access count (avoids infinite recursion in update path)
-pub fn get_raw(conn: &rusqlite::Connection, id: &str) -> Result<Option<Memory>> {
-    let mut stmt = conn.prepare(
-        "SELECT id, content, vector, metadata, created_at, updated_at, last_accessed, access_count
-         FROM memories WHERE id = ?1",
-    )?;
+/// Split a JSON pointer into unescaped reference tokens (`~1` -> `/`, `~0` -> `~`).
+fn pointer_tokens(path: &str) -> Result<Vec<String>> {
+    if path.is_empty() {
+        return Ok(Vec::new());
+    }
+    if !path.starts_with('/') {
+        return Err(MemoriError::InvalidPatch(format!(
+            "path '{}' must be empty or start with '/'",
+            path
+        )));
+    }
+    Ok(path[1..]
+        .split('/')
+        .map(|t| t.replace("~1", "/").replace("~0", "~"))
+        .collect())
+}
 
-    let mut rows = stmt.query(params![id])?;
-    match rows.next()? {
-        Some(row) => Ok(Some(row_to_memory(row)?)),
-        None => Ok(None),
+fn array_index(token: &str, len: usize, allow_end: bool, path: &str) -> Result<usize> {
+    if allow_end && token == "-" {
+        return Ok(len);
     }
+    let idx: usize = token
+        .parse()
+        .map_err(|_| MemoriError::InvalidPatch(format!("'{}' is not an array index in '{}'", token, path)))?;
+    let in_bounds = if allow_end { idx <= len } else { idx < len };
+    if !in_bounds {
+        return Err(MemoriError::InvalidPatch(format!("index {} out of bounds in '{}'", idx, path)));
+    }
+    Ok(idx)
 }
 
-pub fn touch(conn: &rusqlite::Connection, id: &str) -> Result<()> {
+/// Apply one patch operation to `doc` in place.
+fn apply_patch_op(doc: &mut Value, op: &PatchOp) -> Result<()> {
+    let (path, value) = match op {
+        PatchOp::Add { path, value } | PatchOp::Replace { path, value } => (path, Some(value)),
+        PatchOp::Remove { path } => (path, None),
+    };
+    let mut tokens = pointer_tokens(path)?;
+
+    let last = match tokens.pop() {
+        Some(t) => t,
+        None => {
+            // Root pointer: add/replace swap the whole document
+            return match value {
+                Some(v) => {
+                    *doc = v.clone();
+                    Ok(())
+                }
+                None => Err(MemoriError::InvalidPatch("cannot remove the root".to_string())),
+            };
+        }
+    };
+
+    let mut parent = &mut *doc;
+    for token in &tokens {
+        parent = match parent {
+            Value::Object(map) => map.get_mut(token),
+            Value::Array(arr) => {
+                let idx = array_index(token, arr.len(), false, path)?;
+                arr.get_mut(idx)
+            }
+            _ => None,
+        }
+        .ok_or_else(|| MemoriError::InvalidPatch(format!("path '{}' does not exist", path)))?;
+    }
+
+    let missing = || MemoriError::InvalidPatch(format!("path '{}' does not exist", path));
+    match (op, parent) {
+        (PatchOp::Add { value, .. }, Value::Object(map)) => {
+            map.insert(last, value.clone());
+        }
+        (PatchOp::Add { value, .. }, Value::Array(arr)) => {
+            let idx = array_index(&last, arr.len(), true, path)?;
+            arr.insert(idx, value.clone());
+        }
+        (PatchOp::Replace { value, .. }, Value::Object(map)) => {
+            let slot = map.get_mut(&last).ok_or_else(missing)?;
+            *slot = value.clone();
+        }
+        (PatchOp::Replace { value, .. }, Value::Array(arr)) => {
+            let idx = array_index(&last, arr.len(), false, path)?;
+            arr[idx] = value.clone();
+        }
+        (PatchOp::Remove { .. }, Value::Object(map)) => {
+            map.remove(&last).ok_or_else(missing)?;
+        }
+        (PatchOp::Remove { .. }, Value::Array(arr)) => {
+            let idx = array_index(&last, arr.len(), false, path)?;
+            arr.remove(idx);
+        }
+        _ => return Err(missing()),
+    }
+    Ok(())
+}
+
+/// Overwrite a memory's content, vector, and metadata in one UPDATE. The
+/// vector is taken as given (subject to `normalize_vectors`); `None`
+/// auto-embeds the new content, or clears the vector without the embeddings
+/// feature. `None` metadata clears it. `created_at` and access stats are kept.
+pub fn replace(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    id: &str,
+    content: &str,
+    vector: Option<&[f32]>,
+    metadata: Option<Value>,
+) -> Result<()> {
+    let over_budget = check_token_budget(config, content)?;
+    let explicit = explicit_vector(config, vector);
+    let auto_vec = auto_embed(config, content, vector);
+    let effective_vec = explicit.as_deref().or(auto_vec.as_deref());
+    let hash = auto_vec.as_ref().map(|_| embed_hash(config, content));
+    let (metadata_str, metadata_z) = metadata_columns(config, metadata.as_ref());
     let ts = now();
-    conn.execute(
-        "UPDATE memories SET last_accessed = ?1, access_count = access_count + 1 WHERE id = ?2",
-        params![ts, id],
+    record_history(conn, config, id, ts)?;
+
+    let changed = conn.execute(
+        "UPDATE memories SET content = ?1, content_hash = ?2, vector = ?3, vector_norm = ?4,
+                embed_hash = ?5, metadata = ?6, metadata_z = ?7, updated_at = ?8, content_ref = NULL
+         WHERE id = ?9 AND collection = ?10",
+        params![
+            content,
+            content_hash(content),
+            effective_vec.map(|v| encode_vector(config, v)),
+            effective_vec.map(stored_norm),
+            hash,
+            metadata_str,
+            metadata_z,
+            ts,
+            id,
+            config.collection()
+        ],
     )?;
+    if changed == 0 {
+        return Err(MemoriError::NotFound(id.to_string()));
+    }
+    clear_chunks(conn, id)?;
+    warn_token_budget(config, id, over_budget);
     Ok(())
 }
 
-pub fn delete(conn: &rusqlite::Connection, id: &str) -> Result<()> {
-    let affected = conn.execute("DELETE FROM memories WHERE id = ?1", params![id])?;
-    if affected == 0 {
-        return Err(MemoriError::NotFound(id.to_string()));
+/// Snapshot a memory's current content and metadata into `memory_history`
+/// before a change at `ts`, keeping the newest `config.history_limit`
+/// versions. Does nothing when the limit is 0 or the memory is missing.
+fn record_history(conn: &rusqlite::Connection, config: &MemoriConfig, id: &str, ts: f64) -> Result<()> {
+    if config.history_limit == 0 {
+        return Ok(());
+    }
+    let recorded = conn.execute(
+        "INSERT INTO memory_history (id, version, content, metadata, changed_at)
+         SELECT id, COALESCE((SELECT MAX(version) FROM memory_history WHERE id = ?1), 0) + 1,
+                COALESCE(content_ref, content), COALESCE(metadata_z, metadata), ?2
+         FROM memories WHERE id = ?1 AND collection = ?3",
+        params![id, ts, config.collection()],
+    )?;
+    if recorded > 0 {
+        conn.execute(
+            "DELETE FROM memory_history WHERE id = ?1
+               AND version <= (SELECT MAX(version) FROM memory_history WHERE id = ?1) - ?2",
+            params![id, config.history_limit as i64],
+        )?;
     }
     Ok(())
 }
 
-pub fn count(conn: &rusqlite::Connection) -> Result<usize> {
-    let c: i64 = conn.query_row("SELECT COUNT(*) FROM memories", [], |row| row.get(0))?;
-    Ok(c as usize)
+/// Recorded prior versions of `id`, newest first.
+pub fn history(conn: &rusqlite::Connection, config: &MemoriConfig, id: &str) -> Result<Vec<HistoryEntry>> {
+    let mut stmt = conn.prepare(
+        "SELECT version, content, metadata, changed_at FROM memory_history
+         WHERE id = ?1 ORDER BY version DESC",
+    )?;
+    let mut rows = stmt.query(params![id])?;
+    let mut entries = Vec::new();
+    while let Some(row) = rows.next()? {
+        entries.push(HistoryEntry {
+            version: row.get(0)?,
+            content: row.get(1)?,
+            metadata: read_metadata(config, id, row.get(2)?)?,
+            changed_at: row.get(3)?,
+        });
+    }
+    Ok(entries)
 }
 
-pub fn list(
+/// Apply RFC 6902-style operations to a memory's metadata. Ops are applied in
+/// order against a working copy and written back only if all succeed; the
+/// write goes through `update` so FTS triggers fire and the vector is re-embedded.
+/// A memory without metadata is patched as `{}`.
+pub fn patch_metadata(
     conn: &rusqlite::Connection,
-    type_filter: Option<&str>,
+    config: &MemoriConfig,
+    id: &str,
+    ops: &[PatchOp],
+) -> Result<()> {
+    let existing = get_raw(conn, config, id)?.ok_or_else(|| MemoriError::NotFound(id.to_string()))?;
+    let mut doc = existing
+        .metadata
+        .unwrap_or_else(|| Value::Object(serde_json::Map::new()));
+    for op in ops {
+        apply_patch_op(&mut doc, op)?;
+    }
+    update(conn, config, id, None, None, Some(doc), false)
+}
+
+/// Add `delta` to the number at top-level metadata `key` (a missing key
+/// counts as 0) and return the new value. A plain row whose result stays
+/// under `compress_metadata_over` changes in a single `json_set` UPDATE.
+/// Anything else (compressed rows, rows about to compress, and every error)
+/// goes through `metadata_columns` inside an IMMEDIATE transaction, so
+/// concurrent handles queue on the write lock instead of failing. Integers
+/// stay integers when `delta` is whole. Like an access bump, this doesn't
+/// re-embed the memory.
+pub fn increment_metadata_number(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    id: &str,
+    key: &str,
+    delta: f64,
+) -> Result<f64> {
+    use rusqlite::types::Value as SqlValue;
+    if !crate::search::is_valid_filter_key(key) {
+        return Err(MemoriError::InvalidPatch(format!(
+            "metadata key '{}' must match [a-zA-Z_][a-zA-Z0-9_]*",
+            key
+        )));
+    }
+    if !delta.is_finite() {
+        return Err(MemoriError::InvalidPatch(format!("delta {} is not finite", delta)));
+    }
+    let whole = delta.fract() == 0.0 && delta.abs() < 9_007_199_254_740_992.0;
+    let delta_sql = if whole {
+        SqlValue::Integer(delta as i64)
+    } else {
+        SqlValue::Real(delta)
+    };
+    let limit = if cfg!(feature = "compress_metadata") {
+        config.compress_metadata_over.map(|l| l as i64)
+    } else {
+        None
+    };
+
+    // CASE, not AND, so json_type never sees malformed JSON
+    let plain: Option<f64> = conn
+        .query_row(
+            "UPDATE memories
+             SET metadata = json_set(COALESCE(metadata, '{}'), ?2,
+                                     COALESCE(json_extract(metadata, ?2), 0) + ?3),
+                 updated_at = ?4
+             WHERE id = ?1 AND metadata_z IS NULL
+               AND CASE WHEN metadata IS NULL THEN 1
+                        WHEN NOT json_valid(metadata) THEN 0
+                        WHEN json_type(metadata) != 'object' THEN 0
+                        ELSE COALESCE(json_type(metadata, ?2), 'null') IN ('integer', 'real', 'null')
+                   END
+               AND (?5 IS NULL
+                    OR length(json_set(COALESCE(metadata, '{}'), ?2,
+                                       COALESCE(json_extract(metadata, ?2), 0) + ?3)) <= ?5)
+             RETURNING json_extract(metadata, ?2)",
+            params![id, format!("$.{}", key), delta_sql, now(), limit],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(value) = plain {
+        return Ok(value);
+    }
+
+    let tx = rusqlite::Transaction::new_unchecked(conn, rusqlite::TransactionBehavior::Immediate)?;
+    let raw: SqlValue = tx
+        .query_row(
+            "SELECT COALESCE(metadata_z, metadata) FROM memories WHERE id = ?1",
+            params![id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| MemoriError::NotFound(id.to_string()))?;
+    // Parsed strictly: unreadable metadata is an error, not an empty object
+    let mut doc = match raw {
+        SqlValue::Text(s) => serde_json::from_str(&s)?,
+        SqlValue::Blob(b) => serde_json::from_str(&inflate_metadata(&b)?)?,
+        _ => Value::Object(serde_json::Map::new()),
+    };
+    let map = doc
+        .as_object_mut()
+        .ok_or_else(|| MemoriError::InvalidPatch("metadata is not an object".to_string()))?;
+    let next = match map.get(key) {
+        None | Some(Value::Null) if whole => Value::from(delta as i64),
+        None | Some(Value::Null) => Value::from(delta),
+        Some(Value::Number(n)) => match (n.as_i64(), whole) {
+            (Some(i), true) => i
+                .checked_add(delta as i64)
+                .map(Value::from)
+                .unwrap_or_else(|| Value::from(i as f64 + delta)),
+            _ => Value::from(n.as_f64().unwrap_or(0.0) + delta),
+        },
+        Some(other) => {
+            return Err(MemoriError::InvalidPatch(format!(
+                "metadata '{}' is {}, not a number",
+                key,
+                json_type_name(other)
+            )))
+        }
+    };
+    let value = next.as_f64().unwrap_or(0.0);
+    map.insert(key.to_string(), next);
+    let (metadata_str, metadata_z) = metadata_columns(config, Some(&doc));
+    tx.execute(
+        "UPDATE memories SET metadata = ?1, metadata_z = ?2, updated_at = ?3 WHERE id = ?4",
+        params![metadata_str, metadata_z, now(), id],
+    )?;
+    tx.commit()?;
+    Ok(value)
+}
+
+fn json_type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "text",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+/// Fold `drop_id` into `keep_id` in one transaction: deep-merge metadata (the
+/// survivor wins on conflicting keys), optionally concatenate content, keep the
+/// higher access_count and later last_accessed, re-embed via `update`, then
+/// delete the dropped row.
+pub fn merge(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    keep_id: &str,
+    drop_id: &str,
+    strategy: MergeStrategy,
+) -> Result<()> {
+    if keep_id == drop_id {
+        return Err(MemoriError::InvalidMerge("cannot merge a memory into itself".to_string()));
+    }
+
+    let tx = conn.unchecked_transaction()?;
+    let (keep, keep_ref) =
+        get_stored(&tx, config, keep_id)?.ok_or_else(|| MemoriError::NotFound(keep_id.to_string()))?;
+    let (dropped, _) = get_stored(&tx, config, drop_id)?.ok_or_else(|| MemoriError::NotFound(drop_id.to_string()))?;
+
+    let content = match strategy {
+        MergeStrategy::KeepContent => None,
+        MergeStrategy::Concatenate => Some(format!("{}\n\n{}", keep.content, dropped.content)),
+    };
+    let metadata = match (&dropped.metadata, keep.metadata) {
+        (Some(d), Some(k)) => Some(merge_json(d, &k, config.array_merge)),
+        (d, k) => k.or_else(|| d.clone()),
+    };
+    update(&tx, config, keep_id, content.as_deref(), None, metadata, false)?;
+
+    // Concatenating joins summaries; an external survivor keeps its reference
+    tx.execute(
+        "UPDATE memories SET access_count = MAX(access_count, ?1),
+                             last_accessed = MAX(last_accessed, ?2),
+                             content_ref = ?4
+         WHERE id = ?3",
+        params![dropped.access_count, dropped.last_accessed, keep_id, keep_ref],
+    )?;
+    delete(&tx, drop_id)?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn union_find_root(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+/// Groups of ids whose vectors are near-duplicates of each other: within one
+/// metadata `type` (untyped memories form their own group), ids are joined
+/// when their cosine similarity exceeds `threshold`, transitively. Only groups
+/// of two or more are returned, each in insertion order.
+pub(crate) fn find_duplicate_clusters(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    threshold: f32,
+) -> Result<Vec<Vec<String>>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, vector, CAST(json_extract({}, '$.type') AS TEXT) FROM memories
+         WHERE collection = ?1 AND vector IS NOT NULL ORDER BY rowid",
+        crate::search::metadata_column(conn, "memories")?
+    ))?;
+    let mut by_type: HashMap<Option<String>, Vec<(String, Vec<f32>)>> = HashMap::new();
+    let mut rows = stmt.query(params![config.collection()])?;
+    while let Some(row) = rows.next()? {
+        let blob: Vec<u8> = row.get(1)?;
+        by_type
+            .entry(row.get(2)?)
+            .or_default()
+            .push((row.get(0)?, decode_vector(config, &blob)));
+    }
+
+    let mut clusters = Vec::new();
+    for group in by_type.into_values() {
+        // Union-find over the group, each root the earliest member
+        let mut parent: Vec<usize> = (0..group.len()).collect();
+        for i in 0..group.len() {
+            for j in i + 1..group.len() {
+                if cosine_similarity(&group[i].1, &group[j].1) > threshold {
+                    let (a, b) = (union_find_root(&mut parent, i), union_find_root(&mut parent, j));
+                    parent[a.max(b)] = a.min(b);
+                }
+            }
+        }
+        let mut members: HashMap<usize, Vec<String>> = HashMap::new();
+        for (i, (id, _)) in group.iter().enumerate() {
+            let r = union_find_root(&mut parent, i);
+            members.entry(r).or_default().push(id.clone());
+        }
+        let mut found: Vec<(usize, Vec<String>)> = members.into_iter().filter(|(_, ids)| ids.len() > 1).collect();
+        found.sort_by_key(|(r, _)| *r);
+        clusters.extend(found.into_iter().map(|(_, ids)| ids));
+    }
+    Ok(clusters)
+}
+
+/// Collapse every cluster from `find_duplicate_clusters` into its
+/// most-accessed member (earliest on ties), in one transaction. The survivor
+/// keeps its content, deep-merges the others' metadata under its own, sums
+/// their access counts and takes the latest `last_accessed`; the rest are
+/// deleted. Returns how many memories were merged away.
+pub fn dedup_existing(conn: &rusqlite::Connection, config: &MemoriConfig, threshold: f32) -> Result<usize> {
+    let tx = conn.unchecked_transaction()?;
+    let mut merged = 0;
+    for cluster in find_duplicate_clusters(&tx, config, threshold)? {
+        let mut members = Vec::with_capacity(cluster.len());
+        for id in &cluster {
+            members.push(get_stored(&tx, config, id)?.ok_or_else(|| MemoriError::NotFound(id.clone()))?.0);
+        }
+        // Stable sort keeps insertion order among equally accessed members
+        members.sort_by_key(|m| std::cmp::Reverse(m.access_count));
+        let (keep, rest) = members.split_first().expect("clusters have two or more members");
+
+        let mut metadata = keep.metadata.clone();
+        let mut access_count = keep.access_count;
+        let mut last_accessed = keep.last_accessed;
+        for dropped in rest {
+            metadata = match (&dropped.metadata, metadata) {
+                (Some(d), Some(k)) => Some(merge_json(d, &k, config.array_merge)),
+                (d, k) => k.or_else(|| d.clone()),
+            };
+            access_count += dropped.access_count;
+            last_accessed = last_accessed.max(dropped.last_accessed);
+        }
+        update(&tx, config, &keep.id, None, None, metadata, false)?;
+        tx.execute(
+            "UPDATE memories SET access_count = ?1, last_accessed = ?2 WHERE id = ?3",
+            params![access_count, last_accessed, keep.id],
+        )?;
+        for dropped in rest {
+            delete(&tx, &dropped.id)?;
+        }
+        merged += rest.len();
+    }
+    tx.commit()?;
+    Ok(merged)
+}
+
+/// Raw get without touching access count (avoids infinite recursion in update path)
+pub fn get_raw(conn: &rusqlite::Connection, config: &MemoriConfig, id: &str) -> Result<Option<Memory>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, COALESCE(content_ref, content), vector, COALESCE(metadata_z, metadata), created_at, updated_at, last_accessed, access_count
+         FROM memories WHERE id = ?1",
+    )?;
+
+    let mut rows = stmt.query(params![id])?;
+    match rows.next()? {
+        Some(row) => Ok(Some(row_to_memory(row, config)?)),
+        None => Ok(None),
+    }
+}
+
+/// Like `get_raw`, but `content` is the stored text (an external memory's
+/// indexed summary) with its `content_ref` alongside. Internal rewrites read
+/// this so they re-embed and snapshot what is indexed, not the reference.
+pub(crate) fn get_stored(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    id: &str,
+) -> Result<Option<(Memory, Option<String>)>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, content, vector, COALESCE(metadata_z, metadata), created_at, updated_at, last_accessed, access_count,
+                content_ref
+         FROM memories WHERE id = ?1",
+    )?;
+
+    let mut rows = stmt.query(params![id])?;
+    match rows.next()? {
+        Some(row) => Ok(Some((row_to_memory(row, config)?, row.get(8)?))),
+        None => Ok(None),
+    }
+}
+
+/// Read a row's stored columns without parsing or touching access stats.
+/// FTS presence is checked against the `memories_fts_docsize` shadow table,
+/// since selecting from an external-content FTS table reads the base table.
+pub fn inspect(conn: &rusqlite::Connection, id: &str) -> Result<Option<RawRow>> {
+    let fts_indexed = if crate::schema::has_fts(conn)? {
+        "EXISTS(SELECT 1 FROM memories_fts_docsize d WHERE d.id = memories.rowid)"
+    } else {
+        "0"
+    };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, rowid, content, metadata, length(vector), created_at, updated_at,
+                last_accessed, access_count, {}
+         FROM memories WHERE id = ?1",
+        fts_indexed
+    ))?;
+
+    let mut rows = stmt.query(params![id])?;
+    match rows.next()? {
+        Some(row) => {
+            let raw_metadata: Option<String> = row.get(3)?;
+            let metadata_valid = raw_metadata
+                .as_deref()
+                .map_or(true, |s| serde_json::from_str::<Value>(s).is_ok());
+            let vector_bytes: Option<i64> = row.get(4)?;
+            let content: String = row.get(2)?;
+            Ok(Some(RawRow {
+                id: row.get(0)?,
+                rowid: row.get(1)?,
+                estimated_tokens: crate::embed::estimate_tokens(&content),
+                content,
+                raw_metadata,
+                metadata_valid,
+                vector_bytes: vector_bytes.map(|n| n as usize),
+                created_at: row.get(5)?,
+                updated_at: row.get(6)?,
+                last_accessed: row.get(7)?,
+                access_count: row.get(8)?,
+                fts_indexed: row.get(9)?,
+            }))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Diff the FTS index against `memories` by rowid (via the docsize shadow
+/// table) and run FTS5's `integrity-check`. The `rank = 1` form of the check
+/// is not used: it compares against `memories.content` alone, while the
+/// triggers index content plus metadata, so it would always fail.
+pub fn verify_fts(conn: &rusqlite::Connection) -> Result<FtsReport> {
+    if !crate::schema::has_fts(conn)? {
+        return Err(MemoriError::FtsDisabled);
+    }
+    let index_ok = match conn.execute(
+        "INSERT INTO memories_fts(memories_fts) VALUES('integrity-check')",
+        [],
+    ) {
+        Ok(_) => true,
+        Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == rusqlite::ErrorCode::DatabaseCorrupt => {
+            false
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut stmt = conn.prepare(
+        "SELECT id FROM memories
+         WHERE rowid NOT IN (SELECT id FROM memories_fts_docsize)
+         ORDER BY rowid",
+    )?;
+    let missing = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id FROM memories_fts_docsize
+         WHERE id NOT IN (SELECT rowid FROM memories)
+         ORDER BY id",
+    )?;
+    let orphaned = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<i64>>>()?;
+
+    Ok(FtsReport { missing, orphaned, index_ok })
+}
+
+/// Reinstall the FTS triggers with `config.text_separator` and refill the
+/// index from `memories`, fixing any drift `verify_fts` reports. Splits a
+/// combined index under `config.split_fts`; a split one stays split. The
+/// separator is recorded in `memori_meta` for later handles.
+pub fn rebuild_fts(conn: &rusqlite::Connection, config: &MemoriConfig) -> Result<()> {
+    // Installing the triggers without the table would break every write
+    if !crate::schema::has_fts(conn)? {
+        return Err(MemoriError::FtsDisabled);
+    }
+    let tx = conn.unchecked_transaction()?;
+    let split = config.split_fts || crate::schema::has_split_fts(&tx)?;
+    crate::schema::rebuild_fts_with_layout(&tx, config.text_separator(), split)?;
+    crate::schema::set_meta(&tx, "text_separator", config.text_separator())?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Move a memory from the hot table into `memories_archive`, out of default
+/// search, list, and count. `id` must be a full id in the collection.
+pub fn archive(conn: &rusqlite::Connection, config: &MemoriConfig, id: &str) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    let moved = tx.execute(
+        "INSERT INTO memories_archive (id, content, vector, metadata, created_at, updated_at,
+                                       last_accessed, access_count, collection, archived_at,
+                                       vector_norm, embed_hash, content_hash, metadata_z,
+                                       content_ref)
+         SELECT id, content, vector, metadata, created_at, updated_at,
+                last_accessed, access_count, collection, ?3, vector_norm, embed_hash, content_hash,
+                metadata_z, content_ref
+         FROM memories WHERE id = ?1 AND collection = ?2",
+        params![id, config.collection(), now()],
+    )?;
+    if moved == 0 {
+        return Err(MemoriError::NotFound(id.to_string()));
+    }
+    tx.execute("DELETE FROM memories WHERE id = ?1", params![id])?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Move a memory from the hot table into `memories_deleted`, leaving a
+/// tombstone that only `include_deleted` searches and lists return. A
+/// tombstone already left under the same id is replaced.
+pub fn soft_delete(conn: &rusqlite::Connection, config: &MemoriConfig, id: &str) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    let moved = tx.execute(
+        "INSERT OR REPLACE INTO memories_deleted (id, content, vector, metadata, created_at, updated_at,
+                                                  last_accessed, access_count, collection, vector_norm,
+                                                  embed_hash, content_hash, metadata_z, deleted_at,
+                                                  content_ref)
+         SELECT id, content, vector, metadata, created_at, updated_at,
+                last_accessed, access_count, collection, vector_norm, embed_hash, content_hash,
+                metadata_z, ?3, content_ref
+         FROM memories WHERE id = ?1 AND collection = ?2",
+        params![id, config.collection(), now()],
+    )?;
+    if moved == 0 {
+        return Err(MemoriError::NotFound(id.to_string()));
+    }
+    tx.execute("DELETE FROM memories WHERE id = ?1", params![id])?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Set `deleted_at` on tombstones read from `memories_deleted`.
+pub(crate) fn fill_deleted_at(conn: &rusqlite::Connection, memories: &mut [Memory]) -> Result<()> {
+    let mut stmt = conn.prepare_cached("SELECT deleted_at FROM memories_deleted WHERE id = ?1")?;
+    for mem in memories.iter_mut() {
+        mem.deleted_at = stmt.query_row(params![mem.id], |row| row.get(0)).optional()?;
+    }
+    Ok(())
+}
+
+/// Move an archived memory back into the hot table (re-indexing it for FTS).
+/// `id_or_prefix` is resolved against the archive.
+pub fn unarchive(conn: &rusqlite::Connection, config: &MemoriConfig, id_or_prefix: &str) -> Result<String> {
+    let id = resolve_prefix_in(conn, config, "memories_archive", id_or_prefix)?;
+    let tx = conn.unchecked_transaction()?;
+    let moved = tx.execute(
+        "INSERT INTO memories (id, content, vector, metadata, created_at, updated_at,
+                               last_accessed, access_count, collection, vector_norm, embed_hash,
+                               content_hash, metadata_z, content_ref)
+         SELECT id, content, vector, metadata, created_at, updated_at,
+                last_accessed, access_count, collection, vector_norm, embed_hash, content_hash,
+                metadata_z, content_ref
+         FROM memories_archive WHERE id = ?1",
+        params![id],
+    )?;
+    if moved == 0 {
+        return Err(MemoriError::NotFound(id));
+    }
+    tx.execute("DELETE FROM memories_archive WHERE id = ?1", params![id])?;
+    tx.commit()?;
+    Ok(id)
+}
+
+pub fn archive_count(conn: &rusqlite::Connection, config: &MemoriConfig) -> Result<usize> {
+    let c: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM memories_archive WHERE collection = ?1",
+        params![config.collection()],
+        |row| row.get(0),
+    )?;
+    Ok(c as usize)
+}
+
+pub fn touch(conn: &rusqlite::Connection, id: &str) -> Result<()> {
+    let ts = now();
+    conn.execute(
+        "UPDATE memories SET last_accessed = ?1, access_count = access_count + 1 WHERE id = ?2",
+        params![ts, id],
+    )?;
+    Ok(())
+}
+
+/// Touch every id in `ids` in one statement (ids are bound as a JSON array).
+pub fn touch_many(conn: &rusqlite::Connection, ids: &[String]) -> Result<()> {
+    if ids.is_empty() {
+        return Ok(());
+    }
+    conn.execute(
+        "UPDATE memories SET last_accessed = ?1, access_count = access_count + 1
+         WHERE id IN (SELECT value FROM json_each(?2))",
+        params![now(), serde_json::to_string(ids)?],
+    )?;
+    Ok(())
+}
+
+pub fn delete(conn: &rusqlite::Connection, id: &str) -> Result<()> {
+    let affected = conn.execute("DELETE FROM memories WHERE id = ?1", params![id])?;
+    if affected == 0 {
+        return Err(MemoriError::NotFound(id.to_string()));
+    }
+    Ok(())
+}
+
+pub fn count(conn: &rusqlite::Connection, config: &MemoriConfig) -> Result<usize> {
+    let c: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM memories WHERE collection = ?1",
+        params![config.collection()],
+        |row| row.get(0),
+    )?;
+    Ok(c as usize)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn list(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    type_filter: Option<&str>,
     sort: &SortField,
     limit: usize,
     offset: usize,
     before: Option<f64>,
     after: Option<f64>,
+    include_deleted: bool,
+) -> Result<Vec<Memory>> {
+    let sorts = [(sort.clone(), SortDirection::Desc)];
+    list_sorted(conn, config, type_filter, &sorts, limit, offset, before, after, include_deleted)
+}
+
+/// Hot rows and tombstones under the hot table's name, with `deleted_at`
+/// NULL for hot rows, so list queries can read from either unchanged.
+const WITH_DELETED_SQL: &str = "(SELECT id, content, vector, metadata, metadata_z, created_at, updated_at,
+                last_accessed, access_count, collection, content_ref, NULL AS deleted_at FROM memories
+         UNION ALL
+         SELECT id, content, vector, metadata, metadata_z, created_at, updated_at,
+                last_accessed, access_count, collection, content_ref, deleted_at FROM memories_deleted)
+         AS memories";
+
+/// List with a multi-column `ORDER BY`, applied in the order given. `sorts`
+/// must be non-empty. `include_deleted` also lists soft-deleted memories,
+/// with `deleted_at` set.
+#[allow(clippy::too_many_arguments)]
+pub fn list_sorted(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    type_filter: Option<&str>,
+    sorts: &[(SortField, SortDirection)],
+    limit: usize,
+    offset: usize,
+    before: Option<f64>,
+    after: Option<f64>,
+    include_deleted: bool,
 ) -> Result<Vec<Memory>> {
+    if sorts.is_empty() {
+        return Err(MemoriError::InvalidSort("at least one sort field is required".to_string()));
+    }
     // Build WHERE conditions dynamically
     let mut conditions: Vec<String> = Vec::new();
     let mut param_values: Vec<Box<dyn rusqlite::types::ToSql>> = Vec::new();
 
+    param_values.push(Box::new(config.collection().to_string()));
+    conditions.push(format!("collection = ?{}", param_values.len()));
     if let Some(tf) = type_filter {
         param_values.push(Box::new(tf.to_string()));
-        conditions.push(format!("json_extract(metadata, '$.type') = ?{}", param_values.len()));
+        conditions.push(format!(
+            "json_extract({}, '$.type') = ?{}",
+            crate::search::metadata_column(conn, "memories")?,
+            param_values.len()
+        ));
     }
     if let Some(b) = before {
         conditions.push(format!("created_at < {}", b));
@@ -343,11 +1690,7 @@ pub fn list(
         conditions.push(format!("created_at > {}", a));
     }
 
-    let where_clause = if conditions.is_empty() {
-        String::new()
-    } else {
-        format!("WHERE {}", conditions.join(" AND "))
-    };
+    let where_clause = format!("WHERE {}", conditions.join(" AND "));
 
     // Limit and offset are the next positional params
     let limit_idx = param_values.len() + 1;
@@ -355,10 +1698,22 @@ pub fn list(
     param_values.push(Box::new(limit as i64));
     param_values.push(Box::new(offset as i64));
 
+    let order_by = sorts
+        .iter()
+        .map(|(field, dir)| format!("{} {}", field.sql_column(), dir.sql_keyword()))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let (source, deleted_at) = if include_deleted {
+        (WITH_DELETED_SQL, "deleted_at")
+    } else {
+        ("memories", "NULL")
+    };
     let sql = format!(
-        "SELECT id, content, vector, metadata, created_at, updated_at, last_accessed, access_count
-         FROM memories {} ORDER BY {} DESC LIMIT ?{} OFFSET ?{}",
-        where_clause, sort.sql_column(), limit_idx, offset_idx
+        "SELECT id, COALESCE(content_ref, content), vector, COALESCE(metadata_z, metadata), created_at, updated_at, last_accessed, access_count,
+                {}
+         FROM {} {} ORDER BY {} LIMIT ?{} OFFSET ?{}",
+        deleted_at, source, where_clause, order_by, limit_idx, offset_idx
     );
 
     let mut stmt = conn.prepare(&sql)?;
@@ -367,19 +1722,116 @@ pub fn list(
 
     let mut results = Vec::new();
     while let Some(row) = rows.next()? {
-        results.push(row_to_memory(row)?);
+        let mut mem = row_to_memory(row, config)?;
+        mem.deleted_at = row.get(8)?;
+        results.push(mem);
     }
     Ok(results)
 }
 
-pub fn type_distribution(conn: &rusqlite::Connection) -> Result<HashMap<String, usize>> {
+/// Up to `n` random memories in the collection matching the metadata
+/// `filter`, without touching access stats. With a `seed` the pick is
+/// deterministic: rows are ordered by an FNV-1a hash of the seed and id, so
+/// the same seed over the same rows gives the same sample.
+pub fn sample(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    n: usize,
+    filter: Option<&Value>,
+    seed: Option<u64>,
+) -> Result<Vec<Memory>> {
+    let mut where_clause = "collection = ?1".to_string();
+    if let Some(filter) = filter {
+        let clause = crate::search::build_filter_clause(filter, &crate::search::metadata_column(conn, "memories")?)?;
+        if clause != "1=1" {
+            where_clause = format!("{} AND {}", where_clause, clause);
+        }
+    }
+
+    let Some(seed) = seed else {
+        let sql = format!(
+            "SELECT id, COALESCE(content_ref, content), vector, COALESCE(metadata_z, metadata), created_at, updated_at, last_accessed, access_count
+             FROM memories WHERE {} ORDER BY RANDOM() LIMIT ?2",
+            where_clause
+        );
+        let mut stmt = conn.prepare(&sql)?;
+        let mut rows = stmt.query(params![config.collection(), n as i64])?;
+        let mut results = Vec::new();
+        while let Some(row) = rows.next()? {
+            results.push(row_to_memory(row, config)?);
+        }
+        return Ok(results);
+    };
+
+    let mut stmt = conn.prepare(&format!("SELECT id FROM memories WHERE {}", where_clause))?;
+    let mut ids = stmt
+        .query_map(params![config.collection()], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    let key = |id: &str| {
+        let mut bytes = seed.to_le_bytes().to_vec();
+        bytes.extend_from_slice(id.as_bytes());
+        crate::util::fnv1a_64(&bytes)
+    };
+    ids.sort_by_cached_key(|id| (key(id), id.clone()));
+    ids.truncate(n);
+    let mut results = Vec::with_capacity(ids.len());
+    for id in &ids {
+        results.extend(get_raw(conn, config, id)?);
+    }
+    Ok(results)
+}
+
+/// Memories in the collection after the cursor `(since, after_id)`, ordered
+/// by `(changed_at, id)`, where `changed_at` is `updated_at`, or `deleted_at`
+/// for a soft-deleted memory, which is reported with `deleted_at` set: rows
+/// changed after `since`, plus rows changed at exactly `since` whose id sorts
+/// after `after_id`. Without `after_id` the cursor is `changed_at > since`.
+/// Passing the last row's `changed_at` and id resumes exactly, even when rows
+/// tied on it straddle a page.
+pub fn changed_since(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    since: f64,
+    after_id: Option<&str>,
+    limit: usize,
+) -> Result<Vec<Memory>> {
     let mut stmt = conn.prepare(
-        "SELECT json_extract(metadata, '$.type') as mtype, COUNT(*) as cnt
-         FROM memories WHERE mtype IS NOT NULL GROUP BY mtype",
+        "SELECT * FROM (
+             SELECT id, COALESCE(content_ref, content), vector, COALESCE(metadata_z, metadata), created_at, updated_at,
+                    last_accessed, access_count, NULL AS deleted_at, updated_at AS changed_at
+             FROM memories WHERE collection = ?1
+             UNION ALL
+             SELECT id, COALESCE(content_ref, content), vector, COALESCE(metadata_z, metadata), created_at, updated_at,
+                    last_accessed, access_count, deleted_at, deleted_at AS changed_at
+             FROM memories_deleted WHERE collection = ?1
+         )
+         WHERE changed_at > ?2 OR (changed_at = ?2 AND id > ?3)
+         ORDER BY changed_at ASC, id ASC LIMIT ?4",
     )?;
+    // NULL never compares greater, so no id means a strict timestamp cursor
+    let mut rows = stmt.query(params![config.collection(), since, after_id, limit as i64])?;
+
+    let mut results = Vec::new();
+    while let Some(row) = rows.next()? {
+        let mut mem = row_to_memory(row, config)?;
+        mem.deleted_at = row.get(8)?;
+        results.push(mem);
+    }
+    Ok(results)
+}
+
+pub fn type_distribution(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+) -> Result<HashMap<String, usize>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT json_extract({}, '$.type') as mtype, COUNT(*) as cnt
+         FROM memories WHERE collection = ?1 AND mtype IS NOT NULL GROUP BY mtype",
+        crate::search::metadata_column(conn, "memories")?
+    ))?;
 
     let mut map = HashMap::new();
-    let mut rows = stmt.query([])?;
+    let mut rows = stmt.query(params![config.collection()])?;
     while let Some(row) = rows.next()? {
         let mtype: String = row.get(0)?;
         let cnt: i64 = row.get(1)?;
@@ -389,30 +1841,189 @@ pub fn type_distribution(conn: &rusqlite::Connection) -> Result<HashMap<String,
     Ok(map)
 }
 
-pub fn delete_before(conn: &rusqlite::Connection, before_timestamp: f64) -> Result<usize> {
+/// Distinct collection names in the file, hot or archived, sorted.
+pub fn collections(conn: &rusqlite::Connection) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT collection FROM memories UNION SELECT collection FROM memories_archive
+         ORDER BY collection",
+    )?;
+    let names = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<std::result::Result<Vec<String>, _>>()?;
+    Ok(names)
+}
+
+pub fn delete_before(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    before_timestamp: f64,
+) -> Result<usize> {
     let affected = conn.execute(
-        "DELETE FROM memories WHERE created_at < ?1",
-        params![before_timestamp],
+        "DELETE FROM memories WHERE collection = ?1 AND created_at < ?2",
+        params![config.collection(), before_timestamp],
     )?;
     Ok(affected)
 }
 
-pub fn delete_by_type(conn: &rusqlite::Connection, type_value: &str) -> Result<usize> {
+pub fn delete_by_type(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    type_value: &str,
+) -> Result<usize> {
     let affected = conn.execute(
-        "DELETE FROM memories WHERE json_extract(metadata, '$.type') = ?1",
-        params![type_value],
+        &format!(
+            "DELETE FROM memories WHERE collection = ?1 AND json_extract({}, '$.type') = ?2",
+            crate::search::metadata_column(conn, "memories")?
+        ),
+        params![config.collection(), type_value],
     )?;
     Ok(affected)
 }
 
+/// Capture the collection's ids in `sort` order (descending, ties by insertion
+/// order) into a connection-local temp table, to be paged by `snapshot_page`.
+pub fn list_snapshot(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    sort: &SortField,
+    page_size: usize,
+) -> Result<ListSnapshot> {
+    if page_size == 0 {
+        return Err(MemoriError::InvalidQuery("page_size must be at least 1".to_string()));
+    }
+    conn.execute_batch(
+        "CREATE TEMP TABLE IF NOT EXISTS list_snapshots (
+             token TEXT NOT NULL,
+             pos   INTEGER NOT NULL,
+             id    TEXT NOT NULL,
+             PRIMARY KEY (token, pos)
+         )",
+    )?;
+    let token = uuid::Uuid::new_v4().to_string();
+    let total = conn.execute(
+        &format!(
+            "INSERT INTO temp.list_snapshots (token, pos, id)
+             SELECT ?1, ROW_NUMBER() OVER (ORDER BY {} DESC, rowid), id
+             FROM memories WHERE collection = ?2",
+            sort.sql_column()
+        ),
+        params![token, config.collection()],
+    )?;
+    Ok(ListSnapshot {
+        token,
+        total,
+        page_size,
+    })
+}
+
+fn has_snapshots(conn: &rusqlite::Connection) -> Result<bool> {
+    Ok(conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_temp_master WHERE name = 'list_snapshots')",
+        [],
+        |row| row.get(0),
+    )?)
+}
+
+/// Page `page` (0-based) of a snapshot, with each memory's current contents.
+/// Memories deleted since the snapshot are skipped, so pages may come up
+/// short. Like `list`, this doesn't count as an access.
+pub fn snapshot_page(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    snapshot: &ListSnapshot,
+    page: usize,
+) -> Result<Vec<Memory>> {
+    if !has_snapshots(conn)? {
+        return Ok(Vec::new());
+    }
+    let start = page * snapshot.page_size;
+    let mut stmt = conn.prepare(
+        "SELECT m.id, COALESCE(m.content_ref, m.content), m.vector, COALESCE(m.metadata_z, m.metadata), m.created_at, m.updated_at,
+                m.last_accessed, m.access_count
+         FROM temp.list_snapshots s JOIN memories m ON m.id = s.id
+         WHERE s.token = ?1 AND s.pos > ?2 AND s.pos <= ?3
+         ORDER BY s.pos",
+    )?;
+    let mut rows = stmt.query(params![
+        snapshot.token,
+        start as i64,
+        (start + snapshot.page_size) as i64
+    ])?;
+    let mut results = Vec::new();
+    while let Some(row) = rows.next()? {
+        results.push(row_to_memory(row, config)?);
+    }
+    Ok(results)
+}
+
+/// Drop a snapshot's captured ordering. Unknown tokens are ignored.
+pub fn release_snapshot(conn: &rusqlite::Connection, token: &str) -> Result<()> {
+    if has_snapshots(conn)? {
+        conn.execute("DELETE FROM temp.list_snapshots WHERE token = ?1", params![token])?;
+    }
+    Ok(())
+}
+
 /// Run SQLite VACUUM to compact the database file.
 pub fn vacuum(conn: &rusqlite::Connection) -> Result<()> {
     conn.execute_batch("VACUUM")?;
     Ok(())
 }
 
+/// Write a compacted, consistent copy of the database to `dest_path` with
+/// `VACUUM INTO`. Works for in-memory databases too. SQLite refuses a
+/// destination that already exists and is non-empty.
+pub fn backup_to(conn: &rusqlite::Connection, dest_path: &str) -> Result<()> {
+    conn.execute("VACUUM INTO ?1", params![dest_path])?;
+    Ok(())
+}
+
+/// Run `PRAGMA wal_checkpoint` in `mode`.
+pub fn checkpoint(conn: &rusqlite::Connection, mode: CheckpointMode) -> Result<CheckpointResult> {
+    let sql = format!("PRAGMA wal_checkpoint({})", mode.sql_keyword());
+    let result = conn.query_row(&sql, [], |row| {
+        Ok(CheckpointResult {
+            busy: row.get::<_, i64>(0)? != 0,
+            log_frames: row.get(1)?,
+            checkpointed_frames: row.get(2)?,
+        })
+    })?;
+    Ok(result)
+}
+
+/// Run SQLite ANALYZE to refresh the query planner's statistics.
+pub fn analyze(conn: &rusqlite::Connection) -> Result<()> {
+    conn.execute_batch("ANALYZE")?;
+    Ok(())
+}
+
+/// Create an index on `(collection, json_extract(metadata, '$.key'))`, the
+/// terms a metadata filter on `key` compiles to, so filtered searches seek
+/// instead of scanning the collection. Idempotent; returns the index name.
+/// `type` already has `idx_memories_type`. With compressed metadata the
+/// index reads it through `memori_metadata`, as filters do.
+pub fn add_metadata_index(conn: &rusqlite::Connection, key: &str) -> Result<String> {
+    if !crate::search::is_valid_filter_key(key) {
+        return Err(MemoriError::InvalidFilter(format!(
+            "key '{}' must match [a-zA-Z_][a-zA-Z0-9_]*",
+            key
+        )));
+    }
+    if key == "type" {
+        return Ok("idx_memories_type".to_string());
+    }
+    let name = format!("idx_memories_meta_{}", key);
+    let metadata = crate::schema::metadata_sql("", crate::schema::has_compressed_metadata(conn)?);
+    conn.execute_batch(&format!(
+        "CREATE INDEX IF NOT EXISTS {} ON memories(collection, json_extract({}, '$.{}'))",
+        name, metadata, key
+    ))?;
+    Ok(name)
+}
+
 /// Set access stats (last_accessed, access_count) for a memory by ID.
-/// Used to restore access stats during import.
+/// Used to restore access stats during import. `last_accessed: None` leaves
+/// the stored timestamp untouched; use `clear_last_accessed` to reset it.
 pub fn set_access_stats(
     conn: &rusqlite::Connection,
     id: &str,
@@ -420,7 +2031,8 @@ pub fn set_access_stats(
     access_count: i64,
 ) -> Result<()> {
     let affected = conn.execute(
-        "UPDATE memories SET last_accessed = ?1, access_count = ?2 WHERE id = ?3",
+        "UPDATE memories SET last_accessed = COALESCE(?1, last_accessed), access_count = ?2
+         WHERE id = ?3",
         params![last_accessed, access_count, id],
     )?;
     if affected == 0 {
@@ -429,75 +2041,383 @@ pub fn set_access_stats(
     Ok(())
 }
 
+/// Recreate an exported memory (see `Memori::export_memory`) under its own id
+/// with its timestamps and access stats (and `content_ref`, for an external
+/// memory), in one transaction. The token budget doesn't apply.
+pub fn import_memory(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    mem: &Memory,
+    content_ref: Option<&str>,
+) -> Result<String> {
+    let tx = conn.unchecked_transaction()?;
+    write_with_id(
+        &tx,
+        config,
+        &mem.id,
+        &mem.content,
+        mem.vector.as_deref(),
+        mem.metadata.clone(),
+        mem.created_at,
+        mem.updated_at,
+    )?;
+    set_access_stats(&tx, &mem.id, Some(mem.last_accessed), mem.access_count)?;
+    if content_ref.is_some() {
+        tx.execute("UPDATE memories SET content_ref = ?1 WHERE id = ?2", params![content_ref, mem.id])?;
+    }
+    tx.commit()?;
+    Ok(mem.id.clone())
+}
+
+/// `set_access_stats` for many `(id, last_accessed, access_count)` entries in
+/// one transaction. Ids are exact (no prefix resolution); ids that don't
+/// exist in the handle's collection are skipped. Returns how many matched.
+pub fn set_access_stats_batch(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    entries: &[(String, Option<f64>, i64)],
+) -> Result<usize> {
+    let tx = conn.unchecked_transaction()?;
+    let mut matched = 0;
+    {
+        let mut stmt = tx.prepare(
+            "UPDATE memories SET last_accessed = COALESCE(?1, last_accessed), access_count = ?2
+             WHERE id = ?3 AND collection = ?4",
+        )?;
+        for (id, last_accessed, access_count) in entries {
+            matched += stmt.execute(params![last_accessed, access_count, id, config.collection()])?;
+        }
+    }
+    tx.commit()?;
+    Ok(matched)
+}
+
+/// Add batched `(id, accesses, latest access time)` bumps to stored access
+/// stats in one transaction. Rows deleted since the bump are skipped.
+pub fn apply_access_deltas(conn: &rusqlite::Connection, deltas: &[(String, i64, f64)]) -> Result<()> {
+    if deltas.is_empty() {
+        return Ok(());
+    }
+    let tx = conn.unchecked_transaction()?;
+    {
+        let mut stmt = tx.prepare(
+            "UPDATE memories SET access_count = access_count + ?1,
+                                 last_accessed = MAX(last_accessed, ?2)
+             WHERE id = ?3",
+        )?;
+        for (id, count, last) in deltas {
+            stmt.execute(params![count, last, id])?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Reset last_accessed to 0.0, the "never accessed" value that the decay
+/// scoring treats as no penalty. access_count is left as-is.
+pub fn clear_last_accessed(conn: &rusqlite::Connection, id: &str) -> Result<()> {
+    let affected = conn.execute(
+        "UPDATE memories SET last_accessed = 0.0 WHERE id = ?1",
+        params![id],
+    )?;
+    if affected == 0 {
+        return Err(MemoriError::NotFound(id.to_string()));
+    }
+    Ok(())
+}
+
+/// Counts and approximate data size for `collection`, or for every
+/// collection in the file when `None`.
+pub fn store_stats(conn: &rusqlite::Connection, collection: Option<&str>) -> Result<StoreStats> {
+    let mut stats = StoreStats::default();
+    for table in ["memories", "memories_archive", "memories_deleted"] {
+        let sql = format!(
+            "SELECT COUNT(*), COUNT(vector),
+                    COALESCE(SUM(LENGTH(CAST(content AS BLOB)) + COALESCE(LENGTH(CAST(metadata AS BLOB)), 0)
+                                 + COALESCE(LENGTH(metadata_z), 0) + COALESCE(LENGTH(vector), 0)), 0)
+             FROM {} WHERE ?1 IS NULL OR collection = ?1",
+            table
+        );
+        let (rows, embedded, bytes): (i64, i64, i64) =
+            conn.query_row(&sql, params![collection], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        match table {
+            "memories" => {
+                stats.memories = rows as usize;
+                stats.embedded = embedded as usize;
+            }
+            "memories_archive" => stats.archived = rows as usize,
+            _ => stats.deleted = rows as usize,
+        }
+        stats.data_bytes += bytes as u64;
+    }
+    Ok(stats)
+}
+
 /// Return (embedded_count, total_count) for embedding coverage stats
-pub fn embedding_stats(conn: &rusqlite::Connection) -> Result<(usize, usize)> {
-    let total: i64 = conn.query_row("SELECT COUNT(*) FROM memories", [], |row| row.get(0))?;
+pub fn embedding_stats(conn: &rusqlite::Connection, config: &MemoriConfig) -> Result<(usize, usize)> {
+    let collection = config.collection();
+    let total: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM memories WHERE collection = ?1",
+        params![collection],
+        |row| row.get(0),
+    )?;
     let embedded: i64 = conn.query_row(
-        "SELECT COUNT(*) FROM memories WHERE vector IS NOT NULL",
-        [],
+        "SELECT COUNT(*) FROM memories WHERE collection = ?1 AND vector IS NOT NULL",
+        params![collection],
         |row| row.get(0),
     )?;
     Ok((embedded as usize, total as usize))
 }
 
-/// Backfill embeddings for memories that have vector = NULL.
-/// Returns the number of memories processed.
-pub fn backfill_embeddings(conn: &rusqlite::Connection, batch_size: usize) -> Result<usize> {
-    #[cfg(not(feature = "embeddings"))]
+/// Number of memories in the collection without a vector.
+pub fn missing_embedding_count(conn: &rusqlite::Connection, config: &MemoriConfig) -> Result<usize> {
+    let missing: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM memories WHERE collection = ?1 AND vector IS NULL",
+        params![config.collection()],
+        |row| row.get(0),
+    )?;
+    Ok(missing as usize)
+}
+
+/// `(embedded, total)` per metadata `type`. Untyped memories are omitted, as
+/// in `type_distribution`.
+pub fn embedding_coverage_by_type(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+) -> Result<HashMap<String, (usize, usize)>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT json_extract({}, '$.type') as mtype, COUNT(vector), COUNT(*)
+         FROM memories WHERE collection = ?1 AND mtype IS NOT NULL GROUP BY mtype",
+        crate::search::metadata_column(conn, "memories")?
+    ))?;
+
+    let mut map = HashMap::new();
+    let mut rows = stmt.query(params![config.collection()])?;
+    while let Some(row) = rows.next()? {
+        let mtype: String = row.get(0)?;
+        let embedded: i64 = row.get(1)?;
+        let total: i64 = row.get(2)?;
+        map.insert(mtype, (embedded as usize, total as usize));
+    }
+
+    Ok(map)
+}
+
+/// IDs of memories in the collection whose stored vector is not
+/// `expected_dim` floats long. `cosine_similarity` scores such vectors 0.0
+/// against every query, so vector search can never surface them.
+pub fn find_dimension_mismatches(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    expected_dim: usize,
+) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT id FROM memories
+         WHERE collection = ?1 AND vector IS NOT NULL AND length(vector) != ?2
+         ORDER BY rowid",
+    )?;
+    let expected_bytes = (expected_dim * config.vector_precision.bytes_per_dim()) as i64;
+    let ids = stmt
+        .query_map(params![config.collection(), expected_bytes], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    Ok(ids)
+}
+
+/// Replace the vectors `find_dimension_mismatches` reports with fresh
+/// embeddings of the content. Without the embeddings feature the stale
+/// vectors are cleared instead, leaving the rows for a later backfill.
+/// Vector-only memories (empty content) have nothing to embed and keep their
+/// vector, so they stay reported. Returns the number of memories fixed.
+pub fn reembed_mismatches(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    expected_dim: usize,
+) -> Result<usize> {
+    let tx = conn.unchecked_transaction()?;
+    let expected_bytes = (expected_dim * config.vector_precision.bytes_per_dim()) as i64;
+    let (ids, contents): (Vec<String>, Vec<String>) = {
+        let mut stmt = tx.prepare(
+            "SELECT id, content FROM memories
+             WHERE collection = ?1 AND vector IS NOT NULL AND length(vector) != ?2 AND content != ''
+             ORDER BY rowid",
+        )?;
+        let rows = stmt
+            .query_map(params![config.collection(), expected_bytes], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<Vec<(String, String)>>>()?;
+        rows.into_iter().unzip()
+    };
+
+    #[cfg(any(feature = "embeddings", feature = "mock_embeddings"))]
+    {
+        let prepared: Vec<_> = contents.iter().map(|c| config.embed_preprocess.apply(c)).collect();
+        let texts: Vec<&str> = prepared.iter().map(|c| c.as_ref()).collect();
+        let mut embeddings = crate::embed::embed_batch(&texts);
+        for ((id, content), embedding) in ids.iter().zip(&contents).zip(embeddings.iter_mut()) {
+            if config.normalize_embeddings {
+                l2_normalize(embedding);
+            }
+            tx.execute(
+                "UPDATE memories SET vector = ?1, vector_norm = ?2, embed_hash = ?3 WHERE id = ?4",
+                params![encode_vector(config, embedding), stored_norm(embedding), embed_hash(config, content), id],
+            )?;
+        }
+    }
+
+    #[cfg(not(any(feature = "embeddings", feature = "mock_embeddings")))]
+    for id in &ids {
+        tx.execute(
+            "UPDATE memories SET vector = NULL, vector_norm = NULL, embed_hash = NULL WHERE id = ?1",
+            params![id],
+        )?;
+    }
+    #[cfg(not(any(feature = "embeddings", feature = "mock_embeddings")))]
+    let _ = contents;
+
+    tx.commit()?;
+    Ok(ids.len())
+}
+
+/// Backfill embeddings for memories that have vector = NULL, except those
+/// with empty content.
+/// Returns the number of memories processed. `progress(processed, total)` is
+/// called after each batch, where `total` is the NULL-vector count taken up front.
+/// With `parallelism` above 1, that many batches are embedded at once on
+/// scoped threads sharing the model; writes stay on `conn`, in batch order.
+pub fn backfill_embeddings<F: FnMut(usize, usize)>(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    batch_size: usize,
+    parallelism: usize,
+    mut progress: F,
+) -> Result<usize> {
+    #[cfg(not(any(feature = "embeddings", feature = "mock_embeddings")))]
     {
-        let _ = (conn, batch_size);
-        return Ok(0);
+        let _ = (conn, config, batch_size, parallelism, &mut progress);
+        Ok(0)
     }
 
-    #[cfg(feature = "embeddings")]
+    #[cfg(any(feature = "embeddings", feature = "mock_embeddings"))]
     {
         let mut total_processed = 0usize;
+        let batch_size = batch_size.max(1);
+        let parallelism = parallelism.max(1);
+        let collection = config.collection();
+        let total: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM memories WHERE collection = ?1 AND vector IS NULL AND content != ''",
+            params![collection],
+            |row| row.get(0),
+        )?;
 
         loop {
             let mut stmt = conn.prepare(
-                "SELECT id, content FROM memories WHERE vector IS NULL LIMIT ?1",
+                "SELECT id, content FROM memories
+                 WHERE collection = ?1 AND vector IS NULL AND content != '' LIMIT ?2",
             )?;
-            let mut rows = stmt.query(params![batch_size as i64])?;
+            let mut rows = stmt.query(params![collection, (batch_size * parallelism) as i64])?;
 
-            let mut batch: Vec<(String, String)> = Vec::new();
+            let mut round: Vec<(String, String)> = Vec::new();
             while let Some(row) = rows.next()? {
                 let id: String = row.get(0)?;
                 let content: String = row.get(1)?;
-                batch.push((id, content));
+                round.push((id, content));
             }
 
-            if batch.is_empty() {
+            if round.is_empty() {
                 break;
             }
 
-            let texts: Vec<&str> = batch.iter().map(|(_, c)| c.as_str()).collect();
-            let embeddings = crate::embed::embed_batch(&texts);
+            let embed_chunk = |batch: &[(String, String)]| {
+                let prepared: Vec<_> = batch.iter().map(|(_, c)| config.embed_preprocess.apply(c)).collect();
+                let texts: Vec<&str> = prepared.iter().map(|c| c.as_ref()).collect();
+                let mut embeddings = crate::embed::embed_batch(&texts);
+                if config.normalize_embeddings {
+                    for v in embeddings.iter_mut() {
+                        l2_normalize(v);
+                    }
+                }
+                embeddings
+            };
+            let batches: Vec<&[(String, String)]> = round.chunks(batch_size).collect();
+            let embedded: Vec<Vec<Vec<f32>>> = if batches.len() == 1 {
+                vec![embed_chunk(batches[0])]
+            } else {
+                std::thread::scope(|scope| {
+                    let handles: Vec<_> = batches.iter().map(|b| scope.spawn(|| embed_chunk(b))).collect();
+                    handles.into_iter().map(|h| h.join().expect("embedding thread panicked")).collect()
+                })
+            };
 
-            for ((id, _), embedding) in batch.iter().zip(embeddings.iter()) {
-                let blob = vec_to_blob(embedding);
-                conn.execute(
-                    "UPDATE memories SET vector = ?1 WHERE id = ?2",
-                    params![blob, id],
-                )?;
+            for (batch, embeddings) in batches.iter().zip(&embedded) {
+                for ((id, content), embedding) in batch.iter().zip(embeddings.iter()) {
+                    let blob = encode_vector(config, embedding);
+                    conn.execute(
+                        "UPDATE memories SET vector = ?1, vector_norm = ?2, embed_hash = ?3 WHERE id = ?4",
+                        params![blob, stored_norm(embedding), embed_hash(config, content), id],
+                    )?;
+                }
+                total_processed += batch.len();
+                progress(total_processed, total as usize);
             }
-
-            total_processed += batch.len();
         }
 
         Ok(total_processed)
     }
 }
 
-/// Resolve a short ID prefix to the full 36-char UUID.
-/// If the prefix is already 36+ chars, returns it as-is (full UUID passthrough).
-/// Returns NotFound if no match, AmbiguousPrefix if 2+ matches.
-pub fn resolve_prefix(conn: &rusqlite::Connection, prefix: &str) -> Result<String> {
+/// Resolve a short ID prefix to the full 36-char UUID within the handle's
+/// collection. If the prefix is already 36+ chars, returns it as-is (full UUID
+/// passthrough) unless it belongs to another collection. With
+/// `dash_insensitive_prefix`, dashes are dropped from both the prefix and the
+/// stored ids before comparing.
+/// Returns NotFound if no match, AmbiguousPrefix if 2+ matches, and
+/// PrefixTooShort for a prefix under `min_prefix_len` that isn't an exact id.
+pub fn resolve_prefix(conn: &rusqlite::Connection, config: &MemoriConfig, prefix: &str) -> Result<String> {
+    resolve_prefix_in(conn, config, "memories", prefix)
+}
+
+/// `resolve_prefix` against `table` (`memories`, `memories_archive`, or
+/// `memories_deleted`).
+pub(crate) fn resolve_prefix_in(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    table: &str,
+    prefix: &str,
+) -> Result<String> {
+    let collection = config.collection();
     if prefix.len() >= 36 {
+        let elsewhere: bool = conn.query_row(
+            &format!("SELECT EXISTS(SELECT 1 FROM {} WHERE id = ?1 AND collection != ?2)", table),
+            params![prefix, collection],
+            |row| row.get(0),
+        )?;
+        if elsewhere {
+            return Err(MemoriError::NotFound(prefix.to_string()));
+        }
         return Ok(prefix.to_string());
     }
 
-    let mut stmt = conn.prepare("SELECT id FROM memories WHERE id LIKE ?1 || '%' LIMIT 2")?;
-    let mut rows = stmt.query(params![prefix])?;
+    if prefix.len() < config.min_prefix_len {
+        let exact: bool = conn.query_row(
+            &format!("SELECT EXISTS(SELECT 1 FROM {} WHERE id = ?1 AND collection = ?2)", table),
+            params![prefix, collection],
+            |row| row.get(0),
+        )?;
+        if exact {
+            return Ok(prefix.to_string());
+        }
+        return Err(MemoriError::PrefixTooShort(prefix.to_string(), config.min_prefix_len));
+    }
+
+    let (id_expr, pattern) = if config.dash_insensitive_prefix {
+        ("REPLACE(id, '-', '')", Cow::Owned(prefix.replace('-', "")))
+    } else {
+        ("id", Cow::Borrowed(prefix))
+    };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id FROM {} WHERE collection = ?1 AND {} LIKE ?2 || '%' LIMIT 2",
+        table, id_expr
+    ))?;
+    let mut rows = stmt.query(params![collection, pattern])?;
 
     let first = match rows.next()? {
         Some(row) => {
@@ -511,8 +2431,11 @@ pub fn resolve_prefix(conn: &rusqlite::Connection, prefix: &str) -> Result<Strin
     if rows.next()?.is_some() {
         // Count total matches for the error message
         let count: i64 = conn.query_row(
-            "SELECT COUNT(*) FROM memories WHERE id LIKE ?1 || '%'",
-            params![prefix],
+            &format!(
+                "SELECT COUNT(*) FROM {} WHERE collection = ?1 AND {} LIKE ?2 || '%'",
+                table, id_expr
+            ),
+            params![collection, pattern],
             |row| row.get(0),
         )?;
         return Err(MemoriError::AmbiguousPrefix(
@@ -524,19 +2447,247 @@ pub fn resolve_prefix(conn: &rusqlite::Connection, prefix: &str) -> Result<Strin
     Ok(first)
 }
 
-pub fn row_to_memory(row: &rusqlite::Row) -> rusqlite::Result<Memory> {
+/// Parse a stored metadata string. Strict mode surfaces parse failures as
+/// `MemoriError::Json`; lenient mode drops the value (reporting it to the
+/// configured warning hook) so one bad row doesn't break a whole query.
+pub fn parse_metadata(
+    config: &MemoriConfig,
+    id: &str,
+    metadata_str: Option<String>,
+) -> Result<Option<Value>> {
+    let Some(s) = metadata_str else {
+        return Ok(None);
+    };
+    match serde_json::from_str(&s) {
+        Ok(v) => Ok(Some(v)),
+        Err(e) if config.strict_metadata => Err(e.into()),
+        Err(e) => {
+            if let Some(hook) = &config.metadata_warning {
+                (hook.0)(id, &format!("dropping unparseable metadata: {}", e));
+            }
+            Ok(None)
+        }
+    }
+}
+
+/// Parse a selected `COALESCE(metadata_z, metadata)` column: text as JSON, a
+/// blob inflated first. A blob that can't be inflated (or any blob without
+/// the `compress_metadata` feature) is handled like unparseable JSON.
+pub(crate) fn read_metadata(
+    config: &MemoriConfig,
+    id: &str,
+    raw: rusqlite::types::Value,
+) -> Result<Option<Value>> {
+    use rusqlite::types::Value as SqlValue;
+    let text = match raw {
+        SqlValue::Text(s) => s,
+        SqlValue::Blob(b) => match inflate_metadata(&b) {
+            Ok(s) => s,
+            Err(e) if config.strict_metadata => return Err(serde_json::Error::io(e).into()),
+            Err(e) => {
+                if let Some(hook) = &config.metadata_warning {
+                    (hook.0)(id, &format!("dropping unreadable compressed metadata: {}", e));
+                }
+                return Ok(None);
+            }
+        },
+        _ => return Ok(None),
+    };
+    parse_metadata(config, id, Some(text))
+}
+
+/// Values for the `metadata` and `metadata_z` columns. Under
+/// `compress_metadata_over`, an object whose JSON is longer than the limit is
+/// deflated into `metadata_z` and `metadata` keeps its top-level scalars; SQL
+/// sees the rest through `memori_metadata` (see `settle_metadata_compression`).
+pub(crate) fn metadata_columns(
+    config: &MemoriConfig,
+    metadata: Option<&Value>,
+) -> (Option<String>, Option<Vec<u8>>) {
+    let Some(meta) = metadata else {
+        return (None, None);
+    };
+    let json = meta.to_string();
+
+    #[cfg(feature = "compress_metadata")]
+    if let (Some(limit), Value::Object(map)) = (config.compress_metadata_over, meta) {
+        if json.len() > limit {
+            if let Ok(z) = deflate_metadata(&json) {
+                let scalars: serde_json::Map<String, Value> = map
+                    .iter()
+                    .filter(|(_, v)| !v.is_object() && !v.is_array())
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect();
+                return (Some(Value::Object(scalars).to_string()), Some(z));
+            }
+        }
+    }
+    #[cfg(not(feature = "compress_metadata"))]
+    let _ = config;
+
+    (Some(json), None)
+}
+
+#[cfg(feature = "compress_metadata")]
+fn deflate_metadata(json: &str) -> std::io::Result<Vec<u8>> {
+    use std::io::Write;
+    let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(json.as_bytes())?;
+    encoder.finish()
+}
+
+#[cfg(feature = "compress_metadata")]
+fn inflate_metadata(blob: &[u8]) -> std::io::Result<String> {
+    use std::io::Read;
+    let mut json = String::new();
+    flate2::read::DeflateDecoder::new(blob).read_to_string(&mut json)?;
+    Ok(json)
+}
+
+#[cfg(not(feature = "compress_metadata"))]
+fn inflate_metadata(_blob: &[u8]) -> std::io::Result<String> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "reading it needs the compress_metadata feature",
+    ))
+}
+
+/// Register `memori_metadata(metadata, metadata_z)` on `conn`: the row's full
+/// metadata JSON, inflating `metadata_z` when it is set. The FTS triggers,
+/// filters, and metadata indexes of a database with compressed metadata call
+/// it (see `schema::metadata_sql`). Without the `compress_metadata` feature
+/// it fails on a compressed row, so such writes are refused rather than
+/// indexing partial text.
+pub(crate) fn register_metadata_function(conn: &rusqlite::Connection) -> Result<()> {
+    use rusqlite::functions::FunctionFlags;
+    conn.create_scalar_function(
+        "memori_metadata",
+        2,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| match ctx.get_raw(1) {
+            rusqlite::types::ValueRef::Blob(blob) => inflate_metadata(blob)
+                .map(Some)
+                .map_err(|e| rusqlite::Error::UserFunctionError(Box::new(e))),
+            _ => ctx.get::<Option<String>>(0),
+        },
+    )?;
+    Ok(())
+}
+
+/// Mark the database as holding compressed metadata the first time a
+/// writable handle with `compress_metadata_over` opens it. The FTS triggers
+/// and metadata indexes are rebuilt to read the full JSON through
+/// `memori_metadata`, since a compressed row's `metadata` keeps only
+/// top-level scalars. A marked database stays marked.
+pub fn settle_metadata_compression(
+    conn: &rusqlite::Connection,
+    config: &MemoriConfig,
+    read_only: bool,
+) -> Result<()> {
+    if read_only
+        || !cfg!(feature = "compress_metadata")
+        || config.compress_metadata_over.is_none()
+        || crate::schema::has_compressed_metadata(conn)?
+    {
+        return Ok(());
+    }
+    let tx = conn.unchecked_transaction()?;
+    crate::schema::set_meta(&tx, crate::schema::COMPRESSED_METADATA_KEY, "deflate")?;
+
+    let keys: Vec<String> = tx
+        .prepare("SELECT name FROM sqlite_master WHERE type = 'index' AND name LIKE 'idx_memories_meta_%'")?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<std::result::Result<Vec<_>, _>>()?
+        .into_iter()
+        .map(|name| name["idx_memories_meta_".len()..].to_string())
+        .collect();
+    tx.execute_batch("DROP INDEX IF EXISTS idx_memories_type;")?;
+    for key in &keys {
+        tx.execute_batch(&format!("DROP INDEX idx_memories_meta_{};", key))?;
+        add_metadata_index(&tx, key)?;
+    }
+    crate::schema::ensure_indexes(&tx)?;
+
+    if crate::schema::has_fts(&tx)? {
+        let separator = crate::schema::get_meta(&tx, "text_separator")?;
+        let separator = separator.as_deref().unwrap_or(config.text_separator());
+        crate::schema::rebuild_fts_with_layout(&tx, separator, crate::schema::has_split_fts(&tx)?)?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Encode `v` for the `vector` column at the database's precision.
+pub(crate) fn encode_vector<'a>(config: &MemoriConfig, v: &'a [f32]) -> Cow<'a, [u8]> {
+    match config.vector_precision {
+        #[cfg(feature = "f16")]
+        VectorPrecision::F16 => Cow::Owned(crate::util::vec_to_f16_blob(v)),
+        _ => Cow::Borrowed(vec_to_blob(v)),
+    }
+}
+
+/// Decode a `vector` column BLOB written by `encode_vector`.
+pub(crate) fn decode_vector(config: &MemoriConfig, blob: &[u8]) -> Vec<f32> {
+    match config.vector_precision {
+        #[cfg(feature = "f16")]
+        VectorPrecision::F16 => crate::util::f16_blob_to_vec(blob),
+        _ => blob_to_vec(blob),
+    }
+}
+
+/// The precision this database's vectors are stored at, recording
+/// `requested` if none is recorded yet (unless `read_only`). A database that
+/// already holds vectors from before the setting existed is treated as f32.
+pub fn settle_vector_precision(
+    conn: &rusqlite::Connection,
+    requested: VectorPrecision,
+    read_only: bool,
+) -> Result<VectorPrecision> {
+    let recorded = crate::schema::get_meta(conn, "vector_precision")?;
+    let precision = match &recorded {
+        Some(s) => VectorPrecision::from_str(s).map_err(MemoriError::InvalidVector)?,
+        None => {
+            let has_vectors: bool = conn.query_row(
+                "SELECT EXISTS(SELECT 1 FROM memories WHERE vector IS NOT NULL)
+                     OR EXISTS(SELECT 1 FROM memories_archive WHERE vector IS NOT NULL)
+                     OR EXISTS(SELECT 1 FROM memories_deleted WHERE vector IS NOT NULL)",
+                [],
+                |row| row.get(0),
+            )?;
+            if has_vectors {
+                VectorPrecision::F32
+            } else {
+                requested
+            }
+        }
+    };
+    if precision == VectorPrecision::F16 && !cfg!(feature = "f16") {
+        return Err(MemoriError::InvalidVector(
+            "f16 vector storage needs the f16 feature".to_string(),
+        ));
+    }
+    if recorded.is_none() && !read_only {
+        crate::schema::set_meta(conn, "vector_precision", precision.as_str())?;
+    }
+    Ok(precision)
+}
+
+pub fn row_to_memory(row: &rusqlite::Row, config: &MemoriConfig) -> Result<Memory> {
+    let id: String = row.get(0)?;
     let vector_blob: Option<Vec<u8>> = row.get(2)?;
-    let metadata_str: Option<String> = row.get(3)?;
+    let metadata = read_metadata(config, &id, row.get(3)?)?;
 
     Ok(Memory {
-        id: row.get(0)?,
+        id,
         content: row.get(1)?,
-        vector: vector_blob.map(|b| blob_to_vec(&b)),
-        metadata: metadata_str.and_then(|s| serde_json::from_str(&s).ok()),
+        vector: vector_blob.map(|b| decode_vector(config, &b)),
+        metadata,
         created_at: row.get(4)?,
         updated_at: row.get(5)?,
         last_accessed: row.get(6)?,
         access_count: row.get(7)?,
         score: None,
+        base_score: None,
+        deleted_at: None,
     })
 }
@@ -0,0 +1,70 @@
+//! Optional `tracing` spans for the hot paths. With the `tracing` feature off,
+//! `span!` expands to a zero-sized guard whose methods compile away.
+//!
+//! Every span declares the same fields so call sites can record whichever
+//! apply: `candidates` (rows scanned or fused), `results` (rows returned),
+//! `dedup` (whether deduplication fired), and `elapsed_us` (set on drop).
+
+#[cfg(feature = "tracing")]
+pub(crate) struct Span {
+    span: tracing::span::EnteredSpan,
+    start: std::time::Instant,
+}
+
+#[cfg(feature = "tracing")]
+impl Span {
+    pub(crate) fn new(span: tracing::Span) -> Self {
+        Self {
+            span: span.entered(),
+            start: std::time::Instant::now(),
+        }
+    }
+
+    pub(crate) fn record(&self, field: &str, value: u64) {
+        self.span.record(field, value);
+    }
+
+    pub(crate) fn record_bool(&self, field: &str, value: bool) {
+        self.span.record(field, value);
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Drop for Span {
+    fn drop(&mut self) {
+        self.span
+            .record("elapsed_us", self.start.elapsed().as_micros() as u64);
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct Span;
+
+#[cfg(not(feature = "tracing"))]
+impl Span {
+    #[inline(always)]
+    pub(crate) fn record(&self, _field: &str, _value: u64) {}
+
+    #[inline(always)]
+    pub(crate) fn record_bool(&self, _field: &str, _value: bool) {}
+}
+
+/// Open a DEBUG span named `memori.<name>` that stays entered until the
+/// returned guard is dropped.
+macro_rules! span {
+    ($name:literal) => {{
+        #[cfg(feature = "tracing")]
+        let span = $crate::trace::Span::new(tracing::debug_span!(
+            concat!("memori.", $name),
+            candidates = tracing::field::Empty,
+            results = tracing::field::Empty,
+            dedup = tracing::field::Empty,
+            elapsed_us = tracing::field::Empty,
+        ));
+        #[cfg(not(feature = "tracing"))]
+        let span = $crate::trace::Span;
+        span
+    }};
+}
+
+pub(crate) use span;
@@ -1,4 +1,7 @@
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::Arc;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -18,8 +21,41 @@ pub enum MemoriError {
     #[error("ambiguous prefix '{0}': matches {1} memories")]
     AmbiguousPrefix(String, usize),
 
+    #[error("prefix '{0}' is shorter than the minimum of {1} characters")]
+    PrefixTooShort(String, usize),
+
     #[error("invalid filter key: {0}")]
     InvalidFilter(String),
+
+    #[error("invalid patch: {0}")]
+    InvalidPatch(String),
+
+    #[error("invalid sort: {0}")]
+    InvalidSort(String),
+
+    #[error("invalid merge: {0}")]
+    InvalidMerge(String),
+
+    #[error("invalid query: {0}")]
+    InvalidQuery(String),
+
+    #[error("full-text search is disabled for this database")]
+    FtsDisabled,
+
+    #[error("invalid content hash '{0}': expected 16 hex digits")]
+    InvalidHash(String),
+
+    #[error("content is an estimated {0} tokens, over the budget of {1}")]
+    OverTokenBudget(usize, usize),
+
+    #[error("database schema v{0} is newer than this build supports (v{1}); upgrade memori")]
+    SchemaTooNew(i32, i32),
+
+    #[error("database schema v{0} needs migrating to v{1}, which a read-only handle can't do; open it writable once")]
+    SchemaNeedsMigration(i32, i32),
+
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
 }
 
 pub type Result<T> = std::result::Result<T, MemoriError>;
@@ -35,6 +71,827 @@ pub struct Memory {
     pub last_accessed: f64,
     pub access_count: i64,
     pub score: Option<f32>,
+    /// Search score before the access boost and recency decay: cosine
+    /// similarity for vector results, negated BM25 rank for text results.
+    /// Hybrid results carry the vector similarity when the row was a vector
+    /// candidate, else the text score. `None` outside search.
+    pub base_score: Option<f32>,
+    /// When the memory was soft-deleted. Only tombstones returned by
+    /// `include_deleted` searches and lists carry it.
+    #[serde(default)]
+    pub deleted_at: Option<f64>,
+}
+
+impl Memory {
+    /// The `type` metadata field, if present and a string.
+    pub fn type_str(&self) -> Option<&str> {
+        self.meta_str("type")
+    }
+
+    /// A top-level metadata field as a string. `None` if absent or not a string.
+    pub fn meta_str(&self, key: &str) -> Option<&str> {
+        self.meta(key)?.as_str()
+    }
+
+    /// A top-level metadata field as an integer. `None` if absent or not an integer.
+    pub fn meta_i64(&self, key: &str) -> Option<i64> {
+        self.meta(key)?.as_i64()
+    }
+
+    /// A top-level metadata field as a bool. `None` if absent or not a bool.
+    pub fn meta_bool(&self, key: &str) -> Option<bool> {
+        self.meta(key)?.as_bool()
+    }
+
+    /// A metadata value by slash-separated path (`type`, `author/name`,
+    /// `tags/0`), the client-side counterpart of `json_extract(metadata,
+    /// '$.author.name')`. Segments use JSON pointer escaping (`~1` for `/`,
+    /// `~0` for `~`). `None` if any segment is missing.
+    pub fn meta_path(&self, path: &str) -> Option<&serde_json::Value> {
+        if path.is_empty() {
+            return None;
+        }
+        let path = path.strip_prefix('/').unwrap_or(path);
+        self.metadata.as_ref()?.pointer(&format!("/{}", path))
+    }
+
+    fn meta(&self, key: &str) -> Option<&serde_json::Value> {
+        self.metadata.as_ref()?.get(key)
+    }
+}
+
+/// Search or list results laid out by column, for handing to dataframe
+/// libraries in bulk. Row `i` of every column is the same memory, in result
+/// order. Vectors are left out. This is not an Arrow `RecordBatch`, and there
+/// is no `search_arrow`/`list_arrow`: the columns are plain vectors, and
+/// Python callers convert the dict with `pyarrow.table(cols)` or
+/// `polars.DataFrame(cols)`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct MemoryColumns {
+    pub id: Vec<String>,
+    pub content: Vec<String>,
+    /// Metadata serialized as a JSON string.
+    pub metadata: Vec<Option<String>>,
+    pub created_at: Vec<f64>,
+    pub updated_at: Vec<f64>,
+    pub last_accessed: Vec<f64>,
+    pub access_count: Vec<i64>,
+    pub score: Vec<Option<f32>>,
+    pub base_score: Vec<Option<f32>>,
+    /// When each memory was soft-deleted; `None` unless `include_deleted`.
+    pub deleted_at: Vec<Option<f64>>,
+}
+
+impl MemoryColumns {
+    pub fn len(&self) -> usize {
+        self.id.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.id.is_empty()
+    }
+}
+
+impl FromIterator<Memory> for MemoryColumns {
+    fn from_iter<I: IntoIterator<Item = Memory>>(iter: I) -> Self {
+        let mut cols = MemoryColumns::default();
+        for m in iter {
+            cols.metadata.push(m.metadata.as_ref().map(|v| v.to_string()));
+            cols.id.push(m.id);
+            cols.content.push(m.content);
+            cols.created_at.push(m.created_at);
+            cols.updated_at.push(m.updated_at);
+            cols.last_accessed.push(m.last_accessed);
+            cols.access_count.push(m.access_count);
+            cols.score.push(m.score);
+            cols.base_score.push(m.base_score);
+            cols.deleted_at.push(m.deleted_at);
+        }
+        cols
+    }
+}
+
+/// A memory's content and metadata as they were before one `update` or
+/// `replace`, from `Memori::history`.
+#[derive(Clone, Debug, Serialize)]
+pub struct HistoryEntry {
+    /// 1 for the first recorded change, counting up per memory.
+    pub version: i64,
+    pub content: String,
+    pub metadata: Option<serde_json::Value>,
+    /// When the change that replaced this version was made.
+    pub changed_at: f64,
+}
+
+/// Everything the database stores for one row, before any parsing. Returned by
+/// `Memori::inspect` for debugging and admin tooling.
+#[derive(Clone, Debug, Serialize)]
+pub struct RawRow {
+    pub id: String,
+    pub rowid: i64,
+    pub content: String,
+    /// The metadata column exactly as stored (may not be valid JSON).
+    pub raw_metadata: Option<String>,
+    /// Whether `raw_metadata` parses as JSON. `false` means `get` reports `metadata: None`.
+    pub metadata_valid: bool,
+    /// Length of the vector BLOB in bytes (`dim * 4` for f32, `dim * 2` for
+    /// f16), `None` if no vector.
+    pub vector_bytes: Option<usize>,
+    /// Whether the row has an entry in the FTS5 index.
+    pub fts_indexed: bool,
+    pub created_at: f64,
+    pub updated_at: f64,
+    pub last_accessed: f64,
+    pub access_count: i64,
+    /// `embed::estimate_tokens` of the content.
+    pub estimated_tokens: usize,
+}
+
+/// A binary blob stored alongside a memory (see `Memori::add_attachment`).
+/// Attachments are never searched or embedded.
+#[derive(Clone, Debug, Serialize)]
+pub struct Attachment {
+    pub memory_id: String,
+    pub name: String,
+    pub mime: String,
+    pub data: Vec<u8>,
+    pub created_at: f64,
+}
+
+/// An attachment's description without its data, as listed by
+/// `Memori::list_attachments`.
+#[derive(Clone, Debug, Serialize)]
+pub struct AttachmentInfo {
+    pub name: String,
+    pub mime: String,
+    /// Length of the data in bytes.
+    pub size: usize,
+    pub created_at: f64,
+}
+
+/// Result of `Memori::verify_fts`: how the FTS5 index differs from `memories`.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct FtsReport {
+    /// IDs of memories with no entry in the FTS index (text search can't find them).
+    pub missing: Vec<String>,
+    /// FTS index rowids with no matching row in `memories`.
+    pub orphaned: Vec<i64>,
+    /// Whether FTS5's own `integrity-check` passed on the index structure.
+    pub index_ok: bool,
+}
+
+impl FtsReport {
+    pub fn is_consistent(&self) -> bool {
+        self.index_ok && self.missing.is_empty() && self.orphaned.is_empty()
+    }
+}
+
+/// Result of `Memori::migrate_legacy`.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct MigrationReport {
+    /// `user_version` the file had when this handle opened it (0 for a new file
+    /// or a pre-v1 database).
+    pub from_version: i32,
+    /// `user_version` after the migrations run by `open`.
+    pub to_version: i32,
+    /// Rows re-indexed by the forced FTS rebuild, across all collections.
+    pub reindexed: usize,
+    /// Memories in this collection given an embedding by the backfill.
+    pub embedded: usize,
+}
+
+/// Result of `Memori::open_diagnostic`: what opening the file did.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct OpenReport {
+    /// The database had no `memories` table, i.e. this open created it.
+    pub created: bool,
+    /// `user_version` before the migrations run by `open` (0 when created).
+    pub version_before: i32,
+    /// `user_version` after them.
+    pub version_after: i32,
+    /// Migrations upgraded an existing database.
+    pub migrated: bool,
+    /// The build can embed text (the `embeddings` or `mock_embeddings` feature).
+    pub embeddings_enabled: bool,
+    /// The database has the `memories_fts` index.
+    pub fts_enabled: bool,
+}
+
+/// How vector BLOBs are encoded. Recorded per database on first open.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VectorPrecision {
+    /// 4 bytes per dimension.
+    #[default]
+    F32,
+    /// IEEE half precision, 2 bytes per dimension; widened to f32 on read.
+    /// Needs the `f16` feature.
+    F16,
+}
+
+impl VectorPrecision {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VectorPrecision::F32 => "f32",
+            VectorPrecision::F16 => "f16",
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> std::result::Result<Self, String> {
+        match s {
+            "f32" => Ok(VectorPrecision::F32),
+            "f16" => Ok(VectorPrecision::F16),
+            _ => Err(format!("invalid vector precision '{}': expected f32|f16", s)),
+        }
+    }
+
+    pub fn bytes_per_dim(&self) -> usize {
+        match self {
+            VectorPrecision::F32 => 4,
+            VectorPrecision::F16 => 2,
+        }
+    }
+}
+
+/// What `search` does with a query that has neither a vector nor text.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EmptyQueryBehavior {
+    /// The most recently updated memories, up to `limit`.
+    #[default]
+    Recent,
+    /// No results.
+    Empty,
+    /// `InvalidQuery`.
+    Error,
+}
+
+/// Tuning for how search turns similarity into a ranking score.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ScoringConfig {
+    /// Upper bound on the access-frequency multiplier `1 + 0.1 * ln(1 + count)`
+    /// (e.g. `1.5`), so a heavily used memory is favored without swamping a
+    /// far more relevant one. `None` leaves it unbounded. `importance_score`
+    /// is not capped.
+    pub max_access_boost: Option<f32>,
+}
+
+/// How hybrid search combines its vector and text arms.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum FusionMode {
+    /// Reciprocal rank fusion: only each candidate's rank in each arm counts.
+    #[default]
+    Rrf,
+    /// Min-max normalize each arm's scores to 0..1 and rank by
+    /// `vector_weight * vector + text_weight * text`, a candidate missing
+    /// from an arm scoring 0 there. Keeps how much better one match is.
+    WeightedScore { vector_weight: f32, text_weight: f32 },
+}
+
+/// What writing content over `MemoriConfig::token_budget` does.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TokenBudgetBehavior {
+    /// Insert anyway and call `MemoriConfig::token_warning`.
+    #[default]
+    Warn,
+    /// Refuse with `OverTokenBudget`.
+    Error,
+}
+
+/// `PRAGMA wal_checkpoint` mode for `Memori::checkpoint`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CheckpointMode {
+    /// Copy what it can without waiting on readers or writers.
+    #[default]
+    Passive,
+    /// Wait for writers, then copy the whole log.
+    Full,
+    /// `Full`, then truncate the `-wal` file to zero bytes.
+    Truncate,
+}
+
+impl CheckpointMode {
+    pub fn sql_keyword(&self) -> &'static str {
+        match self {
+            CheckpointMode::Passive => "PASSIVE",
+            CheckpointMode::Full => "FULL",
+            CheckpointMode::Truncate => "TRUNCATE",
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> std::result::Result<Self, String> {
+        match s {
+            "passive" => Ok(CheckpointMode::Passive),
+            "full" => Ok(CheckpointMode::Full),
+            "truncate" => Ok(CheckpointMode::Truncate),
+            _ => Err(format!(
+                "invalid checkpoint mode '{}': expected passive|full|truncate",
+                s
+            )),
+        }
+    }
+}
+
+/// What SQLite reported for a `Memori::checkpoint`. Frame counts are -1 when
+/// the database isn't in WAL mode (e.g. `:memory:`), and 0 after a
+/// successful `Truncate`, which empties the log before reporting.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CheckpointResult {
+    /// The checkpoint couldn't finish because another connection was busy.
+    pub busy: bool,
+    /// Frames in the WAL when the checkpoint ran.
+    pub log_frames: i64,
+    /// Frames copied back into the database file.
+    pub checkpointed_frames: i64,
+}
+
+/// Row counts and approximate size of one collection, or of the whole file.
+/// See `Memori::collection_stats` and `Memori::stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct StoreStats {
+    /// Memories in the hot table.
+    pub memories: usize,
+    /// Hot memories with a vector.
+    pub embedded: usize,
+    pub archived: usize,
+    /// Soft-deleted tombstones.
+    pub deleted: usize,
+    /// Bytes of content, metadata, and vectors across all three tiers. An
+    /// estimate of space used: SQLite can't size a subset of the file, and
+    /// indexes, the FTS index, and page overhead aren't counted.
+    pub data_bytes: u64,
+}
+
+/// Operation counts since a `Memori` handle opened (or since
+/// `Memori::reset_metrics`). See `Memori::metrics`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct MetricsSnapshot {
+    /// Successful `insert`/`insert_with_id` calls, deduplicated ones included.
+    pub inserts: u64,
+    /// Inserts that merged into an existing memory instead of creating one.
+    pub dedup_merges: u64,
+    /// Successful `search`, `search_archive`, and `search_all` calls.
+    pub searches: u64,
+    /// `get` calls, whether or not the memory was found.
+    pub gets: u64,
+}
+
+/// A frozen `list` ordering from `Memori::list_snapshot`. Pages read through
+/// it keep their order even as access stats change underneath.
+#[derive(Clone, Debug, Serialize)]
+pub struct ListSnapshot {
+    /// Identifies the snapshot on the connection that created it.
+    pub token: String,
+    /// Memories captured when the snapshot was taken.
+    pub total: usize,
+    pub page_size: usize,
+}
+
+impl ListSnapshot {
+    /// Number of pages, counting a final partial one.
+    pub fn pages(&self) -> usize {
+        if self.page_size == 0 {
+            0
+        } else {
+            self.total.div_ceil(self.page_size)
+        }
+    }
+}
+
+type WarningFn = dyn Fn(&str, &str) + Send + Sync;
+
+/// Callback invoked with `(id, message)` when a lenient read drops metadata
+/// that fails to parse as JSON.
+#[derive(Clone)]
+pub struct WarningHook(pub Arc<WarningFn>);
+
+impl WarningHook {
+    pub fn new(f: impl Fn(&str, &str) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+}
+
+impl fmt::Debug for WarningHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("WarningHook(..)")
+    }
+}
+
+type PreprocessFn = dyn Fn(&str) -> String + Send + Sync;
+
+/// Rewrite applied to text just before it is embedded: on insert, on
+/// re-embeds after updates, in backfills, and to query text. Stored content
+/// and the FTS index always keep the original.
+#[derive(Clone, Default)]
+pub enum EmbedPreprocess {
+    #[default]
+    None,
+    /// Lowercase and collapse whitespace.
+    Lowercase,
+    /// Drop markdown syntax and collapse whitespace; see `util::strip_markdown`.
+    StripMarkdown,
+    Custom(Arc<PreprocessFn>),
+}
+
+impl EmbedPreprocess {
+    pub fn custom(f: impl Fn(&str) -> String + Send + Sync + 'static) -> Self {
+        Self::Custom(Arc::new(f))
+    }
+
+    /// The text to embed for `text`.
+    pub fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match self {
+            EmbedPreprocess::None => Cow::Borrowed(text),
+            EmbedPreprocess::Lowercase => Cow::Owned(crate::util::collapse_whitespace(&text.to_lowercase())),
+            EmbedPreprocess::StripMarkdown => Cow::Owned(crate::util::strip_markdown(text)),
+            EmbedPreprocess::Custom(f) => Cow::Owned(f(text)),
+        }
+    }
+}
+
+impl fmt::Debug for EmbedPreprocess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmbedPreprocess::None => f.write_str("None"),
+            EmbedPreprocess::Lowercase => f.write_str("Lowercase"),
+            EmbedPreprocess::StripMarkdown => f.write_str("StripMarkdown"),
+            EmbedPreprocess::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+/// How `insert` generates ids for new memories.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IdStrategy {
+    /// Random UUIDv4 (36 chars).
+    #[default]
+    UuidV4,
+    /// Time-ordered UUIDv7 (36 chars): ids sort in creation order, including
+    /// within the same millisecond for one process.
+    UuidV7,
+    /// ULID (26 Crockford base32 chars): sorts by creation millisecond.
+    Ulid,
+}
+
+impl IdStrategy {
+    pub fn generate(&self) -> String {
+        match self {
+            IdStrategy::UuidV4 => uuid::Uuid::new_v4().to_string(),
+            IdStrategy::UuidV7 => uuid::Uuid::now_v7().to_string(),
+            IdStrategy::Ulid => {
+                let millis = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_millis() as u64;
+                let mut random = [0u8; 10];
+                random.copy_from_slice(&uuid::Uuid::new_v4().as_bytes()[..10]);
+                crate::util::encode_ulid(millis, &random)
+            }
+        }
+    }
+}
+
+/// Options for `Memori::open_with_config`. `Default` matches `Memori::open`.
+#[derive(Clone, Debug)]
+pub struct MemoriConfig {
+    /// L2-normalize vectors produced by the embedding model (insert, update,
+    /// backfill) before storing them.
+    pub normalize_embeddings: bool,
+    /// Also L2-normalize vectors passed explicitly by the caller.
+    pub normalize_vectors: bool,
+    /// Return `MemoriError::Json` when a row's stored metadata fails to parse,
+    /// instead of reporting `metadata: None`.
+    pub strict_metadata: bool,
+    /// Called when lenient mode drops unparseable metadata. Ignored when strict.
+    pub metadata_warning: Option<WarningHook>,
+    /// Number of query-text embeddings to memoize (LRU) for repeated searches.
+    /// 0 disables the cache. Only used with the `embeddings` feature.
+    pub query_cache_size: usize,
+    /// Text placed between content and metadata in the FTS index and in the
+    /// text embedded after a metadata update. `None` means a single space.
+    /// Punctuation alone doesn't separate FTS5 phrase positions; a sentinel
+    /// word such as `" memorisep "` does. The FTS triggers are baked into the
+    /// database, so this applies when one is created, and to existing ones
+    /// via `Memori::rebuild_fts`. The database records the separator it was
+    /// built with; opening it with `None` adopts that one.
+    pub text_separator: Option<String>,
+    /// Number of memories `get` keeps in an in-memory LRU, skipping SQLite on
+    /// hits. 0 disables it. Hits still count as accesses: the bumps are
+    /// written back in batches, so `search`/`list` may briefly see slightly
+    /// stale access stats for cached ids.
+    pub get_cache_size: usize,
+    /// Named collection this handle reads and writes; `None` means
+    /// `"default"`. Collections share one file (and its FTS index) but are
+    /// otherwise isolated, including deduplication.
+    pub collection: Option<String>,
+    /// Id format for `insert`. Prefix resolution works with all of them.
+    pub id_strategy: IdStrategy,
+    /// Ignore dashes when resolving id prefixes, so `aaa1-11` finds
+    /// `aaa111...`. Off by default: prefixes must match exactly.
+    pub dash_insensitive_prefix: bool,
+    /// Create the FTS5 index and its triggers when creating a new database.
+    /// With `false`, writes skip FTS maintenance and text or hybrid search
+    /// returns `MemoriError::FtsDisabled`. Existing databases keep whatever
+    /// they were created with.
+    pub enable_fts: bool,
+    /// Which existing memories `insert` considers when deduplicating.
+    pub dedup_scope: DedupScope,
+    /// Stop the dedup scan at the first candidate at least this similar,
+    /// instead of finding the most similar one. Faster on large scopes, but
+    /// which memory absorbs an insert then depends on scan order.
+    pub dedup_sure_match: Option<f32>,
+    /// Treat an insert whose content is byte-for-byte identical to an
+    /// existing memory's (within `dedup_scope`) as a duplicate, whatever
+    /// `dedup_threshold` and `no_embed` say. The existing memory takes the
+    /// new metadata, if any, and a fresh `updated_at`.
+    pub exact_dedup: bool,
+    /// Buffer the access-stat writes of `get` in memory and write them in one
+    /// transaction every this many accesses, on `Memori::flush_access`, or
+    /// on drop. 0 writes each access immediately. Until a flush, search
+    /// ranking and `list` see the last-written stats.
+    pub touch_batch_size: usize,
+    /// Number of `search` result sets to memoize (LRU), keyed on the whole
+    /// query. Any write through this handle clears it. 0 disables it. Cached
+    /// results keep the scores they were ranked with, so access bumps from
+    /// `get` and the passing of time don't re-rank them until the next write.
+    pub result_cache_size: usize,
+    /// Rewrite text before embedding it; see `EmbedPreprocess`. Changing it
+    /// doesn't touch existing vectors, but the next re-embed of each memory
+    /// (e.g. on a metadata update) uses the new rewrite.
+    pub embed_preprocess: EmbedPreprocess,
+    /// Rank `related` (and `export_neighborhood`) by cosine similarity alone,
+    /// ignoring access counts and recency. On by default; `false` applies
+    /// search's access boost.
+    pub related_pure_similarity: bool,
+    /// Most distinct terms a text query sends to FTS5; later ones are
+    /// ignored. Hybrid search still embeds the full text. 0 means no cap.
+    pub max_query_terms: usize,
+    /// Store metadata JSON longer than this many bytes deflated, keeping only
+    /// its top-level scalar values in the plain column; reads return the full
+    /// object. Once a handle with this set opens a database, FTS, filters,
+    /// and metadata indexes there read the full JSON through the
+    /// `memori_metadata` SQL function, so nested values still match. Needs
+    /// the `compress_metadata` feature (ignored without it), which writes to
+    /// such a database then require too; `None` (the default) never
+    /// compresses.
+    ///
+    /// The blob is raw deflate, not the zstd the feature was requested with:
+    /// flate2's pure-Rust backend builds without a C toolchain. The choice is
+    /// awaiting the requester's sign-off; a switch to zstd would need a new
+    /// schema marker, since existing `metadata_z` blobs are deflate.
+    pub compress_metadata_over: Option<usize>,
+    /// Whether `get` and `get_many` count an access (`access_count`,
+    /// `last_accessed`). On by default; off, reads write nothing and skip
+    /// the `get` cache, like `get_raw`.
+    pub track_access: bool,
+    /// Encoding for stored vectors. Only takes effect on a database with no
+    /// recorded precision and no vectors yet; after that the recorded one is
+    /// used whatever this says, and `config()` reports it.
+    pub vector_precision: VectorPrecision,
+    /// Shortest id prefix `resolve_prefix` will search for; shorter ones are
+    /// `PrefixTooShort` unless they are an exact id. Default 4; 0 allows any.
+    pub min_prefix_len: usize,
+    /// How `search` (and the archive and tombstone tiers) answer a query
+    /// with no vector and no text. Default `Recent`.
+    pub empty_query_behavior: EmptyQueryBehavior,
+    /// SQL functions `SearchQuery::raw_filter` may call, besides
+    /// `json_extract`. `Memori::register_scalar_function` adds to it; a name
+    /// listed here but never registered fails when the query runs. Empty by
+    /// default.
+    pub filter_functions: Vec<String>,
+    /// Prior versions `update` and `replace` keep per memory in
+    /// `memory_history` (see `Memori::history`), dropping the oldest beyond
+    /// this. 0 (the default) records nothing.
+    pub history_limit: usize,
+    /// Apply search's recency decay to full-text scores as well as vector
+    /// ones. On by default; `false` keeps only the access-count boost in a
+    /// text result's `score`, since the multiplicative decay distorts BM25's
+    /// scale. Text results are returned in FTS rank order either way.
+    pub text_decay: bool,
+    /// Estimated tokens (`embed::estimate_tokens`) content may have on
+    /// insert, `insert_with_id`, `update` or `replace` before
+    /// `token_budget_behavior` applies, typically
+    /// `embed::MODEL_MAX_TOKENS` so truncated embeddings don't go unnoticed.
+    /// `None` (the default) skips the check. Imports are exempt.
+    pub token_budget: Option<usize>,
+    pub token_budget_behavior: TokenBudgetBehavior,
+    /// Called with `(id, message)` after an over-budget write under
+    /// `TokenBudgetBehavior::Warn`.
+    pub token_warning: Option<WarningHook>,
+    /// Create a new database's FTS index with content and metadata in
+    /// separate columns instead of joined by `text_separator`, so
+    /// `bm25_weights` can rank them differently. An existing database keeps
+    /// its layout until `Memori::rebuild_fts` splits it; a split index is
+    /// never joined back.
+    pub split_fts: bool,
+    /// `(content, metadata)` column weights for `bm25()` in text search, e.g.
+    /// `(2.0, 1.0)` to rank content matches above metadata ones. Only used
+    /// with a split FTS index; `None` keeps FTS5's default equal weights.
+    pub bm25_weights: Option<(f64, f64)>,
+    /// How hybrid search fuses its arms. Defaults to RRF.
+    pub fusion: FusionMode,
+    /// Search score shaping, e.g. the access-boost ceiling.
+    pub scoring: ScoringConfig,
+    /// Create a file database's missing parent directories on open. Off, a
+    /// missing parent fails with an `Io` error naming it.
+    pub create_parent_dirs: bool,
+    /// How dedup merges combine array-valued metadata: a duplicate insert
+    /// (exact or by vector), `merge` and `dedup_existing`. `update` with
+    /// `merge_metadata` always replaces arrays.
+    pub array_merge: ArrayMerge,
+}
+
+impl Default for MemoriConfig {
+    fn default() -> Self {
+        Self {
+            normalize_embeddings: false,
+            normalize_vectors: false,
+            strict_metadata: false,
+            metadata_warning: None,
+            query_cache_size: 0,
+            text_separator: None,
+            get_cache_size: 0,
+            collection: None,
+            id_strategy: IdStrategy::default(),
+            dash_insensitive_prefix: false,
+            enable_fts: true,
+            dedup_scope: DedupScope::default(),
+            dedup_sure_match: None,
+            exact_dedup: false,
+            touch_batch_size: 0,
+            result_cache_size: 0,
+            embed_preprocess: EmbedPreprocess::None,
+            related_pure_similarity: true,
+            max_query_terms: 64,
+            compress_metadata_over: None,
+            track_access: true,
+            vector_precision: VectorPrecision::F32,
+            min_prefix_len: 4,
+            empty_query_behavior: EmptyQueryBehavior::Recent,
+            filter_functions: Vec::new(),
+            history_limit: 0,
+            text_decay: true,
+            token_budget: None,
+            token_budget_behavior: TokenBudgetBehavior::Warn,
+            token_warning: None,
+            split_fts: false,
+            bm25_weights: None,
+            fusion: FusionMode::default(),
+            scoring: ScoringConfig::default(),
+            create_parent_dirs: false,
+            array_merge: ArrayMerge::Replace,
+        }
+    }
+}
+
+impl MemoriConfig {
+    /// The configured `text_separator`, or the default single space.
+    pub fn text_separator(&self) -> &str {
+        self.text_separator
+            .as_deref()
+            .unwrap_or(crate::schema::DEFAULT_TEXT_SEPARATOR)
+    }
+
+    /// The configured `collection`, or `"default"`.
+    pub fn collection(&self) -> &str {
+        self.collection
+            .as_deref()
+            .unwrap_or(crate::schema::DEFAULT_COLLECTION)
+    }
+}
+
+/// Which existing memories are deduplication candidates for an insert.
+/// Candidates are always limited to the handle's collection.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum DedupScope {
+    /// Memories with the same metadata `type` when the incoming memory has
+    /// one, otherwise every memory.
+    #[default]
+    Type,
+    /// Every memory, regardless of metadata.
+    Global,
+    /// Memories whose metadata agrees with the incoming memory's on every
+    /// listed key. A key the incoming memory lacks only matches memories that
+    /// also lack it. Keys must match `[a-zA-Z_][a-zA-Z0-9_]*`.
+    Keys(Vec<String>),
+}
+
+/// Which retrieval path `search` takes. A vector is "derivable" when the query
+/// has one, or has text and the embeddings feature is compiled in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SearchMode {
+    /// Hybrid when both text and a derivable vector exist, else whichever one
+    /// does; neither returns the most recently updated memories.
+    #[default]
+    Auto,
+    /// FTS5 only; any vector is ignored. Requires `text`.
+    TextOnly,
+    /// Cosine similarity only; any text is used just to derive the vector.
+    VectorOnly,
+    /// Fusion (`MemoriConfig::fusion`) of text and vector search. Requires `text` and a derivable vector.
+    Hybrid,
+}
+
+impl SearchMode {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> std::result::Result<Self, String> {
+        match s {
+            "auto" => Ok(SearchMode::Auto),
+            "text" => Ok(SearchMode::TextOnly),
+            "vector" => Ok(SearchMode::VectorOnly),
+            "hybrid" => Ok(SearchMode::Hybrid),
+            _ => Err(format!(
+                "invalid search mode '{}': expected auto|text|vector|hybrid",
+                s
+            )),
+        }
+    }
+}
+
+/// What `search` reports in each result's `score`. Ordering is unaffected.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ScoreReport {
+    /// The score results were ranked by (fused, in hybrid mode).
+    #[default]
+    Fusion,
+    /// Cosine similarity to the query vector, `None` for a result without a
+    /// vector or a query without a derivable one.
+    VectorCosine,
+    /// The raw BM25 score for the query text, `None` for a result the text
+    /// does not match or a query without text.
+    TextRank,
+}
+
+impl ScoreReport {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> std::result::Result<Self, String> {
+        match s {
+            "fusion" => Ok(ScoreReport::Fusion),
+            "vector_cosine" => Ok(ScoreReport::VectorCosine),
+            "text_rank" => Ok(ScoreReport::TextRank),
+            _ => Err(format!(
+                "invalid report_score '{}': expected fusion|vector_cosine|text_rank",
+                s
+            )),
+        }
+    }
+}
+
+/// Why `search` leaves a memory out of a query's results, from
+/// `Memori::explain_absence`. Gates are checked in the order listed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AbsenceReason {
+    /// Not absent: `search` returns it at this 0-based position.
+    Returned { rank: usize },
+    /// Listed in `exclude_ids`.
+    Excluded,
+    /// Fails the metadata `filter` or the `raw_filter` predicate.
+    FilterMismatch,
+    /// Created outside the `before`/`after` range.
+    DateOutOfRange,
+    /// The query only ranks by vector and the memory has none.
+    NoVector,
+    /// The query's text does not match it (and no vector arm can find it).
+    TextNoMatch,
+    /// Its text match scores below `min_fts_score`.
+    BelowScoreFloor,
+    /// Dropped as a near-duplicate of a higher-ranked result (`dedup_results`).
+    CollapsedDuplicate,
+    /// Passes every gate but ranks at this 0-based position by relevance,
+    /// past the limit (or the `rerank_by` pool).
+    TruncatedByLimit { rank: usize },
+    /// Passes every gate but the query ranks nothing, e.g. an empty query
+    /// under `EmptyQueryBehavior::Empty`.
+    NotRanked,
+}
+
+/// How the terms of a text query combine in the FTS5 MATCH expression.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextMatchMode {
+    /// Every term must appear (FTS5's implicit AND).
+    #[default]
+    All,
+    /// Any term may appear; rows matching more terms still rank higher.
+    Any,
+}
+
+impl TextMatchMode {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> std::result::Result<Self, String> {
+        match s {
+            "all" => Ok(TextMatchMode::All),
+            "any" => Ok(TextMatchMode::Any),
+            _ => Err(format!("invalid text match mode '{}': expected all|any", s)),
+        }
+    }
+
+    /// Separator placed between quoted terms.
+    pub(crate) fn joiner(self) -> &'static str {
+        match self {
+            TextMatchMode::All => " ",
+            TextMatchMode::Any => " OR ",
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -43,24 +900,78 @@ pub struct SearchQuery {
     pub text: Option<String>,
     pub filter: Option<serde_json::Value>,
     pub limit: usize,
-    /// When true, skip auto-vectorization and use FTS5 only for text queries.
+    pub mode: SearchMode,
+    /// Alias for `mode: SearchMode::TextOnly`, honoured when `mode` is `Auto`
+    /// and the query has text but no vector.
+    #[deprecated(note = "use `mode: SearchMode::TextOnly`")]
     pub text_only: bool,
     /// Filter: only return memories created before this timestamp (epoch seconds).
     pub before: Option<f64>,
     /// Filter: only return memories created after this timestamp (epoch seconds).
     pub after: Option<f64>,
+    /// Collapse near-duplicate results: drop any result whose cosine similarity to
+    /// an already-kept, higher-ranked result exceeds this threshold.
+    pub dedup_results: Option<f32>,
+    /// Drop text-search candidates whose BM25 base score (`-rank`) is below
+    /// this value, before the access boost. Applies to text-only and hybrid.
+    pub min_fts_score: Option<f32>,
+    /// Extra predicate ANDed into the WHERE clause, e.g.
+    /// `json_extract(metadata, '$.priority') >= 2 OR json_extract(metadata, '$.pinned') = 1`.
+    /// Only `json_extract(metadata, '$.path')`, numeric and quoted-string
+    /// literals, comparisons, AND/OR/NOT, and parentheses are accepted;
+    /// anything else is `InvalidFilter`.
+    pub raw_filter: Option<String>,
+    /// Two-stage ranking: take the `limit * rerank_oversample` most relevant
+    /// results, then order that pool by this field, descending (newest,
+    /// most accessed first), and keep `limit`. Ties keep relevance order.
+    pub rerank_by: Option<SortField>,
+    /// Candidate pool multiplier for `rerank_by`. Default 5.
+    pub rerank_oversample: usize,
+    /// Whether text search needs all query terms (default) or any of them.
+    pub text_match: TextMatchMode,
+    /// Also search soft-deleted memories, returned with `deleted_at` set.
+    /// Off by default.
+    pub include_deleted: bool,
+    /// Ids (or prefixes) to leave out of the results, e.g. memories already
+    /// in the caller's context.
+    pub exclude_ids: Vec<String>,
+    /// Chain the search arms so a query always yields some context, first
+    /// non-empty arm wins: vector search (the query's vector, or its text
+    /// embedded) keeping results whose base score reaches
+    /// `fallback_min_score`; then text search on `text`; then the most
+    /// recently updated memories. Replaces `mode` and `empty_query_behavior`.
+    pub fallback: bool,
+    /// Cosine floor for the vector arm of `fallback`. `None` accepts any
+    /// vector result.
+    pub fallback_min_score: Option<f32>,
+    /// What each result's `score` holds, e.g. a human-readable cosine for
+    /// hybrid results instead of their RRF score.
+    pub report_score: ScoreReport,
 }
 
 impl Default for SearchQuery {
+    #[allow(deprecated)]
     fn default() -> Self {
         Self {
             vector: None,
             text: None,
             filter: None,
             limit: 10,
+            mode: SearchMode::Auto,
             text_only: false,
             before: None,
             after: None,
+            dedup_results: None,
+            min_fts_score: None,
+            raw_filter: None,
+            rerank_by: None,
+            rerank_oversample: 5,
+            text_match: TextMatchMode::All,
+            include_deleted: false,
+            exclude_ids: Vec::new(),
+            fallback: false,
+            fallback_min_score: None,
+            report_score: ScoreReport::default(),
         }
     }
 }
@@ -76,6 +987,16 @@ pub enum SortField {
 }
 
 impl SortField {
+    /// The field's value on `mem`, for sorting results in memory.
+    pub fn value_of(&self, mem: &Memory) -> f64 {
+        match self {
+            SortField::Created => mem.created_at,
+            SortField::Updated => mem.updated_at,
+            SortField::Accessed => mem.last_accessed,
+            SortField::Count => mem.access_count as f64,
+        }
+    }
+
     pub fn sql_column(&self) -> &'static str {
         match self {
             SortField::Created => "created_at",
@@ -85,6 +1006,7 @@ impl SortField {
         }
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> std::result::Result<Self, String> {
         match s {
             "created" => Ok(SortField::Created),
@@ -99,6 +1021,59 @@ impl SortField {
     }
 }
 
+/// Sort direction for `list_sorted`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortDirection {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl SortDirection {
+    pub fn sql_keyword(&self) -> &'static str {
+        match self {
+            SortDirection::Asc => "ASC",
+            SortDirection::Desc => "DESC",
+        }
+    }
+}
+
+/// How `Memori::merge` combines the content of the two memories.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// Keep the survivor's content unchanged.
+    #[default]
+    KeepContent,
+    /// Append the dropped memory's content to the survivor's, separated by a blank line.
+    Concatenate,
+}
+
+/// How a dedup merge (duplicate insert, `Memori::merge`, `dedup_existing`)
+/// combines an array-valued metadata key present on both memories.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ArrayMerge {
+    /// The survivor's array (an insert's incoming one) replaces the other's.
+    #[default]
+    Replace,
+    /// Elements of either array, each once, the dropped (or stored) memory's first.
+    Union,
+    /// The dropped (or stored) memory's elements followed by the survivor's,
+    /// duplicates kept.
+    Append,
+}
+
+impl ArrayMerge {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> std::result::Result<Self, String> {
+        match s {
+            "replace" => Ok(ArrayMerge::Replace),
+            "union" => Ok(ArrayMerge::Union),
+            "append" => Ok(ArrayMerge::Append),
+            _ => Err(format!("invalid array merge '{}': expected replace|union|append", s)),
+        }
+    }
+}
+
 /// Result of an insert operation -- either a new memory was created or
 /// an existing one was updated via deduplication.
 #[derive(Clone, Debug)]
@@ -118,3 +1093,140 @@ impl InsertResult {
         matches!(self, InsertResult::Deduplicated(_))
     }
 }
+
+/// Result of `Memori::get_or_insert_by_metadata`: the memory already carrying
+/// the key, or the one just created for it.
+#[derive(Clone, Debug)]
+pub enum GetOrInsert {
+    Found(Memory),
+    Created(Memory),
+}
+
+impl GetOrInsert {
+    pub fn memory(&self) -> &Memory {
+        match self {
+            GetOrInsert::Found(m) | GetOrInsert::Created(m) => m,
+        }
+    }
+
+    pub fn into_memory(self) -> Memory {
+        match self {
+            GetOrInsert::Found(m) | GetOrInsert::Created(m) => m,
+        }
+    }
+
+    pub fn was_created(&self) -> bool {
+        matches!(self, GetOrInsert::Created(_))
+    }
+}
+
+/// A single RFC 6902 operation for `patch_metadata`. Paths are JSON pointers
+/// (RFC 6901), e.g. `/author/name` or `/tags/0`; `""` addresses the whole object.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOp {
+    Add { path: String, value: serde_json::Value },
+    Remove { path: String },
+    Replace { path: String, value: serde_json::Value },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn mem_with(metadata: Option<serde_json::Value>) -> Memory {
+        Memory {
+            id: "id".to_string(),
+            content: "content".to_string(),
+            vector: None,
+            metadata,
+            created_at: 0.0,
+            updated_at: 0.0,
+            last_accessed: 0.0,
+            access_count: 0,
+            score: None,
+            base_score: None,
+            deleted_at: None,
+        }
+    }
+
+    #[test]
+    fn test_memory_columns_keep_row_order() {
+        let mut second = mem_with(None);
+        second.id = "second".to_string();
+        second.score = Some(0.5);
+        let cols: MemoryColumns = vec![mem_with(Some(json!({"type": "fact"}))), second].into_iter().collect();
+        assert_eq!(cols.len(), 2);
+        assert_eq!(cols.id, vec!["id", "second"]);
+        assert_eq!(cols.metadata, vec![Some(r#"{"type":"fact"}"#.to_string()), None]);
+        assert_eq!(cols.score, vec![None, Some(0.5)]);
+        assert_eq!(cols.deleted_at, vec![None, None]);
+    }
+
+    #[test]
+    fn test_meta_accessors_present() {
+        let m = mem_with(Some(json!({"type": "fact", "source": "web", "n": 42, "ok": true})));
+        assert_eq!(m.type_str(), Some("fact"));
+        assert_eq!(m.meta_str("source"), Some("web"));
+        assert_eq!(m.meta_i64("n"), Some(42));
+        assert_eq!(m.meta_bool("ok"), Some(true));
+    }
+
+    #[test]
+    fn test_meta_accessors_absent() {
+        let none = mem_with(None);
+        assert_eq!(none.type_str(), None);
+        assert_eq!(none.meta_i64("n"), None);
+
+        let empty = mem_with(Some(json!({})));
+        assert_eq!(empty.meta_str("source"), None);
+        assert_eq!(empty.meta_bool("ok"), None);
+
+        // Non-object metadata has no fields
+        let array = mem_with(Some(json!(["type"])));
+        assert_eq!(array.type_str(), None);
+    }
+
+    #[test]
+    fn test_meta_accessors_wrong_type() {
+        let m = mem_with(Some(json!({"type": 7, "n": "42", "f": 1.5, "ok": "true"})));
+        assert_eq!(m.type_str(), None);
+        assert_eq!(m.meta_i64("n"), None);
+        assert_eq!(m.meta_i64("f"), None);
+        assert_eq!(m.meta_bool("ok"), None);
+        assert_eq!(m.meta_str("ok"), Some("true"));
+    }
+
+    #[test]
+    fn test_meta_path() {
+        let m = mem_with(Some(json!({
+            "type": "fact",
+            "author": {"name": "ada", "a/b": 1},
+            "tags": ["x", "y"]
+        })));
+        assert_eq!(m.meta_path("type"), Some(&json!("fact")));
+        assert_eq!(m.meta_path("author/name"), Some(&json!("ada")));
+        assert_eq!(m.meta_path("/author/name"), Some(&json!("ada")));
+        assert_eq!(m.meta_path("author/a~1b"), Some(&json!(1)));
+        assert_eq!(m.meta_path("tags/1"), Some(&json!("y")));
+        assert_eq!(m.meta_path("author"), Some(&json!({"name": "ada", "a/b": 1})));
+
+        assert_eq!(m.meta_path("author/email"), None);
+        assert_eq!(m.meta_path("type/name"), None);
+        assert_eq!(m.meta_path("tags/5"), None);
+        assert_eq!(m.meta_path(""), None);
+        assert_eq!(mem_with(None).meta_path("type"), None);
+    }
+
+    #[test]
+    fn test_embed_preprocess_apply() {
+        let text = "Kafka  Uses\n**Topics**";
+        assert_eq!(EmbedPreprocess::None.apply(text), text);
+        assert_eq!(EmbedPreprocess::Lowercase.apply(text), "kafka uses **topics**");
+        assert_eq!(EmbedPreprocess::StripMarkdown.apply(text), "Kafka Uses Topics");
+        let custom = EmbedPreprocess::custom(|t| t.replace("Kafka", "broker"));
+        assert_eq!(custom.apply(text), "broker  Uses\n**Topics**");
+        assert_eq!(format!("{:?}", custom), "Custom(..)");
+    }
+}
@@ -1,4 +1,4 @@
-/// Shared low-level utilities for vector serialization and similarity.
+//! Shared low-level utilities for vector serialization, similarity, and id encoding.
 
 /// Convert a float vector to a raw byte slice for SQLite BLOB storage.
 ///
@@ -29,6 +29,60 @@ pub fn blob_to_vec(b: &[u8]) -> Vec<f32> {
     v
 }
 
+/// Encode a vector as IEEE half-precision bytes (2 per dimension), rounding
+/// each component to the nearest f16.
+#[cfg(feature = "f16")]
+pub fn vec_to_f16_blob(v: &[f32]) -> Vec<u8> {
+    v.iter()
+        .flat_map(|x| half::f16::from_f32(*x).to_le_bytes())
+        .collect()
+}
+
+/// Decode half-precision bytes from `vec_to_f16_blob`, widening to f32.
+///
+/// # Panics
+/// Panics if `b.len()` is not a multiple of 2.
+#[cfg(feature = "f16")]
+pub fn f16_blob_to_vec(b: &[u8]) -> Vec<f32> {
+    assert!(b.len() % 2 == 0, "f16 blob length must be a multiple of 2");
+    b.chunks_exact(2)
+        .map(|c| half::f16::from_le_bytes([c[0], c[1]]).to_f32())
+        .collect()
+}
+
+/// The `p`th percentile (0-100) of ascending, non-empty `sorted`,
+/// interpolating linearly between the two nearest ranks.
+pub fn percentile(sorted: &[f32], p: f32) -> f32 {
+    let rank = (p / 100.0).clamp(0.0, 1.0) * (sorted.len() - 1) as f32;
+    let lo = rank.floor() as usize;
+    let hi = rank.ceil() as usize;
+    sorted[lo] + (sorted[hi] - sorted[lo]) * (rank - lo as f32)
+}
+
+/// Scale a vector in place to unit L2 norm. Zero vectors are left unchanged.
+pub fn l2_normalize(v: &mut [f32]) {
+    let norm: f32 = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        for x in v.iter_mut() {
+            *x /= norm;
+        }
+    }
+}
+
+const CROCKFORD: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Encode a ULID: 48-bit millisecond timestamp then 80 random bits, as 26
+/// Crockford base32 characters. Lexicographic order follows the timestamp.
+pub fn encode_ulid(millis: u64, random: &[u8; 10]) -> String {
+    let mut value = ((millis & 0xFFFF_FFFF_FFFF) as u128) << 80;
+    for (i, b) in random.iter().enumerate() {
+        value |= (*b as u128) << (72 - 8 * i);
+    }
+    (0..26)
+        .map(|i| CROCKFORD[((value >> (125 - 5 * i)) & 0x1F) as usize] as char)
+        .collect()
+}
+
 /// Compute cosine similarity between two float vectors.
 /// Returns 0.0 for empty vectors, mismatched lengths, or zero-norm vectors.
 pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
@@ -54,13 +108,130 @@ pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
     }
 }
 
+/// 64-bit FNV-1a hash. Stable across Rust versions and platforms, unlike
+/// `DefaultHasher`, so it is safe to store.
+pub fn fnv1a_64(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &b in bytes {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+/// Parse a caller-supplied content hash: exactly 16 hex digits (a 64-bit
+/// value, the width `content_hash` stores), either case.
+pub fn parse_content_hash(hex: &str) -> Option<u64> {
+    if hex.len() != 16 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    u64::from_str_radix(hex, 16).ok()
+}
+
+/// Euclidean norm of `v`, stored alongside each vector as `vector_norm`.
+pub fn vector_norm(v: &[f32]) -> f32 {
+    v.iter().map(|x| x * x).sum::<f32>().sqrt()
+}
+
+/// `cosine_similarity` with both norms known up front, so only the dot
+/// product is computed.
+pub fn cosine_similarity_with_norms(a: &[f32], norm_a: f32, b: &[f32], norm_b: f32) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let denom = norm_a * norm_b;
+    if denom == 0.0 {
+        0.0
+    } else {
+        dot / denom
+    }
+}
+
+/// Runs of whitespace collapsed to single spaces, trimmed at both ends.
+pub fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// `text` with its markdown syntax dropped and whitespace collapsed: code
+/// fence lines, heading/quote/list markers, emphasis and inline-code marks
+/// around words, and link/image targets (the link text is kept). Code inside
+/// fences, and underscores inside words such as `snake_case`, are kept.
+pub fn strip_markdown(text: &str) -> String {
+    let mut words: Vec<String> = Vec::new();
+    for line in text.lines() {
+        let mut line = line.trim_start();
+        if line.starts_with("```") || line.starts_with("~~~") {
+            continue;
+        }
+        line = line.trim_start_matches(['>', ' ']);
+        let hashes = line.len() - line.trim_start_matches('#').len();
+        if hashes > 0 && line[hashes..].starts_with(' ') {
+            line = &line[hashes..];
+        }
+        for marker in ["- ", "* ", "+ "] {
+            if let Some(rest) = line.strip_prefix(marker) {
+                line = rest;
+                break;
+            }
+        }
+        let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits > 0 && line[digits..].starts_with(". ") {
+            line = &line[digits + 2..];
+        }
+        for word in drop_link_targets(line).split_whitespace() {
+            let word = word.trim_matches(|c| matches!(c, '*' | '_' | '`' | '~'));
+            if !word.is_empty() {
+                words.push(word.to_string());
+            }
+        }
+    }
+    words.join(" ")
+}
+
+/// `[text](target)` and `![text](target)` reduced to `text`.
+fn drop_link_targets(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(open) = rest.find('[') {
+        let link = rest[open..].find("](").and_then(|mid| {
+            let close = rest[open + mid..].find(')')?;
+            Some((open + mid, open + mid + close))
+        });
+        let Some((mid, close)) = link else {
+            break;
+        };
+        let before = &rest[..open];
+        out.push_str(before.strip_suffix('!').unwrap_or(before));
+        out.push_str(&rest[open + 1..mid]);
+        rest = &rest[close + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_encode_ulid_layout_and_order() {
+        assert_eq!(encode_ulid(0, &[0; 10]), "00000000000000000000000000");
+        assert_eq!(
+            encode_ulid(0xFFFF_FFFF_FFFF, &[0xFF; 10]),
+            "7ZZZZZZZZZZZZZZZZZZZZZZZZZ"
+        );
+        // Timestamp occupies the first 10 characters
+        assert_eq!(&encode_ulid(1, &[0; 10])[..10], "0000000001");
+        assert!(
+            encode_ulid(1_700_000_000_000, &[0xFF; 10]) < encode_ulid(1_700_000_000_001, &[0; 10])
+        );
+    }
+
     #[test]
     fn test_vec_blob_roundtrip() {
-        let original = vec![1.0f32, -2.5, 3.14, 0.0];
+        let original = vec![1.0f32, -2.5, 3.5, 0.0];
         let blob = vec_to_blob(&original);
         let restored = blob_to_vec(blob);
         assert_eq!(original, restored);
@@ -74,12 +245,32 @@ mod tests {
         assert_eq!(original, restored);
     }
 
+    #[cfg(feature = "f16")]
+    #[test]
+    fn test_f16_blob_roundtrip() {
+        let original = vec![1.0f32, -2.5, 0.333, 0.0];
+        let blob = vec_to_f16_blob(&original);
+        assert_eq!(blob.len(), 8);
+        let restored = f16_blob_to_vec(&blob);
+        assert_eq!(&restored[..2], &original[..2]);
+        assert!((restored[2] - 0.333).abs() < 1e-3);
+    }
+
     #[test]
     #[should_panic(expected = "blob length must be a multiple of 4")]
     fn test_blob_to_vec_bad_length() {
         blob_to_vec(&[1, 2, 3]);
     }
 
+    #[test]
+    fn test_percentile_interpolates() {
+        let sorted = [0.0, 1.0, 2.0, 4.0];
+        assert_eq!(percentile(&sorted, 0.0), 0.0);
+        assert_eq!(percentile(&sorted, 100.0), 4.0);
+        assert!((percentile(&sorted, 50.0) - 1.5).abs() < 1e-6);
+        assert_eq!(percentile(&[3.0], 90.0), 3.0);
+    }
+
     #[test]
     fn test_cosine_identical() {
         let v = vec![1.0, 2.0, 3.0];
@@ -100,8 +291,46 @@ mod tests {
         assert_eq!(cosine_similarity(&[], &[]), 0.0);
     }
 
+    #[test]
+    fn test_l2_normalize() {
+        let mut v = vec![3.0, 4.0];
+        l2_normalize(&mut v);
+        assert!((v[0] - 0.6).abs() < 1e-6);
+        assert!((v[1] - 0.8).abs() < 1e-6);
+
+        let mut zero = vec![0.0, 0.0];
+        l2_normalize(&mut zero);
+        assert_eq!(zero, vec![0.0, 0.0]);
+    }
+
     #[test]
     fn test_cosine_mismatched_lengths() {
         assert_eq!(cosine_similarity(&[1.0], &[1.0, 2.0]), 0.0);
     }
+
+    #[test]
+    fn test_parse_content_hash() {
+        assert_eq!(parse_content_hash("00000000000000ff"), Some(255));
+        assert_eq!(parse_content_hash("CBF29CE484222325"), Some(0xcbf2_9ce4_8422_2325));
+        for bad in ["", "ff", "0x000000000000ff", "00000000000000fg", "000000000000000ff"] {
+            assert_eq!(parse_content_hash(bad), None, "accepted {:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_fnv1a_64_known_values() {
+        assert_eq!(fnv1a_64(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a_64(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a_64(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn test_strip_markdown() {
+        let md = "# Setup\n\n> **Note:** run `cargo build` first.\n\n```rust\nlet snake_case = 1;\n```\n\n- see [the docs](https://example.com) and ![logo](logo.png)\n2. done";
+        assert_eq!(
+            strip_markdown(md),
+            "Setup Note: run cargo build first. let snake_case = 1; see the docs and logo done"
+        );
+        assert_eq!(collapse_whitespace("  a \n\t b  "), "a b");
+    }
 }
@@ -1,4 +1,9 @@
-use memori_core::{InsertResult, Memori, SearchQuery, SortField};
+use memori_core::{
+    AbsenceReason, ArrayMerge, CheckpointMode, DedupScope, EmptyQueryBehavior, FusionMode,
+    IdStrategy, InsertResult, Memori, MemoriConfig, MemoriError, MemoryColumns, MergeStrategy,
+    MetricsSnapshot, OpenFlags, PatchOp, ScoreReport, ScoringConfig, SearchMode, SearchQuery,
+    SortDirection, SortField, TextMatchMode, TokenBudgetBehavior, VectorPrecision, WarningHook,
+};
 use serde_json::json;
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -198,7 +203,7 @@ fn test_text_search_fts5() {
 
     let query = SearchQuery {
         text: Some("brown".to_string()),
-        text_only: true,
+        mode: SearchMode::TextOnly,
         limit: 10,
         ..Default::default()
     };
@@ -499,10 +504,10 @@ fn test_fts5_metadata_search() {
         .unwrap();
 
     // Search for "kafka" which only appears in metadata, not content
-    // Use text_only to test pure FTS5 behavior
+    // Use text-only mode to test pure FTS5 behavior
     let query = SearchQuery {
         text: Some("kafka".to_string()),
-        text_only: true,
+        mode: SearchMode::TextOnly,
         limit: 10,
         ..Default::default()
     };
@@ -677,9 +682,22 @@ fn test_dedup_disabled_with_none_threshold() {
     assert_eq!(db.count().unwrap(), 2);
 }
 
+#[test]
+fn test_no_embed_still_dedups_with_explicit_vector() {
+    let db = open_temp();
+    let meta = Some(json!({"type": "fact"}));
+    let r1 = db.insert("first", Some(&[1.0, 0.0, 0.0]), meta.clone(), Some(0.92), true).unwrap();
+    let r2 = db.insert("second", Some(&[0.99, 0.01, 0.0]), meta, Some(0.92), true).unwrap();
+
+    assert!(r2.is_deduplicated());
+    assert_eq!(r2.id(), r1.id());
+    assert_eq!(db.count().unwrap(), 1);
+}
+
 // -- v0.3.1 tests: text_only flag --
 
 #[test]
+#[allow(deprecated)]
 fn test_text_only_search_skips_vectorization() {
     let db = open_temp();
     db.insert("kafka uses partitioned topics", None, None, None, false)
@@ -761,7 +779,7 @@ fn test_search_date_range_with_text() {
 
     let query = SearchQuery {
         text: Some("kafka".to_string()),
-        text_only: true,
+        mode: SearchMode::TextOnly,
         after: Some(now - 3600.0),
         limit: 10,
         ..Default::default()
@@ -788,7 +806,7 @@ fn test_list_basic() {
         .unwrap();
     }
 
-    let results = db.list(None, &SortField::Created, 10, 0, None, None).unwrap();
+    let results = db.list(None, &SortField::Created, 10, 0, None, None, false).unwrap();
     assert_eq!(results.len(), 5);
 }
 
@@ -802,7 +820,7 @@ fn test_list_type_filter() {
     db.insert("fact 2", None, Some(json!({"type": "fact"})), None, false)
         .unwrap();
 
-    let results = db.list(Some("fact"), &SortField::Created, 10, 0, None, None).unwrap();
+    let results = db.list(Some("fact"), &SortField::Created, 10, 0, None, None, false).unwrap();
     assert_eq!(results.len(), 2);
     assert!(results.iter().all(|m| {
         m.metadata.as_ref().unwrap().get("type").unwrap() == "fact"
@@ -817,8 +835,8 @@ fn test_list_pagination() {
             .unwrap();
     }
 
-    let page1 = db.list(None, &SortField::Created, 3, 0, None, None).unwrap();
-    let page2 = db.list(None, &SortField::Created, 3, 3, None, None).unwrap();
+    let page1 = db.list(None, &SortField::Created, 3, 0, None, None, false).unwrap();
+    let page2 = db.list(None, &SortField::Created, 3, 3, None, None, false).unwrap();
     assert_eq!(page1.len(), 3);
     assert_eq!(page2.len(), 3);
     // Pages shouldn't overlap
@@ -838,7 +856,7 @@ fn test_list_sort_by_access_count() {
         let _ = db.get(r2.id());
     }
 
-    let results = db.list(None, &SortField::Count, 10, 0, None, None).unwrap();
+    let results = db.list(None, &SortField::Count, 10, 0, None, None, false).unwrap();
     assert_eq!(results.len(), 2);
     // Most accessed should be first (DESC order)
     assert_eq!(results[0].id, r2.id().to_string());
@@ -922,19 +940,19 @@ fn test_prefix_ambiguous() {
         .unwrap()
         .as_secs_f64();
 
-    // Insert two memories with the same 3-char prefix
-    db.insert_with_id("aaa11111-1111-1111-1111-111111111111", "first", None, None, ts, ts).unwrap();
-    db.insert_with_id("aaa22222-2222-2222-2222-222222222222", "second", None, None, ts, ts).unwrap();
+    // Insert two memories with the same 4-char prefix
+    db.insert_with_id("aaaa1111-1111-1111-1111-111111111111", "first", None, None, ts, ts).unwrap();
+    db.insert_with_id("aaaa2222-2222-2222-2222-222222222222", "second", None, None, ts, ts).unwrap();
 
-    // 3-char prefix "aaa" is ambiguous
-    let result = db.update("aaa", Some("fail"), None, None, false);
+    // 4-char prefix "aaaa" is ambiguous
+    let result = db.update("aaaa", Some("fail"), None, None, false);
     assert!(result.is_err());
     let err_msg = result.unwrap_err().to_string();
     assert!(err_msg.contains("ambiguous"));
     assert!(err_msg.contains("2"));
 
     // But 8-char prefix is unique
-    let mem = db.get("aaa11111").unwrap().expect("8-char prefix should resolve");
+    let mem = db.get("aaaa1111").unwrap().expect("8-char prefix should resolve");
     assert_eq!(mem.content, "first");
 }
 
@@ -992,7 +1010,7 @@ fn test_related_finds_similar() {
     // First result should be the similar one
     assert_eq!(results[0].content, "similar");
     // Self should be excluded
-    assert!(results.iter().all(|r| r.id != r1.id().to_string()));
+    assert!(results.iter().all(|r| r.id != r1.id()));
 }
 
 #[test]
@@ -1000,10 +1018,10 @@ fn test_related_excludes_self() {
     let db = open_temp();
     let v = vec![1.0, 0.0, 0.0];
     let r1 = db.insert("self", Some(&v), None, None, false).unwrap();
-    db.insert("other", Some(&vec![0.9, 0.1, 0.0]), None, None, false).unwrap();
+    db.insert("other", Some(&[0.9, 0.1, 0.0]), None, None, false).unwrap();
 
     let results = db.related(r1.id(), 10).unwrap();
-    assert!(results.iter().all(|r| r.id != r1.id().to_string()));
+    assert!(results.iter().all(|r| r.id != r1.id()));
 }
 
 #[test]
@@ -1051,7 +1069,7 @@ fn test_list_before_filter() {
     db.insert_with_id("old-1", "old memory", None, None, now - 7200.0, now - 7200.0).unwrap();
     db.insert("recent memory", None, None, None, false).unwrap();
 
-    let results = db.list(None, &SortField::Created, 10, 0, Some(now - 3600.0), None).unwrap();
+    let results = db.list(None, &SortField::Created, 10, 0, Some(now - 3600.0), None, false).unwrap();
     assert_eq!(results.len(), 1);
     assert_eq!(results[0].content, "old memory");
 }
@@ -1067,7 +1085,7 @@ fn test_list_after_filter() {
     db.insert_with_id("old-1", "old memory", None, None, now - 7200.0, now - 7200.0).unwrap();
     db.insert("recent memory", None, None, None, false).unwrap();
 
-    let results = db.list(None, &SortField::Created, 10, 0, None, Some(now - 3600.0)).unwrap();
+    let results = db.list(None, &SortField::Created, 10, 0, None, Some(now - 3600.0), false).unwrap();
     assert_eq!(results.len(), 1);
     assert_eq!(results[0].content, "recent memory");
 }
@@ -1085,7 +1103,7 @@ fn test_list_combined_type_and_date() {
     db.insert("new fact", None, Some(json!({"type": "fact"})), None, false).unwrap();
 
     // Only old facts
-    let results = db.list(Some("fact"), &SortField::Created, 10, 0, Some(now - 3600.0), None).unwrap();
+    let results = db.list(Some("fact"), &SortField::Created, 10, 0, Some(now - 3600.0), None, false).unwrap();
     assert_eq!(results.len(), 1);
     assert_eq!(results[0].content, "old fact");
 }
@@ -1099,7 +1117,7 @@ fn test_fts5_query_with_quotes() {
 
     let query = SearchQuery {
         text: Some("\"hello\"".to_string()),
-        text_only: true,
+        mode: SearchMode::TextOnly,
         limit: 10,
         ..Default::default()
     };
@@ -1114,7 +1132,7 @@ fn test_fts5_query_with_parentheses() {
 
     let query = SearchQuery {
         text: Some("(with args)".to_string()),
-        text_only: true,
+        mode: SearchMode::TextOnly,
         limit: 10,
         ..Default::default()
     };
@@ -1131,7 +1149,7 @@ fn test_fts5_query_with_operators() {
     // Searching for "AND" or "OR" should not be interpreted as FTS5 operators
     let query = SearchQuery {
         text: Some("AND OR NOT".to_string()),
-        text_only: true,
+        mode: SearchMode::TextOnly,
         limit: 10,
         ..Default::default()
     };
@@ -1146,7 +1164,7 @@ fn test_fts5_query_with_asterisk() {
 
     let query = SearchQuery {
         text: Some("wildcard*".to_string()),
-        text_only: true,
+        mode: SearchMode::TextOnly,
         limit: 10,
         ..Default::default()
     };
@@ -1161,7 +1179,7 @@ fn test_fts5_query_with_colons() {
 
     let query = SearchQuery {
         text: Some("12:30:00".to_string()),
-        text_only: true,
+        mode: SearchMode::TextOnly,
         limit: 10,
         ..Default::default()
     };
@@ -1176,7 +1194,7 @@ fn test_fts5_empty_query() {
 
     let query = SearchQuery {
         text: Some("".to_string()),
-        text_only: true,
+        mode: SearchMode::TextOnly,
         limit: 10,
         ..Default::default()
     };
@@ -1184,3 +1202,5739 @@ fn test_fts5_empty_query() {
     let results = db.search(query).unwrap();
     assert!(results.is_empty());
 }
+
+// -- access stats restore --
+
+#[test]
+fn test_set_access_stats_count_only_preserves_timestamp() {
+    let db = open_temp();
+    let r = db.insert("imported", None, None, None, false).unwrap();
+    db.set_access_stats(r.id(), Some(1_700_000_000.0), 2).unwrap();
+
+    // None leaves last_accessed alone and only rewrites the count
+    db.set_access_stats(r.id(), None, 7).unwrap();
+
+    let mem = db.get_readonly(r.id()).unwrap().unwrap();
+    assert_eq!(mem.access_count, 7);
+    assert_eq!(mem.last_accessed, 1_700_000_000.0);
+}
+
+#[test]
+fn test_clear_last_accessed() {
+    let db = open_temp();
+    let r = db.insert("imported", None, None, None, false).unwrap();
+    db.set_access_stats(r.id(), Some(1_700_000_000.0), 4).unwrap();
+
+    db.clear_last_accessed(r.id()).unwrap();
+
+    let mem = db.get_readonly(r.id()).unwrap().unwrap();
+    assert_eq!(mem.last_accessed, 0.0);
+    assert_eq!(mem.access_count, 4);
+    assert!(db.clear_last_accessed("nonexistent-id").is_err());
+}
+
+// -- JSON-Patch metadata ops --
+
+#[test]
+fn test_patch_metadata_remove_nested_key() {
+    let db = open_temp();
+    let r = db
+        .insert("patch me", None, Some(json!({"type": "fact", "author": {"name": "a", "email": "a@x"}})), None, false)
+        .unwrap();
+
+    db.patch_metadata(r.id(), vec![PatchOp::Remove { path: "/author/email".to_string() }])
+        .unwrap();
+
+    let meta = db.get_readonly(r.id()).unwrap().unwrap().metadata.unwrap();
+    assert_eq!(meta, json!({"type": "fact", "author": {"name": "a"}}));
+}
+
+#[test]
+fn test_patch_metadata_replace_nested_value() {
+    let db = open_temp();
+    let r = db
+        .insert("patch me", None, Some(json!({"ctx": {"status": "draft", "rev": 1}})), None, false)
+        .unwrap();
+
+    db.patch_metadata(
+        r.id(),
+        vec![PatchOp::Replace { path: "/ctx/status".to_string(), value: json!("final") }],
+    )
+    .unwrap();
+
+    let meta = db.get_readonly(r.id()).unwrap().unwrap().metadata.unwrap();
+    assert_eq!(meta, json!({"ctx": {"status": "final", "rev": 1}}));
+
+    // Replace requires the target to exist; a failed op leaves metadata untouched
+    let err = db.patch_metadata(
+        r.id(),
+        vec![PatchOp::Replace { path: "/ctx/missing".to_string(), value: json!(1) }],
+    );
+    assert!(err.is_err());
+    let meta = db.get_readonly(r.id()).unwrap().unwrap().metadata.unwrap();
+    assert_eq!(meta, json!({"ctx": {"status": "final", "rev": 1}}));
+}
+
+#[test]
+fn test_patch_metadata_add_nested_object_is_searchable() {
+    let db = open_temp();
+    let r = db.insert("patch me", None, None, None, false).unwrap();
+
+    db.patch_metadata(
+        r.id(),
+        vec![
+            PatchOp::Add { path: "/source".to_string(), value: json!({"repo": "zeppelin"}) },
+            PatchOp::Add { path: "/source/tags".to_string(), value: json!(["a"]) },
+            PatchOp::Add { path: "/source/tags/-".to_string(), value: json!("b") },
+        ],
+    )
+    .unwrap();
+
+    let meta = db.get_readonly(r.id()).unwrap().unwrap().metadata.unwrap();
+    assert_eq!(meta, json!({"source": {"repo": "zeppelin", "tags": ["a", "b"]}}));
+
+    // FTS triggers re-index the patched metadata
+    let results = db
+        .search(SearchQuery {
+            text: Some("zeppelin".to_string()),
+            mode: SearchMode::TextOnly,
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(results.len(), 1);
+}
+
+// -- search-time result dedup --
+
+#[test]
+fn test_search_dedup_results_collapses_near_duplicates() {
+    let db = open_temp();
+    let best = db.insert("kafka partitions", Some(&[1.0, 0.0, 0.0]), None, None, false).unwrap();
+    db.insert("kafka partitions (copy)", Some(&[0.999, 0.001, 0.0]), None, None, false).unwrap();
+    let distinct = db.insert("kafka consumers", Some(&[0.7, 0.7, 0.0]), None, None, false).unwrap();
+
+    let base = SearchQuery {
+        vector: Some(vec![1.0, 0.0, 0.0]),
+        limit: 2,
+        ..Default::default()
+    };
+
+    // Without collapsing, the two near-identical rows fill the page
+    let plain = db.search(base.clone()).unwrap();
+    assert_eq!(plain.len(), 2);
+    assert!(plain.iter().all(|m| m.id != distinct.id()));
+
+    let deduped = db
+        .search(SearchQuery { dedup_results: Some(0.95), ..base })
+        .unwrap();
+    assert_eq!(deduped.len(), 2);
+    assert_eq!(deduped[0].id, best.id());
+    assert_eq!(deduped[1].id, distinct.id());
+}
+
+// -- embedding normalization --
+
+fn norm(v: &[f32]) -> f32 {
+    v.iter().map(|x| x * x).sum::<f32>().sqrt()
+}
+
+#[test]
+fn test_normalize_explicit_vectors() {
+    let config = MemoriConfig {
+        normalize_vectors: true,
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(":memory:", config).unwrap();
+    let r = db.insert("explicit", Some(&[3.0, 4.0, 0.0]), None, None, false).unwrap();
+
+    let stored = db.get_readonly(r.id()).unwrap().unwrap().vector.unwrap();
+    assert!((norm(&stored) - 1.0).abs() < 1e-5);
+    assert!((stored[0] - 0.6).abs() < 1e-5);
+
+    // Default config stores explicit vectors verbatim
+    let plain = open_temp();
+    let r = plain.insert("explicit", Some(&[3.0, 4.0, 0.0]), None, None, false).unwrap();
+    let stored = plain.get_readonly(r.id()).unwrap().unwrap().vector.unwrap();
+    assert_eq!(stored, vec![3.0, 4.0, 0.0]);
+}
+
+#[cfg(any(feature = "embeddings", feature = "mock_embeddings"))]
+#[test]
+fn test_normalize_embeddings_unit_norm() {
+    let config = MemoriConfig {
+        normalize_embeddings: true,
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(":memory:", config).unwrap();
+    let r = db.insert("auto-embedded content", None, None, None, false).unwrap();
+
+    let stored = db.get_readonly(r.id()).unwrap().unwrap().vector.unwrap();
+    assert!((norm(&stored) - 1.0).abs() < 1e-5);
+}
+
+// -- raw row inspection --
+
+fn temp_db_path() -> String {
+    std::env::temp_dir()
+        .join(format!("memori-test-{}.db", uuid::Uuid::new_v4()))
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[test]
+fn test_inspect_reveals_corrupt_metadata() {
+    let path = temp_db_path();
+    let db = Memori::open(&path).unwrap();
+    let r = db
+        .insert("inspect me", Some(&[1.0, 0.0, 0.0]), Some(json!({"type": "fact"})), None, false)
+        .unwrap();
+
+    let raw = db.inspect(r.id()).unwrap().unwrap();
+    assert_eq!(raw.raw_metadata.as_deref(), Some(r#"{"type":"fact"}"#));
+    assert!(raw.metadata_valid);
+    assert_eq!(raw.vector_bytes, Some(12));
+    assert!(raw.fts_indexed);
+
+    // Corrupt the metadata behind memori's back. The json_extract expression
+    // index rejects malformed JSON on write, so an external tool would have to
+    // drop it first.
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    conn.execute_batch("DROP INDEX idx_memories_type").unwrap();
+    conn.execute(
+        "UPDATE memories SET metadata = '{not json' WHERE id = ?1",
+        [r.id()],
+    )
+    .unwrap();
+
+    let raw = db.inspect(r.id()).unwrap().unwrap();
+    assert_eq!(raw.raw_metadata.as_deref(), Some("{not json"));
+    assert!(!raw.metadata_valid);
+    // get() silently drops what it can't parse
+    assert!(db.get(r.id()).unwrap().unwrap().metadata.is_none());
+
+    assert!(db.inspect("nonexistent-id").unwrap().is_none());
+    drop(conn);
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+// -- strict vs lenient metadata parsing --
+
+/// Insert one memory into a file DB and overwrite its metadata with invalid JSON.
+fn corrupt_metadata_db(path: &str) -> String {
+    let db = Memori::open(path).unwrap();
+    let r = db.insert("tampered", None, Some(json!({"type": "fact"})), None, false).unwrap();
+    let conn = rusqlite::Connection::open(path).unwrap();
+    conn.execute_batch("DROP INDEX idx_memories_type").unwrap();
+    conn.execute("UPDATE memories SET metadata = '{not json' WHERE id = ?1", [r.id()])
+        .unwrap();
+    r.id().to_string()
+}
+
+#[test]
+fn test_strict_metadata_errors_on_corrupt_row() {
+    let path = temp_db_path();
+    let id = corrupt_metadata_db(&path);
+
+    let config = MemoriConfig {
+        strict_metadata: true,
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(&path, config).unwrap();
+    assert!(matches!(db.get(&id), Err(MemoriError::Json(_))));
+    assert!(matches!(
+        db.list(None, &SortField::Created, 10, 0, None, None, false),
+        Err(MemoriError::Json(_))
+    ));
+
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_lenient_metadata_drops_and_warns() {
+    let path = temp_db_path();
+    let id = corrupt_metadata_db(&path);
+
+    let warned = std::sync::Arc::new(std::sync::Mutex::new(Vec::<String>::new()));
+    let sink = warned.clone();
+    let config = MemoriConfig {
+        metadata_warning: Some(WarningHook::new(move |id, _msg| {
+            sink.lock().unwrap().push(id.to_string());
+        })),
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(&path, config).unwrap();
+    let mem = db.get(&id).unwrap().unwrap();
+    assert!(mem.metadata.is_none());
+    assert_eq!(*warned.lock().unwrap(), vec![id]);
+
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+// -- multi-field list sort --
+
+#[test]
+fn test_list_sorted_secondary_breaks_ties() {
+    let db = open_temp();
+    let ts = 1_700_000_000.0;
+    db.insert_with_id("older-hot", "older hot", None, None, ts, ts).unwrap();
+    db.insert_with_id("newer-hot", "newer hot", None, None, ts + 10.0, ts + 10.0).unwrap();
+    db.insert_with_id("cold", "cold", None, None, ts + 20.0, ts + 20.0).unwrap();
+    db.set_access_stats("older-hot", None, 5).unwrap();
+    db.set_access_stats("newer-hot", None, 5).unwrap();
+
+    // Count ties between the two "hot" rows; created_at DESC resolves it
+    let sorts = [
+        (SortField::Count, SortDirection::Desc),
+        (SortField::Created, SortDirection::Desc),
+    ];
+    let results = db.list_sorted(None, &sorts, 10, 0, None, None).unwrap();
+    let ids: Vec<_> = results.iter().map(|m| m.id.as_str()).collect();
+    assert_eq!(ids, vec!["newer-hot", "older-hot", "cold"]);
+
+    // Flipping the secondary direction flips only the tied pair
+    let sorts = [
+        (SortField::Count, SortDirection::Desc),
+        (SortField::Created, SortDirection::Asc),
+    ];
+    let results = db.list_sorted(None, &sorts, 10, 0, None, None).unwrap();
+    let ids: Vec<_> = results.iter().map(|m| m.id.as_str()).collect();
+    assert_eq!(ids, vec!["older-hot", "newer-hot", "cold"]);
+}
+
+#[test]
+fn test_list_sorted_rejects_empty_sorts() {
+    let db = open_temp();
+    let result = db.list_sorted(None, &[], 10, 0, None, None);
+    assert!(matches!(result, Err(MemoriError::InvalidSort(_))));
+}
+
+// -- backfill progress --
+
+#[cfg(any(feature = "embeddings", feature = "mock_embeddings"))]
+#[test]
+fn test_backfill_progress_callback() {
+    let db = open_temp();
+    for i in 0..5 {
+        db.insert(&format!("unembedded {}", i), None, None, None, true).unwrap();
+    }
+
+    let mut calls: Vec<(usize, usize)> = Vec::new();
+    let processed = db
+        .backfill_embeddings_with_progress(2, |done, total| calls.push((done, total)))
+        .unwrap();
+
+    assert_eq!(processed, 5);
+    assert_eq!(calls.len(), 3); // batches of 2, 2, 1
+    assert!(calls.windows(2).all(|w| w[0].0 < w[1].0));
+    assert!(calls.iter().all(|&(_, total)| total == 5));
+    assert_eq!(calls.last().unwrap().0, processed);
+}
+
+// -- query embedding cache --
+
+#[cfg(any(feature = "embeddings", feature = "mock_embeddings"))]
+#[test]
+fn test_query_cache_reuses_embedding() {
+    let config = MemoriConfig {
+        query_cache_size: 8,
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(":memory:", config).unwrap();
+    db.insert("kafka uses partitioned topics", None, None, None, false).unwrap();
+
+    let query = SearchQuery {
+        text: Some("message queue partitions".to_string()),
+        ..Default::default()
+    };
+    let first = db.search(query.clone()).unwrap();
+    let second = db.search(query).unwrap();
+
+    assert_eq!(first.len(), second.len());
+    assert_eq!(first[0].score, second[0].score);
+    // One model call for the first search, served from cache for the second
+    assert_eq!(db.query_cache_stats(), (1, 1));
+}
+
+// -- manual merge --
+
+#[test]
+fn test_merge_combines_metadata_and_stats() {
+    let db = open_temp();
+    let keep = db
+        .insert("kafka retention is 7 days", Some(&[1.0, 0.0, 0.0]), Some(json!({"type": "fact", "topic": "kafka"})), None, false)
+        .unwrap();
+    let dropped = db
+        .insert("retention can be set per topic", Some(&[0.9, 0.1, 0.0]), Some(json!({"type": "note", "verified": true})), None, false)
+        .unwrap();
+    db.set_access_stats(keep.id(), Some(1_700_000_000.0), 2).unwrap();
+    db.set_access_stats(dropped.id(), Some(1_700_000_500.0), 9).unwrap();
+
+    db.merge(keep.id(), &dropped.id()[..8], MergeStrategy::Concatenate).unwrap();
+
+    let survivor = db.get_readonly(keep.id()).unwrap().unwrap();
+    assert_eq!(survivor.content, "kafka retention is 7 days\n\nretention can be set per topic");
+    // Survivor wins on conflicting keys; keys only on the dropped memory carry over
+    assert_eq!(survivor.metadata, Some(json!({"type": "fact", "topic": "kafka", "verified": true})));
+    assert_eq!(survivor.access_count, 9);
+    assert_eq!(survivor.last_accessed, 1_700_000_500.0);
+
+    assert_eq!(db.count().unwrap(), 1);
+    assert!(db.get(dropped.id()).unwrap().is_none());
+    assert!(db.resolve_id(&dropped.id()[..8]).is_err());
+}
+
+#[test]
+fn test_merge_keep_content_and_self_merge_rejected() {
+    let db = open_temp();
+    let keep = db.insert("keep me", None, None, None, false).unwrap();
+    let dropped = db.insert("drop me", None, None, None, false).unwrap();
+
+    assert!(matches!(
+        db.merge(keep.id(), keep.id(), MergeStrategy::KeepContent),
+        Err(MemoriError::InvalidMerge(_))
+    ));
+
+    db.merge(keep.id(), dropped.id(), MergeStrategy::KeepContent).unwrap();
+    assert_eq!(db.get_readonly(keep.id()).unwrap().unwrap().content, "keep me");
+    assert_eq!(db.count().unwrap(), 1);
+}
+
+// -- min_fts_score --
+
+fn seed_fts_cutoff_corpus(db: &Memori) -> (String, String) {
+    for i in 0..10 {
+        db.insert(&format!("filler note number {} about redis caching", i), None, None, None, true).unwrap();
+    }
+    let strong = db.insert("kafka kafka partitions in kafka brokers", None, None, None, true).unwrap();
+    let weak = db
+        .insert(
+            "a long note about deployment pipelines, rollbacks, canaries, feature flags, \
+             dashboards, alerts, on-call rotations, runbooks, and once mentions kafka in \
+             passing among many other unrelated operational topics we care about",
+            None,
+            None,
+            None,
+            true,
+        )
+        .unwrap();
+    (strong.id().to_string(), weak.id().to_string())
+}
+
+#[test]
+fn test_min_fts_score_drops_incidental_match() {
+    let db = open_temp();
+    let (strong, weak) = seed_fts_cutoff_corpus(&db);
+
+    let all = db
+        .search(SearchQuery { text: Some("kafka".into()), mode: SearchMode::TextOnly, ..Default::default() })
+        .unwrap();
+    assert_eq!(all.len(), 2);
+
+    let cut = db
+        .search(SearchQuery {
+            text: Some("kafka".into()),
+            mode: SearchMode::TextOnly,
+            min_fts_score: Some(1.5),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(cut.len(), 1);
+    assert_eq!(cut[0].id, strong);
+    assert!(cut.iter().all(|m| m.id != weak));
+}
+
+#[test]
+fn test_min_fts_score_applies_to_hybrid_text_leg() {
+    let db = open_temp();
+    let (strong, weak) = seed_fts_cutoff_corpus(&db);
+
+    // Rows are stored without vectors, so hybrid results come only from the text leg
+    let results = db
+        .search(SearchQuery {
+            vector: Some(vec![1.0, 0.0, 0.0]),
+            text: Some("kafka".into()),
+            min_fts_score: Some(1.5),
+            ..Default::default()
+        })
+        .unwrap();
+    let ids: Vec<&str> = results.iter().map(|m| m.id.as_str()).collect();
+    assert_eq!(ids, vec![strong.as_str()]);
+    assert!(!ids.contains(&weak.as_str()));
+}
+
+// -- search_timeline --
+
+#[test]
+fn test_search_timeline_partitions_by_created_at() {
+    let db = open_temp();
+    let q = [1.0f32, 0.0, 0.0];
+    // Recent bucket: [1000, inf), older bucket: [0, 1000)
+    db.insert_with_id("new-best", "a", Some(&[1.0, 0.0, 0.0]), None, 1500.0, 1500.0).unwrap();
+    db.insert_with_id("new-worse", "b", Some(&[0.6, 0.8, 0.0]), None, 1200.0, 1200.0).unwrap();
+    db.insert_with_id("old-best", "c", Some(&[0.9, 0.1, 0.0]), None, 500.0, 500.0).unwrap();
+    db.insert_with_id("old-worse", "d", Some(&[0.1, 0.9, 0.0]), None, 100.0, 100.0).unwrap();
+    db.insert_with_id("ancient", "e", Some(&[1.0, 0.0, 0.0]), None, -50.0, -50.0).unwrap();
+
+    let grouped = db
+        .search_timeline(
+            SearchQuery { vector: Some(q.to_vec()), ..Default::default() },
+            &[("recent", Some(1000.0), None), ("older", Some(0.0), Some(1000.0))],
+        )
+        .unwrap();
+
+    assert_eq!(grouped.len(), 2);
+    assert_eq!(grouped[0].0, "recent");
+    let recent: Vec<&str> = grouped[0].1.iter().map(|m| m.id.as_str()).collect();
+    assert_eq!(recent, vec!["new-best", "new-worse"]);
+    assert_eq!(grouped[1].0, "older");
+    let older: Vec<&str> = grouped[1].1.iter().map(|m| m.id.as_str()).collect();
+    assert_eq!(older, vec!["old-best", "old-worse"]);
+    // Falls outside every bucket
+    assert!(grouped.iter().all(|(_, ms)| ms.iter().all(|m| m.id != "ancient")));
+}
+
+#[test]
+fn test_search_timeline_first_matching_bucket_wins() {
+    let db = open_temp();
+    db.insert_with_id("x", "overlap", Some(&[1.0, 0.0]), None, 50.0, 50.0).unwrap();
+
+    let grouped = db
+        .search_timeline(
+            SearchQuery { vector: Some(vec![1.0, 0.0]), ..Default::default() },
+            &[("first", None, Some(100.0)), ("second", Some(0.0), None)],
+        )
+        .unwrap();
+    assert_eq!(grouped[0].1.len(), 1);
+    assert!(grouped[1].1.is_empty());
+}
+
+// -- tracing spans --
+
+#[cfg(feature = "tracing")]
+mod tracing_spans {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    /// Records `(span name, field) -> value` for every u64/bool field recorded.
+    #[derive(Default)]
+    struct Capture {
+        next_id: AtomicU64,
+        names: Mutex<HashMap<u64, &'static str>>,
+        fields: Arc<Mutex<Vec<(String, String, String)>>>,
+    }
+
+    struct FieldVisitor<'a> {
+        span: &'static str,
+        out: &'a Mutex<Vec<(String, String, String)>>,
+    }
+
+    impl Visit for FieldVisitor<'_> {
+        fn record_u64(&mut self, field: &Field, value: u64) {
+            self.push(field, value.to_string());
+        }
+        fn record_bool(&mut self, field: &Field, value: bool) {
+            self.push(field, value.to_string());
+        }
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.push(field, format!("{:?}", value));
+        }
+    }
+
+    impl FieldVisitor<'_> {
+        fn push(&mut self, field: &Field, value: String) {
+            self.out
+                .lock()
+                .unwrap()
+                .push((self.span.to_string(), field.name().to_string(), value));
+        }
+    }
+
+    impl Subscriber for Capture {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, attrs: &Attributes<'_>) -> Id {
+            let id = self.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+            self.names.lock().unwrap().insert(id, attrs.metadata().name());
+            Id::from_u64(id)
+        }
+        fn record(&self, span: &Id, values: &Record<'_>) {
+            let name = self.names.lock().unwrap()[&span.into_u64()];
+            values.record(&mut FieldVisitor { span: name, out: &self.fields });
+        }
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, _: &Event<'_>) {}
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    fn recorded(fields: &[(String, String, String)], span: &str, field: &str) -> Option<String> {
+        fields
+            .iter()
+            .find(|(s, f, _)| s == span && f == field)
+            .map(|(_, _, v)| v.clone())
+    }
+
+    #[test]
+    fn test_search_spans_record_candidate_counts() {
+        let db = open_temp();
+        for i in 0..4 {
+            db.insert(&format!("tracing note {}", i), Some(&[1.0, i as f32]), None, None, false)
+                .unwrap();
+        }
+
+        let capture = Capture::default();
+        let fields = Arc::clone(&capture.fields);
+        tracing::subscriber::with_default(capture, || {
+            db.search(SearchQuery { vector: Some(vec![1.0, 0.0]), limit: 2, ..Default::default() })
+                .unwrap();
+            db.insert("dup", Some(&[1.0, 0.0]), None, Some(0.99), false).unwrap();
+        });
+
+        let fields = fields.lock().unwrap();
+        assert_eq!(recorded(&fields, "memori.vector_search", "candidates").as_deref(), Some("4"));
+        assert_eq!(recorded(&fields, "memori.vector_search", "results").as_deref(), Some("2"));
+        assert_eq!(recorded(&fields, "memori.search", "results").as_deref(), Some("2"));
+        assert!(recorded(&fields, "memori.search", "elapsed_us").is_some());
+        assert_eq!(recorded(&fields, "memori.find_duplicate", "candidates").as_deref(), Some("4"));
+        assert_eq!(recorded(&fields, "memori.insert", "dedup").as_deref(), Some("true"));
+    }
+}
+
+// -- verify_fts / repair_fts --
+
+#[test]
+fn test_verify_fts_detects_and_repair_fixes_divergence() {
+    let path = temp_db_path();
+    let db = Memori::open(&path).unwrap();
+    let kept = db.insert("zeppelin logbook", None, Some(json!({"type": "fact"})), None, false).unwrap();
+    let lost = db.insert("quokka sighting", None, Some(json!({"topic": "wildlife"})), None, false).unwrap();
+    assert!(db.verify_fts().unwrap().is_consistent());
+
+    // Out-of-band: drop one row from the index and add an entry with no base row
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    conn.execute(
+        "INSERT INTO memories_fts(memories_fts, rowid, content)
+         SELECT 'delete', rowid, content || ' ' || COALESCE(metadata, '') FROM memories WHERE id = ?1",
+        [lost.id()],
+    )
+    .unwrap();
+    conn.execute("INSERT INTO memories_fts(rowid, content) VALUES (9999, 'ghost')", [])
+        .unwrap();
+
+    let report = db.verify_fts().unwrap();
+    assert!(!report.is_consistent());
+    assert!(report.index_ok);
+    assert_eq!(report.missing, vec![lost.id().to_string()]);
+    assert_eq!(report.orphaned, vec![9999]);
+    let text = |q: &str| {
+        db.search(SearchQuery { text: Some(q.into()), mode: SearchMode::TextOnly, ..Default::default() })
+            .unwrap()
+    };
+    assert!(text("quokka").is_empty());
+
+    db.repair_fts().unwrap();
+    assert!(db.verify_fts().unwrap().is_consistent());
+    assert_eq!(text("quokka")[0].id, lost.id());
+    // Metadata is indexed again alongside content, matching the triggers
+    assert_eq!(text("wildlife")[0].id, lost.id());
+    assert_eq!(text("zeppelin")[0].id, kept.id());
+    assert!(text("ghost").is_empty());
+
+    // Triggers still keep the rebuilt index in sync afterwards
+    db.delete(kept.id()).unwrap();
+    assert!(db.verify_fts().unwrap().is_consistent());
+
+    drop(conn);
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+// -- text_separator --
+
+#[test]
+fn test_text_separator_blocks_phrase_across_content_and_metadata() {
+    let phrase_hits = |path: &str| -> i64 {
+        let conn = rusqlite::Connection::open(path).unwrap();
+        conn.query_row(
+            "SELECT COUNT(*) FROM memories_fts WHERE memories_fts MATCH '\"apache kafka\"'",
+            [],
+            |r| r.get(0),
+        )
+        .unwrap()
+    };
+
+    // Default single space: last content word and first metadata value form a phrase
+    let default_path = temp_db_path();
+    let db = Memori::open(&default_path).unwrap();
+    db.insert("we deployed apache", None, Some(json!({"kafka": 1})), None, true).unwrap();
+    assert_eq!(phrase_hits(&default_path), 1);
+    drop(db);
+
+    let config = MemoriConfig { text_separator: Some(" memorisep ".into()), ..Default::default() };
+    let sep_path = temp_db_path();
+    let db = Memori::open_with_config(&sep_path, config).unwrap();
+    let r = db.insert("we deployed apache", None, Some(json!({"kafka": 1})), None, true).unwrap();
+    assert_eq!(phrase_hits(&sep_path), 0);
+    // The separator is indexed between the two halves
+    let conn = rusqlite::Connection::open(&sep_path).unwrap();
+    let bridged: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM memories_fts WHERE memories_fts MATCH '\"apache memorisep kafka\"'",
+            [],
+            |r| r.get(0),
+        )
+        .unwrap();
+    assert_eq!(bridged, 1);
+    // Both halves stay searchable on their own
+    let hits = db
+        .search(SearchQuery { text: Some("kafka".into()), mode: SearchMode::TextOnly, ..Default::default() })
+        .unwrap();
+    assert_eq!(hits[0].id, r.id());
+    assert!(db.verify_fts().unwrap().is_consistent());
+    drop(conn);
+    drop(db);
+
+    // An existing database adopts a new separator through rebuild_fts
+    let config = MemoriConfig { text_separator: Some(" memorisep ".into()), ..Default::default() };
+    let db = Memori::open_with_config(&default_path, config).unwrap();
+    assert_eq!(phrase_hits(&default_path), 1);
+    db.rebuild_fts().unwrap();
+    assert_eq!(phrase_hits(&default_path), 0);
+    db.insert("more apache", None, Some(json!({"kafka": 2})), None, true).unwrap();
+    assert_eq!(phrase_hits(&default_path), 0);
+    assert!(db.verify_fts().unwrap().is_consistent());
+    drop(db);
+
+    let _ = std::fs::remove_file(&default_path);
+    let _ = std::fs::remove_file(&sep_path);
+}
+
+#[test]
+fn test_text_separator_is_kept_by_later_handles() {
+    // Every term must be indexed, so this shows which separator sits between
+    let indexed = |db: &Memori, terms: &str| db.count_text_matches(terms, None).unwrap();
+    let path = temp_db_path();
+    let config = MemoriConfig {
+        text_separator: Some(" memorisep ".into()),
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(&path, config).unwrap();
+    db.insert(
+        "we deployed apache",
+        None,
+        Some(json!({"kafka": 1})),
+        None,
+        true,
+    )
+    .unwrap();
+    drop(db);
+
+    // A default handle adopts the recorded separator, and its rebuild keeps it
+    let db = Memori::open(&path).unwrap();
+    assert_eq!(db.config().text_separator(), " memorisep ");
+    db.rebuild_fts().unwrap();
+    assert_eq!(indexed(&db, "apache memorisep kafka"), 1);
+    drop(db);
+
+    // An explicit separator replaces it, for later handles too
+    let config = MemoriConfig {
+        text_separator: Some(" otherwall ".into()),
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(&path, config).unwrap();
+    db.rebuild_fts().unwrap();
+    drop(db);
+    let db = Memori::open(&path).unwrap();
+    assert_eq!(db.config().text_separator(), " otherwall ");
+    db.rebuild_fts().unwrap();
+    assert_eq!(indexed(&db, "apache otherwall kafka"), 1);
+    assert_eq!(indexed(&db, "apache memorisep kafka"), 0);
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+// -- get cache --
+
+#[test]
+fn test_get_cache_serves_hits_and_invalidates_on_update() {
+    let path = temp_db_path();
+    let config = MemoriConfig { get_cache_size: 8, ..Default::default() };
+    let db = Memori::open_with_config(&path, config).unwrap();
+    let id = db.insert("cached memory", None, Some(json!({"v": 1})), None, true).unwrap();
+    let id = id.id().to_string();
+
+    let first = db.get(&id).unwrap().unwrap();
+    assert_eq!(first.access_count, 0);
+    let second = db.get(&id).unwrap().unwrap();
+    assert_eq!(second.content, "cached memory");
+    assert_eq!(second.access_count, 1);
+    // Prefix lookups share the cached entry
+    let third = db.get(&id[..8]).unwrap().unwrap();
+    assert_eq!(third.access_count, 2);
+
+    // Hits aren't written yet: another connection sees only the first (miss) touch
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    let stored = |conn: &rusqlite::Connection| -> i64 {
+        conn.query_row("SELECT access_count FROM memories WHERE id = ?1", [&id], |r| r.get(0))
+            .unwrap()
+    };
+    assert_eq!(stored(&conn), 1);
+
+    // Update invalidates the entry and writes back the pending accesses
+    db.update(&id, Some("rewritten"), None, None, false).unwrap();
+    assert_eq!(stored(&conn), 3);
+    let fresh = db.get(&id).unwrap().unwrap();
+    assert_eq!(fresh.content, "rewritten");
+    assert_eq!(fresh.access_count, 3);
+    let again = db.get(&id).unwrap().unwrap();
+    assert_eq!(again.access_count, 4);
+
+    // Dropping the handle flushes the rest
+    drop(db);
+    assert_eq!(stored(&conn), 5);
+    drop(conn);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_get_cache_flushes_in_batches_and_drops_deleted() {
+    let config = MemoriConfig { get_cache_size: 2, ..Default::default() };
+    let db = Memori::open_with_config(":memory:", config).unwrap();
+    let id = db.insert("hot", None, None, None, true).unwrap().id().to_string();
+    for _ in 0..100 {
+        db.get(&id).unwrap();
+    }
+    assert_eq!(db.get(&id).unwrap().unwrap().access_count, 100);
+    let stored = db.get_readonly(&id).unwrap().unwrap().access_count;
+    // One miss touch plus 100 cached hits written back 32 at a time
+    assert_eq!(stored, 1 + 96);
+
+    db.delete(&id).unwrap();
+    assert!(db.get(&id).unwrap().is_none());
+}
+
+// -- collections --
+
+fn open_collection(path: &str, name: &str) -> Memori {
+    let config = MemoriConfig { collection: Some(name.to_string()), ..Default::default() };
+    Memori::open_with_config(path, config).unwrap()
+}
+
+#[test]
+fn test_collections_do_not_dedup_across_each_other() {
+    let path = temp_db_path();
+    let a = open_collection(&path, "agent-a");
+    let b = open_collection(&path, "agent-b");
+    let v = [1.0f32, 0.0, 0.0];
+
+    let first = a.insert("shared fact", Some(&v), Some(json!({"type": "fact"})), Some(0.9), false).unwrap();
+    let second = b.insert("shared fact", Some(&v), Some(json!({"type": "fact"})), Some(0.9), false).unwrap();
+    assert!(!second.is_deduplicated());
+    assert_ne!(first.id(), second.id());
+
+    // Within a collection dedup still applies
+    let again = a.insert("shared fact again", Some(&v), Some(json!({"type": "fact"})), Some(0.9), false).unwrap();
+    assert!(again.is_deduplicated());
+    assert_eq!(again.id(), first.id());
+
+    drop(a);
+    drop(b);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_collections_scope_count_and_reads() {
+    let path = temp_db_path();
+    let a = open_collection(&path, "agent-a");
+    let b = open_collection(&path, "agent-b");
+    let default = Memori::open(&path).unwrap();
+
+    let a_id = a.insert("alpha kafka note", Some(&[1.0, 0.0]), Some(json!({"type": "note"})), None, false).unwrap();
+    a.insert("alpha second", Some(&[0.9, 0.1]), None, None, false).unwrap();
+    b.insert("beta kafka note", Some(&[1.0, 0.0]), Some(json!({"type": "note"})), None, false).unwrap();
+
+    assert_eq!(a.count().unwrap(), 2);
+    assert_eq!(b.count().unwrap(), 1);
+    assert_eq!(default.count().unwrap(), 0);
+    assert_eq!(b.type_distribution().unwrap().get("note"), Some(&1));
+    assert_eq!(b.list(None, &SortField::Created, 10, 0, None, None, false).unwrap().len(), 1);
+
+    // Point lookups by full id or prefix don't cross collections
+    assert!(b.get(a_id.id()).unwrap().is_none());
+    assert!(b.get(&a_id.id()[..8]).unwrap().is_none());
+    assert!(matches!(b.delete(a_id.id()), Err(MemoriError::NotFound(_))));
+    assert!(a.get(a_id.id()).unwrap().is_some());
+
+    let text = b
+        .search(SearchQuery { text: Some("kafka".into()), mode: SearchMode::TextOnly, ..Default::default() })
+        .unwrap();
+    assert_eq!(text.len(), 1);
+    assert_eq!(text[0].content, "beta kafka note");
+    let vector = b
+        .search(SearchQuery { vector: Some(vec![1.0, 0.0]), ..Default::default() })
+        .unwrap();
+    assert_eq!(vector.len(), 1);
+    let related = a.related(a_id.id(), 10).unwrap();
+    assert!(related.iter().all(|m| m.content.starts_with("alpha")));
+
+    assert_eq!(b.delete_by_type("note").unwrap(), 1);
+    assert_eq!(a.count().unwrap(), 2);
+    assert_eq!(a.type_distribution().unwrap().get("note"), Some(&1));
+
+    // collections() lists every name in the file, archived-only ones too
+    b.insert("beta archived", None, None, None, true).unwrap();
+    let archived = b.list(None, &SortField::Created, 1, 0, None, None, false).unwrap();
+    b.archive(&archived[0].id).unwrap();
+    assert_eq!(b.count().unwrap(), 0);
+    assert_eq!(default.collections().unwrap(), vec!["agent-a".to_string(), "agent-b".to_string()]);
+
+    drop(a);
+    drop(b);
+    drop(default);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_collection_name_survives_fts_filter_rewrite() {
+    // Text search rewrites "metadata" in filter SQL for its join; a collection
+    // name containing it must still match
+    let db = Memori::open_with_config(
+        ":memory:",
+        MemoriConfig { collection: Some("metadata's".into()), ..Default::default() },
+    )
+    .unwrap();
+    db.insert("quoted collection", None, None, None, true).unwrap();
+    let hits = db
+        .search(SearchQuery { text: Some("quoted".into()), mode: SearchMode::TextOnly, ..Default::default() })
+        .unwrap();
+    assert_eq!(hits.len(), 1);
+}
+
+// -- raw_filter --
+
+#[test]
+fn test_raw_filter_complex_predicate() {
+    let db = open_temp();
+    let v = [1.0f32, 0.0];
+    db.insert("p1 bug", Some(&v), Some(json!({"priority": 1, "kind": "bug"})), None, true).unwrap();
+    db.insert("p3 bug", Some(&v), Some(json!({"priority": 3, "kind": "bug"})), None, true).unwrap();
+    db.insert("p1 pinned", Some(&v), Some(json!({"priority": 1, "kind": "idea", "pinned": true})), None, true).unwrap();
+    db.insert("p5 idea", Some(&v), Some(json!({"priority": 5, "kind": "idea"})), None, true).unwrap();
+
+    let raw = "(json_extract(metadata, '$.priority') >= 3 AND json_extract(metadata, '$.kind') = 'bug') \
+               OR json_extract(metadata, '$.pinned') = 1";
+    let query = |raw: &str, text: Option<&str>| {
+        db.search(SearchQuery {
+            vector: text.is_none().then(|| v.to_vec()),
+            text: text.map(str::to_string),
+            mode: if text.is_some() { SearchMode::TextOnly } else { SearchMode::Auto },
+            raw_filter: Some(raw.to_string()),
+            ..Default::default()
+        })
+    };
+    let mut hits: Vec<String> = query(raw, None).unwrap().into_iter().map(|m| m.content).collect();
+    hits.sort();
+    assert_eq!(hits, vec!["p1 pinned", "p3 bug"]);
+
+    // Same predicate through the FTS join
+    let hits = query(raw, Some("bug")).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].content, "p3 bug");
+}
+
+#[test]
+fn test_raw_filter_rejects_injection() {
+    let db = open_temp();
+    db.insert("victim", Some(&[1.0, 0.0]), None, None, true).unwrap();
+    for attack in [
+        "json_extract(metadata, '$.a') = 1; DROP TABLE memories",
+        "json_extract(metadata, '$.a') = 1 --",
+        "json_extract(metadata, '$.a') IN (SELECT id FROM memories)",
+        "json_extract(metadata, '$.a') = (SELECT 1)",
+        "1 = 1 UNION SELECT * FROM memories",
+        "json_extract(metadata, '$.a'' OR 1=1 --') = 1",
+    ] {
+        let err = db
+            .search(SearchQuery {
+                vector: Some(vec![1.0, 0.0]),
+                raw_filter: Some(attack.to_string()),
+                ..Default::default()
+            })
+            .unwrap_err();
+        assert!(matches!(err, MemoriError::InvalidFilter(_)), "{:?} -> {:?}", attack, err);
+    }
+    assert_eq!(db.count().unwrap(), 1);
+}
+
+#[test]
+fn test_filters_with_metadata_literal_through_fts_join() {
+    let db = open_temp();
+    db.insert(
+        "tagged note",
+        None,
+        Some(json!({"source": "metadata"})),
+        None,
+        true,
+    )
+    .unwrap();
+    db.insert(
+        "other note",
+        None,
+        Some(json!({"source": "manual"})),
+        None,
+        true,
+    )
+    .unwrap();
+
+    let text_query = |filter: Option<serde_json::Value>, raw: Option<&str>| {
+        db.search(SearchQuery {
+            text: Some("note".to_string()),
+            mode: SearchMode::TextOnly,
+            filter,
+            raw_filter: raw.map(str::to_string),
+            ..Default::default()
+        })
+        .unwrap()
+    };
+    // The literal 'metadata' must reach SQL untouched
+    let hits = text_query(Some(json!({"source": "metadata"})), None);
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].content, "tagged note");
+    let hits = text_query(
+        None,
+        Some("json_extract(metadata, '$.source') = 'metadata'"),
+    );
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].content, "tagged note");
+    assert_eq!(
+        db.count_text_matches("note", Some(&json!({"source": "metadata"})))
+            .unwrap(),
+        1
+    );
+}
+
+// -- set_access_stats_batch --
+
+#[test]
+fn test_set_access_stats_batch_skips_missing_ids() {
+    let db = open_temp();
+    let a = db.insert("first", None, None, None, true).unwrap().id().to_string();
+    let b = db.insert("second", None, None, None, true).unwrap().id().to_string();
+    db.set_access_stats(&b, Some(500.0), 1).unwrap();
+
+    let entries = vec![
+        (a.clone(), Some(1234.5), 7),
+        ("00000000-0000-0000-0000-000000000000".to_string(), Some(1.0), 99),
+        (b.clone(), None, 3),
+        ("not-a-real-id".to_string(), None, 1),
+    ];
+    assert_eq!(db.set_access_stats_batch(&entries).unwrap(), 2);
+
+    let a_mem = db.get_readonly(&a).unwrap().unwrap();
+    assert_eq!(a_mem.access_count, 7);
+    assert_eq!(a_mem.last_accessed, 1234.5);
+    let b_mem = db.get_readonly(&b).unwrap().unwrap();
+    assert_eq!(b_mem.access_count, 3);
+    // None leaves last_accessed unchanged, like set_access_stats
+    assert_eq!(b_mem.last_accessed, 500.0);
+    assert_eq!(db.count().unwrap(), 2);
+}
+
+// -- id_strategy --
+
+#[test]
+fn test_uuid_v7_ids_sort_in_creation_order() {
+    let config = MemoriConfig {
+        id_strategy: IdStrategy::UuidV7,
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(":memory:", config).unwrap();
+    let ids: Vec<String> = (0..50)
+        .map(|i| {
+            db.insert(&format!("m{}", i), None, None, None, true)
+                .unwrap()
+                .id()
+                .to_string()
+        })
+        .collect();
+
+    let mut sorted = ids.clone();
+    sorted.sort();
+    assert_eq!(sorted, ids);
+    assert!(ids
+        .iter()
+        .all(|id| id.len() == 36 && id.as_bytes()[14] == b'7'));
+
+    // Ids created back to back share a timestamp prefix, so resolve by the
+    // shortest prefix that is unique
+    let last = ids.last().unwrap();
+    let unique = (6..=36)
+        .map(|n| &last[..n])
+        .find(|p| ids.iter().filter(|id| id.starts_with(p)).count() == 1)
+        .unwrap();
+    assert_eq!(db.resolve_id(unique).unwrap(), *last);
+    assert_eq!(db.get(unique).unwrap().unwrap().content, "m49");
+}
+
+#[test]
+fn test_ulid_ids_resolve_by_prefix() {
+    let config = MemoriConfig {
+        id_strategy: IdStrategy::Ulid,
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(":memory:", config).unwrap();
+    let id = db
+        .insert("ulid memory", None, None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    assert_eq!(id.len(), 26);
+    assert_eq!(db.resolve_id(&id).unwrap(), id);
+    assert_eq!(db.resolve_id(&id[..12]).unwrap(), id);
+    assert_eq!(db.get(&id).unwrap().unwrap().content, "ulid memory");
+}
+
+// -- base_score --
+
+#[test]
+fn test_base_score_separates_similarity_from_decay() {
+    let db = open_temp();
+    let old = db
+        .insert("stale", Some(&[1.0, 0.0, 0.0]), None, None, false)
+        .unwrap()
+        .id()
+        .to_string();
+    db.insert("fresh", Some(&[1.0, 0.0, 0.0]), None, None, false).unwrap();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs_f64();
+    let year_ago = now - 365.0 * 86400.0;
+    db.set_access_stats(&old, Some(year_ago), 3).unwrap();
+
+    let results = db
+        .search(SearchQuery {
+            vector: Some(vec![1.0, 0.0, 0.0]),
+            limit: 2,
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].content, "fresh");
+    let fresh = &results[0];
+    let stale = &results[1];
+    assert!((fresh.base_score.unwrap() - 1.0).abs() < 1e-6);
+    assert_eq!(fresh.base_score, fresh.score);
+    assert!((stale.base_score.unwrap() - 1.0).abs() < 1e-6);
+    assert!(stale.base_score.unwrap() > stale.score.unwrap());
+
+    // Text results carry the BM25 score; get() carries none
+    let text = db
+        .search(SearchQuery {
+            text: Some("fresh".to_string()),
+            mode: SearchMode::TextOnly,
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(text[0].base_score, text[0].score);
+    assert!(db.get(&old).unwrap().unwrap().base_score.is_none());
+}
+
+// -- search modes --
+
+fn mode_fixture() -> Memori {
+    let db = open_temp();
+    db.insert("kafka topics", Some(&[0.0, 1.0, 0.0]), None, None, false)
+        .unwrap();
+    db.insert("unrelated words", Some(&[1.0, 0.0, 0.0]), None, None, false)
+        .unwrap();
+    db
+}
+
+fn mode_query(mode: SearchMode) -> SearchQuery {
+    SearchQuery {
+        vector: Some(vec![1.0, 0.0, 0.0]),
+        text: Some("kafka".to_string()),
+        mode,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_search_mode_text_only_ignores_vector() {
+    let db = mode_fixture();
+    let results = db.search(mode_query(SearchMode::TextOnly)).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].content, "kafka topics");
+
+    let err = db
+        .search(SearchQuery {
+            vector: Some(vec![1.0, 0.0, 0.0]),
+            mode: SearchMode::TextOnly,
+            ..Default::default()
+        })
+        .unwrap_err();
+    assert!(matches!(err, MemoriError::InvalidQuery(_)));
+}
+
+#[test]
+fn test_search_mode_vector_only_ignores_text() {
+    let db = mode_fixture();
+    let results = db.search(mode_query(SearchMode::VectorOnly)).unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].content, "unrelated words");
+    assert!((results[0].base_score.unwrap() - 1.0).abs() < 1e-6);
+}
+
+#[test]
+fn test_search_mode_hybrid_fuses_both() {
+    let db = mode_fixture();
+    let results = db.search(mode_query(SearchMode::Hybrid)).unwrap();
+    assert_eq!(results.len(), 2);
+    // RRF scores are small rank-based values, unlike cosine similarity
+    assert!(results.iter().all(|m| m.score.unwrap() < 0.1));
+
+    let err = db
+        .search(SearchQuery {
+            text: Some("kafka".to_string()),
+            mode: SearchMode::Hybrid,
+            ..Default::default()
+        })
+        .map(|_| ());
+    if cfg!(any(feature = "embeddings", feature = "mock_embeddings")) {
+        assert!(err.is_ok());
+    } else {
+        assert!(matches!(err, Err(MemoriError::InvalidQuery(_))));
+    }
+}
+
+#[test]
+fn test_search_mode_auto_matches_default() {
+    let db = mode_fixture();
+    let auto = db.search(mode_query(SearchMode::Auto)).unwrap();
+    let hybrid = db.search(mode_query(SearchMode::Hybrid)).unwrap();
+    let ids = |v: &[memori_core::Memory]| v.iter().map(|m| m.id.clone()).collect::<Vec<_>>();
+    assert_eq!(ids(&auto), ids(&hybrid));
+}
+
+// -- migrate_legacy --
+
+#[test]
+fn test_migrate_legacy_from_v0_database() {
+    let path = temp_db_path();
+    {
+        // memori v0.2 layout: FTS over content only, no access or collection columns
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE memories (
+                 id TEXT PRIMARY KEY, content TEXT NOT NULL, vector BLOB,
+                 metadata TEXT, created_at REAL NOT NULL, updated_at REAL NOT NULL
+             );
+             CREATE VIRTUAL TABLE memories_fts USING fts5(content, content=memories, content_rowid=rowid);
+             CREATE TRIGGER memories_ai AFTER INSERT ON memories BEGIN
+                 INSERT INTO memories_fts(rowid, content) VALUES (new.rowid, new.content);
+             END;
+             INSERT INTO memories VALUES ('legacy-1', 'broker setup notes', NULL, '{\"topic\": \"kafka\"}', 1.0, 1.0);
+             INSERT INTO memories VALUES ('legacy-2', 'deploy checklist', NULL, NULL, 2.0, 2.0);",
+        )
+        .unwrap();
+    }
+
+    let db = Memori::open(&path).unwrap();
+    let report = db.migrate_legacy().unwrap();
+    assert_eq!(report.from_version, 0);
+    assert_eq!(report.to_version, memori_core::schema::CURRENT_SCHEMA_VERSION);
+    assert_eq!(report.reindexed, 2);
+    assert!(db.verify_fts().unwrap().is_consistent());
+
+    let text = |q: &str| {
+        db.search(SearchQuery {
+            text: Some(q.to_string()),
+            mode: SearchMode::TextOnly,
+            ..Default::default()
+        })
+        .unwrap()
+        .into_iter()
+        .map(|m| m.id)
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(text("kafka"), vec!["legacy-1"]);
+    assert_eq!(text("checklist"), vec!["legacy-2"]);
+    assert_eq!(db.count().unwrap(), 2);
+
+    // A second run on the now-current file is a no-op apart from the rebuild
+    drop(db);
+    let db = Memori::open(&path).unwrap();
+    let report = db.migrate_legacy().unwrap();
+    let current = memori_core::schema::CURRENT_SCHEMA_VERSION;
+    assert_eq!((report.from_version, report.to_version), (current, current));
+    assert_eq!(report.embedded, 0);
+}
+
+// -- dimension mismatches --
+
+#[test]
+fn test_dimension_mismatch_reported_and_fixed() {
+    let db = open_temp();
+    // The model's dimension with embeddings compiled in, else whatever we insert
+    let dim = if cfg!(any(feature = "embeddings", feature = "mock_embeddings")) { 384 } else { 3 };
+    let good = vec![0.5f32; dim];
+    db.insert("current model", Some(&good), None, None, false).unwrap();
+    let stale = db
+        .insert("old model", Some(&[1.0, 0.0]), None, None, false)
+        .unwrap()
+        .id()
+        .to_string();
+    db.insert("no vector", None, None, None, true).unwrap();
+
+    assert_eq!(db.find_dimension_mismatches(dim).unwrap(), vec![stale.clone()]);
+    assert_eq!(db.reembed_mismatches(dim).unwrap(), 1);
+    assert!(db.find_dimension_mismatches(dim).unwrap().is_empty());
+
+    let fixed = db.get_readonly(&stale).unwrap().unwrap();
+    if cfg!(any(feature = "embeddings", feature = "mock_embeddings")) {
+        assert_eq!(fixed.vector.unwrap().len(), dim);
+    } else {
+        assert!(fixed.vector.is_none());
+    }
+    assert_eq!(db.reembed_mismatches(dim).unwrap(), 0);
+}
+
+#[test]
+fn test_reembed_mismatches_leaves_vector_only_memories() {
+    let db = open_temp();
+    let dim = if cfg!(any(feature = "embeddings", feature = "mock_embeddings")) {
+        384
+    } else {
+        3
+    };
+    let id = db
+        .insert("", Some(&[1.0, 0.0]), None, None, false)
+        .unwrap()
+        .id()
+        .to_string();
+
+    assert_eq!(db.reembed_mismatches(dim).unwrap(), 0);
+    assert_eq!(db.find_dimension_mismatches(dim).unwrap(), vec![id.clone()]);
+    assert_eq!(
+        db.get_readonly(&id).unwrap().unwrap().vector.unwrap(),
+        vec![1.0, 0.0]
+    );
+}
+
+// -- dash-insensitive prefixes --
+
+#[test]
+fn test_dash_insensitive_prefix_resolution() {
+    let path = temp_db_path();
+    let config = MemoriConfig {
+        dash_insensitive_prefix: true,
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(&path, config).unwrap();
+    db.insert_with_id("aaa11111-2222-3333-4444-555555555555", "first", None, None, 1.0, 1.0)
+        .unwrap();
+    db.insert_with_id("bbb11111-2222-3333-4444-555555555555", "second", None, None, 1.0, 1.0)
+        .unwrap();
+    db.insert_with_id("bbb11111-2229-3333-4444-555555555555", "third", None, None, 1.0, 1.0)
+        .unwrap();
+
+    assert_eq!(db.resolve_id("aaa1-11").unwrap(), "aaa11111-2222-3333-4444-555555555555");
+    assert_eq!(db.resolve_id("aaa111112222").unwrap(), "aaa11111-2222-3333-4444-555555555555");
+    assert_eq!(db.resolve_id("bbb11111-2229").unwrap(), "bbb11111-2229-3333-4444-555555555555");
+    assert!(matches!(db.resolve_id("bbb1-1111-222"), Err(MemoriError::AmbiguousPrefix(_, 2))));
+
+    // Exact matching stays the default
+    let exact = Memori::open(&path).unwrap();
+    assert!(matches!(exact.resolve_id("aaa1-11"), Err(MemoriError::NotFound(_))));
+    assert_eq!(exact.resolve_id("aaa11111-2").unwrap(), "aaa11111-2222-3333-4444-555555555555");
+}
+
+// -- get_or_insert_by_metadata --
+
+#[test]
+fn test_get_or_insert_by_metadata_is_idempotent() {
+    let db = open_temp();
+    let key = json!("JIRA-42");
+    let first = db
+        .get_or_insert_by_metadata("ticket", &key, "login page 500s", None, Some(json!({"type": "bug"})))
+        .unwrap();
+    assert!(first.was_created());
+    assert_eq!(first.memory().metadata, Some(json!({"type": "bug", "ticket": "JIRA-42"})));
+
+    for _ in 0..3 {
+        let again = db
+            .get_or_insert_by_metadata("ticket", &key, "different text", None, None)
+            .unwrap();
+        assert!(!again.was_created());
+        assert_eq!(again.memory().id, first.memory().id);
+        assert_eq!(again.memory().content, "login page 500s");
+    }
+    assert_eq!(db.count().unwrap(), 1);
+
+    let other = db
+        .get_or_insert_by_metadata("ticket", &json!("JIRA-43"), "another", None, None)
+        .unwrap();
+    assert!(other.was_created());
+    assert_eq!(db.count().unwrap(), 2);
+
+    assert!(matches!(
+        db.get_or_insert_by_metadata("bad key", &key, "x", None, None),
+        Err(MemoriError::InvalidFilter(_))
+    ));
+}
+
+#[test]
+fn test_get_or_insert_by_metadata_is_atomic_across_handles() {
+    let path = temp_db_path();
+    Memori::open(&path).unwrap();
+    const N: usize = 300;
+    let workers: Vec<_> = (0..2)
+        .map(|w| {
+            let path = path.clone();
+            std::thread::spawn(move || {
+                let db = Memori::open(&path).unwrap();
+                for i in 0..N {
+                    db.get_or_insert_by_metadata(
+                        "ticket",
+                        &json!(i),
+                        &format!("ticket {i} via {w}"),
+                        None,
+                        None,
+                    )
+                    .unwrap();
+                }
+            })
+        })
+        .collect();
+    for w in workers {
+        w.join().unwrap();
+    }
+    assert_eq!(Memori::open(&path).unwrap().count().unwrap(), N);
+}
+// -- similarity_matrix --
+
+#[test]
+fn test_similarity_matrix_pairwise_cosine() {
+    let db = open_temp();
+    let vecs = [vec![1.0f32, 0.0, 0.0], vec![0.0, 1.0, 0.0], vec![1.0, 1.0, 0.0]];
+    let ids: Vec<String> = vecs
+        .iter()
+        .enumerate()
+        .map(|(i, v)| db.insert(&format!("v{}", i), Some(v), None, None, false).unwrap().id().to_string())
+        .collect();
+    db.insert("no vector", None, None, None, true).unwrap();
+
+    let (order, matrix) = db.similarity_matrix(None).unwrap();
+    assert_eq!(order, ids);
+    assert_eq!(matrix.len(), 3);
+    let half_sqrt2 = std::f32::consts::FRAC_1_SQRT_2;
+    let expected = [
+        [1.0, 0.0, half_sqrt2],
+        [0.0, 1.0, half_sqrt2],
+        [half_sqrt2, half_sqrt2, 1.0],
+    ];
+    for i in 0..3 {
+        for j in 0..3 {
+            assert!((matrix[i][j] - expected[i][j]).abs() < 1e-6, "[{}][{}] = {}", i, j, matrix[i][j]);
+        }
+    }
+
+    // An explicit subset, by prefix, keeps the caller's order
+    let subset = vec![ids[2][..8].to_string(), ids[0].clone()];
+    let (order, matrix) = db.similarity_matrix(Some(&subset)).unwrap();
+    assert_eq!(order, vec![ids[2].clone(), ids[0].clone()]);
+    assert!((matrix[0][1] - half_sqrt2).abs() < 1e-6);
+}
+
+// -- enable_fts --
+
+#[test]
+fn test_fts_disabled_database() {
+    let path = temp_db_path();
+    let config = MemoriConfig {
+        enable_fts: false,
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(&path, config).unwrap();
+    let id = db
+        .insert("kafka topics", Some(&[1.0, 0.0]), Some(json!({"type": "note"})), None, false)
+        .unwrap()
+        .id()
+        .to_string();
+    db.update(&id, Some("kafka partitions"), None, None, true).unwrap();
+    db.insert("other", Some(&[0.0, 1.0]), None, None, false).unwrap();
+
+    let hits = db
+        .search(SearchQuery {
+            vector: Some(vec![1.0, 0.0]),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(hits[0].id, id);
+    assert!(!db.inspect(&id).unwrap().unwrap().fts_indexed);
+
+    let text = |db: &Memori, mode: SearchMode| {
+        db.search(SearchQuery {
+            vector: Some(vec![1.0, 0.0]),
+            text: Some("kafka".to_string()),
+            mode,
+            ..Default::default()
+        })
+    };
+    assert!(matches!(text(&db, SearchMode::TextOnly), Err(MemoriError::FtsDisabled)));
+    assert!(matches!(text(&db, SearchMode::Hybrid), Err(MemoriError::FtsDisabled)));
+    assert!(matches!(db.verify_fts(), Err(MemoriError::FtsDisabled)));
+    assert!(matches!(db.rebuild_fts(), Err(MemoriError::FtsDisabled)));
+
+    // The choice is baked into the file: a default handle doesn't add FTS
+    db.delete(&id).unwrap();
+    drop(db);
+    let db = Memori::open(&path).unwrap();
+    assert_eq!(db.count().unwrap(), 1);
+    assert!(matches!(text(&db, SearchMode::TextOnly), Err(MemoriError::FtsDisabled)));
+    assert_eq!(db.migrate_legacy().unwrap().reindexed, 0);
+}
+
+// -- rerank_by --
+
+#[test]
+fn test_rerank_by_created_among_relevant() {
+    let db = open_temp();
+    for i in 0..8 {
+        let ts = 1000.0 + i as f64;
+        db.insert_with_id(&format!("rel-{}", i), "relevant", Some(&[1.0, 0.0]), None, ts, ts)
+            .unwrap();
+    }
+    // Newer than everything relevant, but outside the relevance pool
+    for i in 0..4 {
+        let ts = 5000.0 + i as f64;
+        db.insert_with_id(&format!("off-{}", i), "off topic", Some(&[0.0, 1.0]), None, ts, ts)
+            .unwrap();
+    }
+
+    let query = |rerank_by: Option<SortField>| {
+        db.search(SearchQuery {
+            vector: Some(vec![1.0, 0.0]),
+            limit: 2,
+            rerank_by,
+            rerank_oversample: 4,
+            ..Default::default()
+        })
+        .unwrap()
+        .into_iter()
+        .map(|m| m.id)
+        .collect::<Vec<_>>()
+    };
+
+    assert_eq!(query(Some(SortField::Created)), vec!["rel-7", "rel-6"]);
+    let plain = query(None);
+    assert_eq!(plain.len(), 2);
+    assert!(plain.iter().all(|id| id.starts_with("rel-")));
+}
+
+// -- query dimension mismatch --
+
+#[test]
+fn test_vector_search_rejects_wrong_query_dimension() {
+    let db = open_temp();
+    db.insert("a", Some(&vec![0.1f32; 384]), None, None, false).unwrap();
+    db.insert("b", Some(&vec![0.2f32; 384]), None, None, false).unwrap();
+
+    let search = |dim: usize| {
+        db.search(SearchQuery {
+            vector: Some(vec![0.5; dim]),
+            ..Default::default()
+        })
+    };
+    match search(128) {
+        Err(MemoriError::InvalidVector(msg)) => assert!(msg.contains("128") && msg.contains("384"), "{}", msg),
+        other => panic!("expected InvalidVector, got {:?}", other),
+    }
+    assert_eq!(search(384).unwrap().len(), 2);
+
+    // A stray old-dimension row doesn't block queries matching the rest
+    db.insert("stale", Some(&[1.0, 0.0]), None, None, false).unwrap();
+    assert_eq!(search(384).unwrap().len(), 3);
+}
+
+// -- archive tier --
+
+#[test]
+fn test_archive_round_trip() {
+    let db = open_temp();
+    let cold = db
+        .insert("kafka retention policy", Some(&[1.0, 0.0]), Some(json!({"type": "note"})), None, false)
+        .unwrap()
+        .id()
+        .to_string();
+    let hot = db
+        .insert("kafka consumer groups", Some(&[0.9, 0.1]), None, None, false)
+        .unwrap()
+        .id()
+        .to_string();
+    db.set_access_stats(&cold, None, 4).unwrap();
+
+    db.archive(&cold[..8]).unwrap();
+    assert_eq!(db.count().unwrap(), 1);
+    assert_eq!(db.archive_count().unwrap(), 1);
+    assert!(db.get(&cold).unwrap().is_none());
+    assert!(matches!(db.archive(&cold), Err(MemoriError::NotFound(_))));
+
+    let ids = |v: Vec<memori_core::Memory>| v.into_iter().map(|m| m.id).collect::<Vec<_>>();
+    let vector = || SearchQuery {
+        vector: Some(vec![1.0, 0.0]),
+        ..Default::default()
+    };
+    let text = || SearchQuery {
+        text: Some("KAFKA retention".to_string()),
+        mode: SearchMode::TextOnly,
+        ..Default::default()
+    };
+    assert_eq!(ids(db.search(vector()).unwrap()), vec![hot.clone()]);
+    assert!(db.search(text()).unwrap().is_empty());
+    assert!(db.list(None, &SortField::Created, 10, 0, None, None, false).unwrap().iter().all(|m| m.id != cold));
+
+    assert_eq!(ids(db.search_archive(vector()).unwrap()), vec![cold.clone()]);
+    assert_eq!(ids(db.search_archive(text()).unwrap()), vec![cold.clone()]);
+    let filtered = SearchQuery {
+        text: Some("note".to_string()),
+        filter: Some(json!({"type": "note"})),
+        mode: SearchMode::TextOnly,
+        ..Default::default()
+    };
+    assert_eq!(ids(db.search_archive(filtered).unwrap()), vec![cold.clone()]);
+    // Vector results from both tiers merge by score
+    assert_eq!(ids(db.search_all(vector()).unwrap()), vec![cold.clone(), hot.clone()]);
+
+    assert_eq!(db.unarchive(&cold[..8]).unwrap(), cold);
+    assert_eq!(db.archive_count().unwrap(), 0);
+    let back = db.get_readonly(&cold).unwrap().unwrap();
+    assert_eq!(back.content, "kafka retention policy");
+    assert_eq!(back.metadata, Some(json!({"type": "note"})));
+    assert_eq!(back.vector, Some(vec![1.0, 0.0]));
+    assert_eq!(back.access_count, 4);
+    assert_eq!(ids(db.search(text()).unwrap()), vec![cold.clone()]);
+    assert!(matches!(db.unarchive(&cold), Err(MemoriError::NotFound(_))));
+}
+
+// -- dedup scope --
+
+#[test]
+fn test_dedup_scope_keys_require_every_key_to_match() {
+    let config = MemoriConfig {
+        dedup_scope: DedupScope::Keys(vec!["type".to_string(), "topic".to_string()]),
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(":memory:", config).unwrap();
+    let insert = |content: &str, vector: &[f32], meta: serde_json::Value| {
+        db.insert(content, Some(vector), Some(meta), Some(0.92), false)
+            .unwrap()
+    };
+
+    let first = insert(
+        "kafka partitions",
+        &[1.0, 0.0, 0.0],
+        json!({"type": "note", "topic": "kafka"}),
+    );
+    // Same type, different topic: kept apart despite near-identical vectors
+    let other = insert(
+        "pulsar partitions",
+        &[0.99, 0.01, 0.0],
+        json!({"type": "note", "topic": "pulsar"}),
+    );
+    assert!(matches!(other, InsertResult::Created(_)));
+    // A missing key only matches memories that also lack it
+    let untopiced = insert("partitions", &[0.99, 0.01, 0.0], json!({"type": "note"}));
+    assert!(matches!(untopiced, InsertResult::Created(_)));
+    let same = insert(
+        "kafka partitioning",
+        &[0.99, 0.01, 0.0],
+        json!({"type": "note", "topic": "kafka"}),
+    );
+    assert_eq!(same.id(), first.id());
+    assert_eq!(db.count().unwrap(), 3);
+}
+
+#[test]
+fn test_dedup_scope_global_and_invalid_key() {
+    let config = MemoriConfig {
+        dedup_scope: DedupScope::Global,
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(":memory:", config).unwrap();
+    let first = db
+        .insert(
+            "a",
+            Some(&[1.0, 0.0]),
+            Some(json!({"type": "note"})),
+            Some(0.92),
+            false,
+        )
+        .unwrap();
+    let second = db
+        .insert(
+            "b",
+            Some(&[1.0, 0.0]),
+            Some(json!({"type": "fact"})),
+            Some(0.92),
+            false,
+        )
+        .unwrap();
+    assert!(matches!(second, InsertResult::Deduplicated(_)));
+    assert_eq!(second.id(), first.id());
+
+    let config = MemoriConfig {
+        dedup_scope: DedupScope::Keys(vec!["type') OR 1=1 --".to_string()]),
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(":memory:", config).unwrap();
+    let err = db
+        .insert("a", Some(&[1.0, 0.0]), None, Some(0.92), false)
+        .unwrap_err();
+    assert!(matches!(err, MemoriError::InvalidFilter(_)));
+    // Without a threshold the scope is never consulted
+    assert!(db.insert("a", Some(&[1.0, 0.0]), None, None, false).is_ok());
+}
+
+// -- embedding coverage --
+
+#[test]
+fn test_embedding_coverage_by_type() {
+    let db = open_temp();
+    let v = [1.0, 0.0];
+    for (vector, meta) in [
+        (Some(&v[..]), Some(json!({"type": "fact"}))),
+        (None, Some(json!({"type": "fact"}))),
+        (Some(&v[..]), Some(json!({"type": "fact"}))),
+        (None, Some(json!({"type": "note"}))),
+        (Some(&v[..]), Some(json!({"type": "pref"}))),
+        (None, None),
+    ] {
+        db.insert("row", vector, meta, None, true).unwrap();
+    }
+
+    assert_eq!(db.missing_embedding_count().unwrap(), 3);
+    let coverage = db.embedding_coverage_by_type().unwrap();
+    assert_eq!(coverage.len(), 3);
+    assert_eq!(coverage["fact"], (2, 3));
+    assert_eq!(coverage["note"], (0, 1));
+    assert_eq!(coverage["pref"], (1, 1));
+    assert_eq!(db.embedding_stats().unwrap(), (3, 6));
+}
+
+// -- dedup sure match --
+
+#[test]
+fn test_dedup_sure_match_takes_first_sure_candidate() {
+    let insert_all = |db: &Memori| {
+        let near = db.insert("near", Some(&[0.995, 0.0999]), None, None, false).unwrap();
+        let exact = db.insert("exact", Some(&[1.0, 0.0]), None, None, false).unwrap();
+        let dup = db.insert("query", Some(&[1.0, 0.0]), None, Some(0.92), false).unwrap();
+        (near.id().to_string(), exact.id().to_string(), dup.id().to_string())
+    };
+
+    // Default: the scan finds the true best match
+    let (_, exact, dup) = insert_all(&open_temp());
+    assert_eq!(dup, exact);
+
+    // With a cutoff the earlier, merely sure-enough match wins
+    let config = MemoriConfig {
+        dedup_sure_match: Some(0.99),
+        ..Default::default()
+    };
+    let (near, _, dup) = insert_all(&Memori::open_with_config(":memory:", config).unwrap());
+    assert_eq!(dup, near);
+}
+
+// -- ensure indexes --
+
+#[test]
+fn test_ensure_indexes_recreates_dropped_index() {
+    let path = temp_db_path();
+    let db = Memori::open(&path).unwrap();
+    db.insert("x", None, Some(json!({"type": "note"})), None, true)
+        .unwrap();
+
+    let indexes = || {
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        let mut stmt = conn
+            .prepare("SELECT name FROM pragma_index_list('memories')")
+            .unwrap();
+        let names: Vec<String> = stmt
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .map(|n| n.unwrap())
+            .collect();
+        names
+    };
+    assert!(indexes().contains(&"idx_memories_type".to_string()));
+
+    rusqlite::Connection::open(&path)
+        .unwrap()
+        .execute_batch("DROP INDEX idx_memories_type")
+        .unwrap();
+    assert!(!indexes().contains(&"idx_memories_type".to_string()));
+
+    db.ensure_indexes().unwrap();
+    db.ensure_indexes().unwrap();
+    assert!(indexes().contains(&"idx_memories_type".to_string()));
+    assert!(indexes().contains(&"idx_memories_collection".to_string()));
+
+    db.analyze().unwrap();
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    let stats: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM sqlite_stat1 WHERE tbl = 'memories'",
+            [],
+            |r| r.get(0),
+        )
+        .unwrap();
+    assert!(stats > 0);
+}
+
+// -- stored vector norms --
+
+#[test]
+fn test_stored_norms_match_full_recompute() {
+    let path = temp_db_path();
+    let db = Memori::open(&path).unwrap();
+    for i in 0..20 {
+        let x = i as f32;
+        let v = [
+            1.0 + x,
+            (x * 0.7).sin() * 3.0,
+            0.5 - x * 0.1,
+            (x * 1.3).cos(),
+        ];
+        db.insert(&format!("m{}", i), Some(&v), None, None, false)
+            .unwrap();
+    }
+    let query = || SearchQuery {
+        vector: Some(vec![2.0, -1.0, 0.25, 0.5]),
+        limit: 20,
+        ..Default::default()
+    };
+    let stored = db.search(query()).unwrap();
+
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    let with_norm: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM memories WHERE vector_norm IS NOT NULL",
+            [],
+            |r| r.get(0),
+        )
+        .unwrap();
+    assert_eq!(with_norm, 20);
+    conn.execute_batch("UPDATE memories SET vector_norm = NULL")
+        .unwrap();
+    let recomputed = db.search(query()).unwrap();
+
+    assert_eq!(stored.len(), 20);
+    for (a, b) in stored.iter().zip(&recomputed) {
+        assert_eq!(a.id, b.id);
+        assert!((a.base_score.unwrap() - b.base_score.unwrap()).abs() < 1e-5);
+    }
+
+    // Updating the vector rewrites its norm
+    let id = &stored[0].id;
+    db.update(id, None, Some(&[0.0, 0.0, 3.0, 4.0]), None, false)
+        .unwrap();
+    let norm: f64 = conn
+        .query_row(
+            "SELECT vector_norm FROM memories WHERE id = ?1",
+            [id],
+            |r| r.get(0),
+        )
+        .unwrap();
+    assert!((norm - 5.0).abs() < 1e-6);
+}
+
+// -- embed text hash --
+
+#[cfg(any(feature = "embeddings", feature = "mock_embeddings"))]
+#[test]
+fn test_update_skips_reembedding_unchanged_text() {
+    use memori_core::embed::model_calls;
+
+    let db = open_temp();
+    let id = db
+        .insert("kafka uses partitioned topics", None, Some(json!({"type": "fact"})), None, false)
+        .unwrap()
+        .id()
+        .to_string();
+
+    // The first metadata update changes the embedded text (content + values)
+    db.update(&id, None, None, Some(json!({"topic": "kafka"})), true).unwrap();
+    let tagged = db.get_readonly(&id).unwrap().unwrap().vector.unwrap();
+
+    // Merging the same values again yields the same text: no model call
+    let before = model_calls();
+    db.update(&id, None, None, Some(json!({"topic": "kafka"})), true).unwrap();
+    assert_eq!(model_calls(), before);
+    let after = db.get_readonly(&id).unwrap().unwrap().vector.unwrap();
+    assert_eq!(
+        tagged.iter().map(|x| x.to_bits()).collect::<Vec<_>>(),
+        after.iter().map(|x| x.to_bits()).collect::<Vec<_>>()
+    );
+
+    // Changing the content still re-embeds
+    db.update(&id, Some("kafka uses replicated partitions"), None, None, false).unwrap();
+    assert_eq!(model_calls(), before + 1);
+}
+
+#[cfg(any(feature = "embeddings", feature = "mock_embeddings"))]
+#[test]
+fn test_insert_embedded_counts_as_auto_embedded() {
+    use memori_core::embed::model_calls;
+    use memori_core::storage::auto_embedding;
+
+    let db = open_temp();
+    let content = "kafka uses partitioned topics";
+    let embedding = auto_embedding(db.config(), content).unwrap();
+    let id = db
+        .insert_embedded(content, embedding.clone(), None, None)
+        .unwrap()
+        .id()
+        .to_string();
+    assert_eq!(
+        db.get_readonly(&id).unwrap().unwrap().vector.unwrap(),
+        embedding
+    );
+
+    // embed_hash was recorded, so rewriting the same text skips the model
+    let before = model_calls();
+    db.update(&id, Some(content), None, None, false).unwrap();
+    assert_eq!(model_calls(), before);
+
+    let again = db
+        .insert_embedded(content, embedding, None, Some(0.95))
+        .unwrap();
+    assert!(again.is_deduplicated());
+}
+
+// -- list snapshots --
+
+#[test]
+fn test_list_snapshot_pages_ignore_access_changes() {
+    let db = open_temp();
+    let mut ids = Vec::new();
+    for i in 0..5 {
+        let id = db
+            .insert(&format!("m{}", i), None, None, None, true)
+            .unwrap()
+            .id()
+            .to_string();
+        db.set_access_stats(&id, None, 10 - i).unwrap();
+        ids.push(id);
+    }
+
+    let snapshot = db.list_snapshot(&SortField::Count, 2).unwrap();
+    assert_eq!((snapshot.total, snapshot.pages()), (5, 3));
+    let mut seen: Vec<String> = db
+        .snapshot_page(&snapshot, 0)
+        .unwrap()
+        .into_iter()
+        .map(|m| m.id)
+        .collect();
+    assert_eq!(seen, ids[..2]);
+
+    // Accessing the tail memories moves them to the front of a live listing
+    for id in &ids[3..] {
+        for _ in 0..20 {
+            db.get(id).unwrap();
+        }
+    }
+    let live = db.list(None, &SortField::Count, 2, 0, None, None, false).unwrap();
+    assert_eq!(live[0].id, ids[3]);
+
+    for page in 1..snapshot.pages() {
+        let memories = db.snapshot_page(&snapshot, page).unwrap();
+        seen.extend(memories.into_iter().map(|m| m.id));
+    }
+    assert_eq!(seen, ids);
+    // Pages carry current contents
+    assert_eq!(db.snapshot_page(&snapshot, 2).unwrap()[0].access_count, 26);
+    assert!(db.snapshot_page(&snapshot, 3).unwrap().is_empty());
+
+    db.release_snapshot(snapshot.clone()).unwrap();
+    assert!(db.snapshot_page(&snapshot, 0).unwrap().is_empty());
+    assert!(matches!(
+        db.list_snapshot(&SortField::Count, 0),
+        Err(MemoriError::InvalidQuery(_))
+    ));
+}
+
+// -- neighborhood export --
+
+#[test]
+fn test_export_neighborhood_expands_by_depth() {
+    let db = open_temp();
+    // A chain along the unit circle: each point's nearest neighbors are the adjacent ones
+    let ids: Vec<String> = (0..6)
+        .map(|i| {
+            let angle = i as f32 * 0.3;
+            db.insert(
+                &format!("n{}", i),
+                Some(&[angle.cos(), angle.sin()]),
+                None,
+                None,
+                false,
+            )
+            .unwrap()
+            .id()
+            .to_string()
+        })
+        .collect();
+    let ids_of = |v: Vec<memori_core::Memory>| v.into_iter().map(|m| m.id).collect::<Vec<_>>();
+
+    assert_eq!(
+        ids_of(db.export_neighborhood(&ids[0], 0, 2).unwrap()),
+        vec![ids[0].clone()]
+    );
+    assert_eq!(
+        ids_of(db.export_neighborhood(&ids[0], 1, 2).unwrap()),
+        ids[..3]
+    );
+
+    let depth2 = ids_of(db.export_neighborhood(&ids[0], 2, 2).unwrap());
+    // n1 adds nothing new; n2 adds n3
+    assert_eq!(depth2, ids[..4]);
+    let unique: std::collections::HashSet<_> = depth2.iter().collect();
+    assert_eq!(unique.len(), depth2.len());
+
+    // Deep enough to reach everything, still without repeats
+    assert_eq!(db.export_neighborhood(&ids[0], 10, 2).unwrap().len(), 6);
+    assert!(matches!(
+        db.export_neighborhood("missing", 1, 2),
+        Err(MemoriError::NotFound(_))
+    ));
+}
+
+// -- metrics --
+
+#[test]
+fn test_metrics_count_operations() {
+    let db = open_temp();
+    assert_eq!(db.metrics(), MetricsSnapshot::default());
+
+    let meta = || Some(json!({"type": "fact"}));
+    let first = db
+        .insert("kafka topics", Some(&[1.0, 0.0]), meta(), Some(0.92), false)
+        .unwrap();
+    let dup = db
+        .insert(
+            "kafka topics again",
+            Some(&[1.0, 0.0]),
+            meta(),
+            Some(0.92),
+            false,
+        )
+        .unwrap();
+    assert!(dup.is_deduplicated());
+    db.insert("unrelated", Some(&[0.0, 1.0]), meta(), Some(0.92), false)
+        .unwrap();
+    db.insert_with_id("fixed-id", "imported", None, None, 1.0, 1.0)
+        .unwrap();
+    // Only the call that creates the memory counts
+    for _ in 0..2 {
+        db.get_or_insert_by_metadata("ticket", &json!("T-1"), "ticket", None, None)
+            .unwrap();
+    }
+
+    db.get(first.id()).unwrap();
+    db.get("fixed-id").unwrap();
+    db.get("missing").unwrap();
+    db.get_readonly(first.id()).unwrap();
+    let query = || SearchQuery {
+        vector: Some(vec![1.0, 0.0]),
+        ..Default::default()
+    };
+    db.search(query()).unwrap();
+    db.search_all(query()).unwrap();
+
+    assert_eq!(
+        db.metrics(),
+        MetricsSnapshot {
+            inserts: 5,
+            dedup_merges: 1,
+            searches: 2,
+            gets: 3,
+        }
+    );
+
+    db.reset_metrics();
+    assert_eq!(db.metrics(), MetricsSnapshot::default());
+    db.search(query()).unwrap();
+    assert_eq!(db.metrics().searches, 1);
+}
+
+// -- exact dedup --
+
+#[test]
+fn test_exact_dedup_merges_identical_content() {
+    let config = MemoriConfig {
+        exact_dedup: true,
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(":memory:", config).unwrap();
+
+    let first = db
+        .insert(
+            "kafka retention is 7 days",
+            None,
+            Some(json!({"type": "fact"})),
+            None,
+            true,
+        )
+        .unwrap();
+    let again = db
+        .insert(
+            "kafka retention is 7 days",
+            None,
+            Some(json!({"type": "fact", "src": "wiki"})),
+            None,
+            true,
+        )
+        .unwrap();
+    assert!(again.is_deduplicated());
+    assert_eq!(again.id(), first.id());
+    assert_eq!(db.count().unwrap(), 1);
+    let stored = db.get_readonly(first.id()).unwrap().unwrap();
+    assert_eq!(
+        stored.metadata,
+        Some(json!({"type": "fact", "src": "wiki"}))
+    );
+
+    // One character apart is a different memory
+    let other = db
+        .insert(
+            "kafka retention is 8 days",
+            None,
+            Some(json!({"type": "fact"})),
+            None,
+            true,
+        )
+        .unwrap();
+    assert!(matches!(other, InsertResult::Created(_)));
+    // The default scope keeps different types apart
+    let typed = db
+        .insert(
+            "kafka retention is 7 days",
+            None,
+            Some(json!({"type": "note"})),
+            None,
+            true,
+        )
+        .unwrap();
+    assert!(matches!(typed, InsertResult::Created(_)));
+    assert_eq!(db.count().unwrap(), 3);
+
+    // Content updates keep the hash current
+    db.update(other.id(), Some("renamed"), None, None, false)
+        .unwrap();
+    let renamed = db
+        .insert("renamed", None, Some(json!({"type": "fact"})), None, true)
+        .unwrap();
+    assert_eq!(renamed.id(), other.id());
+}
+
+#[test]
+fn test_exact_dedup_off_by_default() {
+    let db = open_temp();
+    db.insert("same", None, None, None, true).unwrap();
+    db.insert("same", None, None, None, true).unwrap();
+    assert_eq!(db.count().unwrap(), 2);
+}
+
+// -- touch batching --
+
+#[test]
+fn test_touch_batching_defers_access_writes() {
+    let path = temp_db_path();
+    let config = MemoriConfig {
+        touch_batch_size: 100,
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(&path, config).unwrap();
+    let id = db
+        .insert("hot memory", None, None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    let stored_count = || {
+        rusqlite::Connection::open(&path)
+            .unwrap()
+            .query_row(
+                "SELECT access_count FROM memories WHERE id = ?1",
+                [&id],
+                |r| r.get::<_, i64>(0),
+            )
+            .unwrap()
+    };
+
+    for i in 0..7 {
+        // Each get reports the accesses before it, flushed or not
+        assert_eq!(db.get(&id).unwrap().unwrap().access_count, i);
+    }
+    assert_eq!(stored_count(), 0);
+
+    db.flush_access().unwrap();
+    assert_eq!(stored_count(), 7);
+    assert_eq!(db.get_readonly(&id).unwrap().unwrap().access_count, 7);
+
+    // Pending accesses are written before a write to the row, and on drop
+    db.get(&id).unwrap();
+    db.set_access_stats(&id, None, 1).unwrap();
+    db.get(&id).unwrap();
+    drop(db);
+    assert_eq!(stored_count(), 2);
+}
+
+#[test]
+fn test_touch_batching_flushes_at_batch_size() {
+    let path = temp_db_path();
+    let config = MemoriConfig {
+        touch_batch_size: 3,
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(&path, config).unwrap();
+    let id = db
+        .insert("m", None, None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    for _ in 0..4 {
+        db.get(&id).unwrap();
+    }
+    assert_eq!(db.get_readonly(&id).unwrap().unwrap().access_count, 3);
+}
+
+// -- result cache --
+
+#[test]
+fn test_result_cache_serves_repeats_until_write() {
+    let path = temp_db_path();
+    let config = MemoriConfig {
+        result_cache_size: 4,
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(&path, config).unwrap();
+    db.insert("alpha needle", Some(&[1.0, 0.0, 0.0]), None, None, true)
+        .unwrap();
+    db.insert("beta needle", Some(&[0.0, 1.0, 0.0]), None, None, true)
+        .unwrap();
+    let query = || SearchQuery {
+        vector: Some(vec![1.0, 0.1, 0.0]),
+        text: Some("needle".to_string()),
+        limit: 5,
+        ..Default::default()
+    };
+    let summary = |results: Vec<memori_core::Memory>| -> Vec<(String, String)> {
+        results.into_iter().map(|m| (m.id, m.content)).collect()
+    };
+
+    let first = summary(db.search(query()).unwrap());
+    assert_eq!(first.len(), 2);
+
+    // Changed behind the handle's back: a cache hit can't see it
+    let other = rusqlite::Connection::open(&path).unwrap();
+    other
+        .execute(
+            "UPDATE memories SET content = 'gamma needle' WHERE id = ?1",
+            [&first[0].0],
+        )
+        .unwrap();
+    // Whitespace differences in the text hit the same entry
+    let spaced = SearchQuery {
+        text: Some("  needle ".to_string()),
+        ..query()
+    };
+    assert_eq!(summary(db.search(query()).unwrap()), first);
+    assert_eq!(summary(db.search(spaced).unwrap()), first);
+    assert_eq!(db.metrics().searches, 3);
+
+    // Any write through the handle invalidates
+    db.insert("delta needle", Some(&[0.0, 0.0, 1.0]), None, None, true)
+        .unwrap();
+    let after = summary(db.search(query()).unwrap());
+    assert_eq!(after.len(), 3);
+    assert!(after.iter().any(|(_, c)| c == "gamma needle"));
+}
+
+// -- checkpoint --
+
+#[test]
+fn test_checkpoint_copies_frames_and_truncate_empties_wal() {
+    let path = temp_db_path();
+    let db = Memori::open(&path).unwrap();
+    for i in 0..20 {
+        db.insert(
+            &format!("memory {}", i),
+            Some(&[i as f32, 1.0, 0.0]),
+            None,
+            None,
+            true,
+        )
+        .unwrap();
+    }
+    let wal = format!("{}-wal", path);
+    let before = std::fs::metadata(&wal).unwrap().len();
+    assert!(before > 0);
+
+    let full = db.checkpoint(CheckpointMode::Full).unwrap();
+    assert!(!full.busy);
+    assert!(full.checkpointed_frames > 0);
+    assert_eq!(full.checkpointed_frames, full.log_frames);
+
+    // Truncate resets the log before counting, so it reports zero frames
+    let truncated = db.checkpoint(CheckpointMode::Truncate).unwrap();
+    assert_eq!((truncated.log_frames, truncated.checkpointed_frames), (0, 0));
+    assert_eq!(std::fs::metadata(&wal).unwrap().len(), 0);
+    assert_eq!(db.count().unwrap(), 20);
+
+    let mem = Memori::open(":memory:").unwrap();
+    assert_eq!(
+        mem.checkpoint(CheckpointMode::Passive).unwrap().log_frames,
+        -1
+    );
+}
+
+// -- embed preprocessing --
+
+#[cfg(any(feature = "embeddings", feature = "mock_embeddings"))]
+#[test]
+fn test_embed_preprocess_lowercase_embeds_normalized_text() {
+    use memori_core::util::cosine_similarity;
+    use memori_core::EmbedPreprocess;
+
+    let config = MemoriConfig {
+        embed_preprocess: EmbedPreprocess::Lowercase,
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(":memory:", config).unwrap();
+    let a = db
+        .insert("Kafka Uses  PARTITIONED Topics", None, None, None, false)
+        .unwrap()
+        .id()
+        .to_string();
+    let b = db
+        .insert("kafka uses partitioned\ntopics", None, None, None, false)
+        .unwrap()
+        .id()
+        .to_string();
+
+    let a = db.get_readonly(&a).unwrap().unwrap();
+    let b = db.get_readonly(&b).unwrap().unwrap();
+    let sim = cosine_similarity(a.vector.as_ref().unwrap(), b.vector.as_ref().unwrap());
+    assert!(sim > 0.999, "similarity {}", sim);
+
+    // Only the embedding input is rewritten
+    assert_eq!(a.content, "Kafka Uses  PARTITIONED Topics");
+    let hits = db
+        .search(SearchQuery {
+            text: Some("PARTITIONED".to_string()),
+            mode: SearchMode::TextOnly,
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(hits.len(), 2);
+}
+
+// -- weighted related --
+
+#[test]
+fn test_related_weighted_boosts_shared_metadata() {
+    let db = open_temp();
+    let source = db
+        .insert(
+            "source",
+            Some(&[1.0, 0.0, 0.0]),
+            Some(json!({"topic": "kafka", "type": "fact"})),
+            None,
+            true,
+        )
+        .unwrap()
+        .id()
+        .to_string();
+    let closest = db
+        .insert(
+            "closest",
+            Some(&[0.99, 0.14, 0.0]),
+            Some(json!({"topic": "redis"})),
+            None,
+            true,
+        )
+        .unwrap()
+        .id()
+        .to_string();
+    let tagged = db
+        .insert(
+            "tagged",
+            Some(&[0.95, 0.31, 0.0]),
+            Some(json!({"topic": "kafka"})),
+            None,
+            true,
+        )
+        .unwrap()
+        .id()
+        .to_string();
+    db.insert(
+        "far",
+        Some(&[0.0, 0.0, 1.0]),
+        Some(json!({"type": "fact"})),
+        None,
+        true,
+    )
+    .unwrap();
+
+    let ids =
+        |results: Vec<memori_core::Memory>| results.into_iter().map(|m| m.id).collect::<Vec<_>>();
+    assert_eq!(
+        ids(db.related_weighted(&source, 2, 0.0).unwrap()),
+        vec![closest.clone(), tagged.clone()]
+    );
+
+    let boosted = db.related_weighted(&source, 3, 0.1).unwrap();
+    assert_eq!(boosted[0].id, tagged);
+    assert_eq!(boosted[1].id, closest);
+    let base = boosted[0].base_score.unwrap();
+    assert!((boosted[0].score.unwrap() - (base + 0.1)).abs() < 1e-6);
+    assert!(boosted.iter().all(|m| m.id != source));
+}
+
+// -- vector-only memories --
+
+#[test]
+fn test_vector_only_memory_with_empty_content() {
+    let db = Memori::open_with_config(
+        ":memory:",
+        MemoriConfig {
+            exact_dedup: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let id = db
+        .insert(
+            "",
+            Some(&[0.0, 1.0, 0.0]),
+            Some(json!({"kind": "image", "camera": "nikon"})),
+            None,
+            false,
+        )
+        .unwrap()
+        .id()
+        .to_string();
+    // Empty content is not an exact duplicate of other empty content
+    let other = db
+        .insert("", Some(&[1.0, 0.0, 0.0]), None, None, false)
+        .unwrap();
+    assert!(!other.is_deduplicated());
+    db.insert("a text memory", Some(&[0.5, 0.5, 0.0]), None, None, true)
+        .unwrap();
+
+    let by_vector = db
+        .search(SearchQuery {
+            vector: Some(vec![0.0, 1.0, 0.0]),
+            limit: 1,
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(by_vector[0].id, id);
+    assert_eq!(by_vector[0].content, "");
+
+    let by_metadata = db
+        .search(SearchQuery {
+            text: Some("nikon".to_string()),
+            mode: SearchMode::TextOnly,
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(by_metadata.len(), 1);
+    assert_eq!(by_metadata[0].id, id);
+
+    // Metadata updates leave the supplied vector alone
+    db.update(&id, None, None, Some(json!({"camera": "canon"})), true)
+        .unwrap();
+    assert_eq!(
+        db.get_readonly(&id).unwrap().unwrap().vector.unwrap(),
+        vec![0.0, 1.0, 0.0]
+    );
+}
+
+#[cfg(any(feature = "embeddings", feature = "mock_embeddings"))]
+#[test]
+fn test_empty_content_is_never_auto_embedded() {
+    use memori_core::embed::model_calls;
+
+    let db = open_temp();
+    let before = model_calls();
+    let id = db
+        .insert("", None, Some(json!({"kind": "image"})), None, false)
+        .unwrap()
+        .id()
+        .to_string();
+    db.update(&id, None, None, Some(json!({"camera": "nikon"})), true)
+        .unwrap();
+    assert_eq!(model_calls(), before);
+    assert!(db.get_readonly(&id).unwrap().unwrap().vector.is_none());
+}
+
+// -- pure-similarity related --
+
+#[test]
+fn test_related_ranks_by_cosine_alone() {
+    let path = temp_db_path();
+    let db = Memori::open(&path).unwrap();
+    let source = db
+        .insert("source", Some(&[1.0, 0.0]), None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    let closer = db
+        .insert("closer", Some(&[0.99, 0.14]), None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    let popular = db
+        .insert("popular", Some(&[0.98, 0.2]), None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs_f64();
+    db.set_access_stats(&popular, Some(now), 500).unwrap();
+
+    let related = db.related(&source, 2).unwrap();
+    assert_eq!(related[0].id, closer);
+    assert_eq!(related[1].id, popular);
+    assert!(related.iter().all(|m| m.score == m.base_score));
+
+    // Opting out restores search's access boost
+    drop(db);
+    let config = MemoriConfig {
+        related_pure_similarity: false,
+        ..Default::default()
+    };
+    let boosted = Memori::open_with_config(&path, config)
+        .unwrap()
+        .related(&source, 2)
+        .unwrap();
+    assert_eq!(boosted[0].id, popular);
+}
+
+// -- replace --
+
+#[test]
+fn test_replace_overwrites_row_and_keeps_history() {
+    let db = open_temp();
+    let id = db
+        .insert(
+            "old text",
+            Some(&[1.0, 0.0, 0.0]),
+            Some(json!({"type": "fact", "topic": "kafka"})),
+            None,
+            true,
+        )
+        .unwrap()
+        .id()
+        .to_string();
+    db.set_access_stats(&id, Some(1_000.0), 7).unwrap();
+    let before = db.get_readonly(&id).unwrap().unwrap();
+
+    db.replace(
+        &id[..8],
+        "new text",
+        Some(&[0.0, 1.0, 0.0]),
+        Some(json!({"type": "decision"})),
+    )
+    .unwrap();
+    let after = db.get_readonly(&id).unwrap().unwrap();
+    assert_eq!(after.content, "new text");
+    assert_eq!(after.vector.unwrap(), vec![0.0, 1.0, 0.0]);
+    // Metadata is replaced, not merged
+    assert_eq!(after.metadata, Some(json!({"type": "decision"})));
+    assert_eq!(after.created_at, before.created_at);
+    assert_eq!((after.access_count, after.last_accessed), (7, 1_000.0));
+    assert!(after.updated_at >= before.updated_at);
+
+    let hits = db
+        .search(SearchQuery {
+            text: Some("new".to_string()),
+            mode: SearchMode::TextOnly,
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(hits.len(), 1);
+
+    // No metadata clears it; without embeddings no vector clears the vector
+    db.replace(&id, "newest", None, None).unwrap();
+    let cleared = db.get_readonly(&id).unwrap().unwrap();
+    assert_eq!(cleared.metadata, None);
+    assert_eq!(cleared.vector.is_some(), cfg!(any(feature = "embeddings", feature = "mock_embeddings")));
+
+    assert!(matches!(
+        db.replace("nonexistent", "x", None, None),
+        Err(MemoriError::NotFound(_))
+    ));
+}
+
+// -- long text queries --
+
+#[test]
+fn test_very_long_text_query_is_capped() {
+    let db = open_temp();
+    db.insert("kafka partitions are replicated", None, None, None, true)
+        .unwrap();
+    db.insert("redis keeps data in memory", None, None, None, true)
+        .unwrap();
+
+    // A pasted wall of text: repeats collapse, so the MATCH stays small
+    let pasted = "Kafka partitions ".repeat(2000);
+    let hits = db
+        .search(SearchQuery {
+            text: Some(pasted),
+            mode: SearchMode::TextOnly,
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(hits.len(), 1);
+    assert!(hits[0].content.starts_with("kafka"));
+
+    let distinct: String = (0..5000).map(|i| format!("word{} ", i)).collect();
+    let hits = db
+        .search(SearchQuery {
+            text: Some(distinct),
+            mode: SearchMode::TextOnly,
+            ..Default::default()
+        })
+        .unwrap();
+    assert!(hits.is_empty());
+}
+
+#[test]
+fn test_max_query_terms_drops_later_terms() {
+    let config = MemoriConfig {
+        max_query_terms: 2,
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(":memory:", config).unwrap();
+    db.insert("kafka partitions", None, None, None, true)
+        .unwrap();
+    let query = |text: &str| SearchQuery {
+        text: Some(text.to_string()),
+        mode: SearchMode::TextOnly,
+        ..Default::default()
+    };
+
+    // "missing" is the third term, so it can't exclude the match
+    assert_eq!(
+        db.search(query("kafka partitions missing")).unwrap().len(),
+        1
+    );
+    assert!(db
+        .search(query("missing kafka partitions"))
+        .unwrap()
+        .is_empty());
+}
+
+// -- mock embeddings --
+
+#[cfg(feature = "mock_embeddings")]
+#[test]
+fn test_mock_embeddings_auto_embed_dedup_and_hybrid() {
+    use memori_core::embed::{embed_text, MOCK_DIM};
+
+    // Deterministic: same text, same vector, every time
+    assert_eq!(
+        embed_text("kafka partitions"),
+        embed_text("kafka partitions")
+    );
+    assert_eq!(embed_text("kafka partitions").len(), MOCK_DIM);
+
+    let db = open_temp();
+    let id = db
+        .insert(
+            "kafka uses partitioned topics",
+            None,
+            Some(json!({"type": "fact"})),
+            None,
+            false,
+        )
+        .unwrap()
+        .id()
+        .to_string();
+    let stored = db.get_readonly(&id).unwrap().unwrap().vector.unwrap();
+    assert_eq!(stored, embed_text("kafka uses partitioned topics"));
+
+    // Same words, different case and punctuation: merged into the first
+    let again = db
+        .insert(
+            "Kafka uses partitioned topics.",
+            None,
+            Some(json!({"type": "fact"})),
+            Some(0.92),
+            false,
+        )
+        .unwrap();
+    assert!(again.is_deduplicated());
+    assert_eq!(again.id(), id);
+    db.insert(
+        "redis keeps data in memory",
+        None,
+        Some(json!({"type": "fact"})),
+        Some(0.92),
+        false,
+    )
+    .unwrap();
+    assert_eq!(db.count().unwrap(), 2);
+
+    // Text-only query: hybrid search embeds it and fuses with FTS
+    let hits = db
+        .search(SearchQuery {
+            text: Some("kafka topics".to_string()),
+            limit: 2,
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(hits[0].id, id);
+    assert!(hits[0].score.unwrap() > hits[1].score.unwrap());
+}
+
+// -- importance --
+
+#[test]
+fn test_importance_prefers_frequent_recent_access() {
+    let db = open_temp();
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs_f64();
+    let hot = db
+        .insert("hot", None, None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    let stale = db
+        .insert("stale", None, None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    let fresh = db
+        .insert("never read", None, None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    db.set_access_stats(&hot, Some(now - 3_600.0), 50).unwrap();
+    db.set_access_stats(&stale, Some(now - 400.0 * 86_400.0), 2)
+        .unwrap();
+
+    let hot_score = db.importance_score(&hot[..8]).unwrap();
+    let stale_score = db.importance_score(&stale).unwrap();
+    assert!(hot_score > stale_score);
+    assert!((0.0..=1.0).contains(&hot_score) && (0.0..=1.0).contains(&stale_score));
+    assert!((db.importance_score(&fresh).unwrap() - 0.5).abs() < 1e-6);
+
+    let top = db.top_important(2).unwrap();
+    assert_eq!(
+        top.iter().map(|m| m.id.as_str()).collect::<Vec<_>>(),
+        vec![hot.as_str(), fresh.as_str()]
+    );
+    assert_eq!(top[0].score, Some(hot_score));
+    // Scoring is read-only
+    assert_eq!(db.get_readonly(&hot).unwrap().unwrap().access_count, 50);
+}
+
+// -- compress_metadata --
+
+#[cfg(feature = "compress_metadata")]
+fn open_compressed(limit: usize) -> Memori {
+    Memori::open_with_config(
+        ":memory:",
+        MemoriConfig {
+            compress_metadata_over: Some(limit),
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
+
+#[cfg(feature = "compress_metadata")]
+#[test]
+fn test_compress_metadata_round_trips_large_object() {
+    let db = open_compressed(64);
+    let notes: Vec<String> = (0..200).map(|i| format!("note number {}", i)).collect();
+    let meta = serde_json::json!({
+        "type": "transcript",
+        "speaker": "zelda",
+        "turns": 200,
+        "notes": notes,
+        "nested": {"a": {"b": [1, 2, {"c": null}]}, "flag": true},
+    });
+    let id = db
+        .insert("long meeting", None, Some(meta.clone()), None, true)
+        .unwrap()
+        .id()
+        .to_string();
+
+    let raw = db.inspect(&id).unwrap().unwrap();
+    let stored = raw.raw_metadata.unwrap();
+    assert!(stored.len() < meta.to_string().len());
+    // The array left the plain column but is still indexed for search
+    let hits = db
+        .search(SearchQuery {
+            text: Some("number 150".to_string()),
+            mode: SearchMode::TextOnly,
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].id, id);
+
+    assert_eq!(db.get(&id).unwrap().unwrap().metadata, Some(meta.clone()));
+    let listed = db
+        .list(None, &SortField::Created, 10, 0, None, None, false)
+        .unwrap();
+    assert_eq!(listed[0].metadata, Some(meta.clone()));
+
+    // Metadata updates recompress; archive and unarchive carry the blob over
+    db.update(
+        &id,
+        None,
+        None,
+        Some(serde_json::json!({"speaker": "link"})),
+        true,
+    )
+    .unwrap();
+    let mut merged = meta.clone();
+    merged["speaker"] = serde_json::json!("link");
+    assert_eq!(db.get(&id).unwrap().unwrap().metadata, Some(merged.clone()));
+    db.archive(&id).unwrap();
+    db.unarchive(&id).unwrap();
+    assert_eq!(db.get(&id).unwrap().unwrap().metadata, Some(merged));
+
+    // Small metadata stays plain
+    let small = db
+        .insert("short", None, Some(serde_json::json!({"k": 1})), None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    assert_eq!(
+        db.inspect(&small).unwrap().unwrap().raw_metadata.as_deref(),
+        Some(r#"{"k":1}"#)
+    );
+}
+
+#[cfg(feature = "compress_metadata")]
+#[test]
+fn test_compress_metadata_keeps_scalars_searchable() {
+    let db = open_compressed(16);
+    let meta =
+        serde_json::json!({"type": "fact", "project": "hyrule", "refs": ["a", "b", "c", "d"]});
+    let id = db
+        .insert("castle layout", None, Some(meta.clone()), None, true)
+        .unwrap()
+        .id()
+        .to_string();
+
+    let hits = db
+        .search(SearchQuery {
+            text: Some("hyrule".to_string()),
+            mode: SearchMode::TextOnly,
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].id, id);
+    assert_eq!(hits[0].metadata, Some(meta));
+
+    let mut filter = serde_json::Map::new();
+    filter.insert("project".to_string(), serde_json::json!("hyrule"));
+    let filtered = db
+        .search(SearchQuery {
+            filter: Some(serde_json::Value::Object(filter)),
+            mode: SearchMode::TextOnly,
+            text: Some("castle".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(filtered.len(), 1);
+}
+
+#[cfg(feature = "compress_metadata")]
+#[test]
+fn test_compress_metadata_keeps_nested_values_filterable() {
+    let path = temp_db_path();
+    let config = MemoriConfig {
+        compress_metadata_over: Some(16),
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(&path, config).unwrap();
+    let meta = json!({"type": "fact", "refs": ["gerudo", "zora"], "place": {"region": "lanayru"}});
+    let id = db
+        .insert("castle layout", None, Some(meta), None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    db.insert("plain note", None, Some(json!({"k": 1})), None, true)
+        .unwrap();
+    // Only the compressed blob holds the nested values
+    let stored = db.inspect(&id).unwrap().unwrap().raw_metadata.unwrap();
+    assert!(!stored.contains("zora") && !stored.contains("lanayru"));
+    db.add_metadata_index("refs").unwrap();
+
+    let search =
+        |db: &Memori, text: Option<&str>, filter: Option<serde_json::Value>, raw: Option<&str>| {
+            db.search(SearchQuery {
+                text: text.map(str::to_string),
+                mode: if text.is_some() { SearchMode::TextOnly } else { SearchMode::Auto },
+                filter,
+                raw_filter: raw.map(str::to_string),
+                ..Default::default()
+            })
+            .unwrap()
+            .into_iter()
+            .map(|m| m.id)
+            .collect::<Vec<_>>()
+        };
+    let check = |db: &Memori| {
+        assert_eq!(search(db, Some("zora"), None, None), vec![id.clone()]);
+        assert_eq!(search(db, Some("lanayru"), None, None), vec![id.clone()]);
+        assert_eq!(
+            search(db, None, Some(json!({"refs": ["gerudo", "zora"]})), None),
+            vec![id.clone()]
+        );
+        let raw = "json_extract(metadata, '$.place.region') = 'lanayru'";
+        assert_eq!(
+            search(db, Some("castle"), None, Some(raw)),
+            vec![id.clone()]
+        );
+        assert_eq!(db.type_distribution().unwrap().get("fact"), Some(&1));
+    };
+    check(&db);
+
+    // Updates keep the index in step; the database stays marked when
+    // reopened without the option
+    db.update(&id, None, None, Some(json!({"refs": ["rito"]})), true)
+        .unwrap();
+    assert_eq!(search(&db, Some("rito"), None, None), vec![id.clone()]);
+    assert!(search(&db, Some("zora"), None, None).is_empty());
+    db.update(
+        &id,
+        None,
+        None,
+        Some(json!({"refs": ["gerudo", "zora"]})),
+        true,
+    )
+    .unwrap();
+    assert!(db.verify_fts().unwrap().is_consistent());
+    drop(db);
+    let db = Memori::open(&path).unwrap();
+    check(&db);
+    assert_eq!(db.delete_by_type("fact").unwrap(), 1);
+    assert!(db.verify_fts().unwrap().is_consistent());
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+// -- text_match --
+
+#[test]
+fn test_text_match_any_vs_all() {
+    let db = open_temp();
+    db.insert("apples only", None, None, None, true).unwrap();
+    db.insert("pears only", None, None, None, true).unwrap();
+    db.insert("apples and pears", None, None, None, true)
+        .unwrap();
+
+    let run = |text: &str, text_match: TextMatchMode| {
+        let mut hits: Vec<String> = db
+            .search(SearchQuery {
+                text: Some(text.to_string()),
+                mode: SearchMode::TextOnly,
+                text_match,
+                ..Default::default()
+            })
+            .unwrap()
+            .into_iter()
+            .map(|m| m.content)
+            .collect();
+        hits.sort();
+        hits
+    };
+
+    assert_eq!(
+        run("apples pears", TextMatchMode::All),
+        vec!["apples and pears"]
+    );
+    assert_eq!(
+        run("apples pears", TextMatchMode::Any),
+        vec!["apples and pears", "apples only", "pears only"]
+    );
+
+    // Operators and quotes stay literal either way
+    for mode in [TextMatchMode::All, TextMatchMode::Any] {
+        for hostile in [
+            "apples OR",
+            "NOT pears",
+            "\"apples\" -pears",
+            "content:apples",
+            "pea*",
+            "apples) OR (pears",
+        ] {
+            assert!(db
+                .search(SearchQuery {
+                    text: Some(hostile.to_string()),
+                    mode: SearchMode::TextOnly,
+                    text_match: mode,
+                    ..Default::default()
+                })
+                .is_ok());
+        }
+    }
+    assert_eq!(run("NOT pears", TextMatchMode::All), Vec::<String>::new());
+    assert_eq!(
+        run("NOT pears", TextMatchMode::Any),
+        vec!["apples and pears", "pears only"]
+    );
+    assert_eq!(TextMatchMode::from_str("any"), Ok(TextMatchMode::Any));
+    assert!(TextMatchMode::from_str("some").is_err());
+}
+
+// -- changed_since --
+
+#[test]
+fn test_changed_since_orders_by_update() {
+    let db = open_temp();
+    let a = db
+        .insert_with_id(
+            "aaaaaaaa-0000-0000-0000-000000000001",
+            "first",
+            None,
+            None,
+            100.0,
+            100.0,
+        )
+        .unwrap();
+    let b = db
+        .insert_with_id(
+            "aaaaaaaa-0000-0000-0000-000000000002",
+            "second",
+            None,
+            None,
+            200.0,
+            200.0,
+        )
+        .unwrap();
+    db.insert_with_id(
+        "aaaaaaaa-0000-0000-0000-000000000003",
+        "old",
+        None,
+        None,
+        50.0,
+        50.0,
+    )
+    .unwrap();
+
+    let ids = |rows: Vec<memori_core::Memory>| rows.into_iter().map(|m| m.id).collect::<Vec<_>>();
+    assert_eq!(
+        ids(db.changed_since(60.0, None, 10).unwrap()),
+        vec![a.clone(), b.clone()]
+    );
+
+    // Updating the oldest change moves it to the end with a fresh updated_at
+    db.update(&a, Some("first, edited"), None, None, false)
+        .unwrap();
+    let changed = db.changed_since(60.0, None, 10).unwrap();
+    assert_eq!(ids(changed.clone()), vec![b.clone(), a.clone()]);
+    assert!(changed[1].updated_at > 200.0);
+
+    // Paging by cursor
+    let page = db.changed_since(60.0, None, 1).unwrap();
+    assert_eq!(ids(page.clone()), vec![b]);
+    assert_eq!(
+        ids(db.changed_since(page[0].updated_at, None, 1).unwrap()),
+        vec![a]
+    );
+    assert!(db
+        .changed_since(changed[1].updated_at, None, 10)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_changed_since_pages_through_tied_timestamps() {
+    let db = open_temp();
+    for i in 1..=5 {
+        db.insert_with_id(
+            &format!("bbbbbbbb-0000-0000-0000-00000000000{}", i),
+            "tied",
+            None,
+            None,
+            100.0,
+            100.0,
+        )
+        .unwrap();
+    }
+
+    let mut seen = Vec::new();
+    let (mut since, mut after) = (0.0, None::<String>);
+    loop {
+        let page = db.changed_since(since, after.as_deref(), 2).unwrap();
+        let Some(last) = page.last() else { break };
+        since = last.updated_at;
+        after = Some(last.id.clone());
+        seen.extend(page.into_iter().map(|m| m.id));
+    }
+    let expected: Vec<String> = (1..=5)
+        .map(|i| format!("bbbbbbbb-0000-0000-0000-00000000000{}", i))
+        .collect();
+    assert_eq!(seen, expected);
+}
+
+#[test]
+fn test_changed_since_reports_soft_deletes() {
+    let db = open_temp();
+    let kept = db
+        .insert_with_id(
+            "cccccccc-0000-0000-0000-000000000001",
+            "kept",
+            None,
+            None,
+            100.0,
+            100.0,
+        )
+        .unwrap();
+    let gone = db
+        .insert_with_id(
+            "cccccccc-0000-0000-0000-000000000002",
+            "gone",
+            None,
+            None,
+            100.0,
+            100.0,
+        )
+        .unwrap();
+    let cursor = db.changed_since(0.0, None, 10).unwrap();
+    assert_eq!(cursor.len(), 2);
+    assert!(cursor.iter().all(|m| m.deleted_at.is_none()));
+
+    db.soft_delete(&gone).unwrap();
+    let last = cursor.last().unwrap();
+    let changes = db
+        .changed_since(last.updated_at, Some(&last.id), 10)
+        .unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].id, gone);
+    let deleted_at = changes[0].deleted_at.expect("tombstone carries deleted_at");
+    assert!(deleted_at > 100.0);
+    assert!(db
+        .changed_since(deleted_at, Some(&gone), 10)
+        .unwrap()
+        .is_empty());
+    assert_eq!(db.changed_since(0.0, None, 10).unwrap()[0].id, kept);
+}
+
+// -- get_many --
+
+fn insert_pair_with_shared_prefix(db: &Memori) -> (String, String) {
+    let a = db
+        .insert_with_id(
+            "abcdef01-0000-0000-0000-000000000000",
+            "one",
+            None,
+            None,
+            1.0,
+            1.0,
+        )
+        .unwrap();
+    let b = db
+        .insert_with_id(
+            "abcdef02-0000-0000-0000-000000000000",
+            "two",
+            None,
+            None,
+            1.0,
+            1.0,
+        )
+        .unwrap();
+    (a, b)
+}
+
+#[test]
+fn test_get_many_reports_per_id_results() {
+    let db = open_temp();
+    let (a, b) = insert_pair_with_shared_prefix(&db);
+
+    let results = db
+        .get_many(&["abcdef01", "abcdef0", &b, "ffffffff"])
+        .unwrap();
+    assert_eq!(results.len(), 4);
+    assert_eq!(results[0].as_ref().unwrap().as_ref().unwrap().id, a);
+    assert!(matches!(
+        results[1],
+        Err(MemoriError::AmbiguousPrefix(_, 2))
+    ));
+    assert_eq!(
+        results[2].as_ref().unwrap().as_ref().unwrap().content,
+        "two"
+    );
+    assert!(matches!(results[3], Ok(None)));
+
+    // Stats come back as of before this access; the fetch touched both rows
+    assert_eq!(
+        results[0].as_ref().unwrap().as_ref().unwrap().access_count,
+        0
+    );
+    let raw = db.inspect(&a).unwrap().unwrap();
+    assert_eq!(raw.access_count, 1);
+    assert!(raw.last_accessed > 1.0);
+    assert_eq!(db.inspect(&b).unwrap().unwrap().access_count, 1);
+}
+
+#[test]
+fn test_get_many_without_track_access_writes_nothing() {
+    let path = temp_db_path();
+    let db = Memori::open_with_config(
+        &path,
+        MemoriConfig {
+            track_access: false,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let (a, b) = insert_pair_with_shared_prefix(&db);
+
+    let results = db.get_many(&[&a, &b]).unwrap();
+    assert!(results.iter().all(|r| matches!(r, Ok(Some(_)))));
+    db.get(&a).unwrap();
+    for id in [&a, &b] {
+        let raw = db.inspect(id).unwrap().unwrap();
+        assert_eq!((raw.access_count, raw.last_accessed), (0, 0.0));
+    }
+}
+
+// -- vector_precision --
+
+#[test]
+fn test_vector_precision_recorded_on_first_open() {
+    let path = temp_db_path();
+    let db = Memori::open(&path).unwrap();
+    assert_eq!(db.config().vector_precision, VectorPrecision::F32);
+    db.insert("v", Some(&[1.0, 0.0, 0.0]), None, None, true)
+        .unwrap();
+    drop(db);
+
+    // A database with f32 vectors keeps them whatever the config asks for
+    let result = Memori::open_with_config(
+        &path,
+        MemoriConfig {
+            vector_precision: VectorPrecision::F16,
+            ..Default::default()
+        },
+    );
+    assert_eq!(
+        result.unwrap().config().vector_precision,
+        VectorPrecision::F32
+    );
+}
+
+#[cfg(not(feature = "f16"))]
+#[test]
+fn test_vector_precision_f16_needs_feature() {
+    let result = Memori::open_with_config(
+        ":memory:",
+        MemoriConfig {
+            vector_precision: VectorPrecision::F16,
+            ..Default::default()
+        },
+    );
+    assert!(matches!(result, Err(MemoriError::InvalidVector(_))));
+}
+
+#[cfg(feature = "f16")]
+#[test]
+fn test_vector_precision_f16_round_trip() {
+    let path = temp_db_path();
+    let db = Memori::open_with_config(
+        &path,
+        MemoriConfig {
+            vector_precision: VectorPrecision::F16,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    // Deterministic pseudo-random 384-dim vectors
+    let mut seed = 7u64;
+    let mut next = || {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((seed >> 33) as f32 / (1u64 << 31) as f32) * 2.0 - 1.0
+    };
+    let vectors: Vec<Vec<f32>> = (0..20)
+        .map(|_| (0..384).map(|_| next()).collect())
+        .collect();
+    let ids: Vec<String> = vectors
+        .iter()
+        .map(|v| {
+            db.insert("vec", Some(v), None, None, true)
+                .unwrap()
+                .id()
+                .to_string()
+        })
+        .collect();
+
+    let query = &vectors[0];
+    for (id, original) in ids.iter().zip(&vectors) {
+        let stored = db.get(id).unwrap().unwrap().vector.unwrap();
+        assert_eq!(stored.len(), 384);
+        let exact = memori_core::util::cosine_similarity(query, original);
+        let widened = memori_core::util::cosine_similarity(query, &stored);
+        assert!((exact - widened).abs() < 0.005, "{} vs {}", exact, widened);
+        assert_eq!(db.inspect(id).unwrap().unwrap().vector_bytes, Some(384 * 2));
+    }
+
+    let hits = db
+        .search(SearchQuery {
+            vector: Some(query.clone()),
+            limit: 1,
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(hits[0].id, ids[0]);
+    drop(db);
+
+    // Reopening with the default config still decodes f16
+    let db = Memori::open(&path).unwrap();
+    assert_eq!(db.config().vector_precision, VectorPrecision::F16);
+    assert_eq!(db.get(&ids[1]).unwrap().unwrap().vector.unwrap().len(), 384);
+}
+
+// -- min_prefix_len --
+
+#[test]
+fn test_min_prefix_len_rejects_short_prefixes() {
+    let db = open_temp();
+    let id = db
+        .insert_with_id(
+            "abcd1234-0000-0000-0000-000000000000",
+            "target",
+            None,
+            None,
+            1.0,
+            1.0,
+        )
+        .unwrap();
+
+    for short in ["", "a", "abc"] {
+        assert!(
+            matches!(db.get(short), Err(MemoriError::PrefixTooShort(_, 4))),
+            "{:?}",
+            short
+        );
+        assert!(matches!(
+            db.delete(short),
+            Err(MemoriError::PrefixTooShort(_, 4))
+        ));
+    }
+    assert_eq!(db.get("abcd").unwrap().unwrap().id, id);
+    assert_eq!(db.get(&id).unwrap().unwrap().content, "target");
+
+    // An exact id shorter than the minimum still resolves
+    db.insert_with_id("x", "short id", None, None, 1.0, 1.0)
+        .unwrap();
+    assert_eq!(db.get("x").unwrap().unwrap().content, "short id");
+
+    let lax = Memori::open_with_config(
+        ":memory:",
+        MemoriConfig {
+            min_prefix_len: 0,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let id = lax
+        .insert("anything", None, None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    assert_eq!(lax.get(&id[..1]).unwrap().unwrap().id, id);
+}
+
+// -- similarity_distribution --
+
+#[test]
+fn test_similarity_distribution_percentiles() {
+    let db = open_temp();
+    let mut seed = 11u64;
+    let mut next = || {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((seed >> 33) as f32 / (1u64 << 31) as f32) * 2.0 - 1.0
+    };
+    let vectors: Vec<Vec<f32>> = (0..50).map(|_| (0..8).map(|_| next()).collect()).collect();
+    for v in &vectors {
+        db.insert("v", Some(v), None, None, true).unwrap();
+    }
+    db.insert("no vector", None, None, None, true).unwrap();
+
+    let query = vec![1.0, 0.5, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+    let observed: Vec<f32> = vectors
+        .iter()
+        .map(|v| memori_core::util::cosine_similarity(&query, v))
+        .collect();
+    let min = observed.iter().cloned().fold(f32::INFINITY, f32::min);
+    let max = observed.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+
+    let dist = db
+        .similarity_distribution(&query, &[10.0, 50.0, 90.0, 100.0, 0.0])
+        .unwrap();
+    assert_eq!(
+        dist.iter().map(|(p, _)| *p).collect::<Vec<_>>(),
+        vec![10.0, 50.0, 90.0, 100.0, 0.0]
+    );
+    let median = dist[1].1;
+    assert!(min < median && median < max);
+    assert!(dist[0].1 <= dist[1].1 && dist[1].1 <= dist[2].1 && dist[2].1 <= dist[3].1);
+    assert!((dist[3].1 - max).abs() < 1e-4 && (dist[4].1 - min).abs() < 1e-4);
+
+    assert!(matches!(
+        db.similarity_distribution(&query, &[101.0]),
+        Err(MemoriError::InvalidQuery(_))
+    ));
+    assert!(open_temp()
+        .similarity_distribution(&query, &[50.0])
+        .unwrap()
+        .is_empty());
+}
+
+// -- increment_metadata_number --
+
+#[test]
+fn test_increment_metadata_number_accumulates() {
+    let db = open_temp();
+    let id = db
+        .insert(
+            "counter",
+            None,
+            Some(serde_json::json!({"type": "stat"})),
+            None,
+            true,
+        )
+        .unwrap()
+        .id()
+        .to_string();
+
+    assert_eq!(db.increment_metadata_number(&id, "hits", 1.0).unwrap(), 1.0);
+    for _ in 0..4 {
+        db.increment_metadata_number(&id, "hits", 1.0).unwrap();
+    }
+    assert_eq!(
+        db.increment_metadata_number(&id, "hits", -2.0).unwrap(),
+        3.0
+    );
+    assert_eq!(
+        db.increment_metadata_number(&id, "score", 0.25).unwrap(),
+        0.25
+    );
+
+    let meta = db.get(&id).unwrap().unwrap().metadata.unwrap();
+    assert_eq!(
+        meta,
+        serde_json::json!({"type": "stat", "hits": 3, "score": 0.25})
+    );
+    assert!(meta["hits"].is_i64());
+
+    // Memories without metadata start from {}
+    let bare = db
+        .insert("bare", None, None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    assert_eq!(db.increment_metadata_number(&bare, "n", 2.0).unwrap(), 2.0);
+
+    assert!(matches!(
+        db.increment_metadata_number(&id, "type", 1.0),
+        Err(MemoriError::InvalidPatch(_))
+    ));
+    assert!(matches!(
+        db.increment_metadata_number(&id, "a.b", 1.0),
+        Err(MemoriError::InvalidPatch(_))
+    ));
+    assert!(matches!(
+        db.increment_metadata_number(&id, "x') --", 1.0),
+        Err(MemoriError::InvalidPatch(_))
+    ));
+    assert!(matches!(
+        db.increment_metadata_number("ffffffff-0000-0000-0000-000000000000", "hits", 1.0),
+        Err(MemoriError::NotFound(_))
+    ));
+}
+
+#[cfg(feature = "compress_metadata")]
+#[test]
+fn test_increment_metadata_number_on_compressed_row() {
+    let db = open_compressed(16);
+    let meta = serde_json::json!({"hits": 1, "refs": ["a", "b", "c", "d"]});
+    let id = db
+        .insert("counter", None, Some(meta), None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    assert_eq!(db.increment_metadata_number(&id, "hits", 2.0).unwrap(), 3.0);
+    let stored = db.get(&id).unwrap().unwrap().metadata.unwrap();
+    assert_eq!(
+        stored,
+        serde_json::json!({"hits": 3, "refs": ["a", "b", "c", "d"]})
+    );
+}
+
+#[test]
+fn test_increment_metadata_number_rejects_non_numbers_by_json_type() {
+    let db = open_temp();
+    let meta = serde_json::json!({"tags": ["a"], "name": "x", "on": true});
+    let id = db
+        .insert("typed", None, Some(meta), None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    for (key, kind) in [("tags", "an array"), ("name", "text"), ("on", "a boolean")] {
+        match db.increment_metadata_number(&id, key, 1.0) {
+            Err(MemoriError::InvalidPatch(msg)) => {
+                assert_eq!(msg, format!("metadata '{}' is {}, not a number", key, kind))
+            }
+            other => panic!("expected InvalidPatch, got {:?}", other),
+        }
+    }
+}
+
+#[cfg(feature = "compress_metadata")]
+#[test]
+fn test_increment_metadata_number_compresses_grown_metadata() {
+    let db = open_compressed(48);
+    let id = db
+        .insert(
+            "counter",
+            None,
+            Some(serde_json::json!({"refs": ["a"], "tags": ["x"]})),
+            None,
+            true,
+        )
+        .unwrap()
+        .id()
+        .to_string();
+    assert!(db
+        .inspect(&id)
+        .unwrap()
+        .unwrap()
+        .raw_metadata
+        .unwrap()
+        .contains("refs"));
+    for key in ["first", "second", "third"] {
+        db.increment_metadata_number(&id, key, 1.0).unwrap();
+    }
+    // Past the limit only the scalars stay in the plain column
+    let plain = db.inspect(&id).unwrap().unwrap().raw_metadata.unwrap();
+    assert!(!plain.contains("refs"));
+    assert!(plain.contains("third"));
+    match db.increment_metadata_number(&id, "refs", 1.0) {
+        Err(MemoriError::InvalidPatch(msg)) => {
+            assert_eq!(msg, "metadata 'refs' is an array, not a number")
+        }
+        other => panic!("expected InvalidPatch, got {:?}", other),
+    }
+    assert_eq!(
+        db.get(&id).unwrap().unwrap().metadata,
+        Some(serde_json::json!({
+            "refs": ["a"], "tags": ["x"], "first": 1, "second": 1, "third": 1
+        }))
+    );
+}
+
+#[test]
+fn test_increment_metadata_number_is_atomic_across_handles() {
+    let path = temp_db_path();
+    let id = Memori::open(&path)
+        .unwrap()
+        .insert("counter", None, None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    const N: usize = 200;
+    let workers: Vec<_> = (0..2)
+        .map(|_| {
+            let (path, id) = (path.clone(), id.clone());
+            std::thread::spawn(move || {
+                let db = Memori::open(&path).unwrap();
+                for _ in 0..N {
+                    db.increment_metadata_number(&id, "hits", 1.0).unwrap();
+                }
+            })
+        })
+        .collect();
+    for w in workers {
+        w.join().unwrap();
+    }
+    let db = Memori::open(&path).unwrap();
+    assert_eq!(
+        db.get(&id).unwrap().unwrap().metadata,
+        Some(json!({"hits": 2 * N}))
+    );
+}
+
+#[cfg(feature = "compress_metadata")]
+#[test]
+fn test_increment_metadata_number_compressed_is_atomic_across_handles() {
+    let path = temp_db_path();
+    let config = || MemoriConfig {
+        compress_metadata_over: Some(16),
+        ..Default::default()
+    };
+    let id = Memori::open_with_config(&path, config())
+        .unwrap()
+        .insert(
+            "counter",
+            None,
+            Some(json!({"refs": ["a", "b", "c", "d"]})),
+            None,
+            true,
+        )
+        .unwrap()
+        .id()
+        .to_string();
+    const N: usize = 1000;
+    let workers: Vec<_> = (0..2)
+        .map(|_| {
+            let (path, id) = (path.clone(), id.clone());
+            std::thread::spawn(move || {
+                let db = Memori::open_with_config(&path, config()).unwrap();
+                for _ in 0..N {
+                    db.increment_metadata_number(&id, "hits", 1.0).unwrap();
+                }
+            })
+        })
+        .collect();
+    for w in workers {
+        w.join().unwrap();
+    }
+    let db = Memori::open_with_config(&path, config()).unwrap();
+    assert_eq!(
+        db.get(&id).unwrap().unwrap().metadata,
+        Some(json!({"refs": ["a", "b", "c", "d"], "hits": 2 * N}))
+    );
+}
+
+// -- soft delete --
+
+#[test]
+fn test_soft_delete_hidden_by_default() {
+    let db = open_temp();
+    let keep = db
+        .insert("kafka consumer lag", Some(&[1.0, 0.0]), None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    let gone = db
+        .insert("kafka broker restart", Some(&[0.9, 0.1]), None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    db.soft_delete(&gone[..8]).unwrap();
+
+    assert!(db.get(&gone).unwrap().is_none());
+    assert_eq!(db.count().unwrap(), 1);
+    let text = db
+        .search(SearchQuery {
+            text: Some("kafka".into()),
+            mode: SearchMode::TextOnly,
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(
+        text.iter().map(|m| m.id.as_str()).collect::<Vec<_>>(),
+        vec![keep.as_str()]
+    );
+    let vector = db
+        .search(SearchQuery {
+            vector: Some(vec![1.0, 0.0]),
+            ..Default::default()
+        })
+        .unwrap();
+    assert!(vector
+        .iter()
+        .all(|m| m.id != gone && m.deleted_at.is_none()));
+    let listed = db
+        .list(None, &SortField::Created, 10, 0, None, None, false)
+        .unwrap();
+    assert_eq!(listed.len(), 1);
+    assert!(matches!(
+        db.soft_delete(&gone),
+        Err(MemoriError::NotFound(_))
+    ));
+}
+
+#[test]
+fn test_include_deleted_surfaces_tombstones() {
+    let db = open_temp();
+    let keep = db
+        .insert("kafka consumer lag", Some(&[1.0, 0.0]), None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    let gone = db
+        .insert("kafka broker restart", Some(&[0.9, 0.1]), None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    db.soft_delete(&gone).unwrap();
+
+    let vector = db
+        .search(SearchQuery {
+            vector: Some(vec![1.0, 0.0]),
+            include_deleted: true,
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(vector.len(), 2);
+    assert_eq!(vector[0].id, keep);
+    assert!(vector[0].deleted_at.is_none());
+    assert_eq!(vector[1].id, gone);
+    assert!(vector[1].deleted_at.is_some());
+
+    let text = db
+        .search(SearchQuery {
+            text: Some("broker".into()),
+            mode: SearchMode::TextOnly,
+            include_deleted: true,
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(text.len(), 1);
+    assert_eq!(text[0].id, gone);
+    assert!(text[0].deleted_at.is_some());
+
+    let listed = db
+        .list(None, &SortField::Created, 10, 0, None, None, true)
+        .unwrap();
+    assert_eq!(listed.len(), 2);
+    let tombstone = listed.iter().find(|m| m.id == gone).unwrap();
+    assert!(tombstone.deleted_at.unwrap() >= tombstone.updated_at);
+    assert!(listed
+        .iter()
+        .find(|m| m.id == keep)
+        .unwrap()
+        .deleted_at
+        .is_none());
+}
+
+// -- count_text_matches --
+
+#[test]
+fn test_count_text_matches_agrees_with_fts_hits() {
+    let path = temp_db_path();
+    let db = Memori::open(&path).unwrap();
+    for (text, kind) in [
+        ("kafka consumer lag", "debugging"),
+        ("kafka broker restart", "ops"),
+        ("kafka topic retention", "debugging"),
+        ("postgres vacuum", "debugging"),
+    ] {
+        db.insert(
+            text,
+            None,
+            Some(serde_json::json!({"type": kind})),
+            None,
+            true,
+        )
+        .unwrap();
+    }
+    let hits = db
+        .search(SearchQuery {
+            text: Some("kafka".into()),
+            mode: SearchMode::TextOnly,
+            limit: 100,
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(db.count_text_matches("kafka", None).unwrap(), hits.len());
+    assert_eq!(hits.len(), 3);
+
+    let filter = serde_json::json!({"type": "debugging"});
+    assert_eq!(db.count_text_matches("kafka", Some(&filter)).unwrap(), 2);
+    assert_eq!(
+        db.count_text_matches("kafka lag", Some(&filter)).unwrap(),
+        1
+    );
+    assert_eq!(db.count_text_matches("   ", None).unwrap(), 0);
+
+    let other = open_collection(&path, "other");
+    assert_eq!(other.count_text_matches("kafka", None).unwrap(), 0);
+}
+
+// -- empty_query_behavior --
+
+fn open_with_empty_behavior(behavior: EmptyQueryBehavior) -> Memori {
+    let config = MemoriConfig {
+        empty_query_behavior: behavior,
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(":memory:", config).unwrap();
+    for text in ["first", "second", "third"] {
+        db.insert(text, None, None, None, true).unwrap();
+    }
+    db
+}
+
+#[test]
+fn test_empty_query_recent_by_default() {
+    let db = open_with_empty_behavior(EmptyQueryBehavior::default());
+    let first = db
+        .list(None, &SortField::Created, 10, 0, None, None, false)
+        .unwrap()
+        .pop()
+        .unwrap();
+    db.update(&first.id, Some("first, edited"), None, None, true)
+        .unwrap();
+    let results = db
+        .search(SearchQuery {
+            limit: 2,
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].id, first.id);
+}
+
+#[test]
+fn test_empty_query_empty_returns_nothing() {
+    let db = open_with_empty_behavior(EmptyQueryBehavior::Empty);
+    assert!(db.search(SearchQuery::default()).unwrap().is_empty());
+    assert_eq!(
+        db.search(SearchQuery {
+            text: Some("second".into()),
+            mode: SearchMode::TextOnly,
+            ..Default::default()
+        })
+        .unwrap()
+        .len(),
+        1
+    );
+}
+
+#[test]
+fn test_empty_query_error() {
+    let db = open_with_empty_behavior(EmptyQueryBehavior::Error);
+    assert!(matches!(
+        db.search(SearchQuery::default()),
+        Err(MemoriError::InvalidQuery(_))
+    ));
+    assert!(matches!(
+        db.search_archive(SearchQuery::default()),
+        Err(MemoriError::InvalidQuery(_))
+    ));
+}
+
+// -- backup_to --
+
+#[test]
+fn test_backup_to_round_trips() {
+    let db = Memori::open(&temp_db_path()).unwrap();
+    let id = db
+        .insert(
+            "kafka consumer lag",
+            Some(&[1.0, 0.0]),
+            Some(json!({"type": "ops"})),
+            None,
+            true,
+        )
+        .unwrap()
+        .id()
+        .to_string();
+    db.insert("postgres vacuum", None, None, None, true)
+        .unwrap();
+    db.get(&id).unwrap();
+
+    let dest = temp_db_path();
+    db.backup_to(&dest).unwrap();
+    let copy = Memori::open(&dest).unwrap();
+    assert_eq!(copy.count().unwrap(), db.count().unwrap());
+    let original = db.get(&id).unwrap().unwrap();
+    let restored = copy.get(&id).unwrap().unwrap();
+    assert_eq!(restored.content, original.content);
+    assert_eq!(restored.vector, original.vector);
+    assert_eq!(restored.metadata, original.metadata);
+    assert_eq!(restored.access_count, 1);
+
+    // The live handle keeps working, and an existing destination is refused
+    db.insert("after backup", None, None, None, true).unwrap();
+    assert_eq!(copy.count().unwrap(), 2);
+    assert!(db.backup_to(&dest).is_err());
+}
+
+#[test]
+fn test_backup_to_from_memory() {
+    let db = open_temp();
+    db.insert("in memory only", None, None, None, true).unwrap();
+    let dest = temp_db_path();
+    db.backup_to(&dest).unwrap();
+    assert_eq!(Memori::open(&dest).unwrap().count().unwrap(), 1);
+}
+
+// -- register_scalar_function --
+
+#[test]
+fn test_register_scalar_function_in_raw_filter() {
+    let mut db = open_temp();
+    db.insert(
+        "note one",
+        None,
+        Some(json!({"author": "Alice"})),
+        None,
+        true,
+    )
+    .unwrap();
+    db.insert("note two", None, Some(json!({"author": "bob"})), None, true)
+        .unwrap();
+    let query = |raw: &str| SearchQuery {
+        raw_filter: Some(raw.to_string()),
+        ..Default::default()
+    };
+
+    let filter = "my_upper(json_extract(metadata, '$.author')) = 'ALICE'";
+    assert!(matches!(
+        db.search(query(filter)),
+        Err(MemoriError::InvalidFilter(_))
+    ));
+
+    db.register_scalar_function("my_upper", 1, |ctx| {
+        Ok(ctx.get::<Option<String>>(0)?.map(|s| s.to_uppercase()))
+    })
+    .unwrap();
+    assert_eq!(db.config().filter_functions, vec!["my_upper".to_string()]);
+    let results = db.search(query(filter)).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].content, "note one");
+    assert_eq!(
+        db.search(query(
+            "MY_UPPER(json_extract(metadata, '$.author')) = 'BOB'"
+        ))
+        .unwrap()
+        .len(),
+        1
+    );
+
+    // Only registered names pass the parser
+    assert!(matches!(
+        db.search(query("upper(json_extract(metadata, '$.author')) = 'ALICE'")),
+        Err(MemoriError::InvalidFilter(_))
+    ));
+    for bad in ["json_extract", "not", "x; DROP"] {
+        assert!(matches!(
+            db.register_scalar_function(bad, 1, |_| Ok(1)),
+            Err(MemoriError::InvalidFilter(_))
+        ));
+    }
+
+    // A name mentioning the column is just another function
+    db.register_scalar_function("metadata_upper", 1, |ctx| {
+        Ok(ctx.get::<Option<String>>(0)?.map(|s| s.to_uppercase()))
+    })
+    .unwrap();
+    let filter = "metadata_upper(json_extract(metadata, '$.author')) = 'BOB'";
+    assert_eq!(db.search(query(filter)).unwrap().len(), 1);
+}
+
+// -- history --
+
+fn open_with_history(limit: usize) -> Memori {
+    let config = MemoriConfig {
+        history_limit: limit,
+        ..Default::default()
+    };
+    Memori::open_with_config(":memory:", config).unwrap()
+}
+
+#[test]
+fn test_history_records_updates_newest_first() {
+    let db = open_with_history(10);
+    let id = db
+        .insert("v1", None, Some(json!({"rev": 1})), None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    db.update(&id, Some("v2"), None, None, true).unwrap();
+    db.update(&id, None, None, Some(json!({"rev": 3})), false)
+        .unwrap();
+
+    let history = db.history(&id[..8]).unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!((history[0].version, history[0].content.as_str()), (2, "v2"));
+    assert_eq!(history[0].metadata, Some(json!({"rev": 1})));
+    assert_eq!((history[1].version, history[1].content.as_str()), (1, "v1"));
+    assert!(history[0].changed_at >= history[1].changed_at);
+
+    let current = db.get(&id).unwrap().unwrap();
+    assert_eq!(
+        (current.content.as_str(), current.metadata),
+        ("v2", Some(json!({"rev": 3})))
+    );
+}
+
+#[test]
+fn test_history_cap_evicts_oldest() {
+    let db = open_with_history(2);
+    let id = db
+        .insert("v1", None, None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    for content in ["v2", "v3", "v4"] {
+        db.replace(&id, content, None, None).unwrap();
+    }
+    let history = db.history(&id).unwrap();
+    let kept: Vec<(i64, &str)> = history
+        .iter()
+        .map(|h| (h.version, h.content.as_str()))
+        .collect();
+    assert_eq!(kept, vec![(3, "v3"), (2, "v2")]);
+
+    let untracked = open_temp();
+    let id = untracked
+        .insert("v1", None, None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    untracked.update(&id, Some("v2"), None, None, true).unwrap();
+    assert!(untracked.history(&id).unwrap().is_empty());
+}
+
+#[test]
+fn test_history_is_dropped_with_its_memory() {
+    let path = temp_db_path();
+    let config = MemoriConfig {
+        history_limit: 10,
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(&path, config).unwrap();
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    let versions = |id: &str| -> i64 {
+        conn.query_row(
+            "SELECT COUNT(*) FROM memory_history WHERE id = ?1",
+            [id],
+            |r| r.get(0),
+        )
+        .unwrap()
+    };
+    let with_history = |content: &str| {
+        let id = db
+            .insert(content, None, None, None, true)
+            .unwrap()
+            .id()
+            .to_string();
+        db.update(&id, Some("edited"), None, None, true).unwrap();
+        assert_eq!(versions(&id), 1);
+        id
+    };
+
+    // Archiving keeps it; every way of removing the memory drops it
+    let archived = with_history("archived");
+    db.archive(&archived).unwrap();
+    db.unarchive(&archived).unwrap();
+    assert_eq!(db.history(&archived).unwrap().len(), 1);
+
+    let deleted = with_history("deleted");
+    db.delete(&deleted).unwrap();
+    assert_eq!(versions(&deleted), 0);
+    // A reused id starts without the old memory's versions
+    db.insert_with_id(&deleted, "reused id", None, None, 1.0, 1.0)
+        .unwrap();
+    assert!(db.history(&deleted).unwrap().is_empty());
+
+    let soft = with_history("soft deleted");
+    db.soft_delete(&soft).unwrap();
+    assert_eq!(versions(&soft), 0);
+
+    let old = with_history("old");
+    db.delete_before(f64::MAX).unwrap();
+    assert_eq!(versions(&old), 0);
+    assert_eq!(versions(&archived), 0);
+    drop(db);
+
+    // Upgrading a v16 file drops the versions it had already orphaned
+    conn.execute_batch(
+        "DROP TRIGGER memories_history_ad;
+         INSERT INTO memory_history (id, version, content, metadata, changed_at)
+             VALUES ('gone', 1, 'orphan', NULL, 1.0);
+         PRAGMA user_version = 16;",
+    )
+    .unwrap();
+    drop(Memori::open(&path).unwrap());
+    assert_eq!(versions("gone"), 0);
+
+    drop(conn);
+    let _ = std::fs::remove_file(&path);
+}
+
+// -- text_decay --
+
+#[test]
+fn test_text_decay_off_scores_old_strong_match_higher() {
+    let path = temp_db_path();
+    let (strong, weak) = {
+        let db = Memori::open(&path).unwrap();
+        let strong = db
+            .insert("kafka kafka kafka", None, None, None, true)
+            .unwrap()
+            .id()
+            .to_string();
+        let weak = db
+            .insert(
+                "a long note about many unrelated things that mentions kafka once",
+                None,
+                None,
+                None,
+                true,
+            )
+            .unwrap()
+            .id()
+            .to_string();
+        (strong, weak)
+    };
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    let long_ago = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs_f64()
+        - 1000.0 * 86400.0;
+    conn.execute(
+        "UPDATE memories SET access_count = 1, last_accessed = ?1 WHERE id = ?2",
+        rusqlite::params![long_ago, strong],
+    )
+    .unwrap();
+    drop(conn);
+
+    // Text results keep FTS rank order; the decay shows in `score`
+    let scores = |config: MemoriConfig| {
+        let db = Memori::open_with_config(&path, config).unwrap();
+        let results = db
+            .search(SearchQuery {
+                text: Some("kafka".into()),
+                mode: SearchMode::TextOnly,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(results[0].id, strong);
+        let score = |id: &str| results.iter().find(|m| m.id == id).unwrap().score.unwrap();
+        (score(&strong), score(&weak))
+    };
+    let (old_strong, recent_weak) = scores(MemoriConfig::default());
+    assert!(old_strong < recent_weak);
+    let (old_strong, recent_weak) = scores(MemoriConfig {
+        text_decay: false,
+        ..Default::default()
+    });
+    assert!(old_strong > recent_weak);
+}
+
+// -- exclude_ids --
+
+#[test]
+fn test_exclude_ids_promotes_next_best() {
+    let db = open_temp();
+    let best = db
+        .insert("best", Some(&[1.0, 0.0]), None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    let next = db
+        .insert("next", Some(&[0.9, 0.1]), None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    db.insert("far", Some(&[0.0, 1.0]), None, None, true)
+        .unwrap();
+
+    let query = |exclude: Vec<String>| SearchQuery {
+        vector: Some(vec![1.0, 0.0]),
+        limit: 2,
+        exclude_ids: exclude,
+        ..Default::default()
+    };
+    assert_eq!(db.search(query(vec![])).unwrap()[0].id, best);
+    let results = db.search(query(vec![best[..8].to_string()])).unwrap();
+    assert_eq!(results[0].id, next);
+    assert!(results.iter().all(|m| m.id != best));
+    assert_eq!(results.len(), 2);
+
+    // An id that matches nothing excludes nothing
+    assert_eq!(
+        db.search(query(vec!["ffffffff".to_string()])).unwrap()[0].id,
+        best
+    );
+}
+
+// -- collection_stats --
+
+#[test]
+fn test_collection_stats_sum_to_global() {
+    let path = temp_db_path();
+    let a = open_collection(&path, "tenant-a");
+    let b = open_collection(&path, "tenant-b");
+    for i in 0..5 {
+        a.insert(
+            &format!("a note {}", i),
+            Some(&[1.0, i as f32]),
+            None,
+            None,
+            true,
+        )
+        .unwrap();
+    }
+    let archived = a
+        .insert("a cold note", None, None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    a.archive(&archived).unwrap();
+    let gone = b
+        .insert(
+            "b note",
+            Some(&[0.0, 1.0]),
+            Some(json!({"type": "x"})),
+            None,
+            true,
+        )
+        .unwrap()
+        .id()
+        .to_string();
+    b.insert("b note two", None, None, None, true).unwrap();
+    b.soft_delete(&gone).unwrap();
+
+    let sa = a.collection_stats("tenant-a").unwrap();
+    let sb = a.collection_stats("tenant-b").unwrap();
+    assert_eq!(
+        (sa.memories, sa.embedded, sa.archived, sa.deleted),
+        (5, 5, 1, 0)
+    );
+    assert_eq!(
+        (sb.memories, sb.embedded, sb.archived, sb.deleted),
+        (1, 0, 0, 1)
+    );
+    assert!(sa.data_bytes > sb.data_bytes);
+
+    let global = b.stats().unwrap();
+    assert_eq!(global.memories, sa.memories + sb.memories);
+    assert_eq!(global.embedded, sa.embedded + sb.embedded);
+    assert_eq!(global.archived + global.deleted, 2);
+    assert_eq!(global.data_bytes, sa.data_bytes + sb.data_bytes);
+    assert_eq!(
+        a.collection_stats("nobody").unwrap(),
+        memori_core::StoreStats::default()
+    );
+
+    let report = a.vacuum_with_stats().unwrap();
+    assert_eq!(
+        report,
+        vec![("tenant-a".to_string(), sa), ("tenant-b".to_string(), sb)]
+    );
+}
+
+// -- open_with_flags --
+
+#[test]
+fn test_open_with_flags_without_create_requires_existing_file() {
+    let missing = temp_db_path();
+    assert!(Memori::open_with_flags(&missing, OpenFlags::SQLITE_OPEN_READ_WRITE).is_err());
+    assert!(!std::path::Path::new(&missing).exists());
+
+    let path = temp_db_path();
+    let id = Memori::open(&path)
+        .unwrap()
+        .insert("kept", None, None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    let db = Memori::open_with_flags(
+        &path,
+        OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .unwrap();
+    assert_eq!(db.count().unwrap(), 1);
+    assert_eq!(db.get(&id).unwrap().unwrap().content, "kept");
+}
+
+#[test]
+fn test_open_with_flags_read_only_skips_writes() {
+    let path = temp_db_path();
+    let id = Memori::open(&path)
+        .unwrap()
+        .insert("kept", None, None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+
+    let db = Memori::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY).unwrap();
+    assert!(!db.config().track_access);
+    assert_eq!(db.get(&id).unwrap().unwrap().access_count, 0);
+    assert_eq!(db.search(SearchQuery::default()).unwrap().len(), 1);
+    assert!(db.insert("refused", None, None, None, true).is_err());
+}
+
+#[test]
+fn test_open_with_flags_read_only_refuses_old_schema() {
+    let path = temp_db_path();
+    drop(Memori::open(&path).unwrap());
+    let old = memori_core::schema::CURRENT_SCHEMA_VERSION - 1;
+    rusqlite::Connection::open(&path)
+        .unwrap()
+        .pragma_update(None, "user_version", old)
+        .unwrap();
+    match Memori::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY) {
+        Err(MemoriError::SchemaNeedsMigration(found, current)) => {
+            assert_eq!((found, current), (old, memori_core::schema::CURRENT_SCHEMA_VERSION));
+        }
+        other => panic!("expected SchemaNeedsMigration, got {:?}", other.map(|_| ())),
+    }
+
+    // A writable open migrates it, after which read-only works
+    drop(Memori::open(&path).unwrap());
+    let db = Memori::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY).unwrap();
+    assert_eq!(db.count().unwrap(), 0);
+}
+
+// -- columnar results --
+
+#[test]
+fn test_memory_columns_match_search_rows() {
+    let db = open_temp();
+    db.insert(
+        "first",
+        Some(&[1.0, 0.0]),
+        Some(json!({"type": "fact"})),
+        None,
+        true,
+    )
+    .unwrap();
+    db.insert("second", Some(&[0.8, 0.2]), None, None, true)
+        .unwrap();
+    let query = SearchQuery {
+        vector: Some(vec![1.0, 0.0]),
+        ..Default::default()
+    };
+    let rows = db.search(query.clone()).unwrap();
+    let cols: MemoryColumns = db.search(query).unwrap().into_iter().collect();
+    assert_eq!(cols.len(), rows.len());
+    assert_eq!(
+        cols.id,
+        rows.iter().map(|m| m.id.clone()).collect::<Vec<_>>()
+    );
+    assert_eq!(cols.content, vec!["first", "second"]);
+    assert_eq!(cols.metadata[0].as_deref(), Some(r#"{"type":"fact"}"#));
+    assert_eq!(cols.score, rows.iter().map(|m| m.score).collect::<Vec<_>>());
+    assert_eq!(cols.deleted_at, vec![None, None]);
+
+    db.soft_delete(&rows[1].id).unwrap();
+    let cols: MemoryColumns = db
+        .search(SearchQuery {
+            vector: Some(vec![1.0, 0.0]),
+            include_deleted: true,
+            ..Default::default()
+        })
+        .unwrap()
+        .into_iter()
+        .collect();
+    assert!(cols.deleted_at[0].is_none());
+    assert!(cols.deleted_at[1].is_some());
+}
+
+// -- schema version --
+
+#[test]
+fn test_open_refuses_newer_schema() {
+    let path = temp_db_path();
+    drop(Memori::open(&path).unwrap());
+    let too_new = memori_core::schema::CURRENT_SCHEMA_VERSION + 1;
+    rusqlite::Connection::open(&path)
+        .unwrap()
+        .pragma_update(None, "user_version", too_new)
+        .unwrap();
+    match Memori::open(&path) {
+        Err(MemoriError::SchemaTooNew(found, supported)) => {
+            assert_eq!(
+                (found, supported),
+                (too_new, memori_core::schema::CURRENT_SCHEMA_VERSION)
+            );
+        }
+        other => panic!("expected SchemaTooNew, got {:?}", other.map(|_| ())),
+    }
+    let err = Memori::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains(&format!("v{}", too_new)));
+}
+
+// -- external content --
+
+#[test]
+fn test_insert_external_indexes_summary() {
+    let db = open_temp();
+    let id = db
+        .insert_external(
+            "file:///docs/runbook.md",
+            "kafka failover runbook",
+            None,
+            None,
+            true,
+        )
+        .unwrap();
+    let ordinary = db
+        .insert("plain note", None, None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+
+    assert_eq!(
+        db.get(&id).unwrap().unwrap().content,
+        "file:///docs/runbook.md"
+    );
+    let query = SearchQuery {
+        text: Some("failover".to_string()),
+        mode: SearchMode::TextOnly,
+        ..Default::default()
+    };
+    let hits = db.search(query).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].content, "file:///docs/runbook.md");
+    let query = SearchQuery {
+        text: Some("runbook.md".to_string()),
+        mode: SearchMode::TextOnly,
+        ..Default::default()
+    };
+    assert!(db.search(query).unwrap().is_empty());
+
+    assert_eq!(
+        db.external_summary(&id).unwrap().as_deref(),
+        Some("kafka failover runbook")
+    );
+    assert_eq!(db.external_summary(&ordinary).unwrap(), None);
+
+    // Rewriting the content makes it an ordinary memory
+    db.update(&id, Some("inline now"), None, None, false)
+        .unwrap();
+    assert_eq!(db.get(&id).unwrap().unwrap().content, "inline now");
+    assert_eq!(db.external_summary(&id).unwrap(), None);
+}
+
+#[cfg(any(feature = "embeddings", feature = "mock_embeddings"))]
+#[test]
+fn test_external_metadata_update_reembeds_summary() {
+    let db = open_temp();
+    let id = db
+        .insert_external(
+            "s3://docs/handbook.pdf",
+            "onboarding handbook summary",
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+    db.update(&id, None, None, Some(json!({"team": "platform"})), false)
+        .unwrap();
+
+    let stored = db.get_readonly(&id).unwrap().unwrap();
+    assert_eq!(stored.content, "s3://docs/handbook.pdf");
+    let expected = memori_core::embed::embed_text("onboarding handbook summary platform");
+    assert_eq!(stored.vector.unwrap(), expected);
+    assert_eq!(
+        db.external_summary(&id).unwrap().as_deref(),
+        Some("onboarding handbook summary")
+    );
+}
+
+#[test]
+fn test_external_memory_survives_merge_and_export() {
+    let db = open_temp();
+    let keep = db
+        .insert_external("s3://a.pdf", "alpha summary", None, None, true)
+        .unwrap();
+    let other = db
+        .insert_external("s3://b.pdf", "beta summary", None, None, true)
+        .unwrap();
+    db.merge(&keep, &other, MergeStrategy::Concatenate).unwrap();
+    assert_eq!(
+        db.get_readonly(&keep).unwrap().unwrap().content,
+        "s3://a.pdf"
+    );
+    assert_eq!(
+        db.external_summary(&keep).unwrap().as_deref(),
+        Some("alpha summary\n\nbeta summary")
+    );
+
+    let exported = db.export_memory(&keep).unwrap();
+    let copy = open_temp();
+    copy.import_memory(&exported).unwrap();
+    assert_eq!(
+        copy.get_readonly(&keep).unwrap().unwrap().content,
+        "s3://a.pdf"
+    );
+    assert_eq!(
+        copy.external_summary(&keep).unwrap().as_deref(),
+        Some("alpha summary\n\nbeta summary")
+    );
+}
+
+// -- token budget --
+
+#[test]
+fn test_token_budget_warns_or_errors_on_long_content() {
+    let long = "word ".repeat(400);
+    let warned = std::sync::Arc::new(std::sync::Mutex::new(Vec::<String>::new()));
+    let sink = warned.clone();
+    let config = MemoriConfig {
+        token_budget: Some(memori_core::embed::MODEL_MAX_TOKENS),
+        token_warning: Some(WarningHook::new(move |id, _msg| {
+            sink.lock().unwrap().push(id.to_string())
+        })),
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(":memory:", config).unwrap();
+    db.insert("short note", None, None, None, true).unwrap();
+    assert!(warned.lock().unwrap().is_empty());
+    let id = db
+        .insert(&long, None, None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    assert_eq!(*warned.lock().unwrap(), vec![id.clone()]);
+    assert_eq!(db.inspect(&id).unwrap().unwrap().estimated_tokens, 400);
+
+    let config = MemoriConfig {
+        token_budget: Some(memori_core::embed::MODEL_MAX_TOKENS),
+        token_budget_behavior: TokenBudgetBehavior::Error,
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(":memory:", config).unwrap();
+    assert!(db.insert("short note", None, None, None, true).is_ok());
+    assert!(matches!(
+        db.insert(&long, None, None, None, true),
+        Err(MemoriError::OverTokenBudget(400, 256))
+    ));
+    assert!(matches!(
+        db.insert_embedded(&long, vec![1.0, 0.0], None, None),
+        Err(MemoriError::OverTokenBudget(400, 256))
+    ));
+    assert_eq!(db.count().unwrap(), 1);
+}
+
+#[test]
+fn test_token_budget_covers_update_replace_and_insert_with_id() {
+    let long = "word ".repeat(400);
+    let warned = std::sync::Arc::new(std::sync::Mutex::new(Vec::<String>::new()));
+    let sink = warned.clone();
+    let config = MemoriConfig {
+        token_budget: Some(memori_core::embed::MODEL_MAX_TOKENS),
+        token_warning: Some(WarningHook::new(move |id, _msg| {
+            sink.lock().unwrap().push(id.to_string())
+        })),
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(":memory:", config).unwrap();
+    let id = db
+        .insert("short note", None, None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    db.update(&id, Some(&long), None, None, false).unwrap();
+    db.replace(&id, &long, None, None).unwrap();
+    // Metadata-only updates don't write content, so they aren't checked
+    db.update(&id, None, None, Some(json!({"type": "note"})), true)
+        .unwrap();
+    let restored = "ffffffff-0000-0000-0000-000000000001";
+    db.insert_with_id(restored, &long, None, None, 1.0, 1.0)
+        .unwrap();
+    assert_eq!(
+        *warned.lock().unwrap(),
+        vec![id.clone(), id.clone(), restored.to_string()]
+    );
+    let exported = db.export_memory(restored).unwrap();
+
+    let config = MemoriConfig {
+        token_budget: Some(memori_core::embed::MODEL_MAX_TOKENS),
+        token_budget_behavior: TokenBudgetBehavior::Error,
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(":memory:", config).unwrap();
+    let id = db
+        .insert("short note", None, None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    assert!(matches!(
+        db.update(&id, Some(&long), None, None, false),
+        Err(MemoriError::OverTokenBudget(400, 256))
+    ));
+    assert!(matches!(
+        db.replace(&id, &long, None, None),
+        Err(MemoriError::OverTokenBudget(400, 256))
+    ));
+    assert!(matches!(
+        db.insert_with_id(restored, &long, None, None, 1.0, 1.0),
+        Err(MemoriError::OverTokenBudget(400, 256))
+    ));
+    assert_eq!(db.get(&id).unwrap().unwrap().content, "short note");
+    assert_eq!(db.count().unwrap(), 1);
+    assert!(db.history(&id).unwrap().is_empty());
+
+    // Imports restore what was exported, whatever the budget
+    assert_eq!(db.import_memory(&exported).unwrap(), restored);
+}
+
+// -- type_distribution_sorted --
+
+#[test]
+fn test_type_distribution_sorted_breaks_ties_by_name() {
+    let db = open_temp();
+    for t in ["ops", "fact", "ops", "bug", "fact", "ops", "note"] {
+        db.insert(
+            &format!("a {} memory", t),
+            None,
+            Some(json!({"type": t})),
+            None,
+            true,
+        )
+        .unwrap();
+    }
+    let sorted = db.type_distribution_sorted().unwrap();
+    let expected: Vec<(String, usize)> = [("ops", 3), ("fact", 2), ("bug", 1), ("note", 1)]
+        .into_iter()
+        .map(|(t, n)| (t.to_string(), n))
+        .collect();
+    assert_eq!(sorted, expected);
+}
+
+// -- precomputed content hash --
+
+#[test]
+fn test_insert_with_hash_dedups_on_supplied_hash() {
+    let config = MemoriConfig {
+        exact_dedup: true,
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(":memory:", config).unwrap();
+    let hash = "0123456789abcdef";
+    let first = db
+        .insert_with_hash("report v1 (full text)", hash, None, None, None, true)
+        .unwrap();
+    let second = db
+        .insert_with_hash("report v1 (full text) ", hash, None, None, None, true)
+        .unwrap();
+    assert!(second.is_deduplicated());
+    assert_eq!(second.id(), first.id());
+    assert_eq!(db.count().unwrap(), 1);
+
+    let other = db
+        .insert_with_hash(
+            "report v1 (full text)",
+            "0123456789ABCDEE",
+            None,
+            None,
+            None,
+            true,
+        )
+        .unwrap();
+    assert!(!other.is_deduplicated());
+    assert!(matches!(
+        db.insert_with_hash("x", "not-a-hash", None, None, None, true),
+        Err(MemoriError::InvalidHash(_))
+    ));
+}
+
+// -- search_many --
+
+#[test]
+fn test_search_many_matches_individual_searches() {
+    let db = open_temp();
+    for (i, v) in [
+        [1.0, 0.0, 0.0],
+        [0.8, 0.6, 0.0],
+        [0.0, 1.0, 0.0],
+        [0.0, 0.6, 0.8],
+    ]
+    .iter()
+    .enumerate()
+    {
+        let t = if i % 2 == 0 { "even" } else { "odd" };
+        db.insert(
+            &format!("note {}", i),
+            Some(v),
+            Some(json!({"type": t})),
+            None,
+            true,
+        )
+        .unwrap();
+    }
+    db.insert("kafka consumer lag", None, None, None, true)
+        .unwrap();
+
+    let queries = vec![
+        SearchQuery {
+            vector: Some(vec![1.0, 0.0, 0.0]),
+            limit: 2,
+            ..Default::default()
+        },
+        SearchQuery {
+            vector: Some(vec![0.0, 0.0, 1.0]),
+            ..Default::default()
+        },
+        SearchQuery {
+            vector: Some(vec![0.0, 1.0, 0.0]),
+            filter: Some(json!({"type": "odd"})),
+            ..Default::default()
+        },
+        SearchQuery {
+            vector: Some(vec![0.5, 0.5, 0.0]),
+            dedup_results: Some(0.99),
+            rerank_by: Some(SortField::Created),
+            ..Default::default()
+        },
+        SearchQuery {
+            text: Some("kafka".to_string()),
+            mode: SearchMode::TextOnly,
+            ..Default::default()
+        },
+    ];
+    let batched = db.search_many(&queries).unwrap();
+    assert_eq!(batched.len(), queries.len());
+    for (query, got) in queries.iter().zip(&batched) {
+        let want = db.search(query.clone()).unwrap();
+        let ids =
+            |rows: &[memori_core::Memory]| rows.iter().map(|m| m.id.clone()).collect::<Vec<_>>();
+        assert_eq!(ids(got), ids(&want));
+        for (g, w) in got.iter().zip(&want) {
+            assert_eq!(g.base_score, w.base_score);
+            assert!((g.score.unwrap() - w.score.unwrap()).abs() < 1e-5);
+        }
+    }
+    assert!(!batched[4].is_empty());
+}
+
+// -- bm25 weights --
+
+#[test]
+fn test_bm25_weights_rank_content_over_metadata() {
+    let config = MemoriConfig {
+        split_fts: true,
+        bm25_weights: Some((10.0, 1.0)),
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(":memory:", config).unwrap();
+    let in_meta = db
+        .insert(
+            "notes from the standup",
+            None,
+            Some(json!({"topic": "kafka"})),
+            None,
+            true,
+        )
+        .unwrap();
+    let in_content = db
+        .insert(
+            "kafka broker restarted",
+            None,
+            Some(json!({"topic": "ops"})),
+            None,
+            true,
+        )
+        .unwrap();
+    let query = SearchQuery {
+        text: Some("kafka".to_string()),
+        mode: SearchMode::TextOnly,
+        ..Default::default()
+    };
+    let ids: Vec<String> = db
+        .search(query.clone())
+        .unwrap()
+        .into_iter()
+        .map(|m| m.id)
+        .collect();
+    assert_eq!(
+        ids,
+        vec![in_content.id().to_string(), in_meta.id().to_string()]
+    );
+
+    // Flipping the weights flips the order
+    let config = MemoriConfig {
+        split_fts: true,
+        bm25_weights: Some((1.0, 10.0)),
+        ..Default::default()
+    };
+    let path = temp_db_path();
+    let db = Memori::open_with_config(&path, config).unwrap();
+    let in_meta = db
+        .insert(
+            "notes from the standup",
+            None,
+            Some(json!({"topic": "kafka"})),
+            None,
+            true,
+        )
+        .unwrap();
+    db.insert(
+        "kafka broker restarted",
+        None,
+        Some(json!({"topic": "ops"})),
+        None,
+        true,
+    )
+    .unwrap();
+    assert_eq!(db.search(query).unwrap()[0].id, in_meta.id());
+    assert!(db.verify_fts().unwrap().is_consistent());
+    db.update(
+        in_meta.id(),
+        Some("kafka in content too"),
+        None,
+        None,
+        false,
+    )
+    .unwrap();
+    db.delete(in_meta.id()).unwrap();
+    assert!(db.verify_fts().unwrap().is_consistent());
+}
+
+#[test]
+fn test_rebuild_fts_splits_existing_index() {
+    let path = temp_db_path();
+    let db = Memori::open(&path).unwrap();
+    db.insert(
+        "kafka broker restarted",
+        None,
+        Some(json!({"topic": "ops"})),
+        None,
+        true,
+    )
+    .unwrap();
+    drop(db);
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    assert!(!memori_core::schema::has_split_fts(&conn).unwrap());
+    drop(conn);
+
+    let config = MemoriConfig {
+        split_fts: true,
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(&path, config).unwrap();
+    db.rebuild_fts().unwrap();
+    drop(db);
+    // A default handle's rebuild keeps the split
+    let db = Memori::open(&path).unwrap();
+    db.rebuild_fts().unwrap();
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    assert!(memori_core::schema::has_split_fts(&conn).unwrap());
+    let query = SearchQuery {
+        text: Some("ops".to_string()),
+        mode: SearchMode::TextOnly,
+        ..Default::default()
+    };
+    assert_eq!(db.search(query).unwrap().len(), 1);
+}
+
+// -- search fallback --
+
+#[test]
+fn test_search_fallback_uses_text_when_vector_below_floor() {
+    let db = open_temp();
+    db.insert(
+        "the deploy runbook lives in the wiki",
+        Some(&[1.0, 0.0, 0.0]),
+        None,
+        None,
+        false,
+    )
+    .unwrap();
+    db.insert(
+        "lunch order for friday",
+        Some(&[0.9, 0.1, 0.0]),
+        None,
+        None,
+        false,
+    )
+    .unwrap();
+
+    let results = db
+        .search(SearchQuery {
+            vector: Some(vec![0.0, 0.0, 1.0]),
+            text: Some("runbook".to_string()),
+            fallback: true,
+            fallback_min_score: Some(0.5),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    assert!(results[0].content.contains("runbook"));
+
+    // With no floor the vector arm answers on its own
+    let results = db
+        .search(SearchQuery {
+            vector: Some(vec![0.0, 0.0, 1.0]),
+            text: Some("runbook".to_string()),
+            fallback: true,
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(results.len(), 2);
+}
+
+#[test]
+fn test_search_fallback_returns_recent_when_both_arms_empty() {
+    let db = open_temp();
+    db.insert("first note", Some(&[1.0, 0.0]), None, None, false)
+        .unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    db.insert("second note", Some(&[1.0, 0.0]), None, None, false)
+        .unwrap();
+
+    let results = db
+        .search(SearchQuery {
+            vector: Some(vec![0.0, 1.0]),
+            text: Some("absent".to_string()),
+            limit: 5,
+            fallback: true,
+            fallback_min_score: Some(0.5),
+            ..Default::default()
+        })
+        .unwrap();
+    let contents: Vec<&str> = results.iter().map(|m| m.content.as_str()).collect();
+    assert_eq!(contents, vec!["second note", "first note"]);
+}
+
+// -- attachments --
+
+#[test]
+fn test_add_and_get_attachment() {
+    let db = open_temp();
+    let id = db
+        .insert("whiteboard photo", None, None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    db.add_attachment(
+        &id[..8],
+        "board.png",
+        "image/png",
+        &[0x89, b'P', b'N', b'G', 0],
+    )
+    .unwrap();
+
+    let got = db.get_attachment(&id, "board.png").unwrap().unwrap();
+    assert_eq!(got.memory_id, id);
+    assert_eq!(got.mime, "image/png");
+    assert_eq!(got.data, vec![0x89, b'P', b'N', b'G', 0]);
+    assert!(db.get_attachment(&id, "other.png").unwrap().is_none());
+
+    // The same name replaces the earlier attachment
+    db.add_attachment(&id, "board.png", "image/png", b"whiteboard")
+        .unwrap();
+    assert_eq!(
+        db.get_attachment(&id, "board.png").unwrap().unwrap().data,
+        b"whiteboard"
+    );
+    assert!(matches!(
+        db.add_attachment("nonexistent", "a", "text/plain", b"x"),
+        Err(MemoriError::NotFound(_))
+    ));
+}
+
+#[test]
+fn test_list_attachments() {
+    let db = open_temp();
+    let id = db
+        .insert("voice memo", None, None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    db.add_attachment(&id, "memo.ogg", "audio/ogg", &[1, 2, 3])
+        .unwrap();
+    db.add_attachment(&id, "cover.jpg", "image/jpeg", &[4, 5])
+        .unwrap();
+
+    let listed: Vec<(String, String, usize)> = db
+        .list_attachments(&id)
+        .unwrap()
+        .into_iter()
+        .map(|a| (a.name, a.mime, a.size))
+        .collect();
+    assert_eq!(
+        listed,
+        vec![
+            ("cover.jpg".to_string(), "image/jpeg".to_string(), 2),
+            ("memo.ogg".to_string(), "audio/ogg".to_string(), 3),
+        ]
+    );
+}
+
+#[test]
+fn test_attachments_removed_with_memory() {
+    let path = temp_db_path();
+    let db = Memori::open(&path).unwrap();
+    let kept = db
+        .insert("archived note", None, None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    let gone = db
+        .insert("deleted note", None, None, None, true)
+        .unwrap()
+        .id()
+        .to_string();
+    db.add_attachment(&kept, "a.bin", "application/octet-stream", &[1])
+        .unwrap();
+    db.add_attachment(&gone, "b.bin", "application/octet-stream", &[2])
+        .unwrap();
+
+    db.archive(&kept).unwrap();
+    db.delete(&gone).unwrap();
+    db.unarchive(&kept).unwrap();
+    assert_eq!(db.list_attachments(&kept).unwrap().len(), 1);
+
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    let left: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM attachments WHERE memory_id = ?1",
+            [&gone],
+            |r| r.get(0),
+        )
+        .unwrap();
+    assert_eq!(left, 0);
+}
+
+// -- fusion mode --
+
+/// "strong" is the best vector match but has no text match; "steady" is a weak
+/// vector match that tops the text arm, and "filler" trails in both.
+fn fusion_fixture(fusion: FusionMode) -> (Memori, String, String) {
+    let config = MemoriConfig {
+        fusion,
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(":memory:", config).unwrap();
+    let strong = db
+        .insert("deploy checklist", Some(&[1.0, 0.0]), None, None, false)
+        .unwrap()
+        .id()
+        .to_string();
+    let steady = db
+        .insert(
+            "kafka kafka broker notes",
+            Some(&[0.3, 0.954]),
+            None,
+            None,
+            false,
+        )
+        .unwrap()
+        .id()
+        .to_string();
+    db.insert(
+        "a long note that mentions kafka once among many other unrelated words",
+        Some(&[0.2, 0.98]),
+        None,
+        None,
+        false,
+    )
+    .unwrap();
+    (db, strong, steady)
+}
+
+fn hybrid_top(db: &Memori) -> String {
+    db.search(SearchQuery {
+        vector: Some(vec![1.0, 0.0]),
+        text: Some("kafka".to_string()),
+        mode: SearchMode::Hybrid,
+        ..Default::default()
+    })
+    .unwrap()[0]
+        .id
+        .clone()
+}
+
+#[test]
+fn test_fusion_rrf_and_weighted_score_disagree() {
+    // RRF: ranks (2, 1) beat (1, absent), however far ahead "strong" is
+    let (db, _, steady) = fusion_fixture(FusionMode::Rrf);
+    assert_eq!(hybrid_top(&db), steady);
+
+    // Weighted: "strong" scores 0.7 * 1.0; "steady" 0.7 * 0.125 + 0.3 * 1.0
+    let (db, strong, _) = fusion_fixture(FusionMode::WeightedScore {
+        vector_weight: 0.7,
+        text_weight: 0.3,
+    });
+    assert_eq!(hybrid_top(&db), strong);
+}
+
+// -- dedup_existing --
+
+#[test]
+fn test_dedup_existing_collapses_near_duplicates() {
+    let db = open_temp();
+    let fact = Some(json!({"type": "fact"}));
+    let a = db
+        .insert(
+            "kafka runs on port 9092",
+            Some(&[1.0, 0.0, 0.0]),
+            Some(json!({"type": "fact", "src": "a"})),
+            None,
+            false,
+        )
+        .unwrap()
+        .id()
+        .to_string();
+    let b = db
+        .insert(
+            "kafka uses port 9092",
+            Some(&[0.99, 0.01, 0.0]),
+            Some(json!({"type": "fact", "tag": "b"})),
+            None,
+            false,
+        )
+        .unwrap()
+        .id()
+        .to_string();
+    let c = db
+        .insert(
+            "kafka port is 9092",
+            Some(&[0.98, 0.02, 0.0]),
+            fact.clone(),
+            None,
+            false,
+        )
+        .unwrap()
+        .id()
+        .to_string();
+    // Same vector but another type, and a dissimilar fact: both untouched
+    let other_type = db
+        .insert(
+            "kafka 9092 preference",
+            Some(&[1.0, 0.0, 0.0]),
+            Some(json!({"type": "preference"})),
+            None,
+            false,
+        )
+        .unwrap()
+        .id()
+        .to_string();
+    let unrelated = db
+        .insert(
+            "lunch is at noon",
+            Some(&[0.0, 1.0, 0.0]),
+            fact,
+            None,
+            false,
+        )
+        .unwrap()
+        .id()
+        .to_string();
+
+    db.get(&b).unwrap();
+    db.get(&b).unwrap();
+    db.get(&a).unwrap();
+
+    assert_eq!(db.dedup_existing(0.95).unwrap(), 2);
+    assert_eq!(db.count().unwrap(), 3);
+    assert!(db.get(&a).unwrap().is_none());
+    assert!(db.get(&c).unwrap().is_none());
+
+    let kept = db.get(&b).unwrap().unwrap();
+    assert_eq!(kept.content, "kafka uses port 9092");
+    // 2 + 1 + 0 accesses before the merge (this get's own bump comes after)
+    assert_eq!(kept.access_count, 3);
+    assert_eq!(
+        kept.metadata,
+        Some(json!({"type": "fact", "tag": "b", "src": "a"}))
+    );
+    assert!(db.get(&other_type).unwrap().is_some());
+    assert!(db.get(&unrelated).unwrap().is_some());
+
+    assert_eq!(db.dedup_existing(0.95).unwrap(), 0);
+}
+
+// -- report_score --
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    dot / (norm(a) * norm(b))
+}
+
+#[test]
+fn test_report_score_vector_cosine_in_hybrid() {
+    let db = open_temp();
+    db.insert(
+        "kafka broker config",
+        Some(&[0.8, 0.6, 0.0]),
+        None,
+        None,
+        false,
+    )
+    .unwrap();
+    db.insert(
+        "kafka consumer lag",
+        Some(&[0.1, 0.9, 0.4]),
+        None,
+        None,
+        false,
+    )
+    .unwrap();
+    db.insert("postgres vacuum", Some(&[0.0, 0.2, 1.0]), None, None, false)
+        .unwrap();
+
+    let query_vec = vec![1.0, 0.3, 0.1];
+    let query = |report_score| SearchQuery {
+        vector: Some(query_vec.clone()),
+        text: Some("kafka".to_string()),
+        mode: SearchMode::Hybrid,
+        report_score,
+        ..Default::default()
+    };
+    let fused = db.search(query(ScoreReport::Fusion)).unwrap();
+    let cosines = db.search(query(ScoreReport::VectorCosine)).unwrap();
+
+    // Same ranking, different number
+    let ids = |rs: &[memori_core::Memory]| rs.iter().map(|m| m.id.clone()).collect::<Vec<_>>();
+    assert_eq!(ids(&fused), ids(&cosines));
+    for m in &cosines {
+        let expected = cosine(&query_vec, m.vector.as_deref().unwrap());
+        assert!(
+            (m.score.unwrap() - expected).abs() < 1e-5,
+            "{:?} vs {}",
+            m.score,
+            expected
+        );
+    }
+    assert!(fused[0].score.unwrap() < 0.1);
+
+    // TextRank leaves non-matching results without a score
+    let ranked = db.search(query(ScoreReport::TextRank)).unwrap();
+    assert_eq!(ids(&fused), ids(&ranked));
+    for m in &ranked {
+        assert_eq!(
+            m.score.is_some(),
+            m.content.contains("kafka"),
+            "{}",
+            m.content
+        );
+    }
+}
+
+// -- create_parent_dirs --
+
+#[test]
+fn test_open_creates_parent_dirs() {
+    let root = std::env::temp_dir().join(format!("memori-test-{}", uuid::Uuid::new_v4()));
+    let path = root.join("some/new/dir/mem.db");
+    let path_str = path.to_str().unwrap();
+
+    match Memori::open(path_str) {
+        Err(MemoriError::Io(e)) => {
+            assert_eq!(e.kind(), std::io::ErrorKind::NotFound);
+            assert!(e.to_string().contains("some/new/dir"), "{}", e);
+        }
+        other => panic!("expected Io error, got {:?}", other.map(|_| ())),
+    }
+    assert!(!root.exists());
+
+    let config = MemoriConfig {
+        create_parent_dirs: true,
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(path_str, config).unwrap();
+    db.insert("persisted", None, None, None, true).unwrap();
+    drop(db);
+    assert!(path.is_file());
+    assert_eq!(Memori::open(path_str).unwrap().count().unwrap(), 1);
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+// -- metadata indexes --
+
+#[test]
+fn test_add_metadata_index_used_by_filtered_search() {
+    let db = open_temp();
+    for i in 0..20 {
+        let meta = json!({"type": "fact", "project": format!("p{}", i % 4)});
+        db.insert(
+            &format!("note {}", i),
+            Some(&[1.0, i as f32]),
+            Some(meta),
+            None,
+            true,
+        )
+        .unwrap();
+    }
+    let query = |filter| SearchQuery {
+        vector: Some(vec![1.0, 0.0]),
+        filter: Some(filter),
+        ..Default::default()
+    };
+    let plan = |filter| db.vector_query_plan(&query(filter)).unwrap().join("\n");
+
+    assert!(
+        plan(json!({"type": "fact"})).contains("USING INDEX idx_memories_type"),
+        "{}",
+        plan(json!({"type": "fact"}))
+    );
+    assert!(!plan(json!({"project": "p1"})).contains("idx_memories_meta_project"));
+
+    assert_eq!(
+        db.add_metadata_index("project").unwrap(),
+        "idx_memories_meta_project"
+    );
+    assert_eq!(
+        db.add_metadata_index("project").unwrap(),
+        "idx_memories_meta_project"
+    );
+    assert!(
+        plan(json!({"project": "p1"})).contains("USING INDEX idx_memories_meta_project"),
+        "{}",
+        plan(json!({"project": "p1"}))
+    );
+    assert_eq!(db.search(query(json!({"project": "p1"}))).unwrap().len(), 5);
+
+    assert!(matches!(
+        db.add_metadata_index("a'; DROP TABLE memories; --"),
+        Err(MemoriError::InvalidFilter(_))
+    ));
+}
+
+// -- export_memory --
+
+#[test]
+fn test_export_import_memory_round_trip() {
+    let src = open_temp();
+    let id = src
+        .insert(
+            "shared for a bug report",
+            Some(&[0.6, 0.8]),
+            Some(json!({"type": "bug", "sev": 2})),
+            None,
+            false,
+        )
+        .unwrap()
+        .id()
+        .to_string();
+    src.set_access_stats(&id, Some(1_700_000_000.5), 7).unwrap();
+
+    let json = src.export_memory(&id[..8]).unwrap();
+    let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed["id"], json!(id));
+    assert_eq!(parsed["access_count"], json!(7));
+    // Exporting is not an access
+    assert_eq!(src.get_readonly(&id).unwrap().unwrap().access_count, 7);
+
+    let dst = open_temp();
+    assert_eq!(dst.import_memory(&json).unwrap(), id);
+    let original = src.get_readonly(&id).unwrap().unwrap();
+    let imported = dst.get_readonly(&id).unwrap().unwrap();
+    assert_eq!(imported.content, original.content);
+    assert_eq!(imported.vector, original.vector);
+    assert_eq!(imported.metadata, original.metadata);
+    assert_eq!(imported.created_at, original.created_at);
+    assert_eq!(imported.updated_at, original.updated_at);
+    assert_eq!(imported.last_accessed, 1_700_000_000.5);
+    assert_eq!(imported.access_count, 7);
+    assert_eq!(dst.export_memory(&id).unwrap(), json);
+
+    assert!(matches!(
+        dst.import_memory("{not json"),
+        Err(MemoriError::Json(_))
+    ));
+}
+
+// -- access boost ceiling --
+
+fn top_with_boost_cap(max_access_boost: Option<f32>) -> String {
+    let config = MemoriConfig {
+        scoring: ScoringConfig { max_access_boost },
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(":memory:", config).unwrap();
+    let popular = db
+        .insert(
+            "popular but off-topic",
+            Some(&[0.5, 0.866]),
+            None,
+            None,
+            false,
+        )
+        .unwrap()
+        .id()
+        .to_string();
+    db.insert(
+        "relevant and fresh",
+        Some(&[0.9, 0.4359]),
+        None,
+        None,
+        false,
+    )
+    .unwrap();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs_f64();
+    db.set_access_stats(&popular, Some(now), 10_000).unwrap();
+
+    let results = db
+        .search(SearchQuery {
+            vector: Some(vec![1.0, 0.0]),
+            ..Default::default()
+        })
+        .unwrap();
+    results[0].content.clone()
+}
+
+#[test]
+fn test_access_boost_ceiling() {
+    // 0.5 * (1 + 0.1 * ln 10001) ~= 0.96 beats 0.9 uncapped; capped at 1.5x it is 0.75
+    assert_eq!(top_with_boost_cap(None), "popular but off-topic");
+    assert_eq!(top_with_boost_cap(Some(1.5)), "relevant and fresh");
+}
+
+// -- multi-vector chunks --
+
+#[test]
+fn test_insert_chunked_scores_best_chunk() {
+    let db = open_temp();
+    let chunked = db
+        .insert_chunked(
+            "long design doc",
+            &[vec![0.0, 1.0, 0.0], vec![1.0, 0.0, 0.0]],
+            None,
+        )
+        .unwrap();
+    db.insert("unrelated", Some(&[0.6, 0.8, 0.0]), None, None, false)
+        .unwrap();
+
+    let query = SearchQuery {
+        vector: Some(vec![1.0, 0.0, 0.0]),
+        limit: 10,
+        ..Default::default()
+    };
+    let results = db.search(query.clone()).unwrap();
+    assert_eq!(results[0].id, chunked);
+    assert_eq!(results.iter().filter(|m| m.id == chunked).count(), 1);
+    assert!((results[0].base_score.unwrap() - 1.0).abs() < 1e-5);
+
+    // Replacing the vector drops the extra chunks
+    db.update(&chunked, None, Some(&[0.0, 1.0, 0.0]), None, false)
+        .unwrap();
+    let results = db.search(query).unwrap();
+    assert_ne!(results[0].id, chunked);
+
+    let err = db
+        .insert_chunked("ragged", &[vec![1.0, 0.0], vec![1.0]], None)
+        .unwrap_err();
+    assert!(matches!(err, MemoriError::InvalidVector(_)));
+}
+
+// -- explain_absence --
+
+#[test]
+fn test_explain_absence_reports_date_gate() {
+    let db = open_temp();
+    let id = db
+        .insert(
+            "quarterly report draft",
+            Some(&[1.0, 0.0]),
+            None,
+            None,
+            false,
+        )
+        .unwrap()
+        .id()
+        .to_string();
+    db.insert(
+        "quarterly report final",
+        Some(&[0.9, 0.1]),
+        None,
+        None,
+        false,
+    )
+    .unwrap();
+    let created = db.get_readonly(&id).unwrap().unwrap().created_at;
+
+    let dated = SearchQuery {
+        text: Some("quarterly".to_string()),
+        after: Some(created + 60.0),
+        ..Default::default()
+    };
+    assert!(db.search(dated.clone()).unwrap().is_empty());
+    assert_eq!(
+        db.explain_absence(&dated, &id[..8]).unwrap(),
+        AbsenceReason::DateOutOfRange
+    );
+
+    let vector = SearchQuery {
+        vector: Some(vec![0.0, 1.0]),
+        limit: 1,
+        ..Default::default()
+    };
+    assert_eq!(
+        db.explain_absence(&vector, &id).unwrap(),
+        AbsenceReason::TruncatedByLimit { rank: 1 }
+    );
+    let text = SearchQuery {
+        text: Some("budget".to_string()),
+        mode: SearchMode::TextOnly,
+        ..Default::default()
+    };
+    assert_eq!(
+        db.explain_absence(&text, &id).unwrap(),
+        AbsenceReason::TextNoMatch
+    );
+    let found = SearchQuery {
+        text: Some("draft".to_string()),
+        mode: SearchMode::TextOnly,
+        ..Default::default()
+    };
+    assert_eq!(
+        db.explain_absence(&found, &id).unwrap(),
+        AbsenceReason::Returned { rank: 0 }
+    );
+}
+
+// -- parallel backfill --
+
+#[cfg(any(feature = "embeddings", feature = "mock_embeddings"))]
+#[test]
+fn test_parallel_backfill_matches_serial() {
+    let serial = open_temp();
+    let parallel = open_temp();
+    let mut ids = Vec::new();
+    for i in 0..23 {
+        let content = format!("backfill note {} about topic {}", i, i % 4);
+        let a = serial
+            .insert(&content, None, None, None, true)
+            .unwrap()
+            .id()
+            .to_string();
+        let b = parallel
+            .insert(&content, None, None, None, true)
+            .unwrap()
+            .id()
+            .to_string();
+        ids.push((a, b));
+    }
+
+    assert_eq!(serial.backfill_embeddings(5).unwrap(), 23);
+    let mut calls = Vec::new();
+    let processed = parallel
+        .backfill_embeddings_parallel(5, 3, |done, _| calls.push(done))
+        .unwrap();
+    assert_eq!(processed, 23);
+    assert_eq!(calls, vec![5, 10, 15, 20, 23]);
+    for (a, b) in &ids {
+        let va = serial.get_readonly(a).unwrap().unwrap().vector;
+        let vb = parallel.get_readonly(b).unwrap().unwrap().vector;
+        assert!(va.is_some());
+        assert_eq!(va, vb);
+    }
+}
+
+// -- open_diagnostic --
+
+#[test]
+fn test_open_diagnostic_reports_migration() {
+    let path = temp_db_path();
+    {
+        // v2 layout: content-feeding FTS plus the access columns
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE memories (
+                 id TEXT PRIMARY KEY, content TEXT NOT NULL, vector BLOB,
+                 metadata TEXT, created_at REAL NOT NULL, updated_at REAL NOT NULL,
+                 last_accessed REAL DEFAULT 0.0, access_count INTEGER DEFAULT 0
+             );
+             CREATE VIRTUAL TABLE memories_fts USING fts5(content, content=memories, content_rowid=rowid);
+             INSERT INTO memories (id, content, created_at, updated_at) VALUES ('v2-1', 'old note', 1.0, 1.0);
+             INSERT INTO memories_fts(memories_fts) VALUES ('rebuild');
+             PRAGMA user_version = 2;",
+        )
+        .unwrap();
+    }
+
+    let (db, report) = Memori::open_diagnostic(&path, MemoriConfig::default()).unwrap();
+    assert!(!report.created);
+    assert_eq!(report.version_before, 2);
+    assert_eq!(
+        report.version_after,
+        memori_core::schema::CURRENT_SCHEMA_VERSION
+    );
+    assert!(report.migrated);
+    assert!(report.fts_enabled);
+    assert_eq!(db.count().unwrap(), 1);
+    drop(db);
+
+    let (_, reopened) = Memori::open_diagnostic(&path, MemoriConfig::default()).unwrap();
+    assert!(!reopened.migrated);
+    assert_eq!(reopened.version_before, reopened.version_after);
+
+    let (_, fresh) = Memori::open_diagnostic(":memory:", MemoriConfig::default()).unwrap();
+    assert!(fresh.created && !fresh.migrated);
+    assert_eq!(fresh.version_before, 0);
+}
+
+// -- array merge policy --
+
+fn merged_tags(array_merge: ArrayMerge) -> Vec<serde_json::Value> {
+    let config = MemoriConfig {
+        array_merge,
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(":memory:", config).unwrap();
+    let keep = db
+        .insert(
+            "deploy runbook",
+            Some(&[1.0, 0.0]),
+            Some(json!({"tags": ["ops", "deploy"]})),
+            None,
+            false,
+        )
+        .unwrap()
+        .id()
+        .to_string();
+    db.insert(
+        "deploy run book",
+        Some(&[0.999, 0.01]),
+        Some(json!({"tags": ["deploy", "ci"]})),
+        None,
+        false,
+    )
+    .unwrap();
+    db.set_access_stats(&keep, None, 5).unwrap();
+
+    assert_eq!(db.dedup_existing(0.95).unwrap(), 1);
+    let meta = db.get_readonly(&keep).unwrap().unwrap().metadata.unwrap();
+    meta["tags"].as_array().unwrap().clone()
+}
+
+#[test]
+fn test_dedup_merge_array_policies() {
+    let mut union = merged_tags(ArrayMerge::Union);
+    union.sort_by_key(|t| t.to_string());
+    assert_eq!(union, vec![json!("ci"), json!("deploy"), json!("ops")]);
+    assert_eq!(
+        merged_tags(ArrayMerge::Replace),
+        vec![json!("ops"), json!("deploy")]
+    );
+    assert_eq!(merged_tags(ArrayMerge::Append).len(), 4);
+}
+
+#[test]
+fn test_insert_dedup_unions_tags() {
+    let config = MemoriConfig {
+        exact_dedup: true,
+        array_merge: ArrayMerge::Union,
+        ..Default::default()
+    };
+    let db = Memori::open_with_config(":memory:", config).unwrap();
+    let first = db
+        .insert(
+            "deploy pipeline is flaky",
+            Some(&[1.0, 0.0]),
+            Some(json!({"type": "ops", "tags": ["ci", "deploy"]})),
+            None,
+            true,
+        )
+        .unwrap()
+        .id()
+        .to_string();
+
+    // Vector duplicate: stored and incoming tags combine
+    let near = db
+        .insert(
+            "the deploy pipeline flakes",
+            Some(&[0.99, 0.01]),
+            Some(json!({"type": "ops", "tags": ["deploy", "ops"]})),
+            Some(0.95),
+            true,
+        )
+        .unwrap();
+    assert_eq!(near.id(), first);
+    assert_eq!(
+        db.get_readonly(&first).unwrap().unwrap().metadata.unwrap()["tags"],
+        json!(["ci", "deploy", "ops"])
+    );
+
+    // Exact duplicate (of the rewritten content): the same merge
+    let exact = db
+        .insert(
+            "the deploy pipeline flakes",
+            None,
+            Some(json!({"type": "ops", "tags": ["flaky"]})),
+            None,
+            true,
+        )
+        .unwrap();
+    assert_eq!(exact.id(), first);
+    let meta = db.get_readonly(&first).unwrap().unwrap().metadata.unwrap();
+    assert_eq!(meta["tags"], json!(["ci", "deploy", "ops", "flaky"]));
+    assert_eq!(db.count().unwrap(), 1);
+}
+
+// -- sample --
+
+#[test]
+fn test_sample_distinct_and_filtered() {
+    let db = open_temp();
+    for i in 0..12 {
+        let kind = if i % 3 == 0 { "bug" } else { "note" };
+        db.insert(
+            &format!("memory number {}", i),
+            None,
+            Some(json!({"type": kind})),
+            None,
+            true,
+        )
+        .unwrap();
+    }
+
+    let picked = db.sample(5, None, None).unwrap();
+    assert_eq!(picked.len(), 5);
+    let ids: std::collections::HashSet<_> = picked.iter().map(|m| m.id.clone()).collect();
+    assert_eq!(ids.len(), 5);
+    assert!(picked.iter().all(|m| m.access_count == 0));
+
+    let bugs = db.sample(10, Some(&json!({"type": "bug"})), None).unwrap();
+    assert_eq!(bugs.len(), 4);
+    assert!(bugs
+        .iter()
+        .all(|m| m.metadata.as_ref().unwrap()["type"] == "bug"));
+
+    let ids_of = |seed| {
+        db.sample(4, None, Some(seed))
+            .unwrap()
+            .into_iter()
+            .map(|m| m.id)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(ids_of(7), ids_of(7));
+    assert_ne!(ids_of(7), ids_of(8));
+    assert_eq!(
+        db.get_readonly(&picked[0].id)
+            .unwrap()
+            .unwrap()
+            .access_count,
+        0
+    );
+}
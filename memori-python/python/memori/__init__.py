@@ -1,7 +1,8 @@
 """Memori -- embedded AI agent memory (SQLite + vector search + FTS5).
 
-This package re-exports the native Rust extension's PyMemori class.
+This package re-exports the native Rust extension's PyMemori class and the
+PyInsertHandle returned by PyMemori.insert_async_bg.
 """
-from .memori import PyMemori
+from .memori import PyInsertHandle, PyMemori
 
-__all__ = ["PyMemori"]
+__all__ = ["PyInsertHandle", "PyMemori"]
@@ -577,6 +577,7 @@ def cmd_import(args):
   new_ids = args.new_ids
   imported = 0
   errors = 0
+  access_stats = []
 
   for line in sys.stdin:
     line = line.strip()
@@ -603,9 +604,9 @@ def cmd_import(args):
           created_at=created_at, updated_at=updated_at,
         )
 
-      # Restore access stats if present in export
+      # Restore access stats if present in export (applied in one batch below)
       if last_accessed is not None or access_count > 0:
-        db.set_access_stats(mem_id, last_accessed=last_accessed, access_count=access_count)
+        access_stats.append((mem_id, last_accessed, access_count))
 
       imported += 1
     except Exception as e:
@@ -613,6 +614,9 @@ def cmd_import(args):
       if not args.json:
         print(f"Error on line {imported + errors}: {e}", file=sys.stderr)
 
+  if access_stats:
+    db.set_access_stats_batch(access_stats)
+
   if args.json:
     print(json.dumps({"imported": imported, "errors": errors}))
   else:
@@ -693,6 +697,9 @@ def cmd_related(args):
     elif "ambiguous" in err_msg:
       error_type = "ambiguous_prefix"
       hint = " (use a longer prefix to disambiguate)"
+    elif "shorter than the minimum" in err_msg:
+      error_type = "prefix_too_short"
+      hint = " (use a longer prefix)"
     else:
       error_type = "not_found"
       hint = " (try 'memori list' to see available memories)"
@@ -1,10 +1,21 @@
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use memori_core::{InsertResult, Memori, Memory, SearchQuery, SortField};
+use memori_core::{
+    ArrayMerge, Attachment, AttachmentInfo, CheckpointMode, InsertResult, Memori, MemoriConfig,
+    Memory, MemoryColumns, ScoreReport, SearchMode, SearchQuery, SortField, StoreStats,
+    TextMatchMode,
+};
 use pyo3::exceptions::PyRuntimeError;
 use pyo3::prelude::*;
-use pyo3::types::{PyDict, PyList};
+use pyo3::types::{PyBytes, PyDict, PyList};
+
+// PyO3 0.22's #[pymethods] expansion trips `useless_conversion` on every
+// PyResult-returning method. The wrappers it generates sit beside the impl
+// and only inherit `cfg` attributes, so the allow has to be on the module.
+#[allow(clippy::useless_conversion)]
+mod methods;
 
 fn memori_err(e: memori_core::MemoriError) -> PyErr {
     PyRuntimeError::new_err(e.to_string())
@@ -94,6 +105,60 @@ fn memory_to_dict(py: Python<'_>, mem: &Memory) -> PyResult<PyObject> {
         None => dict.set_item("score", py.None())?,
     }
 
+    match mem.base_score {
+        Some(s) => dict.set_item("base_score", s)?,
+        None => dict.set_item("base_score", py.None())?,
+    }
+
+    match mem.deleted_at {
+        Some(t) => dict.set_item("deleted_at", t)?,
+        None => dict.set_item("deleted_at", py.None())?,
+    }
+
+    Ok(dict.to_object(py))
+}
+
+fn columns_to_dict(py: Python<'_>, cols: &MemoryColumns) -> PyResult<PyObject> {
+    let dict = PyDict::new_bound(py);
+    dict.set_item("id", &cols.id)?;
+    dict.set_item("content", &cols.content)?;
+    dict.set_item("metadata", &cols.metadata)?;
+    dict.set_item("created_at", &cols.created_at)?;
+    dict.set_item("updated_at", &cols.updated_at)?;
+    dict.set_item("last_accessed", &cols.last_accessed)?;
+    dict.set_item("access_count", &cols.access_count)?;
+    dict.set_item("score", &cols.score)?;
+    dict.set_item("base_score", &cols.base_score)?;
+    dict.set_item("deleted_at", &cols.deleted_at)?;
+    Ok(dict.to_object(py))
+}
+
+fn store_stats_to_dict(py: Python<'_>, stats: &StoreStats) -> PyResult<PyObject> {
+    let dict = PyDict::new_bound(py);
+    dict.set_item("memories", stats.memories)?;
+    dict.set_item("embedded", stats.embedded)?;
+    dict.set_item("archived", stats.archived)?;
+    dict.set_item("deleted", stats.deleted)?;
+    dict.set_item("data_bytes", stats.data_bytes)?;
+    Ok(dict.to_object(py))
+}
+
+fn attachment_to_dict(py: Python<'_>, attachment: &Attachment) -> PyResult<PyObject> {
+    let dict = PyDict::new_bound(py);
+    dict.set_item("memory_id", &attachment.memory_id)?;
+    dict.set_item("name", &attachment.name)?;
+    dict.set_item("mime", &attachment.mime)?;
+    dict.set_item("data", PyBytes::new_bound(py, &attachment.data))?;
+    dict.set_item("created_at", attachment.created_at)?;
+    Ok(dict.to_object(py))
+}
+
+fn attachment_info_to_dict(py: Python<'_>, info: &AttachmentInfo) -> PyResult<PyObject> {
+    let dict = PyDict::new_bound(py);
+    dict.set_item("name", &info.name)?;
+    dict.set_item("mime", &info.mime)?;
+    dict.set_item("size", info.size)?;
+    dict.set_item("created_at", info.created_at)?;
     Ok(dict.to_object(py))
 }
 
@@ -111,264 +176,88 @@ fn insert_result_to_dict(py: Python<'_>, result: &InsertResult) -> PyResult<PyOb
     Ok(dict.to_object(py))
 }
 
+#[allow(deprecated, clippy::too_many_arguments)]
+fn search_query(
+    vector: Option<Vec<f32>>,
+    text: Option<String>,
+    filter: Option<&Bound<'_, PyDict>>,
+    limit: usize,
+    text_only: bool,
+    before: Option<f64>,
+    after: Option<f64>,
+    dedup_results: Option<f32>,
+    min_fts_score: Option<f32>,
+    raw_filter: Option<String>,
+    mode: &str,
+    rerank_by: Option<&str>,
+    rerank_oversample: usize,
+    text_match: &str,
+    include_deleted: bool,
+    exclude_ids: Option<Vec<String>>,
+    fallback: bool,
+    fallback_min_score: Option<f32>,
+    report_score: &str,
+) -> PyResult<SearchQuery> {
+    let filter_val = filter.map(pydict_to_value).transpose()?;
+    let mode = SearchMode::from_str(mode).map_err(PyRuntimeError::new_err)?;
+    let text_match = TextMatchMode::from_str(text_match).map_err(PyRuntimeError::new_err)?;
+    let report_score = ScoreReport::from_str(report_score).map_err(PyRuntimeError::new_err)?;
+    let rerank_by = rerank_by
+        .map(SortField::from_str)
+        .transpose()
+        .map_err(PyRuntimeError::new_err)?;
+    Ok(SearchQuery {
+        vector,
+        text,
+        filter: filter_val,
+        limit,
+        mode,
+        text_only,
+        before,
+        after,
+        dedup_results,
+        min_fts_score,
+        raw_filter,
+        rerank_by,
+        rerank_oversample,
+        text_match,
+        include_deleted,
+        exclude_ids: exclude_ids.unwrap_or_default(),
+        fallback,
+        fallback_min_score,
+        report_score,
+    })
+}
+
 #[pyclass]
 struct PyMemori {
-    inner: Mutex<Memori>,
+    inner: Arc<Mutex<Memori>>,
 }
 
-#[pymethods]
 impl PyMemori {
-    #[new]
-    fn new(path: &str) -> PyResult<Self> {
-        let inner = Memori::open(path).map_err(memori_err)?;
-        Ok(Self {
-            inner: Mutex::new(inner),
-        })
-    }
-
-    #[pyo3(signature = (content, vector=None, metadata=None, dedup_threshold=None, no_embed=false))]
-    fn insert(
-        &self,
-        py: Python<'_>,
-        content: &str,
-        vector: Option<Vec<f32>>,
-        metadata: Option<&Bound<'_, PyDict>>,
-        dedup_threshold: Option<f32>,
-        no_embed: bool,
-    ) -> PyResult<PyObject> {
-        let meta = metadata.map(pydict_to_value).transpose()?;
-        let content_owned = content.to_string();
-        let result = py.allow_threads(|| {
-            self.inner
-                .lock()
-                .unwrap()
-                .insert(&content_owned, vector.as_deref(), meta, dedup_threshold, no_embed)
-                .map_err(memori_err)
-        })?;
-
-        insert_result_to_dict(py, &result)
-    }
-
-    fn get(&self, py: Python<'_>, id: &str) -> PyResult<Option<PyObject>> {
-        let mem = self.inner.lock().unwrap().get(id).map_err(memori_err)?;
-        match mem {
-            Some(m) => Ok(Some(memory_to_dict(py, &m)?)),
-            None => Ok(None),
-        }
-    }
-
-    fn get_readonly(&self, py: Python<'_>, id: &str) -> PyResult<Option<PyObject>> {
-        let mem = self.inner.lock().unwrap().get_readonly(id).map_err(memori_err)?;
-        match mem {
-            Some(m) => Ok(Some(memory_to_dict(py, &m)?)),
-            None => Ok(None),
-        }
-    }
-
-    #[pyo3(signature = (id, content=None, vector=None, metadata=None, merge_metadata=true))]
-    fn update(
-        &self,
-        id: &str,
-        content: Option<&str>,
-        vector: Option<Vec<f32>>,
-        metadata: Option<&Bound<'_, PyDict>>,
-        merge_metadata: bool,
-    ) -> PyResult<()> {
-        let meta = metadata.map(pydict_to_value).transpose()?;
-        self.inner
-            .lock()
-            .unwrap()
-            .update(id, content, vector.as_deref(), meta, merge_metadata)
-            .map_err(memori_err)
-    }
-
-    fn delete(&self, id: &str) -> PyResult<()> {
-        self.inner.lock().unwrap().delete(id).map_err(memori_err)
-    }
-
-    #[pyo3(signature = (vector=None, text=None, filter=None, limit=10, text_only=false, before=None, after=None))]
-    fn search(
-        &self,
-        py: Python<'_>,
-        vector: Option<Vec<f32>>,
-        text: Option<String>,
-        filter: Option<&Bound<'_, PyDict>>,
-        limit: usize,
-        text_only: bool,
-        before: Option<f64>,
-        after: Option<f64>,
-    ) -> PyResult<Vec<PyObject>> {
-        let filter_val = filter.map(pydict_to_value).transpose()?;
-        let query = SearchQuery {
-            vector,
-            text,
-            filter: filter_val,
-            limit,
-            text_only,
-            before,
-            after,
-        };
-
-        let results = py.allow_threads(|| {
-            self.inner.lock().unwrap().search(query).map_err(memori_err)
-        })?;
-
-        results.iter().map(|m| memory_to_dict(py, m)).collect()
-    }
-
-    #[pyo3(signature = (type_filter=None, sort="created", limit=20, offset=0, before=None, after=None))]
-    fn list(
-        &self,
-        py: Python<'_>,
-        type_filter: Option<&str>,
-        sort: &str,
-        limit: usize,
-        offset: usize,
-        before: Option<f64>,
-        after: Option<f64>,
-    ) -> PyResult<Vec<PyObject>> {
-        let sort_field = SortField::from_str(sort)
-            .map_err(|e| PyRuntimeError::new_err(e))?;
-        let results = self
-            .inner
-            .lock()
-            .unwrap()
-            .list(type_filter, &sort_field, limit, offset, before, after)
-            .map_err(memori_err)?;
-        results.iter().map(|m| memory_to_dict(py, m)).collect()
-    }
-
-    fn count(&self) -> PyResult<usize> {
-        self.inner.lock().unwrap().count().map_err(memori_err)
-    }
-
-    #[pyo3(signature = (id, content, vector=None, metadata=None, created_at=None, updated_at=None))]
-    fn insert_with_id(
-        &self,
-        id: &str,
-        content: &str,
-        vector: Option<Vec<f32>>,
-        metadata: Option<&Bound<'_, PyDict>>,
-        created_at: Option<f64>,
-        updated_at: Option<f64>,
-    ) -> PyResult<String> {
-        let meta = metadata.map(pydict_to_value).transpose()?;
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs_f64();
-        let ca = created_at.unwrap_or(now);
-        let ua = updated_at.unwrap_or(now);
-        self.inner
-            .lock()
-            .unwrap()
-            .insert_with_id(id, content, vector.as_deref(), meta, ca, ua)
-            .map_err(memori_err)
-    }
-
-    fn vacuum(&self) -> PyResult<()> {
-        self.inner.lock().unwrap().vacuum().map_err(memori_err)
-    }
-
-    #[pyo3(signature = (id, last_accessed=None, access_count=0))]
-    fn set_access_stats(
-        &self,
-        id: &str,
-        last_accessed: Option<f64>,
-        access_count: i64,
-    ) -> PyResult<()> {
-        self.inner
-            .lock()
-            .unwrap()
-            .set_access_stats(id, last_accessed, access_count)
-            .map_err(memori_err)
-    }
-
-    fn type_distribution(&self, py: Python<'_>) -> PyResult<PyObject> {
-        let dist = self
-            .inner
-            .lock()
-            .unwrap()
-            .type_distribution()
-            .map_err(memori_err)?;
-        let dict = PyDict::new_bound(py);
-        for (k, v) in dist {
-            dict.set_item(k, v)?;
-        }
-        Ok(dict.to_object(py))
-    }
-
-    fn delete_before(&self, before_timestamp: f64) -> PyResult<usize> {
-        self.inner
-            .lock()
-            .unwrap()
-            .delete_before(before_timestamp)
-            .map_err(memori_err)
-    }
-
-    fn delete_by_type(&self, type_value: &str) -> PyResult<usize> {
-        self.inner
-            .lock()
-            .unwrap()
-            .delete_by_type(type_value)
-            .map_err(memori_err)
-    }
-
-    #[pyo3(signature = (text,))]
-    fn embed(&self, text: &str) -> PyResult<Vec<f32>> {
-        #[cfg(feature = "embeddings")]
-        {
-            Ok(memori_core::embed::embed_text(text))
-        }
-        #[cfg(not(feature = "embeddings"))]
-        {
-            let _ = text;
-            Err(PyRuntimeError::new_err(
-                "embeddings feature not enabled at compile time",
-            ))
-        }
-    }
-
-    #[pyo3(signature = (batch_size=50))]
-    fn backfill_embeddings(&self, py: Python<'_>, batch_size: usize) -> PyResult<usize> {
-        py.allow_threads(|| {
-            self.inner
-                .lock()
-                .unwrap()
-                .backfill_embeddings(batch_size)
-                .map_err(memori_err)
-        })
+    fn run_search(&self, py: Python<'_>, query: SearchQuery) -> PyResult<Vec<Memory>> {
+        py.allow_threads(|| self.inner.lock().unwrap().search(query).map_err(memori_err))
     }
+}
 
-    #[pyo3(signature = (id, limit=5))]
-    fn related(&self, py: Python<'_>, id: &str, limit: usize) -> PyResult<Vec<PyObject>> {
-        let id_owned = id.to_string();
-        let results = py.allow_threads(|| {
-            self.inner
-                .lock()
-                .unwrap()
-                .related(&id_owned, limit)
-                .map_err(memori_err)
-        })?;
-        results.iter().map(|m| memory_to_dict(py, m)).collect()
-    }
+/// Iterator returned by `PyMemori.search_iter`: the ranked results of one
+/// search, converted to dicts one at a time.
+#[pyclass]
+struct PySearchIter {
+    results: Mutex<std::vec::IntoIter<Memory>>,
+}
 
-    fn embedding_stats(&self, py: Python<'_>) -> PyResult<PyObject> {
-        let (embedded, total) = self
-            .inner
-            .lock()
-            .unwrap()
-            .embedding_stats()
-            .map_err(memori_err)?;
-        let dict = PyDict::new_bound(py);
-        dict.set_item("embedded", embedded)?;
-        dict.set_item("total", total)?;
-        Ok(dict.to_object(py))
-    }
+/// Completion handle returned by `PyMemori.insert_async_bg`.
+#[pyclass]
+struct PyInsertHandle {
+    thread: Mutex<Option<JoinHandle<Result<InsertResult, String>>>>,
+    outcome: Mutex<Option<Result<InsertResult, String>>>,
 }
 
 #[pymodule]
 fn memori(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<PyMemori>()?;
+    m.add_class::<PyInsertHandle>()?;
+    m.add_class::<PySearchIter>()?;
     Ok(())
 }
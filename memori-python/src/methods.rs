@@ -0,0 +1,876 @@
+//! The `#[pymethods]` of the Python classes declared in `lib.rs`.
+
+use super::*;
+
+#[pymethods]
+impl PySearchIter {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        let next = self.results.lock().unwrap().next();
+        next.map(|m| memory_to_dict(py, &m)).transpose()
+    }
+}
+
+#[pymethods]
+impl PyInsertHandle {
+    /// True once the background insert has finished (successfully or not).
+    fn done(&self) -> bool {
+        if self.outcome.lock().unwrap().is_some() {
+            return true;
+        }
+        match self.thread.lock().unwrap().as_ref() {
+            Some(t) => t.is_finished(),
+            None => true,
+        }
+    }
+
+    /// Block until the insert finishes and return its `{"id", "action"}` dict,
+    /// raising if it failed. Safe to call more than once.
+    fn wait(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let mut outcome = self.outcome.lock().unwrap();
+        if outcome.is_none() {
+            let thread = self.thread.lock().unwrap().take();
+            let joined = py.allow_threads(|| match thread {
+                Some(t) => t
+                    .join()
+                    .unwrap_or_else(|_| Err("background insert panicked".to_string())),
+                None => Err("background insert handle already consumed".to_string()),
+            });
+            *outcome = Some(joined);
+        }
+        match outcome.as_ref().unwrap() {
+            Ok(result) => insert_result_to_dict(py, result),
+            Err(e) => Err(PyRuntimeError::new_err(e.clone())),
+        }
+    }
+}
+
+#[pymethods]
+impl PyMemori {
+    #[new]
+    #[pyo3(signature = (path, collection=None, create_parent_dirs=false, array_merge="replace"))]
+    fn new(
+        path: &str,
+        collection: Option<String>,
+        create_parent_dirs: bool,
+        array_merge: &str,
+    ) -> PyResult<Self> {
+        let config = MemoriConfig {
+            collection,
+            create_parent_dirs,
+            array_merge: ArrayMerge::from_str(array_merge).map_err(PyRuntimeError::new_err)?,
+            ..Default::default()
+        };
+        let inner = Memori::open_with_config(path, config).map_err(memori_err)?;
+        Ok(Self {
+            inner: Arc::new(Mutex::new(inner)),
+        })
+    }
+
+    #[pyo3(signature = (content, vector=None, metadata=None, dedup_threshold=None, no_embed=false, content_hash=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn insert(
+        &self,
+        py: Python<'_>,
+        content: &str,
+        vector: Option<Vec<f32>>,
+        metadata: Option<&Bound<'_, PyDict>>,
+        dedup_threshold: Option<f32>,
+        no_embed: bool,
+        content_hash: Option<String>,
+    ) -> PyResult<PyObject> {
+        let meta = metadata.map(pydict_to_value).transpose()?;
+        let content_owned = content.to_string();
+        let result = py.allow_threads(|| {
+            let db = self.inner.lock().unwrap();
+            match &content_hash {
+                Some(hash) => db.insert_with_hash(&content_owned, hash, vector.as_deref(), meta, dedup_threshold, no_embed),
+                None => db.insert(&content_owned, vector.as_deref(), meta, dedup_threshold, no_embed),
+            }
+            .map_err(memori_err)
+        })?;
+
+        insert_result_to_dict(py, &result)
+    }
+
+    /// Store `reference` as the content of a memory whose body lives
+    /// elsewhere; `summary` is what gets searched and embedded. Returns the id.
+    #[pyo3(signature = (reference, summary, vector=None, metadata=None, no_embed=false))]
+    fn insert_external(
+        &self,
+        py: Python<'_>,
+        reference: &str,
+        summary: &str,
+        vector: Option<Vec<f32>>,
+        metadata: Option<&Bound<'_, PyDict>>,
+        no_embed: bool,
+    ) -> PyResult<String> {
+        let meta = metadata.map(pydict_to_value).transpose()?;
+        py.allow_threads(|| {
+            self.inner
+                .lock()
+                .unwrap()
+                .insert_external(reference, summary, vector.as_deref(), meta, no_embed)
+                .map_err(memori_err)
+        })
+    }
+
+    /// Insert a memory that vector search scores by the best of `vectors`
+    /// (one per chunk; the first is its own vector). Returns the id.
+    #[pyo3(signature = (content, vectors, metadata=None))]
+    fn insert_chunked(
+        &self,
+        py: Python<'_>,
+        content: &str,
+        vectors: Vec<Vec<f32>>,
+        metadata: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<String> {
+        let meta = metadata.map(pydict_to_value).transpose()?;
+        py.allow_threads(|| {
+            self.inner
+                .lock()
+                .unwrap()
+                .insert_chunked(content, &vectors, meta)
+                .map_err(memori_err)
+        })
+    }
+
+    fn external_summary(&self, id: &str) -> PyResult<Option<String>> {
+        self.inner.lock().unwrap().external_summary(id).map_err(memori_err)
+    }
+
+    fn add_attachment(&self, id: &str, name: &str, mime: &str, data: &[u8]) -> PyResult<()> {
+        self.inner
+            .lock()
+            .unwrap()
+            .add_attachment(id, name, mime, data)
+            .map_err(memori_err)
+    }
+
+    fn get_attachment(&self, py: Python<'_>, id: &str, name: &str) -> PyResult<Option<PyObject>> {
+        let attachment = self.inner.lock().unwrap().get_attachment(id, name).map_err(memori_err)?;
+        attachment.map(|a| attachment_to_dict(py, &a)).transpose()
+    }
+
+    fn list_attachments(&self, py: Python<'_>, id: &str) -> PyResult<Vec<PyObject>> {
+        let infos = self.inner.lock().unwrap().list_attachments(id).map_err(memori_err)?;
+        infos.iter().map(|i| attachment_info_to_dict(py, i)).collect()
+    }
+
+    /// Like `insert`, but embeds and writes on a background thread and returns
+    /// a handle immediately. Embedding runs without holding the database lock,
+    /// so other calls are not blocked on model latency; writes still serialize.
+    #[pyo3(signature = (content, vector=None, metadata=None, dedup_threshold=None, no_embed=false))]
+    fn insert_async_bg(
+        &self,
+        content: String,
+        vector: Option<Vec<f32>>,
+        metadata: Option<&Bound<'_, PyDict>>,
+        dedup_threshold: Option<f32>,
+        no_embed: bool,
+    ) -> PyResult<PyInsertHandle> {
+        let meta = metadata.map(pydict_to_value).transpose()?;
+        let inner = Arc::clone(&self.inner);
+        let thread = std::thread::spawn(move || {
+            // Embed under only a brief lock for the config, then write
+            let embedding = match (&vector, no_embed) {
+                (None, false) => {
+                    let config = inner.lock().unwrap().config().clone();
+                    memori_core::storage::auto_embedding(&config, &content)
+                }
+                _ => None,
+            };
+            let db = inner.lock().unwrap();
+            match embedding {
+                Some(v) => db.insert_embedded(&content, v, meta, dedup_threshold),
+                None => db.insert(&content, vector.as_deref(), meta, dedup_threshold, no_embed),
+            }
+            .map_err(|e| e.to_string())
+        });
+        Ok(PyInsertHandle {
+            thread: Mutex::new(Some(thread)),
+            outcome: Mutex::new(None),
+        })
+    }
+
+    fn get(&self, py: Python<'_>, id: &str) -> PyResult<Option<PyObject>> {
+        let mem = self.inner.lock().unwrap().get(id).map_err(memori_err)?;
+        match mem {
+            Some(m) => Ok(Some(memory_to_dict(py, &m)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn get_readonly(&self, py: Python<'_>, id: &str) -> PyResult<Option<PyObject>> {
+        let mem = self.inner.lock().unwrap().get_readonly(id).map_err(memori_err)?;
+        match mem {
+            Some(m) => Ok(Some(memory_to_dict(py, &m)?)),
+            None => Ok(None),
+        }
+    }
+
+    #[pyo3(signature = (id, content=None, vector=None, metadata=None, merge_metadata=true))]
+    fn update(
+        &self,
+        id: &str,
+        content: Option<&str>,
+        vector: Option<Vec<f32>>,
+        metadata: Option<&Bound<'_, PyDict>>,
+        merge_metadata: bool,
+    ) -> PyResult<()> {
+        let meta = metadata.map(pydict_to_value).transpose()?;
+        self.inner
+            .lock()
+            .unwrap()
+            .update(id, content, vector.as_deref(), meta, merge_metadata)
+            .map_err(memori_err)
+    }
+
+    #[pyo3(signature = (id, content, vector=None, metadata=None))]
+    fn replace(
+        &self,
+        id: &str,
+        content: &str,
+        vector: Option<Vec<f32>>,
+        metadata: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<()> {
+        let meta = metadata.map(pydict_to_value).transpose()?;
+        self.inner
+            .lock()
+            .unwrap()
+            .replace(id, content, vector.as_deref(), meta)
+            .map_err(memori_err)
+    }
+
+    #[pyo3(signature = (id, key, delta=1.0))]
+    fn increment_metadata_number(&self, id: &str, key: &str, delta: f64) -> PyResult<f64> {
+        self.inner
+            .lock()
+            .unwrap()
+            .increment_metadata_number(id, key, delta)
+            .map_err(memori_err)
+    }
+
+    fn delete(&self, id: &str) -> PyResult<()> {
+        self.inner.lock().unwrap().delete(id).map_err(memori_err)
+    }
+
+    fn soft_delete(&self, id: &str) -> PyResult<()> {
+        self.inner.lock().unwrap().soft_delete(id).map_err(memori_err)
+    }
+
+    #[pyo3(signature = (vector=None, text=None, filter=None, limit=10, text_only=false, before=None, after=None, dedup_results=None, min_fts_score=None, raw_filter=None, mode="auto", rerank_by=None, rerank_oversample=5, text_match="all", include_deleted=false, exclude_ids=None, fallback=false, fallback_min_score=None, report_score="fusion"))]
+    #[allow(clippy::too_many_arguments)]
+    fn search(
+        &self,
+        py: Python<'_>,
+        vector: Option<Vec<f32>>,
+        text: Option<String>,
+        filter: Option<&Bound<'_, PyDict>>,
+        limit: usize,
+        text_only: bool,
+        before: Option<f64>,
+        after: Option<f64>,
+        dedup_results: Option<f32>,
+        min_fts_score: Option<f32>,
+        raw_filter: Option<String>,
+        mode: &str,
+        rerank_by: Option<&str>,
+        rerank_oversample: usize,
+        text_match: &str,
+        include_deleted: bool,
+        exclude_ids: Option<Vec<String>>,
+        fallback: bool,
+        fallback_min_score: Option<f32>,
+        report_score: &str,
+    ) -> PyResult<Vec<PyObject>> {
+        let query = search_query(
+            vector,
+            text,
+            filter,
+            limit,
+            text_only,
+            before,
+            after,
+            dedup_results,
+            min_fts_score,
+            raw_filter,
+            mode,
+            rerank_by,
+            rerank_oversample,
+            text_match,
+            include_deleted,
+            exclude_ids,
+            fallback,
+            fallback_min_score,
+            report_score,
+        )?;
+        let results = self.run_search(py, query)?;
+        results.iter().map(|m| memory_to_dict(py, m)).collect()
+    }
+
+    /// Like `search`, but returns an iterator that builds each result dict
+    /// only when it is reached, so breaking early skips the rest.
+    #[pyo3(signature = (vector=None, text=None, filter=None, limit=10, text_only=false, before=None, after=None, dedup_results=None, min_fts_score=None, raw_filter=None, mode="auto", rerank_by=None, rerank_oversample=5, text_match="all", include_deleted=false, exclude_ids=None, fallback=false, fallback_min_score=None, report_score="fusion"))]
+    #[allow(clippy::too_many_arguments)]
+    fn search_iter(
+        &self,
+        py: Python<'_>,
+        vector: Option<Vec<f32>>,
+        text: Option<String>,
+        filter: Option<&Bound<'_, PyDict>>,
+        limit: usize,
+        text_only: bool,
+        before: Option<f64>,
+        after: Option<f64>,
+        dedup_results: Option<f32>,
+        min_fts_score: Option<f32>,
+        raw_filter: Option<String>,
+        mode: &str,
+        rerank_by: Option<&str>,
+        rerank_oversample: usize,
+        text_match: &str,
+        include_deleted: bool,
+        exclude_ids: Option<Vec<String>>,
+        fallback: bool,
+        fallback_min_score: Option<f32>,
+        report_score: &str,
+    ) -> PyResult<PySearchIter> {
+        let query = search_query(
+            vector,
+            text,
+            filter,
+            limit,
+            text_only,
+            before,
+            after,
+            dedup_results,
+            min_fts_score,
+            raw_filter,
+            mode,
+            rerank_by,
+            rerank_oversample,
+            text_match,
+            include_deleted,
+            exclude_ids,
+            fallback,
+            fallback_min_score,
+            report_score,
+        )?;
+        let results = self.run_search(py, query)?;
+        Ok(PySearchIter {
+            results: Mutex::new(results.into_iter()),
+        })
+    }
+
+    /// One vector search per entry of `vectors`, sharing `filter` and
+    /// `limit`, in a single scan of the stored vectors. Returns a list of
+    /// result lists in the order of `vectors`.
+    #[pyo3(signature = (vectors, filter=None, limit=10))]
+    fn search_many(
+        &self,
+        py: Python<'_>,
+        vectors: Vec<Vec<f32>>,
+        filter: Option<&Bound<'_, PyDict>>,
+        limit: usize,
+    ) -> PyResult<Vec<Vec<PyObject>>> {
+        let filter_val = filter.map(pydict_to_value).transpose()?;
+        let queries: Vec<SearchQuery> = vectors
+            .into_iter()
+            .map(|v| SearchQuery {
+                vector: Some(v),
+                filter: filter_val.clone(),
+                limit,
+                ..Default::default()
+            })
+            .collect();
+        let results = py.allow_threads(|| {
+            self.inner
+                .lock()
+                .unwrap()
+                .search_many(&queries)
+                .map_err(memori_err)
+        })?;
+        results
+            .iter()
+            .map(|rows| rows.iter().map(|m| memory_to_dict(py, m)).collect())
+            .collect()
+    }
+
+    /// Like `search`, but returns one dict of equal-length lists keyed by
+    /// field (metadata as JSON strings, no vectors), ready for
+    /// `pandas.DataFrame`, `polars.DataFrame` or `pyarrow.table`.
+    #[pyo3(signature = (vector=None, text=None, filter=None, limit=10, text_only=false, before=None, after=None, dedup_results=None, min_fts_score=None, raw_filter=None, mode="auto", rerank_by=None, rerank_oversample=5, text_match="all", include_deleted=false, exclude_ids=None, fallback=false, fallback_min_score=None, report_score="fusion"))]
+    #[allow(clippy::too_many_arguments)]
+    fn search_columns(
+        &self,
+        py: Python<'_>,
+        vector: Option<Vec<f32>>,
+        text: Option<String>,
+        filter: Option<&Bound<'_, PyDict>>,
+        limit: usize,
+        text_only: bool,
+        before: Option<f64>,
+        after: Option<f64>,
+        dedup_results: Option<f32>,
+        min_fts_score: Option<f32>,
+        raw_filter: Option<String>,
+        mode: &str,
+        rerank_by: Option<&str>,
+        rerank_oversample: usize,
+        text_match: &str,
+        include_deleted: bool,
+        exclude_ids: Option<Vec<String>>,
+        fallback: bool,
+        fallback_min_score: Option<f32>,
+        report_score: &str,
+    ) -> PyResult<PyObject> {
+        let query = search_query(
+            vector,
+            text,
+            filter,
+            limit,
+            text_only,
+            before,
+            after,
+            dedup_results,
+            min_fts_score,
+            raw_filter,
+            mode,
+            rerank_by,
+            rerank_oversample,
+            text_match,
+            include_deleted,
+            exclude_ids,
+            fallback,
+            fallback_min_score,
+            report_score,
+        )?;
+        let results = self.run_search(py, query)?;
+        columns_to_dict(py, &results.into_iter().collect())
+    }
+
+    #[pyo3(signature = (type_filter=None, sort="created", limit=20, offset=0, before=None, after=None, include_deleted=false))]
+    #[allow(clippy::too_many_arguments)]
+    fn list(
+        &self,
+        py: Python<'_>,
+        type_filter: Option<&str>,
+        sort: &str,
+        limit: usize,
+        offset: usize,
+        before: Option<f64>,
+        after: Option<f64>,
+        include_deleted: bool,
+    ) -> PyResult<Vec<PyObject>> {
+        let sort_field = SortField::from_str(sort)
+            .map_err(PyRuntimeError::new_err)?;
+        let results = self
+            .inner
+            .lock()
+            .unwrap()
+            .list(type_filter, &sort_field, limit, offset, before, after, include_deleted)
+            .map_err(memori_err)?;
+        results.iter().map(|m| memory_to_dict(py, m)).collect()
+    }
+
+    /// Up to `n` random memories matching `filter`, without counting as
+    /// accesses. The same `seed` over the same rows returns the same sample.
+    #[pyo3(signature = (n, filter=None, seed=None))]
+    fn sample(
+        &self,
+        py: Python<'_>,
+        n: usize,
+        filter: Option<&Bound<'_, PyDict>>,
+        seed: Option<u64>,
+    ) -> PyResult<Vec<PyObject>> {
+        let filter_val = filter.map(pydict_to_value).transpose()?;
+        let results = self
+            .inner
+            .lock()
+            .unwrap()
+            .sample(n, filter_val.as_ref(), seed)
+            .map_err(memori_err)?;
+        results.iter().map(|m| memory_to_dict(py, m)).collect()
+    }
+
+    /// Like `list`, but laid out by column as `search_columns` is.
+    #[pyo3(signature = (type_filter=None, sort="created", limit=20, offset=0, before=None, after=None, include_deleted=false))]
+    #[allow(clippy::too_many_arguments)]
+    fn list_columns(
+        &self,
+        py: Python<'_>,
+        type_filter: Option<&str>,
+        sort: &str,
+        limit: usize,
+        offset: usize,
+        before: Option<f64>,
+        after: Option<f64>,
+        include_deleted: bool,
+    ) -> PyResult<PyObject> {
+        let sort_field = SortField::from_str(sort)
+            .map_err(PyRuntimeError::new_err)?;
+        let results = self
+            .inner
+            .lock()
+            .unwrap()
+            .list(type_filter, &sort_field, limit, offset, before, after, include_deleted)
+            .map_err(memori_err)?;
+        columns_to_dict(py, &results.into_iter().collect())
+    }
+
+    /// Pass the last row's `updated_at` and `id` as `since` and `after_id`
+    /// to fetch the next page.
+    #[pyo3(signature = (since, limit=100, after_id=None))]
+    fn changed_since(
+        &self,
+        py: Python<'_>,
+        since: f64,
+        limit: usize,
+        after_id: Option<&str>,
+    ) -> PyResult<Vec<PyObject>> {
+        let results = self
+            .inner
+            .lock()
+            .unwrap()
+            .changed_since(since, after_id, limit)
+            .map_err(memori_err)?;
+        results.iter().map(|m| memory_to_dict(py, m)).collect()
+    }
+
+    fn importance_score(&self, id: &str) -> PyResult<f32> {
+        self.inner.lock().unwrap().importance_score(id).map_err(memori_err)
+    }
+
+    #[pyo3(signature = (limit=10))]
+    fn top_important(&self, py: Python<'_>, limit: usize) -> PyResult<Vec<PyObject>> {
+        let results = self.inner.lock().unwrap().top_important(limit).map_err(memori_err)?;
+        results.iter().map(|m| memory_to_dict(py, m)).collect()
+    }
+
+    fn collections(&self) -> PyResult<Vec<String>> {
+        self.inner.lock().unwrap().collections().map_err(memori_err)
+    }
+
+    fn count(&self) -> PyResult<usize> {
+        self.inner.lock().unwrap().count().map_err(memori_err)
+    }
+
+    #[pyo3(signature = (id, content, vector=None, metadata=None, created_at=None, updated_at=None))]
+    fn insert_with_id(
+        &self,
+        id: &str,
+        content: &str,
+        vector: Option<Vec<f32>>,
+        metadata: Option<&Bound<'_, PyDict>>,
+        created_at: Option<f64>,
+        updated_at: Option<f64>,
+    ) -> PyResult<String> {
+        let meta = metadata.map(pydict_to_value).transpose()?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs_f64();
+        let ca = created_at.unwrap_or(now);
+        let ua = updated_at.unwrap_or(now);
+        self.inner
+            .lock()
+            .unwrap()
+            .insert_with_id(id, content, vector.as_deref(), meta, ca, ua)
+            .map_err(memori_err)
+    }
+
+    fn vacuum(&self) -> PyResult<()> {
+        self.inner.lock().unwrap().vacuum().map_err(memori_err)
+    }
+
+    fn backup_to(&self, dest_path: &str) -> PyResult<()> {
+        self.inner.lock().unwrap().backup_to(dest_path).map_err(memori_err)
+    }
+
+    fn flush_access(&self) -> PyResult<()> {
+        self.inner.lock().unwrap().flush_access().map_err(memori_err)
+    }
+
+    /// Returns `(busy, log_frames, checkpointed_frames)`.
+    #[pyo3(signature = (mode="passive"))]
+    fn checkpoint(&self, mode: &str) -> PyResult<(bool, i64, i64)> {
+        let mode = CheckpointMode::from_str(mode).map_err(PyRuntimeError::new_err)?;
+        let r = self
+            .inner
+            .lock()
+            .unwrap()
+            .checkpoint(mode)
+            .map_err(memori_err)?;
+        Ok((r.busy, r.log_frames, r.checkpointed_frames))
+    }
+
+    fn ensure_indexes(&self) -> PyResult<()> {
+        self.inner.lock().unwrap().ensure_indexes().map_err(memori_err)
+    }
+
+    fn export_memory(&self, id: &str) -> PyResult<String> {
+        self.inner.lock().unwrap().export_memory(id).map_err(memori_err)
+    }
+
+    fn import_memory(&self, json: &str) -> PyResult<String> {
+        self.inner.lock().unwrap().import_memory(json).map_err(memori_err)
+    }
+
+    fn add_metadata_index(&self, key: &str) -> PyResult<String> {
+        self.inner.lock().unwrap().add_metadata_index(key).map_err(memori_err)
+    }
+
+    fn analyze(&self) -> PyResult<()> {
+        self.inner.lock().unwrap().analyze().map_err(memori_err)
+    }
+
+    #[pyo3(signature = (id, last_accessed=None, access_count=0))]
+    fn set_access_stats(
+        &self,
+        id: &str,
+        last_accessed: Option<f64>,
+        access_count: i64,
+    ) -> PyResult<()> {
+        self.inner
+            .lock()
+            .unwrap()
+            .set_access_stats(id, last_accessed, access_count)
+            .map_err(memori_err)
+    }
+
+    /// `entries` is a list of `(id, last_accessed or None, access_count)`.
+    /// Returns how many ids matched; unknown ids are skipped.
+    fn set_access_stats_batch(
+        &self,
+        py: Python<'_>,
+        entries: Vec<(String, Option<f64>, i64)>,
+    ) -> PyResult<usize> {
+        py.allow_threads(|| {
+            self.inner
+                .lock()
+                .unwrap()
+                .set_access_stats_batch(&entries)
+                .map_err(memori_err)
+        })
+    }
+
+    fn clear_last_accessed(&self, id: &str) -> PyResult<()> {
+        self.inner
+            .lock()
+            .unwrap()
+            .clear_last_accessed(id)
+            .map_err(memori_err)
+    }
+
+    fn type_distribution(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let dist = self
+            .inner
+            .lock()
+            .unwrap()
+            .type_distribution()
+            .map_err(memori_err)?;
+        let dict = PyDict::new_bound(py);
+        for (k, v) in dist {
+            dict.set_item(k, v)?;
+        }
+        Ok(dict.to_object(py))
+    }
+
+    /// `(type, count)` tuples, most common first, ties by type name.
+    fn type_distribution_sorted(&self) -> PyResult<Vec<(String, usize)>> {
+        self.inner
+            .lock()
+            .unwrap()
+            .type_distribution_sorted()
+            .map_err(memori_err)
+    }
+
+    fn delete_before(&self, before_timestamp: f64) -> PyResult<usize> {
+        self.inner
+            .lock()
+            .unwrap()
+            .delete_before(before_timestamp)
+            .map_err(memori_err)
+    }
+
+    fn delete_by_type(&self, type_value: &str) -> PyResult<usize> {
+        self.inner
+            .lock()
+            .unwrap()
+            .delete_by_type(type_value)
+            .map_err(memori_err)
+    }
+
+    fn dedup_existing(&self, threshold: f32) -> PyResult<usize> {
+        self.inner.lock().unwrap().dedup_existing(threshold).map_err(memori_err)
+    }
+
+    #[pyo3(signature = (text,))]
+    fn embed(&self, text: &str) -> PyResult<Vec<f32>> {
+        #[cfg(feature = "embeddings")]
+        {
+            Ok(memori_core::embed::embed_text(text))
+        }
+        #[cfg(not(feature = "embeddings"))]
+        {
+            let _ = text;
+            Err(PyRuntimeError::new_err(
+                "embeddings feature not enabled at compile time",
+            ))
+        }
+    }
+
+    /// `progress`, if given, is called as `progress(processed, total)` after each batch.
+    /// `parallelism` batches are embedded concurrently.
+    #[pyo3(signature = (batch_size=50, progress=None, parallelism=1))]
+    fn backfill_embeddings(
+        &self,
+        py: Python<'_>,
+        batch_size: usize,
+        progress: Option<PyObject>,
+        parallelism: usize,
+    ) -> PyResult<usize> {
+        // The GIL is released while embedding, so the callback re-acquires it.
+        // The first exception it raises is re-raised once the backfill returns.
+        let mut callback_err: Option<PyErr> = None;
+        let processed = py.allow_threads(|| {
+            self.inner
+                .lock()
+                .unwrap()
+                .backfill_embeddings_parallel(batch_size, parallelism, |done, total| {
+                    if let (Some(cb), None) = (&progress, &callback_err) {
+                        if let Err(e) = Python::with_gil(|py| cb.call1(py, (done, total))) {
+                            callback_err = Some(e);
+                        }
+                    }
+                })
+                .map_err(memori_err)
+        })?;
+        match callback_err {
+            Some(e) => Err(e),
+            None => Ok(processed),
+        }
+    }
+
+    #[pyo3(signature = (id, limit=5))]
+    fn related(&self, py: Python<'_>, id: &str, limit: usize) -> PyResult<Vec<PyObject>> {
+        let id_owned = id.to_string();
+        let results = py.allow_threads(|| {
+            self.inner
+                .lock()
+                .unwrap()
+                .related(&id_owned, limit)
+                .map_err(memori_err)
+        })?;
+        results.iter().map(|m| memory_to_dict(py, m)).collect()
+    }
+
+    #[pyo3(signature = (id, limit=5, metadata_boost=0.1))]
+    fn related_weighted(
+        &self,
+        py: Python<'_>,
+        id: &str,
+        limit: usize,
+        metadata_boost: f32,
+    ) -> PyResult<Vec<PyObject>> {
+        let id_owned = id.to_string();
+        let results = py.allow_threads(|| {
+            self.inner
+                .lock()
+                .unwrap()
+                .related_weighted(&id_owned, limit, metadata_boost)
+                .map_err(memori_err)
+        })?;
+        results.iter().map(|m| memory_to_dict(py, m)).collect()
+    }
+
+    #[pyo3(signature = (id, depth=1, per_node=5))]
+    fn export_neighborhood(
+        &self,
+        py: Python<'_>,
+        id: &str,
+        depth: usize,
+        per_node: usize,
+    ) -> PyResult<Vec<PyObject>> {
+        let id_owned = id.to_string();
+        let results = py.allow_threads(|| {
+            self.inner
+                .lock()
+                .unwrap()
+                .export_neighborhood(&id_owned, depth, per_node)
+                .map_err(memori_err)
+        })?;
+        results.iter().map(|m| memory_to_dict(py, m)).collect()
+    }
+
+    fn embedding_stats(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let (embedded, total) = self
+            .inner
+            .lock()
+            .unwrap()
+            .embedding_stats()
+            .map_err(memori_err)?;
+        let dict = PyDict::new_bound(py);
+        dict.set_item("embedded", embedded)?;
+        dict.set_item("total", total)?;
+        Ok(dict.to_object(py))
+    }
+
+    fn collection_stats(&self, py: Python<'_>, collection: &str) -> PyResult<PyObject> {
+        let stats = self
+            .inner
+            .lock()
+            .unwrap()
+            .collection_stats(collection)
+            .map_err(memori_err)?;
+        store_stats_to_dict(py, &stats)
+    }
+
+    fn stats(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let stats = self.inner.lock().unwrap().stats().map_err(memori_err)?;
+        store_stats_to_dict(py, &stats)
+    }
+
+    fn metrics(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let m = self.inner.lock().unwrap().metrics();
+        let dict = PyDict::new_bound(py);
+        dict.set_item("inserts", m.inserts)?;
+        dict.set_item("dedup_merges", m.dedup_merges)?;
+        dict.set_item("searches", m.searches)?;
+        dict.set_item("gets", m.gets)?;
+        Ok(dict.to_object(py))
+    }
+
+    fn reset_metrics(&self) {
+        self.inner.lock().unwrap().reset_metrics();
+    }
+
+    fn missing_embedding_count(&self) -> PyResult<usize> {
+        self.inner
+            .lock()
+            .unwrap()
+            .missing_embedding_count()
+            .map_err(memori_err)
+    }
+
+    fn embedding_coverage_by_type(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let coverage = self
+            .inner
+            .lock()
+            .unwrap()
+            .embedding_coverage_by_type()
+            .map_err(memori_err)?;
+        let dict = PyDict::new_bound(py);
+        for (mtype, (embedded, total)) in coverage {
+            let entry = PyDict::new_bound(py);
+            entry.set_item("embedded", embedded)?;
+            entry.set_item("total", total)?;
+            dict.set_item(mtype, entry)?;
+        }
+        Ok(dict.to_object(py))
+    }
+}
@@ -183,6 +183,19 @@ def test_dedup_same_type(db):
     assert db.count() == 1
 
 
+def test_no_embed_still_dedups_with_explicit_vector(db):
+    """no_embed only suppresses auto-embedding; dedup still runs on an explicit vector."""
+    r1 = db.insert("first", vector=[1.0, 0.0, 0.0], metadata={"type": "fact"},
+                   dedup_threshold=0.92, no_embed=True)
+    assert r1["action"] == "created"
+
+    r2 = db.insert("second", vector=[0.99, 0.01, 0.0], metadata={"type": "fact"},
+                   dedup_threshold=0.92, no_embed=True)
+    assert r2["action"] == "deduplicated"
+    assert r2["id"] == r1["id"]
+    assert db.count() == 1
+
+
 # -- v0.3.1 access tracking tests --
 
 
@@ -325,14 +338,14 @@ def test_prefix_delete(db):
 def test_prefix_ambiguous(db):
     import time
     ts = time.time()
-    db.insert_with_id("aaa11111-1111-1111-1111-111111111111", "first", created_at=ts, updated_at=ts)
-    db.insert_with_id("aaa22222-2222-2222-2222-222222222222", "second", created_at=ts, updated_at=ts)
+    db.insert_with_id("aaaa1111-1111-1111-1111-111111111111", "first", created_at=ts, updated_at=ts)
+    db.insert_with_id("aaaa2222-2222-2222-2222-222222222222", "second", created_at=ts, updated_at=ts)
 
     with pytest.raises(RuntimeError, match="ambiguous"):
-        db.update("aaa", content="fail")
+        db.update("aaaa", content="fail")
 
     # But 8-char prefix works
-    mem = db.get("aaa11111")
+    mem = db.get("aaaa1111")
     assert mem is not None
     assert mem["content"] == "first"
 
@@ -421,3 +434,545 @@ def test_cli_resolve_id_not_found(db):
     """_resolve_id returns the original prefix when no match."""
     from memori_cli import _resolve_id
     assert _resolve_id(db, "nonexistent") == "nonexistent"
+
+
+# -- backfill progress --
+
+
+def test_backfill_progress_callback(db):
+    for i in range(5):
+        db.insert(f"unembedded {i}", no_embed=True)
+
+    calls = []
+    processed = db.backfill_embeddings(batch_size=2, progress=lambda done, total: calls.append((done, total)))
+
+    assert processed == 5
+    assert [done for done, _ in calls] == [2, 4, 5]
+    assert all(total == 5 for _, total in calls)
+
+
+# -- background insert --
+
+
+def test_insert_async_bg_returns_before_insert_completes(db):
+    import time
+
+    start = time.perf_counter()
+    handle = db.insert_async_bg("written in the background", metadata={"type": "note"})
+    elapsed = time.perf_counter() - start
+    # Only spawns the thread; embedding (and first-time model load) happens later
+    assert elapsed < 0.5
+
+    result = handle.wait()
+    assert result["action"] == "created"
+    assert handle.done()
+    mem = db.get(result["id"])
+    assert mem["content"] == "written in the background"
+    assert mem["metadata"]["type"] == "note"
+    # Waiting again returns the same outcome
+    assert handle.wait()["id"] == result["id"]
+
+
+def test_insert_async_bg_embeds_like_insert(db):
+    content = "Kafka  uses partitioned topics"
+    bg = db.get(db.insert_async_bg(content).wait()["id"])
+    direct = db.get(db.insert(content)["id"])
+    assert bg["id"] != direct["id"]
+    # Same preprocessing and normalization as the synchronous path
+    assert bg["vector"] is not None
+    assert bg["vector"] == direct["vector"]
+
+
+
+# -- collections --
+
+
+def test_collections_are_isolated(tmp_path):
+    path = str(tmp_path / "shared.db")
+    a = PyMemori(path, collection="agent-a")
+    b = PyMemori(path, collection="agent-b")
+    a.insert("same text", vector=[1.0, 0.0])
+    r = b.insert("same text", vector=[1.0, 0.0], dedup_threshold=0.9)
+    assert r["action"] == "created"
+    assert a.count() == 1
+    assert b.count() == 1
+    assert b.get(a.list()[0]["id"]) is None
+    assert a.collections() == ["agent-a", "agent-b"]
+
+
+# -- batch access stats --
+
+
+def test_set_access_stats_batch(db):
+    a = db.insert("first")["id"]
+    b = db.insert("second")["id"]
+    matched = db.set_access_stats_batch([(a, 1234.5, 7), ("missing-id", None, 1), (b, None, 2)])
+    assert matched == 2
+    assert db.get_readonly(a)["access_count"] == 7
+    assert db.get_readonly(a)["last_accessed"] == 1234.5
+    assert db.get_readonly(b)["access_count"] == 2
+
+
+# -- base score --
+
+
+def test_search_returns_base_score(db):
+    db.insert("alpha", vector=[1.0, 0.0])
+    results = db.search(vector=[1.0, 0.0], limit=1)
+    assert results[0]["base_score"] is not None
+    assert results[0]["base_score"] == results[0]["score"]
+    assert db.get(results[0]["id"])["base_score"] is None
+
+
+# -- search modes --
+
+
+def test_search_mode_text_ignores_vector(db):
+    db.insert("kafka topics", vector=[0.0, 1.0])
+    db.insert("unrelated words", vector=[1.0, 0.0])
+    results = db.search(vector=[1.0, 0.0], text="kafka", mode="text")
+    assert [r["content"] for r in results] == ["kafka topics"]
+    results = db.search(vector=[1.0, 0.0], text="kafka", mode="vector")
+    assert results[0]["content"] == "unrelated words"
+
+
+def test_search_mode_invalid(db):
+    with pytest.raises(RuntimeError, match="invalid search mode"):
+        db.search(text="x", mode="fuzzy")
+
+
+# -- rerank --
+
+
+def test_search_rerank_by_created(db):
+    for i in range(4):
+        db.insert_with_id(f"rel-{i}", "relevant", vector=[1.0, 0.0], created_at=1000.0 + i, updated_at=1000.0 + i)
+    results = db.search(vector=[1.0, 0.0], limit=2, rerank_by="created", rerank_oversample=2)
+    assert [r["id"] for r in results] == ["rel-3", "rel-2"]
+
+
+# -- embedding coverage --
+
+
+def test_embedding_coverage_by_type(db):
+    db.insert("a", vector=[1.0, 0.0], metadata={"type": "fact"}, no_embed=True)
+    db.insert("b", metadata={"type": "fact"}, no_embed=True)
+    db.insert("c", metadata={"type": "note"}, no_embed=True)
+    db.insert("d", no_embed=True)
+
+    assert db.missing_embedding_count() == 3
+    coverage = db.embedding_coverage_by_type()
+    assert coverage == {
+        "fact": {"embedded": 1, "total": 2},
+        "note": {"embedded": 0, "total": 1},
+    }
+
+
+# -- neighborhood export --
+
+
+def test_export_neighborhood(db):
+    seed = db.insert("seed", vector=[1.0, 0.0])["id"]
+    near = db.insert("near", vector=[0.9, 0.1])["id"]
+    db.insert("far", vector=[0.0, 1.0])
+
+    assert [m["id"] for m in db.export_neighborhood(seed, depth=0)] == [seed]
+    bundle = db.export_neighborhood(seed, depth=1, per_node=1)
+    assert [m["id"] for m in bundle] == [seed, near]
+
+
+# -- metrics --
+
+
+def test_metrics(db):
+    db.insert("a", vector=[1.0, 0.0], dedup_threshold=0.92)
+    db.insert("b", vector=[1.0, 0.0], dedup_threshold=0.92)
+    db.search(vector=[1.0, 0.0])
+
+    assert db.metrics() == {"inserts": 2, "dedup_merges": 1, "searches": 1, "gets": 0}
+    db.reset_metrics()
+    assert db.metrics()["inserts"] == 0
+
+
+# -- checkpoint --
+
+
+def test_checkpoint(tmp_path):
+    path = str(tmp_path / "wal.db")
+    db = PyMemori(path)
+    for i in range(10):
+        db.insert(f"memory {i}", vector=[float(i), 1.0])
+
+    busy, log_frames, checkpointed = db.checkpoint("full")
+    assert not busy
+    assert checkpointed == log_frames > 0
+    assert db.checkpoint("truncate") == (False, 0, 0)
+    assert (tmp_path / "wal.db-wal").stat().st_size == 0
+
+
+# -- weighted related --
+
+
+def test_related_weighted(db):
+    source = db.insert("source", vector=[1.0, 0.0], metadata={"topic": "kafka"})["id"]
+    closest = db.insert("closest", vector=[0.99, 0.14], metadata={"topic": "redis"})["id"]
+    tagged = db.insert("tagged", vector=[0.95, 0.31], metadata={"topic": "kafka"})["id"]
+
+    assert [m["id"] for m in db.related(source, limit=2)] == [closest, tagged]
+    assert [m["id"] for m in db.related_weighted(source, limit=2)] == [tagged, closest]
+
+
+# -- replace --
+
+
+def test_replace(db):
+    mid = db.insert("old", vector=[1.0, 0.0], metadata={"type": "fact", "topic": "kafka"})["id"]
+    created = db.get_readonly(mid)["created_at"]
+
+    db.replace(mid, "new", vector=[0.0, 1.0], metadata={"type": "decision"})
+    mem = db.get_readonly(mid)
+    assert mem["content"] == "new"
+    assert mem["metadata"] == {"type": "decision"}
+    assert mem["created_at"] == created
+
+
+# -- importance --
+
+
+def test_importance(db):
+    import time
+
+    hot = db.insert("hot")["id"]
+    stale = db.insert("stale")["id"]
+    db.set_access_stats(hot, last_accessed=time.time(), access_count=50)
+    db.set_access_stats(stale, last_accessed=time.time() - 400 * 86400, access_count=2)
+
+    assert db.importance_score(hot) > db.importance_score(stale)
+    assert db.top_important(limit=1)[0]["id"] == hot
+
+
+# -- text_match --
+
+
+def test_search_text_match_any(db):
+    db.insert("apples only")
+    db.insert("pears only")
+    db.insert("apples and pears")
+
+    both = db.search(text="apples pears", mode="text")
+    assert [r["content"] for r in both] == ["apples and pears"]
+    either = db.search(text="apples pears", mode="text", text_match="any")
+    assert len(either) == 3
+    with pytest.raises(RuntimeError):
+        db.search(text="apples", text_match="some")
+
+
+# -- changed_since --
+
+
+def test_changed_since(db):
+    import time
+
+    before = time.time() - 1
+    first = db.insert("first", no_embed=True)["id"]
+    second = db.insert("second", no_embed=True)["id"]
+    db.update(first, content="first, edited")
+
+    changed = db.changed_since(before)
+    assert [r["id"] for r in changed] == [second, first]
+    assert db.changed_since(changed[-1]["updated_at"]) == []
+    resumed = db.changed_since(changed[0]["updated_at"], after_id=changed[0]["id"])
+    assert [r["id"] for r in resumed] == [first]
+
+
+# -- search_iter --
+
+
+def test_search_iter_lazy_and_ordered(db):
+    for i in range(100):
+        db.insert(f"memory {i}", vector=[1.0, i / 100.0], no_embed=True)
+
+    expected = db.search(vector=[1.0, 0.0], limit=100)
+    assert len(expected) == 100
+
+    first_three = []
+    for result in db.search_iter(vector=[1.0, 0.0], limit=100):
+        first_three.append(result["id"])
+        if len(first_three) == 3:
+            break
+    assert first_three == [r["id"] for r in expected[:3]]
+
+    everything = [r["id"] for r in db.search_iter(vector=[1.0, 0.0], limit=100)]
+    assert everything == [r["id"] for r in expected]
+
+
+# -- increment_metadata_number --
+
+
+def test_increment_metadata_number(db):
+    mid = db.insert("counter", metadata={"type": "stat"}, no_embed=True)["id"]
+    assert db.increment_metadata_number(mid, "hits") == 1
+    assert db.increment_metadata_number(mid, "hits", 4) == 5
+    assert db.get(mid)["metadata"]["hits"] == 5
+    with pytest.raises(RuntimeError):
+        db.increment_metadata_number(mid, "type")
+
+
+# -- soft_delete --
+
+
+def test_soft_delete_include_deleted(db):
+    keep = db.insert("kafka consumer lag", vector=[1.0, 0.0], no_embed=True)["id"]
+    gone = db.insert("kafka broker restart", vector=[0.9, 0.1], no_embed=True)["id"]
+    db.soft_delete(gone)
+
+    assert db.get(gone) is None
+    assert [r["id"] for r in db.search(vector=[1.0, 0.0])] == [keep]
+    assert [r["id"] for r in db.list()] == [keep]
+
+    results = db.search(vector=[1.0, 0.0], include_deleted=True)
+    assert [r["id"] for r in results] == [keep, gone]
+    assert results[0]["deleted_at"] is None
+    assert results[1]["deleted_at"] is not None
+    listed = {r["id"]: r for r in db.list(include_deleted=True)}
+    assert listed[gone]["deleted_at"] is not None
+    assert listed[keep]["deleted_at"] is None
+
+
+# -- backup_to --
+
+
+def test_backup_to(db, tmp_path):
+    mid = db.insert("kafka consumer lag", metadata={"type": "ops"}, no_embed=True)["id"]
+    dest = str(tmp_path / "backup.db")
+    db.backup_to(dest)
+    copy = PyMemori(dest)
+    assert copy.count() == db.count()
+    assert copy.get(mid)["content"] == "kafka consumer lag"
+
+
+# -- exclude_ids --
+
+
+def test_search_exclude_ids(db):
+    best = db.insert("best", vector=[1.0, 0.0], no_embed=True)["id"]
+    nxt = db.insert("next", vector=[0.9, 0.1], no_embed=True)["id"]
+    results = db.search(vector=[1.0, 0.0], exclude_ids=[best[:8]])
+    assert [r["id"] for r in results] == [nxt]
+
+
+# -- collection_stats --
+
+
+def test_collection_stats(tmp_path):
+    path = str(tmp_path / "shared.db")
+    a = PyMemori(path, collection="a")
+    b = PyMemori(path, collection="b")
+    for i in range(3):
+        a.insert(f"a note {i}", no_embed=True)
+    b.insert("b note", no_embed=True)
+    sa, sb = a.collection_stats("a"), a.collection_stats("b")
+    assert (sa["memories"], sb["memories"]) == (3, 1)
+    total = b.stats()
+    assert total["memories"] == 4
+    assert total["data_bytes"] == sa["data_bytes"] + sb["data_bytes"]
+
+
+# -- columnar results --
+
+
+def test_search_and_list_columns(db):
+    db.insert("first", vector=[1.0, 0.0], metadata={"type": "fact"}, no_embed=True)
+    db.insert("second", vector=[0.8, 0.2], no_embed=True)
+    rows = db.search(vector=[1.0, 0.0])
+    cols = db.search_columns(vector=[1.0, 0.0])
+    assert cols["id"] == [r["id"] for r in rows]
+    assert cols["content"] == ["first", "second"]
+    assert cols["metadata"] == ['{"type":"fact"}', None]
+    assert len(cols["score"]) == 2
+    assert cols["deleted_at"] == [None, None]
+    listed = db.list_columns(sort="created")
+    assert sorted(listed["content"]) == ["first", "second"]
+    assert set(listed) == set(cols)
+
+
+# -- external content --
+
+
+def test_insert_external(db):
+    mid = db.insert_external("s3://docs/handbook.pdf", "onboarding handbook for new hires", no_embed=True)
+    assert db.get(mid)["content"] == "s3://docs/handbook.pdf"
+    assert [r["id"] for r in db.search(text="handbook", text_only=True)] == [mid]
+    assert db.external_summary(mid) == "onboarding handbook for new hires"
+
+
+# -- type_distribution_sorted --
+
+
+def test_type_distribution_sorted(db):
+    for t in ["fact", "fact", "ops", "bug", "bug", "note"]:
+        db.insert(f"a {t}", metadata={"type": t}, no_embed=True)
+    assert db.type_distribution_sorted() == [("bug", 2), ("fact", 2), ("note", 1), ("ops", 1)]
+
+
+# -- precomputed content hash --
+
+
+def test_insert_with_content_hash(db):
+    first = db.insert("big doc", content_hash="00000000deadbeef", no_embed=True)
+    assert first["action"] == "created"
+    with pytest.raises(RuntimeError, match="invalid content hash"):
+        db.insert("big doc", content_hash="xyz", no_embed=True)
+
+
+# -- search_many --
+
+
+def test_search_many_matches_search(db):
+    for i, v in enumerate([[1.0, 0.0], [0.7, 0.7], [0.0, 1.0]]):
+        db.insert(f"note {i}", vector=v, no_embed=True)
+    queries = [[1.0, 0.0], [0.0, 1.0]]
+    batched = db.search_many(queries, limit=2)
+    assert [[r["id"] for r in rows] for rows in batched] == [
+        [r["id"] for r in db.search(vector=q, limit=2)] for q in queries
+    ]
+
+
+# -- search fallback --
+
+
+def test_search_fallback_to_text(db):
+    db.insert("deploy runbook", vector=[1.0, 0.0], no_embed=True)
+    db.insert("lunch order", vector=[1.0, 0.0], no_embed=True)
+    results = db.search(
+        vector=[0.0, 1.0], text="runbook", fallback=True, fallback_min_score=0.5
+    )
+    assert [r["content"] for r in results] == ["deploy runbook"]
+
+
+# -- attachments --
+
+
+def test_attachments_roundtrip_and_cascade(db):
+    mid = db.insert("photo of the whiteboard", no_embed=True)["id"]
+    db.add_attachment(mid, "board.png", "image/png", b"\x89PNG\x00\x01")
+    db.add_attachment(mid, "notes.txt", "text/plain", b"todo")
+    got = db.get_attachment(mid, "board.png")
+    assert got["data"] == b"\x89PNG\x00\x01"
+    assert got["mime"] == "image/png"
+    assert [a["name"] for a in db.list_attachments(mid)] == ["board.png", "notes.txt"]
+    assert db.get_attachment(mid, "missing") is None
+
+
+# -- dedup_existing --
+
+
+def test_dedup_existing(db):
+    for i, v in enumerate([[1.0, 0.0], [0.99, 0.01], [0.98, 0.02]]):
+        db.insert(f"port 9092 ({i})", vector=v, metadata={"type": "fact"}, no_embed=True)
+    assert db.dedup_existing(0.95) == 2
+    assert db.count() == 1
+
+
+def test_array_merge_union_on_duplicate_insert(tmp_path):
+    db = PyMemori(str(tmp_path / "union.db"), array_merge="union")
+    first = db.insert("flaky deploy", vector=[1.0, 0.0], metadata={"tags": ["ci"]}, no_embed=True)
+    dup = db.insert(
+        "flaky deploy again",
+        vector=[0.99, 0.01],
+        metadata={"tags": ["ci", "ops"]},
+        dedup_threshold=0.95,
+        no_embed=True,
+    )
+    assert dup["id"] == first["id"]
+    assert db.get(first["id"])["metadata"]["tags"] == ["ci", "ops"]
+    with pytest.raises(RuntimeError, match="invalid array merge"):
+        PyMemori(str(tmp_path / "bad.db"), array_merge="bogus")
+
+
+# -- report_score --
+
+
+def test_report_score_vector_cosine(db):
+    db.insert("kafka broker", vector=[1.0, 0.0], no_embed=True)
+    db.insert("kafka lag", vector=[0.6, 0.8], no_embed=True)
+    results = db.search(
+        vector=[1.0, 0.0], text="kafka", mode="hybrid", report_score="vector_cosine"
+    )
+    assert sorted(round(r["score"], 4) for r in results) == [0.6, 1.0]
+    with pytest.raises(RuntimeError, match="invalid report_score"):
+        db.search(text="kafka", report_score="bogus")
+
+
+# -- create_parent_dirs --
+
+
+def test_open_creates_parent_dirs(tmp_path):
+    path = tmp_path / "some" / "new" / "mem.db"
+    with pytest.raises(RuntimeError, match="does not exist"):
+        PyMemori(str(path))
+    PyMemori(str(path), create_parent_dirs=True).insert("x", no_embed=True)
+    assert path.is_file()
+
+
+# -- metadata indexes --
+
+
+def test_add_metadata_index(db):
+    db.insert("note", metadata={"project": "apollo"}, no_embed=True)
+    assert db.add_metadata_index("project") == "idx_memories_meta_project"
+    assert len(db.search(text="note", filter={"project": "apollo"})) == 1
+    with pytest.raises(RuntimeError, match="invalid filter key"):
+        db.add_metadata_index("bad key")
+
+
+# -- export_memory --
+
+
+def test_export_import_memory(db, tmp_path):
+    mid = db.insert("share me", vector=[1.0, 0.0], metadata={"type": "bug"}, no_embed=True)["id"]
+    db.set_access_stats(mid, 1700000000.0, 3)
+    other = PyMemori(str(tmp_path / "other.db"))
+    assert other.import_memory(db.export_memory(mid)) == mid
+    got = other.get_readonly(mid)
+    assert got["access_count"] == 3
+    assert got["metadata"] == {"type": "bug"}
+
+
+# -- multi-vector chunks --
+
+
+def test_insert_chunked_scores_best_chunk(db):
+    chunked = db.insert_chunked("long doc", [[0.0, 1.0, 0.0], [1.0, 0.0, 0.0]])
+    db.insert("other", vector=[0.5, 0.5, 0.7], no_embed=True)
+    results = db.search(vector=[1.0, 0.0, 0.0], limit=5)
+    ids = [r["id"] for r in results]
+    assert ids[0] == chunked
+    assert ids.count(chunked) == 1
+
+
+# -- parallel backfill --
+
+
+def test_parallel_backfill(db):
+    for i in range(7):
+        db.insert(f"unembedded {i}", no_embed=True)
+
+    calls = []
+    processed = db.backfill_embeddings(batch_size=2, progress=lambda done, _: calls.append(done), parallelism=3)
+
+    assert processed == 7
+    assert calls == [2, 4, 6, 7]
+
+
+# -- sample --
+
+
+def test_sample(db):
+    for i in range(6):
+        db.insert(f"memory {i}", metadata={"type": "bug" if i % 2 else "note"}, no_embed=True)
+
+    picked = db.sample(3)
+    assert len({m["id"] for m in picked}) == 3
+    assert all(m["metadata"]["type"] == "bug" for m in db.sample(10, filter={"type": "bug"}))
+    assert [m["id"] for m in db.sample(4, seed=1)] == [m["id"] for m in db.sample(4, seed=1)]